@@ -291,6 +291,10 @@ func TestAnalyzeCode(t *testing.T) {
 	require.False(t, report.HasIBCEntryPoints)
 	require.Equal(t, "", report.RequiredFeatures)
 	require.Equal(t, "", report.RequiredCapabilities)
+	require.Equal(t, "execute,instantiate,migrate,query,sudo", report.Entrypoints)
+	require.Equal(t, "interface_version_8", report.InterfaceVersion)
+	require.Equal(t, uint64(len(wasm)), report.CodeSize)
+	require.Nil(t, report.ContractMigrateVersion)
 
 	// Store IBC contract
 	wasm2, err := ioutil.ReadFile(IBC_TEST_CONTRACT)
@@ -17,6 +17,8 @@ typedef GoError (*next_db_fn)(iterator_t idx, gas_meter_t *gas_meter, uint64_t *
 typedef GoError (*humanize_address_fn)(api_t *ptr, U8SliceView src, UnmanagedVector *dest, UnmanagedVector *errOut, uint64_t *used_gas);
 typedef GoError (*canonicalize_address_fn)(api_t *ptr, U8SliceView src, UnmanagedVector *dest, UnmanagedVector *errOut, uint64_t *used_gas);
 typedef GoError (*query_external_fn)(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView request, UnmanagedVector *result, UnmanagedVector *errOut);
+typedef GoError (*query_evm_fn)(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView to, U8SliceView data, uint64_t evm_gas_limit, uint64_t *evm_gas_used, UnmanagedVector *result, UnmanagedVector *errOut);
+typedef GoError (*consume_gas_fn)(querier_t *ptr, uint64_t gas, uint64_t *used_gas, UnmanagedVector *errOut);
 
 // forward declarations (db)
 GoError cGet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, U8SliceView key, UnmanagedVector *val, UnmanagedVector *errOut);
@@ -30,6 +32,8 @@ GoError cHumanAddress_cgo(api_t *ptr, U8SliceView src, UnmanagedVector *dest, Un
 GoError cCanonicalAddress_cgo(api_t *ptr, U8SliceView src, UnmanagedVector *dest, UnmanagedVector *errOut, uint64_t *used_gas);
 // and querier
 GoError cQueryExternal_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView request, UnmanagedVector *result, UnmanagedVector *errOut);
+GoError cQueryEvm_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView to, U8SliceView data, uint64_t evm_gas_limit, uint64_t *evm_gas_used, UnmanagedVector *result, UnmanagedVector *errOut);
+GoError cConsumeGas_cgo(querier_t *ptr, uint64_t gas, uint64_t *used_gas, UnmanagedVector *errOut);
 
 
 */
@@ -388,9 +392,16 @@ var querier_vtable = C.Querier_vtable{
 // contract: original pointer/struct referenced must live longer than C.GoQuerier struct
 // since this is only used internally, we can verify the code that this is the case
 func buildQuerier(q *Querier) C.GoQuerier {
+	vtable := querier_vtable
+	if _, ok := (*q).(types.EvmQuerier); ok {
+		vtable.query_evm = (C.query_evm_fn)(C.cQueryEvm_cgo)
+	}
+	if _, ok := (*q).(types.GasConsumer); ok {
+		vtable.consume_gas = (C.consume_gas_fn)(C.cConsumeGas_cgo)
+	}
 	return C.GoQuerier{
 		state:  (*C.querier_t)(unsafe.Pointer(q)),
-		vtable: querier_vtable,
+		vtable: vtable,
 	}
 }
 
@@ -424,3 +435,59 @@ func cQueryExternal(ptr *C.querier_t, gasLimit cu64, usedGas *cu64, request C.U8
 	*result = newUnmanagedVector(bz)
 	return C.GoError_None
 }
+
+//export cQueryEvm
+func cQueryEvm(ptr *C.querier_t, gasLimit cu64, usedGas *cu64, to C.U8SliceView, data C.U8SliceView, evmGasLimit cu64, evmGasUsed *cu64, result *C.UnmanagedVector, errOut *C.UnmanagedVector) (ret C.GoError) {
+	defer recoverPanic(&ret)
+
+	if ptr == nil || usedGas == nil || evmGasUsed == nil || result == nil || errOut == nil {
+		// we received an invalid pointer
+		return C.GoError_BadArgument
+	}
+	if !(*result).is_none || !(*errOut).is_none {
+		panic("Got a non-none UnmanagedVector we're about to override. This is a bug because someone has to drop the old one.")
+	}
+
+	querier, ok := (*(*Querier)(unsafe.Pointer(ptr))).(types.EvmQuerier)
+	if !ok {
+		return C.GoError_BadArgument
+	}
+
+	gasBefore := querier.GasConsumed()
+	retData, gas, err := querier.EvmCall(copyU8Slice(to), copyU8Slice(data), uint64(evmGasLimit), uint64(gasLimit))
+	gasAfter := querier.GasConsumed()
+	*usedGas = (cu64)(gasAfter - gasBefore)
+	*evmGasUsed = (cu64)(gas)
+	if err != nil {
+		// a reverted call, which is passed to the contract
+		*errOut = newUnmanagedVector([]byte(err.Error()))
+		return C.GoError_User
+	}
+	*result = newUnmanagedVector(retData)
+	return C.GoError_None
+}
+
+//export cConsumeGas
+func cConsumeGas(ptr *C.querier_t, gas cu64, usedGas *cu64, errOut *C.UnmanagedVector) (ret C.GoError) {
+	defer recoverPanic(&ret)
+
+	if ptr == nil || usedGas == nil || errOut == nil {
+		// we received an invalid pointer
+		return C.GoError_BadArgument
+	}
+	if !(*errOut).is_none {
+		panic("Got a non-none UnmanagedVector we're about to override. This is a bug because someone has to drop the old one.")
+	}
+
+	querier, ok := (*(*Querier)(unsafe.Pointer(ptr))).(types.GasConsumer)
+	if !ok {
+		return C.GoError_BadArgument
+	}
+
+	// measured like a query, such that a memoized query is charged exactly like the original one
+	gasBefore := querier.GasConsumed()
+	querier.ConsumeGas(uint64(gas))
+	gasAfter := querier.GasConsumed()
+	*usedGas = (cu64)(gasAfter - gasBefore)
+	return C.GoError_None
+}
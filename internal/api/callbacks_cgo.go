@@ -16,6 +16,8 @@ GoError cHumanAddress(api_t *ptr, U8SliceView src, UnmanagedVector *dest, Unmana
 GoError cCanonicalAddress(api_t *ptr, U8SliceView src, UnmanagedVector *dest, UnmanagedVector *errOut, uint64_t *used_gas);
 // imports (querier)
 GoError cQueryExternal(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView request, UnmanagedVector *result, UnmanagedVector *errOut);
+GoError cQueryEvm(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView to, U8SliceView data, uint64_t evm_gas_limit, uint64_t *evm_gas_used, UnmanagedVector *result, UnmanagedVector *errOut);
+GoError cConsumeGas(querier_t *ptr, uint64_t gas, uint64_t *used_gas, UnmanagedVector *errOut);
 
 // Gateway functions (db)
 GoError cGet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, U8SliceView key, UnmanagedVector *val, UnmanagedVector *errOut) {
@@ -48,6 +50,12 @@ GoError cHumanAddress_cgo(api_t *ptr, U8SliceView src, UnmanagedVector *dest, Un
 GoError cQueryExternal_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView request, UnmanagedVector *result, UnmanagedVector *errOut) {
     return cQueryExternal(ptr, gas_limit, used_gas, request, result, errOut);
 }
+GoError cQueryEvm_cgo(querier_t *ptr, uint64_t gas_limit, uint64_t *used_gas, U8SliceView to, U8SliceView data, uint64_t evm_gas_limit, uint64_t *evm_gas_used, UnmanagedVector *result, UnmanagedVector *errOut) {
+    return cQueryEvm(ptr, gas_limit, used_gas, to, data, evm_gas_limit, evm_gas_used, result, errOut);
+}
+GoError cConsumeGas_cgo(querier_t *ptr, uint64_t gas, uint64_t *used_gas, UnmanagedVector *errOut) {
+    return cConsumeGas(ptr, gas, used_gas, errOut);
+}
 */
 import "C"
 
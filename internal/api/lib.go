@@ -5,8 +5,11 @@ package api
 import "C"
 
 import (
+	"encoding/hex"
+	"encoding/json"
 	"fmt"
 	"runtime"
+	"strings"
 	"syscall"
 
 	"github.com/CosmWasm/wasmvm/types"
@@ -54,10 +57,93 @@ func InitCache(dataDir string, supportedCapabilities string, cacheSize uint32, i
 	return Cache{ptr: ptr}, nil
 }
 
+// InitCacheWithConfig creates a cache from the config, which is sent to libwasmvm as the
+// "v1" version of its JSON encoded cache config.
+func InitCacheWithConfig(config types.VMConfig) (Cache, error) {
+	if config.AvailableCapabilities == nil {
+		config.AvailableCapabilities = []string{}
+	}
+	configBytes, err := json.Marshal(map[string]types.VMConfig{"v1": config})
+	if err != nil {
+		return Cache{}, err
+	}
+	c := makeView(configBytes)
+	defer runtime.KeepAlive(configBytes)
+	errmsg := uninitializedUnmanagedVector()
+
+	ptr, err := C.init_cache_with_config(c, &errmsg)
+	if err != nil {
+		return Cache{}, errorWithMessage(err, errmsg)
+	}
+	return Cache{ptr: ptr}, nil
+}
+
 func ReleaseCache(cache Cache) {
 	C.release_cache(cache.ptr)
 }
 
+func GetCapabilities(cache Cache) (string, error) {
+	errmsg := uninitializedUnmanagedVector()
+	capabilities, err := C.get_capabilities(cache.ptr, &errmsg)
+	if err != nil {
+		return "", errorWithMessage(err, errmsg)
+	}
+	return string(copyAndDestroyUnmanagedVector(capabilities)), nil
+}
+
+// SetCapabilities replaces the available capabilities and returns the checksums of
+// all stored codes that require capabilities which are not available anymore.
+func SetCapabilities(cache Cache, capabilities string) ([][]byte, error) {
+	capabilitiesBytes := []byte(capabilities)
+	c := makeView(capabilitiesBytes)
+	defer runtime.KeepAlive(capabilitiesBytes)
+	errmsg := uninitializedUnmanagedVector()
+	unusable, err := C.set_capabilities(cache.ptr, c, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	unusableCSV := string(copyAndDestroyUnmanagedVector(unusable))
+	if unusableCSV == "" {
+		return [][]byte{}, nil
+	}
+	hexChecksums := strings.Split(unusableCSV, ",")
+	checksums := make([][]byte, len(hexChecksums))
+	for i, hexChecksum := range hexChecksums {
+		checksum, err := hex.DecodeString(hexChecksum)
+		if err != nil {
+			return nil, err
+		}
+		checksums[i] = checksum
+	}
+	return checksums, nil
+}
+
+func SetQuerySnapshot(cache Cache, height uint64, snapshot []byte) error {
+	s := makeView(snapshot)
+	defer runtime.KeepAlive(snapshot)
+	errmsg := uninitializedUnmanagedVector()
+	_, err := C.set_query_snapshot(cache.ptr, cu64(height), s, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+func UpdateQueryRoutes(cache Cache, height uint64, routes map[string]types.QueryRoute) error {
+	routesBytes, err := json.Marshal(routes)
+	if err != nil {
+		return err
+	}
+	r := makeView(routesBytes)
+	defer runtime.KeepAlive(routesBytes)
+	errmsg := uninitializedUnmanagedVector()
+	_, err = C.update_query_routes(cache.ptr, cu64(height), r, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
 func StoreCode(cache Cache, wasm []byte) ([]byte, error) {
 	w := makeView(wasm)
 	defer runtime.KeepAlive(wasm)
@@ -113,6 +199,47 @@ func Pin(cache Cache, checksum []byte) error {
 	return nil
 }
 
+// SetInstanceMemoryLimit sets the instance memory limit in MiB for calls of the checksum.
+// 0 resets it to the instance memory limit of the cache. Limits are stored in the data
+// directory and loaded again by InitCache, unless the cache is in memory only.
+func SetInstanceMemoryLimit(cache Cache, checksum []byte, limitMiB uint32) error {
+	cs := makeView(checksum)
+	defer runtime.KeepAlive(checksum)
+	errmsg := uninitializedUnmanagedVector()
+	_, err := C.set_instance_memory_limit(cache.ptr, cs, cu32(limitMiB), &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// UpdateGasConfig uses the gas config for calls from the block height on.
+// Updates are not persisted and must be made again after a restart.
+func UpdateGasConfig(cache Cache, height uint64, config types.GasConfig) error {
+	configBytes, err := json.Marshal(config)
+	if err != nil {
+		return err
+	}
+	c := makeView(configBytes)
+	defer runtime.KeepAlive(configBytes)
+	errmsg := uninitializedUnmanagedVector()
+	_, err = C.update_gas_config(cache.ptr, cu64(height), c, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// FeatureSetID returns the id of the feature set of calls at the block height
+func FeatureSetID(cache Cache, height uint64) (uint32, error) {
+	errmsg := uninitializedUnmanagedVector()
+	id, err := C.get_feature_set_id(cache.ptr, cu64(height), &errmsg)
+	if err != nil {
+		return 0, errorWithMessage(err, errmsg)
+	}
+	return uint32(id), nil
+}
+
 func Unpin(cache Cache, checksum []byte) error {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
@@ -132,11 +259,58 @@ func AnalyzeCode(cache Cache, checksum []byte) (*types.AnalysisReport, error) {
 	if err != nil {
 		return nil, errorWithMessage(err, errmsg)
 	}
+	return newAnalysisReport(report)
+}
+
+// ValidateWasm runs the same checks as StoreCode without storing anything.
+// The report is returned whenever the Wasm could be analyzed, even if a check failed.
+func ValidateWasm(cache Cache, wasm []byte) (*types.AnalysisReport, error) {
+	w := makeView(wasm)
+	defer runtime.KeepAlive(wasm)
+	errmsg := uninitializedUnmanagedVector()
+	report, err := C.validate_wasm(cache.ptr, w, &errmsg)
+	res, reportErr := newAnalysisReport(report)
+	if err != nil {
+		return res, errorWithMessage(err, errmsg)
+	}
+	if reportErr != nil {
+		return nil, reportErr
+	}
+	return res, nil
+}
+
+// newAnalysisReport converts the report and destroys its unmanaged vectors.
+// Returns nil for an empty report.
+func newAnalysisReport(report C.AnalysisReport) (*types.AnalysisReport, error) {
+	empty := bool(report.entrypoints.is_none)
 	requiredCapabilities := string(copyAndDestroyUnmanagedVector(report.required_capabilities))
+	entrypoints := string(copyAndDestroyUnmanagedVector(report.entrypoints))
+	interfaceVersion := string(copyAndDestroyUnmanagedVector(report.interface_version))
+	customSectionsJSON := copyAndDestroyUnmanagedVector(report.custom_sections)
+	if empty {
+		return nil, nil
+	}
+
+	var customSections map[string][]byte
+	if err := json.Unmarshal(customSectionsJSON, &customSections); err != nil {
+		return nil, fmt.Errorf("cannot parse custom sections: %w", err)
+	}
+	var contractMigrateVersion *uint64
+	if bool(report.contract_migrate_version.is_some) {
+		version := uint64(report.contract_migrate_version.value)
+		contractMigrateVersion = &version
+	}
+
 	res := types.AnalysisReport{
-		HasIBCEntryPoints:    bool(report.has_ibc_entry_points),
-		RequiredFeatures:     requiredCapabilities,
-		RequiredCapabilities: requiredCapabilities,
+		HasIBCEntryPoints:      bool(report.has_ibc_entry_points),
+		RequiredFeatures:       requiredCapabilities,
+		RequiredCapabilities:   requiredCapabilities,
+		Entrypoints:            entrypoints,
+		InterfaceVersion:       interfaceVersion,
+		CodeSize:               uint64(report.code_size),
+		FunctionCount:          uint64(report.function_count),
+		CustomSections:         customSections,
+		ContractMigrateVersion: contractMigrateVersion,
 	}
 	return &res, nil
 }
@@ -172,7 +346,7 @@ func Instantiate(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -189,15 +363,15 @@ func Instantiate(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.instantiate(cache.ptr, cs, e, i, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.instantiate(cache.ptr, cs, e, i, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func Execute(
@@ -212,7 +386,7 @@ func Execute(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -229,15 +403,15 @@ func Execute(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.execute(cache.ptr, cs, e, i, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.execute(cache.ptr, cs, e, i, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func Migrate(
@@ -251,7 +425,7 @@ func Migrate(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -266,15 +440,15 @@ func Migrate(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.migrate(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.migrate(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func Sudo(
@@ -288,7 +462,7 @@ func Sudo(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -303,15 +477,15 @@ func Sudo(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.sudo(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.sudo(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func Reply(
@@ -325,7 +499,7 @@ func Reply(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -340,15 +514,15 @@ func Reply(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.reply(cache.ptr, cs, e, r, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.reply(cache.ptr, cs, e, r, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func Query(
@@ -362,7 +536,7 @@ func Query(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -377,15 +551,15 @@ func Query(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.query(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.query(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCChannelOpen(
@@ -399,7 +573,7 @@ func IBCChannelOpen(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -414,15 +588,15 @@ func IBCChannelOpen(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_channel_open(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_channel_open(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCChannelConnect(
@@ -436,7 +610,7 @@ func IBCChannelConnect(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -451,15 +625,15 @@ func IBCChannelConnect(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_channel_connect(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_channel_connect(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCChannelClose(
@@ -473,7 +647,7 @@ func IBCChannelClose(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -488,15 +662,15 @@ func IBCChannelClose(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_channel_close(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_channel_close(cache.ptr, cs, e, m, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCPacketReceive(
@@ -510,7 +684,7 @@ func IBCPacketReceive(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -525,15 +699,15 @@ func IBCPacketReceive(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_packet_receive(cache.ptr, cs, e, pa, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_packet_receive(cache.ptr, cs, e, pa, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCPacketAck(
@@ -547,7 +721,7 @@ func IBCPacketAck(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -562,15 +736,15 @@ func IBCPacketAck(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_packet_ack(cache.ptr, cs, e, ac, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_packet_ack(cache.ptr, cs, e, ac, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
 }
 
 func IBCPacketTimeout(
@@ -584,7 +758,7 @@ func IBCPacketTimeout(
 	querier *Querier,
 	gasLimit uint64,
 	printDebug bool,
-) ([]byte, uint64, error) {
+) ([]byte, types.CallReport, error) {
 	cs := makeView(checksum)
 	defer runtime.KeepAlive(checksum)
 	e := makeView(env)
@@ -599,15 +773,45 @@ func IBCPacketTimeout(
 	db := buildDB(&dbState, gasMeter)
 	a := buildAPI(api)
 	q := buildQuerier(querier)
-	var gasUsed cu64
+	report := newCallReport()
 	errmsg := uninitializedUnmanagedVector()
 
-	res, err := C.ibc_packet_timeout(cache.ptr, cs, e, pa, db, a, q, cu64(gasLimit), cbool(printDebug), &gasUsed, &errmsg)
+	res, err := C.ibc_packet_timeout(cache.ptr, cs, e, pa, db, a, q, cu64(gasLimit), cbool(printDebug), &report, &errmsg)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
-		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		// Depending on the nature of the error, the report will either have meaningful values, or just zeros.
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
+	}
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
+}
+
+// Replay re-executes a call from a replay file written by a cache with `record_dir` set.
+// The code is loaded from this cache. No Go state is needed since all host responses come from the recording.
+func Replay(cache Cache, recording []byte, printDebug bool) ([]byte, types.CallReport, error) {
+	r := makeView(recording)
+	defer runtime.KeepAlive(recording)
+	report := newCallReport()
+	errmsg := uninitializedUnmanagedVector()
+
+	res, err := C.replay_call(cache.ptr, r, cbool(printDebug), &report, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, toCallReport(report), errorWithMessage(err, errmsg)
+	}
+	return copyAndDestroyUnmanagedVector(res), toCallReport(report), nil
+}
+
+// newCallReport returns an empty report for a call. libwasmvm zeroes it before doing anything
+// else, so calls failing before the contract runs report no gas and no fingerprint.
+func newCallReport() C.CallReport {
+	return C.CallReport{fingerprint: C.UnmanagedVector{is_none: cbool(true)}}
+}
+
+// toCallReport copies the report of a call and destroys its fingerprint vector
+func toCallReport(report C.CallReport) types.CallReport {
+	return types.CallReport{
+		GasUsed:     uint64(report.gas_used),
+		EvmGasUsed:  uint64(report.evm_gas_used),
+		Fingerprint: copyAndDestroyUnmanagedVector(report.fingerprint),
 	}
-	return copyAndDestroyUnmanagedVector(res), uint64(gasUsed), nil
 }
 
 /**** To error module ***/
@@ -618,6 +822,14 @@ func errorWithMessage(err error, b C.UnmanagedVector) error {
 		return types.OutOfGasError{}
 	}
 	msg := copyAndDestroyUnmanagedVector(b)
+	if errno, ok := err.(syscall.Errno); ok {
+		switch int(errno) {
+		case 3:
+			return types.QueryLimitExceededError{Msg: string(msg)}
+		case 4:
+			return types.InstanceMemoryLimitExceededError{Msg: string(msg)}
+		}
+	}
 	if msg == nil {
 		return err
 	}
@@ -7,6 +7,7 @@ import (
 	"io/ioutil"
 	"os"
 	"path/filepath"
+	"strings"
 	"testing"
 	"time"
 
@@ -65,6 +66,70 @@ func TestInitCacheEmptyCapabilities(t *testing.T) {
 	ReleaseCache(cache)
 }
 
+func TestInitCacheWithConfig(t *testing.T) {
+	tmpdir, err := ioutil.TempDir("", "wasmvm-testing")
+	require.NoError(t, err)
+	defer os.RemoveAll(tmpdir)
+
+	maxLimit := uint32(64)
+	maxImports := uint64(100)
+	maxDepth := uint32(5)
+	config := types.VMConfig{
+		DataDir:                   tmpdir,
+		AvailableCapabilities:     strings.Split(TESTING_CAPABILITIES, ","),
+		MemoryCacheSizeMiB:        TESTING_CACHE_SIZE,
+		InstanceMemoryLimitMiB:    TESTING_MEMORY_LIMIT,
+		MaxInstanceMemoryLimitMiB: &maxLimit,
+		UploadLimits:              types.UploadLimits{MaxImports: &maxImports},
+		CapabilityActivations: []types.CapabilityActivation{
+			{Height: 10, Capabilities: []string{"cosmwasm_1_3"}},
+			{Height: 20, HostFeatures: []types.HostFeature{types.HostFeatureCryptoQueries}},
+		},
+		QueryLimits: types.QueryLimits{MaxDepth: &maxDepth},
+		QueryRouteUpdates: []types.QueryRoutesUpdate{
+			{Height: 20, Routes: map[string]types.QueryRoute{"bank.balance": {Handler: "snapshot", GasCost: 1000}}},
+		},
+		AddressCodec: &types.AddressCodecConfig{
+			Bech32Prefix:     "ex",
+			AddressLengths:   []uint64{20, 32},
+			CanonicalizeCost: 4,
+			HumanizeCost:     5,
+		},
+		GasConfigUpdates: []types.GasConfigUpdate{{Height: 30, GasConfig: types.DefaultGasConfig()}},
+		Fingerprints:     true,
+		LogLevel:         types.LogLevelOff,
+	}
+	cache, err := InitCacheWithConfig(config)
+	require.NoError(t, err)
+	id, err := FeatureSetID(cache, 15)
+	require.NoError(t, err)
+	require.Equal(t, uint32(1), id)
+	ReleaseCache(cache)
+
+	// libwasmvm rejects invalid options
+	config.GasConfig = &types.GasConfig{}
+	_, err = InitCacheWithConfig(config)
+	require.ErrorContains(t, err, "wasm_op_cost")
+}
+
+func TestInitCacheWithConfigInMemory(t *testing.T) {
+	cache, err := InitCacheWithConfig(types.VMConfig{
+		MemoryCacheSizeMiB:     TESTING_CACHE_SIZE,
+		InstanceMemoryLimitMiB: TESTING_MEMORY_LIMIT,
+		InMemory:               true,
+	})
+	require.NoError(t, err)
+	defer ReleaseCache(cache)
+
+	wasm, err := ioutil.ReadFile("../../testdata/hackatom.wasm")
+	require.NoError(t, err)
+	checksum, err := StoreCode(cache, wasm)
+	require.NoError(t, err)
+	code, err := GetCode(cache, checksum)
+	require.NoError(t, err)
+	require.Equal(t, wasm, code)
+}
+
 func withCache(t *testing.T) (Cache, func()) {
 	tmpdir, err := ioutil.TempDir("", "wasmvm-testing")
 	require.NoError(t, err)
@@ -357,10 +422,10 @@ func TestInstantiate(t *testing.T) {
 	info := MockInfoBin(t, "creator")
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
-	res, cost, err := Instantiate(cache, checksum, env, info, msg, &igasMeter, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err := Instantiate(cache, checksum, env, info, msg, &igasMeter, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
-	assert.Equal(t, uint64(0x13a78a36c), cost)
+	assert.Equal(t, uint64(0x13a78a36c), report.GasUsed)
 
 	var result types.ContractResult
 	err = json.Unmarshal(res, &result)
@@ -387,12 +452,12 @@ func TestExecute(t *testing.T) {
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
 
 	start := time.Now()
-	res, cost, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	diff := time.Since(start)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
-	assert.Equal(t, uint64(0x13a78a36c), cost)
-	t.Logf("Time (%d gas): %s\n", cost, diff)
+	assert.Equal(t, uint64(0x13a78a36c), report.GasUsed)
+	t.Logf("Time (%d gas): %s\n", report.GasUsed, diff)
 
 	// execute with the same store
 	gasMeter2 := NewMockGasMeter(TESTING_GAS_LIMIT)
@@ -401,11 +466,11 @@ func TestExecute(t *testing.T) {
 	env = MockEnvBin(t)
 	info = MockInfoBin(t, "fred")
 	start = time.Now()
-	res, cost, err = Execute(cache, checksum, env, info, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err = Execute(cache, checksum, env, info, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	diff = time.Since(start)
 	require.NoError(t, err)
-	assert.Equal(t, uint64(0x222892d70), cost)
-	t.Logf("Time (%d gas): %s\n", cost, diff)
+	assert.Equal(t, uint64(0x222892d70), report.GasUsed)
+	t.Logf("Time (%d gas): %s\n", report.GasUsed, diff)
 
 	// make sure it read the balance properly and we got 250 atoms
 	var result types.ContractResult
@@ -508,12 +573,12 @@ func TestExecuteCpuLoop(t *testing.T) {
 	msg := []byte(`{}`)
 
 	start := time.Now()
-	res, cost, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	diff := time.Since(start)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
-	assert.Equal(t, uint64(0xd45091d0), cost)
-	t.Logf("Time (%d gas): %s\n", cost, diff)
+	assert.Equal(t, uint64(0xd45091d0), report.GasUsed)
+	t.Logf("Time (%d gas): %s\n", report.GasUsed, diff)
 
 	// execute a cpu loop
 	maxGas := uint64(40_000_000)
@@ -522,11 +587,11 @@ func TestExecuteCpuLoop(t *testing.T) {
 	store.SetGasMeter(gasMeter2)
 	info = MockInfoBin(t, "fred")
 	start = time.Now()
-	_, cost, err = Execute(cache, checksum, env, info, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, TESTING_PRINT_DEBUG)
+	_, report, err = Execute(cache, checksum, env, info, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, TESTING_PRINT_DEBUG)
 	diff = time.Since(start)
 	require.Error(t, err)
-	assert.Equal(t, cost, maxGas)
-	t.Logf("CPULoop Time (%d gas): %s\n", cost, diff)
+	assert.Equal(t, report.GasUsed, maxGas)
+	t.Logf("CPULoop Time (%d gas): %s\n", report.GasUsed, diff)
 }
 
 func TestExecuteStorageLoop(t *testing.T) {
@@ -557,15 +622,15 @@ func TestExecuteStorageLoop(t *testing.T) {
 	store.SetGasMeter(gasMeter2)
 	info = MockInfoBin(t, "fred")
 	start := time.Now()
-	_, cost, err := Execute(cache, checksum, env, info, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, TESTING_PRINT_DEBUG)
+	_, report, err := Execute(cache, checksum, env, info, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, TESTING_PRINT_DEBUG)
 	diff := time.Since(start)
 	require.Error(t, err)
-	t.Logf("StorageLoop Time (%d gas): %s\n", cost, diff)
+	t.Logf("StorageLoop Time (%d gas): %s\n", report.GasUsed, diff)
 	t.Logf("Gas used: %d\n", gasMeter2.GasConsumed())
-	t.Logf("Wasm gas: %d\n", cost)
+	t.Logf("Wasm gas: %d\n", report.GasUsed)
 
 	// the "sdk gas" * GasMultiplier + the wasm cost should equal the maxGas (or be very close)
-	totalCost := cost + gasMeter2.GasConsumed()
+	totalCost := report.GasUsed + gasMeter2.GasConsumed()
 	require.Equal(t, int64(maxGas), int64(totalCost))
 }
 
@@ -659,11 +724,11 @@ func TestMultipleInstances(t *testing.T) {
 	env := MockEnvBin(t)
 	info := MockInfoBin(t, "regen")
 	msg := []byte(`{"verifier": "fred", "beneficiary": "bob"}`)
-	res, cost, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store1, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err := Instantiate(cache, checksum, env, info, msg, &igasMeter1, store1, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 	// we now count wasm gas charges and db writes
-	assert.Equal(t, uint64(0x138559c5c), cost)
+	assert.Equal(t, uint64(0x138559c5c), report.GasUsed)
 
 	// instance2 controlled by mary
 	gasMeter2 := NewMockGasMeter(TESTING_GAS_LIMIT)
@@ -671,10 +736,10 @@ func TestMultipleInstances(t *testing.T) {
 	store2 := NewLookup(gasMeter2)
 	info = MockInfoBin(t, "chrous")
 	msg = []byte(`{"verifier": "mary", "beneficiary": "sue"}`)
-	res, cost, err = Instantiate(cache, checksum, env, info, msg, &igasMeter2, store2, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err = Instantiate(cache, checksum, env, info, msg, &igasMeter2, store2, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
-	assert.Equal(t, uint64(0x1399177bc), cost)
+	assert.Equal(t, uint64(0x1399177bc), report.GasUsed)
 
 	// fail to execute store1 with mary
 	resp := exec(t, cache, checksum, "mary", store1, api, querier, 0x1218ff5d0)
@@ -920,9 +985,9 @@ func exec(t *testing.T, cache Cache, checksum []byte, signer types.HumanAddress,
 	igasMeter := types.GasMeter(gasMeter)
 	env := MockEnvBin(t)
 	info := MockInfoBin(t, signer)
-	res, cost, err := Execute(cache, checksum, env, info, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
+	res, report, err := Execute(cache, checksum, env, info, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, TESTING_GAS_LIMIT, TESTING_PRINT_DEBUG)
 	require.NoError(t, err)
-	assert.Equal(t, gasExpected, cost)
+	assert.Equal(t, gasExpected, report.GasUsed)
 
 	var result types.ContractResult
 	err = json.Unmarshal(res, &result)
@@ -429,6 +429,12 @@ func (q MockQuerier) GasConsumed() uint64 {
 	return q.usedGas
 }
 
+var _ types.GasConsumer = &MockQuerier{}
+
+func (q *MockQuerier) ConsumeGas(gas uint64) {
+	q.usedGas += gas
+}
+
 type BankQuerier struct {
 	Balances map[string]types.Coins
 }
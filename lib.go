@@ -36,11 +36,63 @@ func NewVM(dataDir string, supportedCapabilities string, memoryLimit uint32, pri
 	return &VM{cache: cache, printDebug: printDebug}, nil
 }
 
+// NewVMWithConfig creates a new VM from a config that covers all options of libwasmvm,
+// in contrast to NewVM which only supports the basic ones.
+//
+// `printDebug` is a flag to enable/disable printing debug logs from the contract to STDOUT. This should be false in production environments.
+func NewVMWithConfig(config types.VMConfig, printDebug bool) (*VM, error) {
+	cache, err := api.InitCacheWithConfig(config)
+	if err != nil {
+		return nil, err
+	}
+	return &VM{cache: cache, printDebug: printDebug}, nil
+}
+
 // Cleanup should be called when no longer using this to free resources on the rust-side
 func (vm *VM) Cleanup() {
 	api.ReleaseCache(vm.cache)
 }
 
+// GetCapabilities returns the comma separated list of capabilities available in this VM.
+func (vm *VM) GetCapabilities() (string, error) {
+	return api.GetCapabilities(vm.cache)
+}
+
+// SetCapabilities replaces the comma separated list of available capabilities, e.g. at an
+// upgrade height. It returns the checksums of all stored codes that require capabilities which
+// are not available anymore. Every stored code is analyzed for this.
+func (vm *VM) SetCapabilities(supportedCapabilities string) ([]Checksum, error) {
+	unusable, err := api.SetCapabilities(vm.cache, supportedCapabilities)
+	if err != nil {
+		return nil, err
+	}
+	checksums := make([]Checksum, len(unusable))
+	for i, checksum := range unusable {
+		checksums[i] = checksum
+	}
+	return checksums, nil
+}
+
+// SetQuerySnapshot replaces the responses used for query routes with the "snapshot" handler
+// (see UpdateQueryRoutes). Those requests are answered without calling the querier.
+// The snapshot only answers calls at the block height, so it must be taken at that height and
+// only contain responses that cannot change within the block.
+func (vm *VM) SetQuerySnapshot(height uint64, entries []types.QuerySnapshotEntry) error {
+	snapshot, err := json.Marshal(entries)
+	if err != nil {
+		return err
+	}
+	return api.SetQuerySnapshot(vm.cache, height, snapshot)
+}
+
+// UpdateQueryRoutes uses the query routes for calls from the block height on. Routes map
+// requests like "bank.balance" or "custom" to a query handler implemented in Rust. Like gas
+// configs, routes must be set the same on all nodes, e.g. from chain parameters. Updates are
+// not persisted, so they must be made again after a restart.
+func (vm *VM) UpdateQueryRoutes(height uint64, routes map[string]types.QueryRoute) error {
+	return api.UpdateQueryRoutes(vm.cache, height, routes)
+}
+
 // Deprecated: Renamed to StoreCode
 func (vm *VM) Create(code WasmCode) (Checksum, error) {
 	return vm.StoreCode(code)
@@ -96,6 +148,31 @@ func (vm *VM) Unpin(checksum Checksum) error {
 	return api.Unpin(vm.cache, checksum)
 }
 
+// SetInstanceMemoryLimit overrides the instance memory limit in MiB for calls of the code.
+// It must not exceed the max_instance_memory_limit_mib of the cache config. 0 resets the
+// limit to the default of the cache. The contract cannot grow its memory beyond the limit and
+// calls fail with a types.InstanceMemoryLimitExceededError if its initial memory exceeds it.
+// The code is compiled again with the limit, so this takes as long as storing it. Limits are
+// stored in the data directory and loaded again when a VM is created with it, unless the
+// cache is in memory only.
+func (vm *VM) SetInstanceMemoryLimit(checksum Checksum, limitMiB uint32) error {
+	return api.SetInstanceMemoryLimit(vm.cache, checksum, limitMiB)
+}
+
+// UpdateGasConfig uses the gas config for calls from the block height on, e.g. after a
+// governance proposal changed the gas costs. Updates are not persisted, so they must be made
+// again after a restart.
+func (vm *VM) UpdateGasConfig(height uint64, config types.GasConfig) error {
+	return api.UpdateGasConfig(vm.cache, height, config)
+}
+
+// FeatureSetID returns the id of the feature set of calls at the block height, i.e. the number
+// of capability activations at or below it. Calls with the same id have the same capabilities
+// and host features active.
+func (vm *VM) FeatureSetID(height uint64) (uint32, error) {
+	return api.FeatureSetID(vm.cache, height)
+}
+
 // Returns a report of static analysis of the wasm contract (uncompiled).
 // This contract must have been stored in the cache previously (via Create).
 // Only info currently returned is if it exposes all ibc entry points, but this may grow later
@@ -103,6 +180,13 @@ func (vm *VM) AnalyzeCode(checksum Checksum) (*types.AnalysisReport, error) {
 	return api.AnalyzeCode(vm.cache, checksum)
 }
 
+// ValidateWasm performs the same static checks and compilation as StoreCode
+// but does not store anything. The report is returned whenever the Wasm could be
+// analyzed, even if a check failed.
+func (vm *VM) ValidateWasm(code WasmCode) (*types.AnalysisReport, error) {
+	return api.ValidateWasm(vm.cache, code)
+}
+
 // GetMetrics some internal metrics for monitoring purposes.
 func (vm *VM) GetMetrics() (*types.Metrics, error) {
 	return api.GetMetrics(vm.cache)
@@ -136,7 +220,9 @@ func (vm *VM) Instantiate(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Instantiate(vm.cache, checksum, envBin, infoBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Instantiate(vm.cache, checksum, envBin, infoBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -184,7 +270,9 @@ func (vm *VM) Execute(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Execute(vm.cache, checksum, envBin, infoBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Execute(vm.cache, checksum, envBin, infoBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -224,7 +312,9 @@ func (vm *VM) Query(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Query(vm.cache, checksum, envBin, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Query(vm.cache, checksum, envBin, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -267,7 +357,9 @@ func (vm *VM) Migrate(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Migrate(vm.cache, checksum, envBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Migrate(vm.cache, checksum, envBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -310,7 +402,9 @@ func (vm *VM) Sudo(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Sudo(vm.cache, checksum, envBin, sudoMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Sudo(vm.cache, checksum, envBin, sudoMsg, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -355,7 +449,9 @@ func (vm *VM) Reply(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.Reply(vm.cache, checksum, envBin, replyBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.Reply(vm.cache, checksum, envBin, replyBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -398,7 +494,9 @@ func (vm *VM) IBCChannelOpen(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCChannelOpen(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCChannelOpen(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -441,7 +539,9 @@ func (vm *VM) IBCChannelConnect(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCChannelConnect(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCChannelConnect(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -484,7 +584,9 @@ func (vm *VM) IBCChannelClose(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCChannelClose(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCChannelClose(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -527,7 +629,9 @@ func (vm *VM) IBCPacketReceive(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCPacketReceive(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCPacketReceive(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -568,7 +672,9 @@ func (vm *VM) IBCPacketAck(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCPacketAck(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCPacketAck(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -612,7 +718,9 @@ func (vm *VM) IBCPacketTimeout(
 	if err != nil {
 		return nil, 0, err
 	}
-	data, gasUsed, err := api.IBCPacketTimeout(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	data, report, err := api.IBCPacketTimeout(vm.cache, checksum, envBin, msgBin, &gasMeter, store, &goapi, &querier, gasLimit, vm.printDebug)
+	reportCall(querier, report)
+	gasUsed := report.GasUsed
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -633,3 +741,10 @@ func (vm *VM) IBCPacketTimeout(
 	}
 	return resp.Ok, gasUsed, nil
 }
+
+// reportCall passes the report of a call to the querier if it is a types.CallReporter
+func reportCall(querier Querier, report types.CallReport) {
+	if reporter, ok := querier.(types.CallReporter); ok {
+		reporter.ReportCall(report)
+	}
+}
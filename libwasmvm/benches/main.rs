@@ -0,0 +1,347 @@
+//! Benchmarks of the FFI call overhead and the cache paths.
+//!
+//! Calls through the C exports use the Rust implementations of the Go callbacks in
+//! `wasmvm::testing`, so their numbers include the cost of the vtables but not of cgo.
+//! The `backend` variants run the same calls with cosmwasm-vm's mock backend for comparison.
+//!
+//! Run with `cargo bench --features testing`.
+
+use std::collections::HashSet;
+
+use cosmwasm_std::{coins, to_vec};
+use cosmwasm_vm::testing::{mock_backend, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_vm::{Backend, Checksum, InstanceOptions, Size};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tempfile::TempDir;
+
+use wasmvm::testing::MockHost;
+use wasmvm::{
+    cache_t, call_entry_point, execute, init_cache_with_config, instantiate, query, release_cache,
+    save_wasm, ByteSliceView, CallReport, EntryPoint, UnmanagedVector, VmCache, VmCacheOptions,
+};
+
+static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+static QUEUE: &[u8] = include_bytes!("../../testdata/queue.wasm");
+static REFLECT: &[u8] = include_bytes!("../../testdata/reflect.wasm");
+
+const GAS_LIMIT: u64 = 500_000_000_000;
+const MEMORY_LIMIT: Size = Size::mebi(32);
+const CAPABILITIES: [&str; 6] = [
+    "iterator",
+    "staking",
+    "stargate",
+    "cosmwasm_1_1",
+    "cosmwasm_1_2",
+    "cosmwasm_1_3",
+];
+
+type MockCache = VmCache<MockApi, MockStorage, MockQuerier>;
+
+/// A contract with the messages of a benchmark
+struct Contract {
+    name: &'static str,
+    wasm: &'static [u8],
+    instantiate_msg: &'static [u8],
+    execute_msg: &'static [u8],
+}
+
+const CONTRACTS: [Contract; 3] = [
+    Contract {
+        name: "hackatom",
+        wasm: HACKATOM,
+        instantiate_msg: br#"{"verifier": "creator", "beneficiary": "benefits"}"#,
+        execute_msg: br#"{"release":{}}"#,
+    },
+    Contract {
+        name: "queue",
+        wasm: QUEUE,
+        instantiate_msg: b"{}",
+        execute_msg: br#"{"enqueue":{"value":42}}"#,
+    },
+    Contract {
+        name: "reflect",
+        wasm: REFLECT,
+        instantiate_msg: b"{}",
+        execute_msg: br#"{"reflect_msg":{"msgs":[{"bank":{"send":{"to_address":"friend","amount":[{"denom":"earth","amount":"1"}]}}}]}}"#,
+    },
+];
+
+fn mock_cache(dir: &TempDir, memory_cache_size: Size) -> MockCache {
+    let options = VmCacheOptions::new(
+        dir.path().to_path_buf(),
+        CAPABILITIES
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<HashSet<_>>(),
+        memory_cache_size,
+        MEMORY_LIMIT,
+    );
+    unsafe { VmCache::new(options) }.unwrap()
+}
+
+/// A cache created via the FFI, released on drop
+struct FfiCache {
+    ptr: *mut cache_t,
+    _dir: TempDir,
+}
+
+impl FfiCache {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let config = format!(
+            r#"{{"v1":{{"data_dir":{:?},"available_capabilities":{:?},"memory_cache_size_mib":100,"instance_memory_limit_mib":32}}}}"#,
+            dir.path().to_str().unwrap(),
+            CAPABILITIES
+        );
+        let mut error_msg = UnmanagedVector::default();
+        let ptr =
+            init_cache_with_config(ByteSliceView::new(config.as_bytes()), Some(&mut error_msg));
+        assert!(error_msg.consume().is_none());
+        FfiCache { ptr, _dir: dir }
+    }
+
+    fn save(&self, wasm: &[u8]) -> Vec<u8> {
+        let mut error_msg = UnmanagedVector::default();
+        let checksum = save_wasm(
+            self.ptr,
+            ByteSliceView::new(wasm),
+            false,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.consume().is_none());
+        checksum.consume().unwrap()
+    }
+
+    fn instantiate(&self, checksum: &[u8], host: &MockHost, msg: &[u8]) {
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let mut report = CallReport::default();
+        let mut error_msg = UnmanagedVector::default();
+        let data = instantiate(
+            self.ptr,
+            ByteSliceView::new(checksum),
+            ByteSliceView::new(&env),
+            ByteSliceView::new(&info),
+            ByteSliceView::new(msg),
+            host.db(),
+            host.api(),
+            host.querier(),
+            GAS_LIMIT,
+            false,
+            Some(&mut report),
+            Some(&mut error_msg),
+        );
+        check(data, error_msg);
+    }
+
+    fn execute(&self, checksum: &[u8], host: &MockHost, env: &[u8], info: &[u8], msg: &[u8]) {
+        let mut report = CallReport::default();
+        let mut error_msg = UnmanagedVector::default();
+        let data = execute(
+            self.ptr,
+            ByteSliceView::new(checksum),
+            ByteSliceView::new(env),
+            ByteSliceView::new(info),
+            ByteSliceView::new(msg),
+            host.db(),
+            host.api(),
+            host.querier(),
+            GAS_LIMIT,
+            false,
+            Some(&mut report),
+            Some(&mut error_msg),
+        );
+        check(data, error_msg);
+    }
+
+    fn query(&self, checksum: &[u8], host: &MockHost, env: &[u8], msg: &[u8]) {
+        let mut report = CallReport::default();
+        let mut error_msg = UnmanagedVector::default();
+        let data = query(
+            self.ptr,
+            ByteSliceView::new(checksum),
+            ByteSliceView::new(env),
+            ByteSliceView::new(msg),
+            host.db(),
+            host.api(),
+            host.querier(),
+            GAS_LIMIT,
+            false,
+            Some(&mut report),
+            Some(&mut error_msg),
+        );
+        check(data, error_msg);
+    }
+}
+
+/// Panics if the call failed in the VM, discards the result otherwise
+fn check(data: UnmanagedVector, error_msg: UnmanagedVector) {
+    let _ = data.consume();
+    if let Some(msg) = error_msg.consume() {
+        panic!("{}", String::from_utf8_lossy(&msg));
+    }
+}
+
+impl Drop for FfiCache {
+    fn drop(&mut self) {
+        release_cache(self.ptr);
+    }
+}
+
+fn bench_save_wasm(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_wasm");
+    group.sample_size(10);
+    let dir = TempDir::new().unwrap();
+    let cache = mock_cache(&dir, Size::mebi(0));
+    for contract in &CONTRACTS {
+        group.bench_function(contract.name, |b| {
+            b.iter(|| cache.save_wasm(contract.wasm).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn bench_get_instance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_instance");
+    let options = InstanceOptions {
+        gas_limit: GAS_LIMIT,
+        print_debug: false,
+    };
+    let get_instance = |cache: &MockCache, checksum: &Checksum| {
+        let backend = mock_backend(&[]);
+        cache.get_instance(checksum, backend, options).unwrap()
+    };
+
+    let dir = TempDir::new().unwrap();
+    let cache = mock_cache(&dir, Size::mebi(100));
+    let checksum = cache.save_wasm(HACKATOM).unwrap();
+    cache.pin(&checksum).unwrap();
+    group.bench_function("pinned", |b| b.iter(|| get_instance(&cache, &checksum)));
+
+    // The first instance puts the module into the memory cache
+    let dir = TempDir::new().unwrap();
+    let cache = mock_cache(&dir, Size::mebi(100));
+    let checksum = cache.save_wasm(HACKATOM).unwrap();
+    get_instance(&cache, &checksum);
+    group.bench_function("memory", |b| b.iter(|| get_instance(&cache, &checksum)));
+
+    // Without a memory cache, every module is loaded from disk
+    let dir = TempDir::new().unwrap();
+    let cache = mock_cache(&dir, Size::mebi(0));
+    let checksum = cache.save_wasm(HACKATOM).unwrap();
+    group.bench_function("fs", |b| b.iter(|| get_instance(&cache, &checksum)));
+
+    group.finish();
+}
+
+fn bench_execute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+    let env = mock_env();
+    let raw_env = to_vec(&env).unwrap();
+    let raw_info = to_vec(&mock_info("creator", &[])).unwrap();
+
+    let cache = FfiCache::new();
+    for contract in &CONTRACTS {
+        let checksum = cache.save(contract.wasm);
+        let host = MockHost::new();
+        host.set_balance(env.contract.address.as_str(), coins(1_000_000, "earth"));
+        cache.instantiate(&checksum, &host, contract.instantiate_msg);
+        group.bench_function(BenchmarkId::new("ffi", contract.name), |b| {
+            b.iter(|| cache.execute(&checksum, &host, &raw_env, &raw_info, contract.execute_msg));
+        });
+    }
+
+    let dir = TempDir::new().unwrap();
+    let cache = mock_cache(&dir, Size::mebi(100));
+    for contract in &CONTRACTS {
+        let checksum = cache.save_wasm(contract.wasm).unwrap();
+        let call = |backend: Backend<_, _, _>, entry_point: EntryPoint, msg: &[u8]| {
+            let args: [&[u8]; 3] = [&raw_env, &raw_info, msg];
+            let output = call_entry_point(
+                &cache,
+                entry_point,
+                &checksum,
+                &args,
+                backend,
+                GAS_LIMIT,
+                false,
+                false,
+            )
+            .unwrap();
+            output.result.unwrap();
+            output.backend.unwrap()
+        };
+        let mut backend = Some(call(
+            mock_backend(&coins(1_000_000, "earth")),
+            EntryPoint::Instantiate,
+            contract.instantiate_msg,
+        ));
+        group.bench_function(BenchmarkId::new("backend", contract.name), |b| {
+            b.iter(|| {
+                let recycled = call(
+                    backend.take().unwrap(),
+                    EntryPoint::Execute,
+                    contract.execute_msg,
+                );
+                backend = Some(recycled);
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_iterators(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterators");
+    let raw_env = to_vec(&mock_env()).unwrap();
+    let raw_info = to_vec(&mock_info("creator", &[])).unwrap();
+    let cache = FfiCache::new();
+    let checksum = cache.save(QUEUE);
+
+    for items in [10, 100, 1000] {
+        let host = MockHost::new();
+        cache.instantiate(&checksum, &host, b"{}");
+        for value in 0..items {
+            let msg = format!(r#"{{"enqueue":{{"value":{}}}}}"#, value);
+            cache.execute(&checksum, &host, &raw_env, &raw_info, msg.as_bytes());
+        }
+
+        group.bench_with_input(BenchmarkId::new("sum", items), &items, |b, _| {
+            b.iter(|| cache.query(&checksum, &host, &raw_env, br#"{"sum":{}}"#));
+        });
+        group.bench_with_input(BenchmarkId::new("list", items), &items, |b, _| {
+            b.iter(|| cache.query(&checksum, &host, &raw_env, br#"{"list":{}}"#));
+        });
+        // One iterator per item, nested in the iterator over all items. This is quadratic and
+        // runs out of gas for larger queues.
+        if items <= 10 {
+            group.bench_with_input(BenchmarkId::new("reducer", items), &items, |b, _| {
+                b.iter(|| cache.query(&checksum, &host, &raw_env, br#"{"reducer":{}}"#));
+            });
+        }
+        // Enqueueing scans for the last item, dequeueing for the first
+        group.bench_with_input(
+            BenchmarkId::new("enqueue_dequeue", items),
+            &items,
+            |b, _| {
+                b.iter(|| {
+                    let enqueue = br#"{"enqueue":{"value":1}}"#;
+                    cache.execute(&checksum, &host, &raw_env, &raw_info, enqueue);
+                    let dequeue = br#"{"dequeue":{}}"#;
+                    cache.execute(&checksum, &host, &raw_env, &raw_info, dequeue);
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_save_wasm,
+    bench_get_instance,
+    bench_execute,
+    bench_iterators
+);
+criterion_main!(benches);
@@ -9,10 +9,19 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+#define FINGERPRINT_LENGTH 32
+
+/**
+ * The cost of every Wasm operation as charged by cosmwasm-vm
+ */
+#define DEFAULT_WASM_OP_COST 150000
+
 enum ErrnoValue {
   ErrnoValue_Success = 0,
   ErrnoValue_Other = 1,
   ErrnoValue_OutOfGas = 2,
+  ErrnoValue_QueryLimitExceeded = 3,
+  ErrnoValue_InstanceMemoryLimitExceeded = 4,
 };
 typedef int32_t ErrnoValue;
 
@@ -193,12 +202,17 @@ typedef struct UnmanagedVector {
   uintptr_t cap;
 } UnmanagedVector;
 
+typedef struct OptionalU64 {
+  bool is_some;
+  uint64_t value;
+} OptionalU64;
+
 /**
  * The result type of the FFI function analyze_code.
  *
- * Please note that the unmanaged vector in `required_capabilities`
- * has to be destroyed exactly once. When calling `analyze_code`
- * from Go this is done via `C.destroy_unmanaged_vector`.
+ * Please note that the unmanaged vectors in `required_capabilities`, `entrypoints`,
+ * `interface_version` and `custom_sections` have to be destroyed exactly once.
+ * When calling `analyze_code` from Go this is done via `C.destroy_unmanaged_vector`.
  */
 typedef struct AnalysisReport {
   bool has_ibc_entry_points;
@@ -207,6 +221,33 @@ typedef struct AnalysisReport {
    * This is never None/nil.
    */
   struct UnmanagedVector required_capabilities;
+  /**
+   * An UTF-8 encoded comma separated list of the exported entry points,
+   * e.g. "execute,instantiate,query". This is never None/nil.
+   */
+  struct UnmanagedVector entrypoints;
+  /**
+   * The UTF-8 encoded name of the interface version marker export, e.g. "interface_version_8".
+   * Empty if the contract has no marker. This is never None/nil.
+   */
+  struct UnmanagedVector interface_version;
+  /**
+   * The size of the Wasm bytecode in bytes
+   */
+  uint64_t code_size;
+  /**
+   * The number of functions defined in the contract (imports not included)
+   */
+  uint64_t function_count;
+  /**
+   * An UTF-8 encoded JSON object mapping custom section names to their base64 encoded
+   * content, e.g. `{"cw2":"Y3ctZXhhbXBsZQ=="}`. This is never None/nil.
+   */
+  struct UnmanagedVector custom_sections;
+  /**
+   * The migrate version from the `cw_migrate_version` custom section, if any
+   */
+  struct OptionalU64 contract_migrate_version;
 } AnalysisReport;
 
 typedef struct Metrics {
@@ -335,6 +376,23 @@ typedef struct Querier_vtable {
                             struct U8SliceView,
                             struct UnmanagedVector*,
                             struct UnmanagedVector*);
+  /**
+   * Answers EVM queries. Those are unsupported if unset.
+   */
+  int32_t (*query_evm)(const struct querier_t*,
+                       uint64_t,
+                       uint64_t*,
+                       struct U8SliceView,
+                       struct U8SliceView,
+                       uint64_t,
+                       uint64_t*,
+                       struct UnmanagedVector*,
+                       struct UnmanagedVector*);
+  /**
+   * Charges the gas of a query answered from memoized responses to the gas meter used by
+   * queries. Queries are not memoized if unset.
+   */
+  int32_t (*consume_gas)(const struct querier_t*, uint64_t, uint64_t*, struct UnmanagedVector*);
 } Querier_vtable;
 
 typedef struct GoQuerier {
@@ -342,17 +400,84 @@ typedef struct GoQuerier {
   struct Querier_vtable vtable;
 } GoQuerier;
 
+/**
+ * The outputs of a call next to its result, written even if the call fails. It is zeroed
+ * before the call, so a call failing before the contract runs reports no gas.
+ */
+typedef struct CallReport {
+  /**
+   * The gas used by the call
+   */
+  uint64_t gas_used;
+  /**
+   * The EVM gas used by EVM queries of the call
+   */
+  uint64_t evm_gas_used;
+  /**
+   * The fingerprint of the call if the cache is configured with `fingerprints`, see
+   * [`crate::fingerprint`]. Must be destroyed by the caller.
+   */
+  struct UnmanagedVector fingerprint;
+} CallReport;
+
 struct cache_t *init_cache(struct ByteSliceView data_dir,
                            struct ByteSliceView available_capabilities,
                            uint32_t cache_size,
                            uint32_t instance_memory_limit,
                            struct UnmanagedVector *error_msg);
 
+/**
+ * Creates a cache from a JSON encoded, versioned config (see `CacheConfig`).
+ *
+ * In contrast to `init_cache`, new options can be added to the config without
+ * changing this function's signature.
+ */
+struct cache_t *init_cache_with_config(struct ByteSliceView config,
+                                       struct UnmanagedVector *error_msg);
+
 struct UnmanagedVector save_wasm(struct cache_t *cache,
                                  struct ByteSliceView wasm,
                                  bool unchecked,
                                  struct UnmanagedVector *error_msg);
 
+struct UnmanagedVector get_capabilities(struct cache_t *cache, struct UnmanagedVector *error_msg);
+
+/**
+ * Replaces the available capabilities of the cache. The capabilities are given
+ * as an UTF-8 encoded comma separated list, like in `init_cache`.
+ *
+ * Returns an UTF-8 encoded comma separated list of the hex encoded checksums of all stored
+ * Wasm blobs that require capabilities which are not available anymore. Every stored blob is
+ * analyzed for this, so this takes longer the more codes are stored.
+ */
+struct UnmanagedVector set_capabilities(struct cache_t *cache,
+                                        struct ByteSliceView available_capabilities,
+                                        struct UnmanagedVector *error_msg);
+
+/**
+ * Replaces the query snapshot of the cache, i.e. the responses used for query routes
+ * with the `snapshot` handler. The snapshot is a JSON encoded list of
+ * `{"request":<base64>,"response":<QuerierResult>}` entries.
+ *
+ * The snapshot only answers calls at the block height, all other calls ask Go. It must only
+ * contain responses that cannot change within the block, since calls of all transactions in
+ * the block use it.
+ */
+void set_query_snapshot(struct cache_t *cache,
+                        uint64_t height,
+                        struct ByteSliceView snapshot,
+                        struct UnmanagedVector *error_msg);
+
+/**
+ * Uses the JSON encoded query routes (see `query_routes` of the cache config) for calls from
+ * the block height on. Updates are not persisted, so they have to be made again after a
+ * restart unless they are part of the cache config.
+ */
+void update_query_routes(struct cache_t *cache,
+                         uint64_t height,
+                         struct ByteSliceView routes,
+                         struct UnmanagedVector *error_msg);
+
 void remove_wasm(struct cache_t *cache,
                  struct ByteSliceView checksum,
                  struct UnmanagedVector *error_msg);
@@ -365,10 +490,51 @@ void pin(struct cache_t *cache, struct ByteSliceView checksum, struct UnmanagedV
 
 void unpin(struct cache_t *cache, struct ByteSliceView checksum, struct UnmanagedVector *error_msg);
 
+/**
+ * Sets the instance memory limit in MiB for calls of the checksum. 0 resets it to the
+ * `instance_memory_limit` of the cache. The limit must not exceed `max_instance_memory_limit_mib`.
+ * Limits are stored in the data directory and loaded again by `init_cache`, unless the cache
+ * is in memory only.
+ */
+void set_instance_memory_limit(struct cache_t *cache,
+                               struct ByteSliceView checksum,
+                               uint32_t limit_mib,
+                               struct UnmanagedVector *error_msg);
+
+/**
+ * Uses the JSON encoded gas config for calls from the block height on, e.g. after a
+ * governance proposal. Unset costs use the defaults. Updates are not persisted, so they
+ * have to be made again after a restart unless they are part of the cache config.
+ */
+void update_gas_config(struct cache_t *cache,
+                       uint64_t height,
+                       struct ByteSliceView config,
+                       struct UnmanagedVector *error_msg);
+
+/**
+ * Returns the id of the feature set of calls at the block height, i.e. the number of
+ * capability activations at or below it. Calls with the same id have the same capabilities
+ * and host features active.
+ */
+uint32_t get_feature_set_id(struct cache_t *cache,
+                            uint64_t height,
+                            struct UnmanagedVector *error_msg);
+
 struct AnalysisReport analyze_code(struct cache_t *cache,
                                    struct ByteSliceView checksum,
                                    struct UnmanagedVector *error_msg);
 
+/**
+ * Runs the same checks as `save_wasm` without storing anything.
+ *
+ * The analysis report is returned whenever the Wasm can be analyzed, even if a check fails.
+ * In that case the error is written to `error_msg` as usual. The report's unmanaged vectors
+ * have to be destroyed in any case.
+ */
+struct AnalysisReport validate_wasm(struct cache_t *cache,
+                                    struct ByteSliceView wasm,
+                                    struct UnmanagedVector *error_msg);
+
 struct Metrics get_metrics(struct cache_t *cache, struct UnmanagedVector *error_msg);
 
 /**
@@ -391,7 +557,7 @@ struct UnmanagedVector instantiate(struct cache_t *cache,
                                    struct GoQuerier querier,
                                    uint64_t gas_limit,
                                    bool print_debug,
-                                   uint64_t *gas_used,
+                                   struct CallReport *report,
                                    struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector execute(struct cache_t *cache,
@@ -404,7 +570,7 @@ struct UnmanagedVector execute(struct cache_t *cache,
                                struct GoQuerier querier,
                                uint64_t gas_limit,
                                bool print_debug,
-                               uint64_t *gas_used,
+                               struct CallReport *report,
                                struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector migrate(struct cache_t *cache,
@@ -416,7 +582,7 @@ struct UnmanagedVector migrate(struct cache_t *cache,
                                struct GoQuerier querier,
                                uint64_t gas_limit,
                                bool print_debug,
-                               uint64_t *gas_used,
+                               struct CallReport *report,
                                struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector sudo(struct cache_t *cache,
@@ -428,7 +594,7 @@ struct UnmanagedVector sudo(struct cache_t *cache,
                             struct GoQuerier querier,
                             uint64_t gas_limit,
                             bool print_debug,
-                            uint64_t *gas_used,
+                            struct CallReport *report,
                             struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector reply(struct cache_t *cache,
@@ -440,7 +606,7 @@ struct UnmanagedVector reply(struct cache_t *cache,
                              struct GoQuerier querier,
                              uint64_t gas_limit,
                              bool print_debug,
-                             uint64_t *gas_used,
+                             struct CallReport *report,
                              struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector query(struct cache_t *cache,
@@ -452,7 +618,7 @@ struct UnmanagedVector query(struct cache_t *cache,
                              struct GoQuerier querier,
                              uint64_t gas_limit,
                              bool print_debug,
-                             uint64_t *gas_used,
+                             struct CallReport *report,
                              struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_channel_open(struct cache_t *cache,
@@ -464,7 +630,7 @@ struct UnmanagedVector ibc_channel_open(struct cache_t *cache,
                                         struct GoQuerier querier,
                                         uint64_t gas_limit,
                                         bool print_debug,
-                                        uint64_t *gas_used,
+                                        struct CallReport *report,
                                         struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_channel_connect(struct cache_t *cache,
@@ -476,7 +642,7 @@ struct UnmanagedVector ibc_channel_connect(struct cache_t *cache,
                                            struct GoQuerier querier,
                                            uint64_t gas_limit,
                                            bool print_debug,
-                                           uint64_t *gas_used,
+                                           struct CallReport *report,
                                            struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_channel_close(struct cache_t *cache,
@@ -488,7 +654,7 @@ struct UnmanagedVector ibc_channel_close(struct cache_t *cache,
                                          struct GoQuerier querier,
                                          uint64_t gas_limit,
                                          bool print_debug,
-                                         uint64_t *gas_used,
+                                         struct CallReport *report,
                                          struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_packet_receive(struct cache_t *cache,
@@ -500,7 +666,7 @@ struct UnmanagedVector ibc_packet_receive(struct cache_t *cache,
                                           struct GoQuerier querier,
                                           uint64_t gas_limit,
                                           bool print_debug,
-                                          uint64_t *gas_used,
+                                          struct CallReport *report,
                                           struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_packet_ack(struct cache_t *cache,
@@ -512,7 +678,7 @@ struct UnmanagedVector ibc_packet_ack(struct cache_t *cache,
                                       struct GoQuerier querier,
                                       uint64_t gas_limit,
                                       bool print_debug,
-                                      uint64_t *gas_used,
+                                      struct CallReport *report,
                                       struct UnmanagedVector *error_msg);
 
 struct UnmanagedVector ibc_packet_timeout(struct cache_t *cache,
@@ -524,9 +690,21 @@ struct UnmanagedVector ibc_packet_timeout(struct cache_t *cache,
                                           struct GoQuerier querier,
                                           uint64_t gas_limit,
                                           bool print_debug,
-                                          uint64_t *gas_used,
+                                          struct CallReport *report,
                                           struct UnmanagedVector *error_msg);
 
+/**
+ * Re-executes a call from a replay file written by a cache with `record_dir` set. The code
+ * is taken from this cache. Returns the result of the call and fails if the replay diverged
+ * from the recording. The report contains the fingerprint of the recorded call. EVM gas is not
+ * part of recordings, so it is reported as 0.
+ */
+struct UnmanagedVector replay_call(struct cache_t *cache,
+                                   struct ByteSliceView recording,
+                                   bool print_debug,
+                                   struct CallReport *report,
+                                   struct UnmanagedVector *error_msg);
+
 struct UnmanagedVector new_unmanaged_vector(bool nil, const uint8_t *ptr, uintptr_t length);
 
 void destroy_unmanaged_vector(struct UnmanagedVector v);
@@ -0,0 +1,168 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use arbitrary::Arbitrary;
+use cosmwasm_std::to_vec;
+use cosmwasm_vm::testing::{mock_env, mock_info};
+use libfuzzer_sys::fuzz_target;
+use tempfile::TempDir;
+use wasmvm::testing::MockHost;
+use wasmvm::{
+    cache_t, execute, init_cache_with_config, instantiate, query, save_wasm, ByteSliceView,
+    UnmanagedVector,
+};
+use wasmvm_fuzz::CallbackResponse;
+
+static QUEUE: &[u8] = include_bytes!("../../../testdata/queue.wasm");
+
+const GAS_LIMIT: u64 = 50_000_000_000;
+
+/// The cache shared by all runs, with queue.wasm stored in it
+struct Cache {
+    ptr: *mut cache_t,
+    checksum: Vec<u8>,
+    _dir: TempDir,
+}
+
+// The cache is only used by the fuzzing thread
+unsafe impl Send for Cache {}
+unsafe impl Sync for Cache {}
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+
+fn cache() -> &'static Cache {
+    CACHE.get_or_init(|| {
+        let dir = TempDir::new().unwrap();
+        let config = format!(
+            r#"{{"v1":{{"data_dir":{:?},"available_capabilities":["iterator"],"memory_cache_size_mib":100,"instance_memory_limit_mib":32}}}}"#,
+            dir.path().to_str().unwrap()
+        );
+        let mut error_msg = UnmanagedVector::default();
+        let ptr =
+            init_cache_with_config(ByteSliceView::new(config.as_bytes()), Some(&mut error_msg));
+        assert!(error_msg.consume().is_none());
+        let mut error_msg = UnmanagedVector::default();
+        let checksum = save_wasm(ptr, ByteSliceView::new(QUEUE), false, Some(&mut error_msg));
+        assert!(error_msg.consume().is_none());
+        Cache {
+            ptr,
+            checksum: checksum.consume().unwrap(),
+            _dir: dir,
+        }
+    })
+}
+
+#[derive(Arbitrary, Debug)]
+enum Call {
+    Enqueue(i32),
+    Dequeue,
+    Count,
+    Sum,
+    Reducer,
+    List,
+    OpenIterators(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    calls: Vec<Call>,
+    /// Scripted before the calls, so they hit whichever callback comes first
+    responses: Vec<CallbackResponse>,
+    gas_per_callback: u32,
+}
+
+fuzz_target!(|input: Input| {
+    let cache = cache();
+    let host = MockHost::new();
+    let env = to_vec(&mock_env()).unwrap();
+    let info = to_vec(&mock_info("creator", &[])).unwrap();
+
+    let mut gas_used = 0;
+    let mut error_msg = UnmanagedVector::default();
+    let data = instantiate(
+        cache.ptr,
+        ByteSliceView::new(&cache.checksum),
+        ByteSliceView::new(&env),
+        ByteSliceView::new(&info),
+        ByteSliceView::new(b"{}"),
+        host.db(),
+        host.api(),
+        host.querier(),
+        GAS_LIMIT,
+        false,
+        Some(&mut gas_used),
+        None,
+        None,
+        Some(&mut error_msg),
+    );
+    assert!(error_msg.consume().is_none());
+    let _ = data.consume();
+
+    host.set_gas_per_callback(input.gas_per_callback.into());
+    for response in input.responses {
+        response.script(&host);
+    }
+
+    for call in input.calls {
+        let mut gas_used = 0;
+        let mut error_msg = UnmanagedVector::default();
+        let data = match call {
+            Call::Enqueue(_) | Call::Dequeue => {
+                let msg = match call {
+                    Call::Enqueue(value) => format!(r#"{{"enqueue":{{"value":{}}}}}"#, value),
+                    _ => r#"{"dequeue":{}}"#.to_string(),
+                };
+                execute(
+                    cache.ptr,
+                    ByteSliceView::new(&cache.checksum),
+                    ByteSliceView::new(&env),
+                    ByteSliceView::new(&info),
+                    ByteSliceView::new(msg.as_bytes()),
+                    host.db(),
+                    host.api(),
+                    host.querier(),
+                    GAS_LIMIT,
+                    false,
+                    Some(&mut gas_used),
+                    None,
+                    None,
+                    Some(&mut error_msg),
+                )
+            }
+            _ => {
+                let msg = match call {
+                    Call::Count => r#"{"count":{}}"#.to_string(),
+                    Call::Sum => r#"{"sum":{}}"#.to_string(),
+                    Call::Reducer => r#"{"reducer":{}}"#.to_string(),
+                    Call::List => r#"{"list":{}}"#.to_string(),
+                    Call::OpenIterators(count) => {
+                        format!(r#"{{"open_iterators":{{"count":{}}}}}"#, count)
+                    }
+                    _ => unreachable!(),
+                };
+                query(
+                    cache.ptr,
+                    ByteSliceView::new(&cache.checksum),
+                    ByteSliceView::new(&env),
+                    ByteSliceView::new(msg.as_bytes()),
+                    host.db(),
+                    host.api(),
+                    host.querier(),
+                    GAS_LIMIT,
+                    false,
+                    Some(&mut gas_used),
+                    None,
+                    None,
+                    Some(&mut error_msg),
+                )
+            }
+        };
+        let data = data.consume().unwrap();
+        // A call either fails with a message or returns data
+        if error_msg.consume().is_some() {
+            assert!(data.is_empty());
+        }
+        assert!(gas_used <= GAS_LIMIT);
+    }
+});
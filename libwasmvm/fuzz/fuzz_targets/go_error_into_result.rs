@@ -0,0 +1,23 @@
+#![no_main]
+
+use cosmwasm_vm::BackendError;
+use libfuzzer_sys::fuzz_target;
+use wasmvm::{GoError, UnmanagedVector};
+use wasmvm_fuzz::ErrorMessage;
+
+fuzz_target!(|input: (i32, Option<ErrorMessage>)| {
+    let (code, error_msg) = input;
+    let error_msg = UnmanagedVector::new(error_msg.map(ErrorMessage::into_bytes));
+    let result = unsafe { GoError::from(code).into_result(error_msg, || "default".to_string()) };
+
+    match (GoError::from(code), result) {
+        (GoError::None, result) => assert!(result.is_ok()),
+        (_, Ok(())) => panic!("error code {} converted to success", code),
+        // Every truncated byte becomes at most one replacement character
+        (GoError::User, Err(BackendError::UserErr { msg, .. })) => {
+            assert!(msg.chars().count() <= 8 * 1024)
+        }
+        (GoError::User, Err(err)) => panic!("user error converted to {:?}", err),
+        (_, Err(_)) => {}
+    }
+});
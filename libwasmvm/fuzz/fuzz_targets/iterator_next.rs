@@ -0,0 +1,62 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use cosmwasm_std::{Order, Record};
+use libfuzzer_sys::fuzz_target;
+use wasmvm::testing::{Callback, MockHost};
+use wasmvm::{VmDb, VmIterator};
+use wasmvm_fuzz::Response;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    records: Vec<(Vec<u8>, Vec<u8>)>,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    descending: bool,
+    /// Responses of `next_db` before the iterator of the host takes over
+    responses: Vec<Response>,
+}
+
+fuzz_target!(|input: Input| {
+    let host = MockHost::new();
+    for (key, value) in &input.records {
+        host.set(key, value);
+    }
+    let order = if input.descending {
+        Order::Descending
+    } else {
+        Order::Ascending
+    };
+    let mut db = host.db();
+    let (iterator, _) = db.scan(input.start.as_deref(), input.end.as_deref(), order);
+    let mut iterator = iterator.unwrap();
+
+    for response in input.responses.iter().cloned() {
+        host.script_next(Callback::NextDb, response.into());
+    }
+    for response in input.responses {
+        let (result, _) = iterator.next();
+        let missing_value = matches!(response.outputs.get(1), None | Some(None));
+        match response.outputs.first() {
+            Some(Some(_)) if response.code == 0 && missing_value => assert!(result.is_err()),
+            _ if response.code != 0 => assert!(result.is_err()),
+            _ => {}
+        }
+    }
+
+    // Scripted responses do not advance the iterator of the host
+    let expected: Vec<Record> = host
+        .records()
+        .into_iter()
+        .filter(|(key, _)| !matches!(&input.start, Some(start) if key < start))
+        .filter(|(key, _)| !matches!(&input.end, Some(end) if key >= end))
+        .collect();
+    let mut actual = Vec::new();
+    while let (Ok(Some(record)), _) = iterator.next() {
+        actual.push(record);
+    }
+    if input.descending {
+        actual.reverse();
+    }
+    assert_eq!(actual, expected);
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasmvm::{destroy_unmanaged_vector, new_unmanaged_vector, UnmanagedVector};
+
+fuzz_target!(|data: Option<Vec<u8>>| {
+    let vector = UnmanagedVector::new(data.clone());
+    assert_eq!(vector.is_none(), data.is_none());
+    assert_eq!(vector.consume(), data);
+
+    // Created like Go does, from memory owned by the caller
+    let (nil, ptr, length) = match &data {
+        Some(data) => (false, data.as_ptr(), data.len()),
+        None => (true, std::ptr::null(), 0),
+    };
+    assert_eq!(new_unmanaged_vector(nil, ptr, length).consume(), data);
+    destroy_unmanaged_vector(new_unmanaged_vector(nil, ptr, length));
+});
@@ -0,0 +1,90 @@
+//! Inputs shared by the fuzz targets: what Go could send back over the FFI boundary.
+
+use arbitrary::Arbitrary;
+use wasmvm::testing::{Callback, MockHost, ScriptedResponse};
+
+/// Longer than the 8 KB of an error message `GoError::into_result` keeps
+const OVERSIZED_LENGTH: usize = 9 * 1024;
+
+/// An error message from Go. This is not necessarily valid UTF-8.
+#[derive(Arbitrary, Debug, Clone)]
+pub struct ErrorMessage {
+    data: Vec<u8>,
+    /// Repeats the data beyond the length limit of error messages
+    oversized: bool,
+}
+
+impl ErrorMessage {
+    pub fn into_bytes(self) -> Vec<u8> {
+        if self.oversized && !self.data.is_empty() {
+            self.data
+                .iter()
+                .copied()
+                .cycle()
+                .take(OVERSIZED_LENGTH)
+                .collect()
+        } else {
+            self.data
+        }
+    }
+}
+
+/// What a callback returns instead of doing its work
+#[derive(Arbitrary, Debug, Clone)]
+pub struct Response {
+    /// Any return code, not only those of `GoError`
+    pub code: i32,
+    /// The outputs of the callback. `None` is a missing output, e.g. the value of a key.
+    pub outputs: Vec<Option<Vec<u8>>>,
+    pub error_msg: Option<ErrorMessage>,
+}
+
+impl From<Response> for ScriptedResponse {
+    fn from(response: Response) -> Self {
+        ScriptedResponse {
+            code: response.code,
+            outputs: response.outputs,
+            error_msg: response.error_msg.map(ErrorMessage::into_bytes),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum FuzzCallback {
+    ReadDb,
+    WriteDb,
+    RemoveDb,
+    ScanDb,
+    NextDb,
+    HumanizeAddress,
+    CanonicalizeAddress,
+    QueryExternal,
+}
+
+impl From<FuzzCallback> for Callback {
+    fn from(callback: FuzzCallback) -> Self {
+        match callback {
+            FuzzCallback::ReadDb => Callback::ReadDb,
+            FuzzCallback::WriteDb => Callback::WriteDb,
+            FuzzCallback::RemoveDb => Callback::RemoveDb,
+            FuzzCallback::ScanDb => Callback::ScanDb,
+            FuzzCallback::NextDb => Callback::NextDb,
+            FuzzCallback::HumanizeAddress => Callback::HumanizeAddress,
+            FuzzCallback::CanonicalizeAddress => Callback::CanonicalizeAddress,
+            FuzzCallback::QueryExternal => Callback::QueryExternal,
+        }
+    }
+}
+
+/// A response scripted for one of the callbacks
+#[derive(Arbitrary, Debug, Clone)]
+pub struct CallbackResponse {
+    pub callback: FuzzCallback,
+    pub response: Response,
+}
+
+impl CallbackResponse {
+    pub fn script(self, host: &MockHost) {
+        host.script_next(self.callback.into(), self.response.into());
+    }
+}
@@ -0,0 +1,278 @@
+//! Height-gated activation of capabilities.
+//!
+//! A chain upgrade can make capabilities available from a certain block height on. Which
+//! capabilities are active is decided per call based on the block height in the call's env,
+//! such that a running node can cross an upgrade height without restarting.
+//!
+//! Activations can also gate host features, i.e. the functionality libwasmvm offers contracts
+//! on top of cosmwasm-vm's imports, like EVM and crypto queries. A gated feature is unsupported
+//! for calls below its activation height.
+//!
+//! The activations at or below the height of a call form its feature set, which is identified
+//! by the number of those activations. Calls with the same feature-set id have the same
+//! capabilities and host features active.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// A host feature that can be activated at a height. Features that are not scheduled are
+/// active at all heights.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HostFeature {
+    /// `{"evm":{...}}` queries, see [`crate::evm`]
+    EvmQueries,
+    /// `{"crypto":{...}}` queries, see [`crate::crypto`]
+    CryptoQueries,
+}
+
+/// Capabilities and host features that become active at a block height
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct CapabilityActivation {
+    pub height: u64,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub host_features: Vec<HostFeature>,
+}
+
+/// The context of a single call that determines which capabilities are active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivationContext {
+    pub height: u64,
+}
+
+#[derive(Deserialize)]
+struct EnvBlock {
+    block: BlockHeight,
+}
+
+#[derive(Deserialize)]
+struct BlockHeight {
+    height: u64,
+}
+
+impl ActivationContext {
+    /// Reads the block height from the JSON encoded `Env` passed to every call
+    pub fn from_env(env: &[u8]) -> Result<Self, Error> {
+        let env: EnvBlock = serde_json::from_slice(env)
+            .map_err(|e| Error::vm_err(format!("Error reading block height from env: {}", e)))?;
+        Ok(ActivationContext {
+            height: env.block.height,
+        })
+    }
+}
+
+/// The host features of a call
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSet {
+    /// The number of activations at or below the height of the call
+    pub id: u32,
+    /// The scheduled host features that are not active yet
+    inactive: Vec<HostFeature>,
+}
+
+impl FeatureSet {
+    pub fn is_active(&self, feature: HostFeature) -> bool {
+        !self.inactive.contains(&feature)
+    }
+}
+
+/// The heights at which capabilities and host features become active. Those not in the
+/// schedule are active at all heights.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActivationSchedule {
+    /// The activation height of each scheduled capability
+    heights: BTreeMap<String, u64>,
+    /// The activation height of each scheduled host feature
+    feature_heights: BTreeMap<HostFeature, u64>,
+    /// The heights of all activations in ascending order
+    activation_heights: Vec<u64>,
+}
+
+impl ActivationSchedule {
+    /// Creates the schedule. Every activation must have its own height.
+    pub fn new(activations: Vec<CapabilityActivation>) -> Result<Self, Error> {
+        let mut schedule = ActivationSchedule::default();
+        for activation in activations {
+            if schedule.activation_heights.contains(&activation.height) {
+                return Err(Error::invalid_config(format!(
+                    "Multiple capability activations at height {}",
+                    activation.height
+                )));
+            }
+            schedule.activation_heights.push(activation.height);
+            for capability in activation.capabilities {
+                let capability = capability.trim().to_string();
+                if capability.is_empty() {
+                    continue;
+                }
+                // If a capability is scheduled multiple times, the earliest height wins
+                let height = schedule
+                    .heights
+                    .entry(capability)
+                    .or_insert(activation.height);
+                *height = (*height).min(activation.height);
+            }
+            for feature in activation.host_features {
+                let height = schedule
+                    .feature_heights
+                    .entry(feature)
+                    .or_insert(activation.height);
+                *height = (*height).min(activation.height);
+            }
+        }
+        schedule.activation_heights.sort_unstable();
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.activation_heights.is_empty()
+    }
+
+    /// The scheduled capabilities, sorted by name
+    pub fn capabilities(&self) -> impl Iterator<Item = &str> {
+        self.heights.keys().map(String::as_str)
+    }
+
+    /// The host features of calls in the given context
+    pub fn feature_set(&self, context: ActivationContext) -> FeatureSet {
+        let id = self
+            .activation_heights
+            .partition_point(|height| *height <= context.height);
+        FeatureSet {
+            id: id as u32,
+            inactive: self
+                .feature_heights
+                .iter()
+                .filter(|(_, height)| context.height < **height)
+                .map(|(feature, _)| *feature)
+                .collect(),
+        }
+    }
+
+    /// Returns the capabilities out of `required` that are not active yet, sorted by name
+    pub fn inactive<'a>(
+        &self,
+        required: &'a HashSet<String>,
+        context: ActivationContext,
+    ) -> Vec<&'a str> {
+        let mut inactive: Vec<&str> = required
+            .iter()
+            .filter(|capability| {
+                matches!(self.heights.get(*capability), Some(height) if context.height < *height)
+            })
+            .map(|capability| capability.as_str())
+            .collect();
+        inactive.sort_unstable();
+        inactive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+
+    #[test]
+    fn from_env_works() {
+        let mut env = mock_env();
+        env.block.height = 4242;
+        let context = ActivationContext::from_env(&serde_json::to_vec(&env).unwrap()).unwrap();
+        assert_eq!(context, ActivationContext { height: 4242 });
+
+        let err = ActivationContext::from_env(br#"{"block":{}}"#).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Error reading block height from env"));
+    }
+
+    #[test]
+    fn inactive_works() {
+        let schedule = ActivationSchedule::new(vec![
+            CapabilityActivation {
+                height: 100,
+                capabilities: vec!["cosmwasm_1_2".to_string(), " ".to_string()],
+                host_features: vec![],
+            },
+            CapabilityActivation {
+                height: 200,
+                capabilities: vec!["cosmwasm_1_3".to_string(), "cosmwasm_1_2".to_string()],
+                host_features: vec![],
+            },
+        ])
+        .unwrap();
+        let required = HashSet::from(["staking", "cosmwasm_1_2", "cosmwasm_1_3"].map(String::from));
+
+        let at = |height| ActivationContext { height };
+        assert_eq!(
+            schedule.inactive(&required, at(99)),
+            vec!["cosmwasm_1_2", "cosmwasm_1_3"]
+        );
+        assert_eq!(schedule.inactive(&required, at(100)), vec!["cosmwasm_1_3"]);
+        assert_eq!(schedule.inactive(&required, at(200)), Vec::<&str>::new());
+
+        assert!(ActivationSchedule::default().is_empty());
+        assert!(ActivationSchedule::default()
+            .inactive(&required, at(0))
+            .is_empty());
+    }
+
+    #[test]
+    fn feature_set_works() {
+        let schedule = ActivationSchedule::new(vec![
+            CapabilityActivation {
+                height: 200,
+                capabilities: vec![],
+                host_features: vec![HostFeature::CryptoQueries],
+            },
+            CapabilityActivation {
+                height: 100,
+                capabilities: vec!["cosmwasm_1_3".to_string()],
+                host_features: vec![HostFeature::EvmQueries],
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            schedule.capabilities().collect::<Vec<_>>(),
+            vec!["cosmwasm_1_3"]
+        );
+
+        let at = |height| schedule.feature_set(ActivationContext { height });
+        assert_eq!(at(99).id, 0);
+        assert!(!at(99).is_active(HostFeature::EvmQueries));
+        assert!(!at(99).is_active(HostFeature::CryptoQueries));
+        assert_eq!(at(100).id, 1);
+        assert!(at(100).is_active(HostFeature::EvmQueries));
+        assert!(!at(199).is_active(HostFeature::CryptoQueries));
+        assert_eq!(at(199), at(100));
+        assert_eq!(at(u64::MAX).id, 2);
+        assert!(at(u64::MAX).is_active(HostFeature::CryptoQueries));
+
+        // unscheduled features are always active
+        let feature_set =
+            ActivationSchedule::default().feature_set(ActivationContext { height: 0 });
+        assert_eq!(feature_set, FeatureSet::default());
+        assert!(feature_set.is_active(HostFeature::EvmQueries));
+    }
+
+    #[test]
+    fn new_rejects_duplicate_heights() {
+        let activation = |capability: &str| CapabilityActivation {
+            height: 100,
+            capabilities: vec![capability.to_string()],
+            host_features: vec![],
+        };
+        let err =
+            ActivationSchedule::new(vec![activation("cosmwasm_1_2"), activation("cosmwasm_1_3")])
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: Multiple capability activations at height 100"
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! A native address codec, such that address conversions do not need to call into Go.
+//!
+//! Addresses are bech32 encoded with the chain's prefix. Optionally, 20 byte addresses can also be
+//! given in the `0x` prefixed hex form used by the EVM. Both forms have the same canonical
+//! representation, and the human readable form of a canonical address is always bech32.
+//!
+//! Contracts can see conversion errors, so the codec behaves like the Go implementation of
+//! Cosmos SDK based chains: bech32 addresses are limited to 90 characters and errors have the
+//! messages of `sdk.AccAddressFromBech32` and `sdk.VerifyAddressFormat`. Lengths rejected only
+//! because of `address_lengths` get the message of an address verifier checking them.
+//! Errors of hex addresses are specific to this codec.
+
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+
+use crate::error::Error;
+
+/// The maximum length of a bech32 human readable part
+const MAX_PREFIX_LENGTH: usize = 83;
+/// The maximum length of a canonical address, as in Cosmos SDK
+const MAX_ADDRESS_LENGTH: usize = 255;
+/// The minimum and maximum length of a bech32 address, as in Go's bech32 decoder
+const MIN_BECH32_LENGTH: usize = 8;
+const MAX_BECH32_LENGTH: usize = 90;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+const CHECKSUM_LENGTH: usize = 6;
+
+/// The length of an EVM address in bytes
+const HEX_ADDRESS_LENGTH: usize = 20;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct AddressCodecConfig {
+    /// The human readable part of the chain's addresses, e.g. `ex`
+    pub bech32_prefix: String,
+    /// The allowed lengths of canonical addresses in bytes, e.g. 20 for accounts
+    /// and 32 for contracts
+    pub address_lengths: Vec<u64>,
+    /// The gas charged for a successful or failed conversion from human to canonical
+    pub canonicalize_cost: u64,
+    /// The gas charged for a successful or failed conversion from canonical to human
+    pub humanize_cost: u64,
+    /// If true, 20 byte addresses are accepted in the `0x` prefixed hex form as well.
+    /// Mixed case hex addresses must have a valid EIP-55 checksum.
+    #[serde(default)]
+    pub hex_addresses: bool,
+}
+
+/// An address codec for a single bech32 prefix. It is `Copy` such that it can live inside a `BackendApi`.
+#[derive(Debug, Clone, Copy)]
+pub struct AddressCodec {
+    prefix: [u8; MAX_PREFIX_LENGTH],
+    prefix_length: usize,
+    /// Bit `n` is set if canonical addresses of length `n` are allowed
+    address_lengths: [u64; 4],
+    pub canonicalize_cost: u64,
+    pub humanize_cost: u64,
+    hex_addresses: bool,
+}
+
+impl AddressCodec {
+    pub fn new(config: &AddressCodecConfig) -> Result<Self, Error> {
+        let prefix_bytes = config.bech32_prefix.as_bytes();
+        if prefix_bytes.is_empty() || prefix_bytes.len() > MAX_PREFIX_LENGTH {
+            return Err(Error::invalid_config(format!(
+                "bech32_prefix must have 1 to {} characters",
+                MAX_PREFIX_LENGTH
+            )));
+        }
+        if !prefix_bytes
+            .iter()
+            .all(|c| (33..=126).contains(c) && !c.is_ascii_uppercase())
+        {
+            return Err(Error::invalid_config(
+                "bech32_prefix must consist of lowercase ASCII characters",
+            ));
+        }
+        if config.address_lengths.is_empty() {
+            return Err(Error::invalid_config("address_lengths must not be empty"));
+        }
+        let mut address_lengths = [0_u64; 4];
+        for length in config.address_lengths.iter().copied() {
+            if length == 0 || length > MAX_ADDRESS_LENGTH as u64 {
+                return Err(Error::invalid_config(format!(
+                    "address_lengths must be between 1 and {}",
+                    MAX_ADDRESS_LENGTH
+                )));
+            }
+            address_lengths[(length / 64) as usize] |= 1 << (length % 64);
+        }
+
+        let mut prefix = [0_u8; MAX_PREFIX_LENGTH];
+        prefix[..prefix_bytes.len()].copy_from_slice(prefix_bytes);
+        Ok(AddressCodec {
+            prefix,
+            prefix_length: prefix_bytes.len(),
+            address_lengths,
+            canonicalize_cost: config.canonicalize_cost,
+            humanize_cost: config.humanize_cost,
+            hex_addresses: config.hex_addresses,
+        })
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.prefix[..self.prefix_length]
+    }
+
+    /// Checks the length of a canonical address like `sdk.VerifyAddressFormat`
+    fn check_length(&self, length: usize) -> Result<(), String> {
+        if length == 0 {
+            return Err("addresses cannot be empty: unknown address".to_string());
+        }
+        if length > MAX_ADDRESS_LENGTH {
+            return Err(format!(
+                "address max length is {}, got {}: unknown address",
+                MAX_ADDRESS_LENGTH, length
+            ));
+        }
+        if self.address_lengths[length / 64] & (1 << (length % 64)) == 0 {
+            return Err(format!(
+                "invalid address length {}: unknown address",
+                length
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decodes and validates a bech32 address with the configured prefix or,
+    /// if enabled, a hex address
+    pub fn canonicalize(&self, human: &str) -> Result<Vec<u8>, String> {
+        if human.trim().is_empty() {
+            return Err("empty address string is not allowed".to_string());
+        }
+        if self.hex_addresses && human.starts_with("0x") {
+            let canonical = decode_hex_address(human)?;
+            self.check_length(canonical.len())?;
+            return Ok(canonical);
+        }
+        let (prefix, data) = decode(human).map_err(|e| format!("decoding bech32 failed: {}", e))?;
+        let canonical = convert_bits(&data, 5, 8, false)
+            .map_err(|e| format!("decoding bech32 failed: {}", e))?;
+        if prefix != self.prefix() {
+            return Err(format!(
+                "invalid Bech32 prefix; expected {}, got {}",
+                String::from_utf8_lossy(self.prefix()),
+                String::from_utf8_lossy(&prefix)
+            ));
+        }
+        self.check_length(canonical.len())?;
+        Ok(canonical)
+    }
+
+    /// Encodes a canonical address as bech32 with the configured prefix
+    pub fn humanize(&self, canonical: &[u8]) -> Result<String, String> {
+        self.check_length(canonical.len())?;
+        let data = convert_bits(canonical, 8, 5, true)?;
+        Ok(encode(self.prefix(), &data))
+    }
+}
+
+/// Returns the EIP-55 mixed case checksum encoding of an EVM address
+pub fn to_checksum_hex_address(canonical: &[u8; HEX_ADDRESS_LENGTH]) -> String {
+    let lower = hex::encode(canonical);
+    let hash = Keccak256::digest(lower.as_bytes());
+    let mut human = String::with_capacity(2 + lower.len());
+    human.push_str("0x");
+    human.extend(lower.chars().enumerate().map(|(i, c)| {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf;
+        if nibble >= 8 {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    }));
+    human
+}
+
+/// Decodes a `0x` prefixed hex address. Addresses in mixed case must have a valid
+/// EIP-55 checksum.
+pub(crate) fn decode_hex_address(human: &str) -> Result<Vec<u8>, String> {
+    let digits = &human[2..];
+    if digits.len() != 2 * HEX_ADDRESS_LENGTH {
+        return Err(format!(
+            "Invalid hex address length: expected {} digits, got {}",
+            2 * HEX_ADDRESS_LENGTH,
+            digits.len()
+        ));
+    }
+    let mut canonical = [0_u8; HEX_ADDRESS_LENGTH];
+    hex::decode_to_slice(digits, &mut canonical)
+        .map_err(|_| "Invalid character in hex address".to_string())?;
+    let has_lower = digits.bytes().any(|c| c.is_ascii_lowercase());
+    let has_upper = digits.bytes().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper && to_checksum_hex_address(&canonical) != human {
+        return Err("Invalid hex address checksum".to_string());
+    }
+    Ok(canonical.to_vec())
+}
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    let mut checksum = 1_u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ff_ffff) << 5) ^ u32::from(value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn expand_prefix(prefix: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    prefix
+        .iter()
+        .map(|c| c >> 5)
+        .chain(std::iter::once(0))
+        .chain(prefix.iter().map(|c| c & 31))
+}
+
+/// Returns the characters of the checksum of the 5 bit data
+fn checksum(prefix: &[u8], data: &[u8]) -> impl Iterator<Item = char> {
+    let checksum = polymod(
+        expand_prefix(prefix)
+            .chain(data.iter().copied())
+            .chain([0; CHECKSUM_LENGTH]),
+    ) ^ 1;
+    (0..CHECKSUM_LENGTH).map(move |i| {
+        let d = (checksum >> (5 * (CHECKSUM_LENGTH - 1 - i))) & 31;
+        CHARSET[d as usize] as char
+    })
+}
+
+fn encode(prefix: &[u8], data: &[u8]) -> String {
+    let mut human = String::with_capacity(prefix.len() + 1 + data.len() + CHECKSUM_LENGTH);
+    human.extend(prefix.iter().map(|c| *c as char));
+    human.push('1');
+    human.extend(data.iter().map(|d| CHARSET[*d as usize] as char));
+    human.extend(checksum(prefix, data));
+    human
+}
+
+/// Returns the lowercase prefix and the 5 bit data without checksum. The checks and
+/// error messages are the ones of Go's bech32 decoder (`github.com/btcsuite/btcutil/bech32`).
+fn decode(human: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let bytes = human.as_bytes();
+    if bytes.len() < MIN_BECH32_LENGTH || bytes.len() > MAX_BECH32_LENGTH {
+        return Err(format!("invalid bech32 string length {}", bytes.len()));
+    }
+    if let Some(c) = human.chars().find(|c| !(33..=126).contains(&(*c as u32))) {
+        return Err(format!("invalid character in string: '{}'", c));
+    }
+    let has_lower = bytes.iter().any(u8::is_ascii_lowercase);
+    let has_upper = bytes.iter().any(u8::is_ascii_uppercase);
+    if has_lower && has_upper {
+        return Err("string not all lowercase or all uppercase".to_string());
+    }
+    let lower = human.to_ascii_lowercase().into_bytes();
+    let separator = match lower.iter().rposition(|c| *c == b'1') {
+        Some(separator) if separator > 0 && separator + 7 <= lower.len() => separator,
+        _ => return Err("invalid index of 1".to_string()),
+    };
+    let data = lower[separator + 1..]
+        .iter()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|d| d == c)
+                .map(|d| d as u8)
+                .ok_or_else(|| {
+                    format!(
+                        "failed converting data to bytes: invalid character not part of charset: {}",
+                        c
+                    )
+                })
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    let prefix = lower[..separator].to_vec();
+    let data_length = data.len() - CHECKSUM_LENGTH;
+    if polymod(expand_prefix(&prefix).chain(data.iter().copied())) != 1 {
+        let expected: String = checksum(&prefix, &data[..data_length]).collect();
+        return Err(format!(
+            "checksum failed. Expected {}, got {}.",
+            expected,
+            String::from_utf8_lossy(&lower[separator + 1 + data_length..])
+        ));
+    }
+    Ok((prefix, data[..data_length].to_vec()))
+}
+
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, String> {
+    let mut acc = 0_u32;
+    let mut bits = 0_u32;
+    let max = (1_u32 << to) - 1;
+    let mut converted = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    for value in data {
+        acc = (acc << from) | u32::from(*value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            converted.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return Err("invalid incomplete group".to_string());
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codec() -> AddressCodec {
+        AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "cosmos".to_string(),
+            address_lengths: vec![20, 32],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn canonicalize_and_humanize_work() {
+        let codec = codec();
+        // from the Cosmos SDK tests
+        let human = "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu";
+        let canonical = codec.canonicalize(human).unwrap();
+        assert_eq!(
+            canonical,
+            hex::decode("0102030405060708090a0b0c0d0e0f1011121314").unwrap()
+        );
+        assert_eq!(codec.humanize(&canonical).unwrap(), human);
+        // upper case is fine
+        assert_eq!(
+            codec.canonicalize(&human.to_ascii_uppercase()).unwrap(),
+            canonical
+        );
+
+        let contract = [0xab; 32];
+        let human = codec.humanize(&contract).unwrap();
+        assert!(human.starts_with("cosmos1"));
+        assert_eq!(codec.canonicalize(&human).unwrap(), contract);
+    }
+
+    #[test]
+    fn canonicalize_rejects_invalid_addresses() {
+        let codec = codec();
+        let human = "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu";
+        let cases = [
+            (" ", "empty address string is not allowed"),
+            ("cosmos1", "decoding bech32 failed: invalid bech32 string length 7"),
+            (
+                "1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu",
+                "decoding bech32 failed: invalid index of 1",
+            ),
+            (
+                "cosmos1qypqx",
+                "decoding bech32 failed: invalid index of 1",
+            ),
+            (
+                "Cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu",
+                "decoding bech32 failed: string not all lowercase or all uppercase",
+            ),
+            (
+                "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xq",
+                "decoding bech32 failed: checksum failed. Expected lzv7xu, got lzv7xq.",
+            ),
+            (
+                "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xi",
+                "decoding bech32 failed: failed converting data to bytes: invalid character not part of charset: 105",
+            ),
+            (
+                "cosmos 1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu",
+                "decoding bech32 failed: invalid character in string: ' '",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                codec.canonicalize(input).unwrap_err(),
+                expected,
+                "{}",
+                input
+            );
+        }
+
+        let other = AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "ex".to_string(),
+            address_lengths: vec![20],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: false,
+        })
+        .unwrap();
+        assert_eq!(
+            other.canonicalize(human).unwrap_err(),
+            "invalid Bech32 prefix; expected ex, got cosmos"
+        );
+
+        let short = codec.humanize(&[1; 20]).unwrap();
+        let only_32 = AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "cosmos".to_string(),
+            address_lengths: vec![32],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: false,
+        })
+        .unwrap();
+        assert_eq!(
+            only_32.canonicalize(&short).unwrap_err(),
+            "invalid address length 20: unknown address"
+        );
+        assert_eq!(
+            only_32.humanize(&[1; 20]).unwrap_err(),
+            "invalid address length 20: unknown address"
+        );
+        assert_eq!(
+            codec.humanize(&[]).unwrap_err(),
+            "addresses cannot be empty: unknown address"
+        );
+        assert_eq!(
+            codec.humanize(&[1; 300]).unwrap_err(),
+            "address max length is 255, got 300: unknown address"
+        );
+    }
+
+    #[test]
+    fn canonicalize_limits_bech32_length() {
+        let codec = AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "cosmos".to_string(),
+            address_lengths: vec![48, 49],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: false,
+        })
+        .unwrap();
+        // 48 bytes are the longest address with 90 characters
+        let longest = codec.humanize(&[1; 48]).unwrap();
+        assert_eq!(longest.len(), 90);
+        assert_eq!(codec.canonicalize(&longest).unwrap(), vec![1; 48]);
+        // longer addresses can be humanized like in Go, but not canonicalized
+        let too_long = codec.humanize(&[1; 49]).unwrap();
+        assert_eq!(too_long.len(), 92);
+        assert_eq!(
+            codec.canonicalize(&too_long).unwrap_err(),
+            "decoding bech32 failed: invalid bech32 string length 92"
+        );
+    }
+
+    fn hex_codec() -> AddressCodec {
+        AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "ex".to_string(),
+            address_lengths: vec![20, 32],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: true,
+        })
+        .unwrap()
+    }
+
+    // from EIP-55
+    const CHECKSUM_ADDRESSES: [&str; 4] = [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn canonicalize_works_for_hex_addresses() {
+        let codec = hex_codec();
+        for human in CHECKSUM_ADDRESSES {
+            let canonical = codec.canonicalize(human).unwrap();
+            assert_eq!(canonical, hex::decode(&human[2..]).unwrap());
+            // all lower and all upper case have no checksum
+            let digits = &human[2..];
+            assert_eq!(
+                codec
+                    .canonicalize(&format!("0x{}", digits.to_ascii_lowercase()))
+                    .unwrap(),
+                canonical
+            );
+            assert_eq!(
+                codec
+                    .canonicalize(&format!("0x{}", digits.to_ascii_uppercase()))
+                    .unwrap(),
+                canonical
+            );
+            // both forms have the same canonical address and humanize to bech32
+            let bech32 = codec.humanize(&canonical).unwrap();
+            assert!(bech32.starts_with("ex1"));
+            assert_eq!(codec.canonicalize(&bech32).unwrap(), canonical);
+            let canonical: [u8; 20] = canonical.try_into().unwrap();
+            assert_eq!(to_checksum_hex_address(&canonical), human);
+        }
+    }
+
+    #[test]
+    fn canonicalize_rejects_invalid_hex_addresses() {
+        let codec = hex_codec();
+        // the case of a single letter is flipped
+        let cases = [
+            (
+                "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+                "Invalid hex address checksum",
+            ),
+            (
+                "0xFb6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+                "Invalid hex address checksum",
+            ),
+            (
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAe",
+                "Invalid hex address length: expected 40 digits, got 39",
+            ),
+            (
+                "0x",
+                "Invalid hex address length: expected 40 digits, got 0",
+            ),
+            (
+                "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeg",
+                "Invalid character in hex address",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                codec.canonicalize(input).unwrap_err(),
+                expected,
+                "{}",
+                input
+            );
+        }
+
+        // 20 bytes must be an allowed length
+        let only_32 = AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "ex".to_string(),
+            address_lengths: vec![32],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: true,
+        })
+        .unwrap();
+        assert_eq!(
+            only_32.canonicalize(CHECKSUM_ADDRESSES[0]).unwrap_err(),
+            "invalid address length 20: unknown address"
+        );
+
+        // hex addresses must be enabled
+        assert_eq!(
+            self::codec()
+                .canonicalize(CHECKSUM_ADDRESSES[0])
+                .unwrap_err(),
+            "decoding bech32 failed: string not all lowercase or all uppercase"
+        );
+    }
+
+    #[test]
+    fn new_validates_config() {
+        let config = |prefix: &str, lengths: Vec<u64>| AddressCodecConfig {
+            bech32_prefix: prefix.to_string(),
+            address_lengths: lengths,
+            canonicalize_cost: 0,
+            humanize_cost: 0,
+            hex_addresses: false,
+        };
+        assert!(AddressCodec::new(&config("ex", vec![1, 20, 64, 255])).is_ok());
+        for invalid in [
+            config("", vec![20]),
+            config("Ex", vec![20]),
+            config(&"a".repeat(84), vec![20]),
+            config("ex", vec![]),
+            config("ex", vec![0]),
+            config("ex", vec![256]),
+        ] {
+            let err = AddressCodec::new(&invalid).err().unwrap();
+            assert!(
+                err.to_string().starts_with("Invalid cache config"),
+                "{}",
+                err
+            );
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use cosmwasm_vm::{BackendApi, BackendError, BackendResult, GasInfo};
 
+use crate::address_codec::AddressCodec;
 use crate::error::GoError;
+use crate::gas_config::current_gas_config;
 use crate::memory::{U8SliceView, UnmanagedVector};
+use crate::replay::{record, BackendRequest, BackendResponse};
+use crate::vm::VmApi;
 
 // this represents something passed in from the caller side of FFI
 // in this case a struct with go function pointers
@@ -45,8 +49,8 @@ pub struct GoApi {
 // see: https://stackoverflow.com/questions/50258359/can-a-struct-containing-a-raw-pointer-implement-send-and-be-ffi-safe
 unsafe impl Send for GoApi {}
 
-impl BackendApi for GoApi {
-    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+impl VmApi for GoApi {
+    fn canonicalize_address(&self, human: &str) -> BackendResult<Vec<u8>> {
         let mut output = UnmanagedVector::default();
         let mut error_msg = UnmanagedVector::default();
         let mut used_gas = 0_u64;
@@ -75,7 +79,7 @@ impl BackendApi for GoApi {
         (result, gas_info)
     }
 
-    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+    fn humanize_address(&self, canonical: &[u8]) -> BackendResult<String> {
         let mut output = UnmanagedVector::default();
         let mut error_msg = UnmanagedVector::default();
         let mut used_gas = 0_u64;
@@ -111,3 +115,129 @@ impl BackendApi for GoApi {
         (result, gas_info)
     }
 }
+
+/// The `BackendApi` used for calls. Address conversions are done natively if an address codec
+/// is configured and by the host (Go by default) otherwise.
+#[derive(Copy, Clone)]
+pub struct HostApi<A = GoApi> {
+    inner: A,
+    address_codec: Option<AddressCodec>,
+}
+
+impl<A: VmApi> HostApi<A> {
+    pub fn new(inner: A, address_codec: Option<AddressCodec>) -> Self {
+        HostApi {
+            inner,
+            address_codec,
+        }
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A: VmApi> BackendApi for HostApi<A> {
+    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+        let outcome = match &self.address_codec {
+            Some(codec) => (
+                codec.canonicalize(human).map_err(BackendError::user_err),
+                GasInfo::with_cost(codec.canonicalize_cost),
+            ),
+            None => self.inner.canonicalize_address(human),
+        };
+        let config = current_gas_config();
+        let outcome = config.charge(outcome, config.canonicalize_address_cost);
+        record(
+            || BackendRequest::CanonicalAddress {
+                human: human.to_string(),
+            },
+            &outcome,
+            |canonical| BackendResponse::Canonical(canonical.as_slice().into()),
+        );
+        outcome
+    }
+
+    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+        let outcome = match &self.address_codec {
+            Some(codec) => (
+                codec.humanize(canonical).map_err(BackendError::user_err),
+                GasInfo::with_cost(codec.humanize_cost),
+            ),
+            None => self.inner.humanize_address(canonical),
+        };
+        let config = current_gas_config();
+        let outcome = config.charge(outcome, config.humanize_address_cost);
+        record(
+            || BackendRequest::HumanAddress {
+                canonical: canonical.into(),
+            },
+            &outcome,
+            |human| BackendResponse::Human(human.clone()),
+        );
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_codec::AddressCodecConfig;
+
+    extern "C" fn unreachable_conversion(
+        _api: *const api_t,
+        _input: U8SliceView,
+        _output: *mut UnmanagedVector,
+        _error_msg: *mut UnmanagedVector,
+        _used_gas: *mut u64,
+    ) -> i32 {
+        panic!("Go must not be called")
+    }
+
+    #[test]
+    fn host_api_uses_address_codec() {
+        let go = GoApi {
+            state: std::ptr::null(),
+            vtable: GoApi_vtable {
+                humanize_address: unreachable_conversion,
+                canonicalize_address: unreachable_conversion,
+            },
+        };
+        let codec = AddressCodec::new(&AddressCodecConfig {
+            bech32_prefix: "ex".to_string(),
+            address_lengths: vec![20],
+            canonicalize_cost: 4,
+            humanize_cost: 5,
+            hex_addresses: true,
+        })
+        .unwrap();
+        let api = HostApi::new(go, Some(codec));
+
+        let (human, gas_info) = api.human_address(&[1; 20]);
+        let human = human.unwrap();
+        assert_eq!(gas_info.cost, 5);
+        let (canonical, gas_info) = api.canonical_address(&human);
+        assert_eq!(canonical.unwrap(), vec![1; 20]);
+        assert_eq!(gas_info.cost, 4);
+
+        // errors are user errors that cost the same gas
+        let (result, gas_info) = api.canonical_address("foo");
+        match result.unwrap_err() {
+            BackendError::UserErr { msg } => {
+                assert_eq!(
+                    msg,
+                    "decoding bech32 failed: invalid bech32 string length 3"
+                )
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(gas_info.cost, 4);
+        // hex addresses are understood as well
+        let (canonical, _) = api.canonical_address("0x0101010101010101010101010101010101010101");
+        assert_eq!(canonical.unwrap(), vec![1; 20]);
+
+        let (result, gas_info) = api.human_address(&[1; 32]);
+        assert!(matches!(result.unwrap_err(), BackendError::UserErr { .. }));
+        assert_eq!(gas_info.cost, 5);
+    }
+}
@@ -1,10 +1,14 @@
 // store some common string for argument names
 pub const DATA_DIR_ARG: &str = "data_dir";
 pub const AVAILABLE_CAPABILITIES_ARG: &str = "available_capabilities";
+pub const CONFIG_ARG: &str = "config";
 pub const CACHE_ARG: &str = "cache";
 pub const WASM_ARG: &str = "wasm";
 pub const CHECKSUM_ARG: &str = "checksum";
-pub const GAS_USED_ARG: &str = "gas_used";
+pub const SNAPSHOT_ARG: &str = "snapshot";
+pub const ROUTES_ARG: &str = "routes";
+pub const RECORDING_ARG: &str = "recording";
+pub const REPORT_ARG: &str = "report";
 pub const ARG1: &str = "arg1";
 pub const ARG2: &str = "arg2";
 pub const ARG3: &str = "arg3";
@@ -0,0 +1,33 @@
+use cosmwasm_vm::testing::MockApi;
+use cosmwasm_vm::{BackendApi, BackendError, BackendResult, GasInfo};
+use wasmvm::AddressCodec;
+
+/// Converts addresses with the native bech32 codec if configured. Otherwise the
+/// mock implementation of cosmwasm-vm is used, which accepts any address of a sane length.
+#[derive(Copy, Clone)]
+pub enum CliApi {
+    Mock(MockApi),
+    Codec(AddressCodec),
+}
+
+impl BackendApi for CliApi {
+    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+        match self {
+            CliApi::Mock(api) => api.canonical_address(human),
+            CliApi::Codec(codec) => (
+                codec.canonicalize(human).map_err(BackendError::user_err),
+                GasInfo::with_cost(codec.canonicalize_cost),
+            ),
+        }
+    }
+
+    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+        match self {
+            CliApi::Mock(api) => api.human_address(canonical),
+            CliApi::Codec(codec) => (
+                codec.humanize(canonical).map_err(BackendError::user_err),
+                GasInfo::with_cost(codec.humanize_cost),
+            ),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Runs contracts against a local state for debugging.
+//!
+//! Calls go through the same cache and call path as the FFI calls, but storage, API and querier
+//! are implemented in Rust. The result, the gas report and the storage diff are printed as JSON.
+
+mod api;
+mod state;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::exit;
+use std::{env, fs};
+
+use cosmwasm_std::{to_vec, Coin};
+use cosmwasm_vm::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_vm::{Backend, Size, Storage};
+use serde_json::{json, Value};
+use wasmvm::{call_entry_point, CacheConfig, EntryPoint, VmCache, VmCacheOptions};
+
+use crate::api::CliApi;
+use crate::state::{diff, Records, StateFile};
+
+const USAGE: &str = "Usage: wasmvm-cli <ENTRY_POINT> --wasm <FILE> [OPTIONS]
+
+Entry points:
+  instantiate, execute, migrate, sudo, reply, query, ibc_channel_open, ibc_channel_connect,
+  ibc_channel_close, ibc_packet_receive, ibc_packet_ack, ibc_packet_timeout
+
+Options:
+  --wasm <FILE>       The contract Wasm
+  --state <FILE>      The contract state. A sled database if it ends in .sled, JSON otherwise.
+                      Defaults to state.json.
+  --env <JSON>        The env. Defaults to a mock env.
+  --info <JSON>       The message info. Defaults to sender `creator` without funds.
+  --msg <JSON>        The message. Use --reply, --packet or --ack for those entry points.
+  --gas-limit <GAS>   Defaults to 100000000000000
+  --config <FILE>     A cache config as accepted by init_cache_with_config. Modules compiled
+                      into its data_dir are executed as native code, so only wasmvm may be
+                      able to write to that directory.
+  --print-debug       Prints debug output of the contract to stderr
+  --dry-run           Does not write the state
+
+JSON arguments starting with @ are read from the file with the following name.";
+
+const DEFAULT_GAS_LIMIT: u64 = 100_000_000_000_000;
+const DEFAULT_CAPABILITIES: &[&str] = &[
+    "iterator",
+    "staking",
+    "stargate",
+    "cosmwasm_1_1",
+    "cosmwasm_1_2",
+    "cosmwasm_1_3",
+];
+
+struct Options {
+    entry_point: EntryPoint,
+    wasm: PathBuf,
+    state: PathBuf,
+    /// The JSON arguments by name
+    args: Vec<(String, String)>,
+    gas_limit: u64,
+    config: Option<PathBuf>,
+    print_debug: bool,
+    dry_run: bool,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let entry_point = args.next().ok_or("Missing entry point")?;
+    let entry_point = EntryPoint::from_name(&entry_point)
+        .ok_or_else(|| format!("Unknown entry point {}", entry_point))?;
+    let mut options = Options {
+        entry_point,
+        wasm: PathBuf::new(),
+        state: PathBuf::from("state.json"),
+        args: vec![],
+        gas_limit: DEFAULT_GAS_LIMIT,
+        config: None,
+        print_debug: false,
+        dry_run: false,
+    };
+    while let Some(flag) = args.next() {
+        let name = flag
+            .strip_prefix("--")
+            .ok_or_else(|| format!("Unexpected argument {}", flag))?;
+        match name {
+            "print-debug" => options.print_debug = true,
+            "dry-run" => options.dry_run = true,
+            _ => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", flag))?;
+                match name {
+                    "wasm" => options.wasm = value.into(),
+                    "state" => options.state = value.into(),
+                    "config" => options.config = Some(value.into()),
+                    "gas-limit" => {
+                        options.gas_limit = value
+                            .parse()
+                            .map_err(|e| format!("Invalid gas limit: {}", e))?
+                    }
+                    _ if entry_point.arg_names().contains(&name) => {
+                        options.args.push((name.to_string(), value))
+                    }
+                    _ => return Err(format!("Unknown option {}", flag)),
+                }
+            }
+        }
+    }
+    if options.wasm.as_os_str().is_empty() {
+        return Err("Missing --wasm".to_string());
+    }
+    Ok(options)
+}
+
+/// Returns the raw JSON argument, reading it from a file if it starts with @
+fn read_arg(options: &Options, name: &str) -> Result<Option<Vec<u8>>, String> {
+    let value = match options.args.iter().find(|(arg, _)| arg == name) {
+        Some((_, value)) => value,
+        None => return Ok(None),
+    };
+    let data = match value.strip_prefix('@') {
+        Some(path) => fs::read(path).map_err(|e| format!("Error reading {}: {}", path, e))?,
+        None => value.as_bytes().to_vec(),
+    };
+    serde_json::from_slice::<Value>(&data)
+        .map_err(|e| format!("Invalid JSON for --{}: {}", name, e))?;
+    Ok(Some(data))
+}
+
+fn default_arg(name: &str) -> Result<Vec<u8>, String> {
+    let data = match name {
+        "env" => to_vec(&mock_env()),
+        "info" => to_vec(&mock_info("creator", &[] as &[Coin])),
+        _ => return Err(format!("Missing --{}", name)),
+    };
+    Ok(data.unwrap())
+}
+
+fn cache_options(options: &Options) -> Result<VmCacheOptions, String> {
+    match &options.config {
+        Some(path) => {
+            let data =
+                fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+            CacheConfig::from_json(&data)
+                .and_then(CacheConfig::into_options)
+                .map_err(|e| e.to_string())
+        }
+        None => {
+            let capabilities: HashSet<String> = DEFAULT_CAPABILITIES
+                .iter()
+                .map(|capability| capability.to_string())
+                .collect();
+            let mut cache_options = VmCacheOptions::new(
+                env::temp_dir(),
+                capabilities,
+                Size::mebi(100),
+                Size::mebi(32),
+            );
+            cache_options.in_memory_wasm_store_size = Some(Size::mebi(100));
+            Ok(cache_options)
+        }
+    }
+}
+
+fn all_records(storage: &mut MockStorage) -> Result<Records, String> {
+    let (iterator_id, _) = storage.scan(None, None, cosmwasm_std::Order::Ascending);
+    let iterator_id = iterator_id.map_err(|e| e.to_string())?;
+    let records = storage.all(iterator_id).0.map_err(|e| e.to_string())?;
+    Ok(records.into_iter().collect())
+}
+
+fn run(options: Options) -> Result<(Value, bool), String> {
+    let wasm = fs::read(&options.wasm)
+        .map_err(|e| format!("Error reading {}: {}", options.wasm.display(), e))?;
+    let state = StateFile::new(options.state.clone());
+    let before = state.load()?;
+
+    let args = options
+        .entry_point
+        .arg_names()
+        .iter()
+        .map(|name| read_arg(&options, name)?.map_or_else(|| default_arg(name), Ok))
+        .collect::<Result<Vec<_>, _>>()?;
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+
+    // SAFETY: the data dir is the user's responsibility, see USAGE
+    let cache: VmCache<CliApi, MockStorage, MockQuerier> =
+        unsafe { VmCache::new(cache_options(&options)?) }.map_err(|e| e.to_string())?;
+    let checksum = cache.save_wasm(&wasm).map_err(|e| e.to_string())?;
+
+    let mut storage = MockStorage::new();
+    for (key, value) in &before {
+        storage.set(key, value).0.map_err(|e| e.to_string())?;
+    }
+    let api = match cache.address_codec() {
+        Some(codec) => CliApi::Codec(codec),
+        None => CliApi::Mock(MockApi::default()),
+    };
+    let backend = Backend {
+        api,
+        storage,
+        querier: MockQuerier::new(&[]),
+    };
+
+    let output = call_entry_point(
+        &cache,
+        options.entry_point,
+        &checksum,
+        &args,
+        backend,
+        options.gas_limit,
+        options.print_debug,
+        false,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut storage = output
+        .backend
+        .ok_or("The instance did not return its backend")?
+        .storage;
+    let changes = diff(&before, &all_records(&mut storage)?);
+
+    let gas_report = json!({
+        "limit": output.gas_report.limit,
+        "remaining": output.gas_report.remaining,
+        "used_externally": output.gas_report.used_externally,
+        "used_internally": output.gas_report.used_internally,
+    });
+    let (result, success) = match output.result {
+        Ok(data) => {
+            let result: Value = serde_json::from_slice(&data)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&data).into()));
+            // contract errors are results like {"error":"…"}
+            let success = result.get("ok").is_some();
+            (json!({ "result": result }), success)
+        }
+        Err(e) => (json!({ "vm_error": e.to_string() }), false),
+    };
+
+    let mut report = result;
+    report["gas_report"] = gas_report;
+    report["storage_diff"] = serde_json::to_value(&changes).unwrap();
+
+    // Like on chain, state changes of failed calls are discarded
+    if success && !options.dry_run && !changes.is_empty() {
+        state.save(&changes)?;
+    }
+    Ok((report, success))
+}
+
+fn main() {
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    match run(options) {
+        Ok((report, success)) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            if !success {
+                exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(2);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Contract state kept in a JSON file or a sled database between runs
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+
+use cosmwasm_std::Binary;
+use serde::Serialize;
+
+pub type Records = BTreeMap<Vec<u8>, Vec<u8>>;
+
+pub enum StateFile {
+    /// A JSON object mapping hex encoded keys to base64 encoded values
+    Json(PathBuf),
+    /// A sled database directory
+    Sled(PathBuf),
+}
+
+impl StateFile {
+    /// Paths ending in `.sled` are sled databases, everything else is JSON
+    pub fn new(path: PathBuf) -> Self {
+        if path.extension() == Some(OsStr::new("sled")) {
+            StateFile::Sled(path)
+        } else {
+            StateFile::Json(path)
+        }
+    }
+
+    /// Loads all records. A missing file is an empty state.
+    pub fn load(&self) -> Result<Records, String> {
+        match self {
+            StateFile::Json(path) => {
+                if !path.exists() {
+                    return Ok(Records::new());
+                }
+                let data = fs::read(path)
+                    .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+                records_from_json(&data)
+                    .map_err(|e| format!("Error parsing {}: {}", path.display(), e))
+            }
+            StateFile::Sled(path) => sled_state::load(path),
+        }
+    }
+
+    /// Applies the changes of a call
+    pub fn save(&self, diff: &[Change]) -> Result<(), String> {
+        match self {
+            StateFile::Json(path) => {
+                let mut records = self.load()?;
+                apply(&mut records, diff);
+                fs::write(path, records_to_json(&records))
+                    .map_err(|e| format!("Error writing {}: {}", path.display(), e))
+            }
+            StateFile::Sled(path) => sled_state::save(path, diff),
+        }
+    }
+}
+
+fn records_from_json(data: &[u8]) -> Result<Records, String> {
+    let encoded: BTreeMap<String, Binary> =
+        serde_json::from_slice(data).map_err(|e| e.to_string())?;
+    encoded
+        .into_iter()
+        .map(|(key, value)| {
+            let key = hex::decode(&key).map_err(|e| format!("Invalid key {}: {}", key, e))?;
+            Ok((key, value.0))
+        })
+        .collect()
+}
+
+fn records_to_json(records: &Records) -> Vec<u8> {
+    let encoded: BTreeMap<String, Binary> = records
+        .iter()
+        .map(|(key, value)| (hex::encode(key), Binary::from(value.as_slice())))
+        .collect();
+    serde_json::to_vec_pretty(&encoded).unwrap()
+}
+
+/// A change of a single key. The value is None for removed keys.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Change {
+    #[serde(serialize_with = "serialize_hex")]
+    pub key: Vec<u8>,
+    pub before: Option<Binary>,
+    pub after: Option<Binary>,
+}
+
+fn serialize_hex<S: serde::Serializer>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(key))
+}
+
+/// Returns the changes from `before` to `after`, ordered by key
+pub fn diff(before: &Records, after: &Records) -> Vec<Change> {
+    let mut changes = vec![];
+    for (key, value) in before {
+        match after.get(key) {
+            Some(new_value) if new_value == value => {}
+            new_value => changes.push(Change {
+                key: key.clone(),
+                before: Some(value.clone().into()),
+                after: new_value.cloned().map(Binary::from),
+            }),
+        }
+    }
+    for (key, value) in after {
+        if !before.contains_key(key) {
+            changes.push(Change {
+                key: key.clone(),
+                before: None,
+                after: Some(value.clone().into()),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+fn apply(records: &mut Records, diff: &[Change]) {
+    for change in diff {
+        match &change.after {
+            Some(value) => records.insert(change.key.clone(), value.to_vec()),
+            None => records.remove(&change.key),
+        };
+    }
+}
+
+#[cfg(feature = "sled")]
+mod sled_state {
+    use std::path::Path;
+
+    use super::{Change, Records};
+
+    fn open(path: &Path) -> Result<sled::Db, String> {
+        sled::open(path).map_err(|e| format!("Error opening {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Records, String> {
+        open(path)?
+            .iter()
+            .map(|record| {
+                record
+                    .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                    .map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+
+    pub fn save(path: &Path, diff: &[Change]) -> Result<(), String> {
+        let db = open(path)?;
+        let mut batch = sled::Batch::default();
+        for change in diff {
+            match &change.after {
+                Some(value) => batch.insert(change.key.as_slice(), value.as_slice()),
+                None => batch.remove(change.key.as_slice()),
+            }
+        }
+        db.apply_batch(batch).map_err(|e| e.to_string())?;
+        db.flush().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "sled"))]
+mod sled_state {
+    use std::path::Path;
+
+    use super::{Change, Records};
+
+    const UNSUPPORTED: &str = "sled state files require the `sled` feature";
+
+    pub fn load(_path: &Path) -> Result<Records, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn save(_path: &Path, _diff: &[Change]) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(entries: &[(&[u8], &[u8])]) -> Records {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_vec(), value.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_works() {
+        let before = records(&[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")]);
+        let after = records(&[(b"a", b"1"), (b"b", b"4"), (b"d", b"5")]);
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes,
+            vec![
+                Change {
+                    key: b"b".to_vec(),
+                    before: Some(Binary::from(b"2")),
+                    after: Some(Binary::from(b"4")),
+                },
+                Change {
+                    key: b"c".to_vec(),
+                    before: Some(Binary::from(b"3")),
+                    after: None,
+                },
+                Change {
+                    key: b"d".to_vec(),
+                    before: None,
+                    after: Some(Binary::from(b"5")),
+                },
+            ]
+        );
+
+        let mut applied = before;
+        apply(&mut applied, &changes);
+        assert_eq!(applied, after);
+    }
+
+    #[test]
+    fn json_state_works() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let state = StateFile::new(dir.path().join("state.json"));
+        assert_eq!(state.load().unwrap(), Records::new());
+
+        let after = records(&[(b"\x00\x05admin", b"\"foo\"")]);
+        state.save(&diff(&Records::new(), &after)).unwrap();
+        assert_eq!(state.load().unwrap(), after);
+        let json = fs::read_to_string(dir.path().join("state.json")).unwrap();
+        assert!(json.contains(r#""000561646d696e": "ImZvbyI=""#), "{}", json);
+
+        fs::write(dir.path().join("state.json"), br#"{"zz":""}"#).unwrap();
+        let err = state.load().unwrap_err();
+        assert!(err.contains("Invalid key zz"), "{}", err);
+    }
+}
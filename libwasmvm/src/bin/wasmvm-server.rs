@@ -0,0 +1,62 @@
+//! Hosts the VM in its own process. Clients connect via a Unix socket, see `RemoteClient`.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::exit;
+
+use wasmvm::{CacheConfig, RemoteServer};
+
+const USAGE: &str = "Usage: wasmvm-server --socket <PATH> --config <FILE>
+
+Options:
+  --socket <PATH>     The Unix socket to listen on. Must not exist yet.
+  --config <FILE>     A cache config as accepted by init_cache_with_config. Modules compiled
+                      into its data_dir are executed as native code, so only the server
+                      may be able to write to that directory.";
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<(PathBuf, PathBuf), String> {
+    let mut socket = None;
+    let mut config = None;
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", flag))?;
+        match flag.as_str() {
+            "--socket" => socket = Some(PathBuf::from(value)),
+            "--config" => config = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    Ok((
+        socket.ok_or("Missing --socket")?,
+        config.ok_or("Missing --config")?,
+    ))
+}
+
+fn run(socket: PathBuf, config: PathBuf) -> Result<(), String> {
+    let data =
+        fs::read(&config).map_err(|e| format!("Error reading {}: {}", config.display(), e))?;
+    let options = CacheConfig::from_json(&data)
+        .and_then(CacheConfig::into_options)
+        .map_err(|e| e.to_string())?;
+    // SAFETY: the data dir is the operator's responsibility, see USAGE
+    let server = unsafe { RemoteServer::new(options) }.map_err(|e| e.to_string())?;
+    server
+        .serve(&socket)
+        .map_err(|e| format!("Error serving {}: {}", socket.display(), e))
+}
+
+fn main() {
+    let (socket, config) = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(e) = run(socket, config) {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+}
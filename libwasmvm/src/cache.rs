@@ -1,25 +1,43 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryInto;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::str::from_utf8;
+use std::sync::Arc;
 
-use cosmwasm_vm::{capabilities_from_csv, Cache, CacheOptions, Checksum, Size};
+use cosmwasm_std::Binary;
+use cosmwasm_vm::{capabilities_from_csv, Checksum, Size};
 
 use crate::api::GoApi;
-use crate::args::{AVAILABLE_CAPABILITIES_ARG, CACHE_ARG, CHECKSUM_ARG, DATA_DIR_ARG, WASM_ARG};
-use crate::error::{handle_c_error_binary, handle_c_error_default, handle_c_error_ptr, Error};
+use crate::args::{
+    AVAILABLE_CAPABILITIES_ARG, CACHE_ARG, CHECKSUM_ARG, CONFIG_ARG, DATA_DIR_ARG, ROUTES_ARG,
+    SNAPSHOT_ARG, WASM_ARG,
+};
+use crate::config::CacheConfig;
+use crate::db::Db;
+use crate::error::{
+    handle_c_error_binary, handle_c_error_default, handle_c_error_ptr, handle_c_error_with_value,
+    Error,
+};
+use crate::gas_config::GasConfig;
+use crate::logger::set_log_level;
 use crate::memory::{ByteSliceView, UnmanagedVector};
 use crate::querier::GoQuerier;
-use crate::storage::GoStorage;
+use crate::query_router::{QueryHandler, QueryRoutes};
+use crate::static_analysis;
+use crate::vm::Vm;
+use crate::vm_cache::VmCacheOptions;
 
 #[repr(C)]
 pub struct cache_t {}
 
-pub fn to_cache(ptr: *mut cache_t) -> Option<&'static mut Cache<GoApi, GoStorage, GoQuerier>> {
+/// The VM behind a `*cache_t`
+pub type GoVm = Vm<GoApi, Db, GoQuerier>;
+
+pub fn to_cache(ptr: *mut cache_t) -> Option<&'static mut GoVm> {
     if ptr.is_null() {
         None
     } else {
-        let c = unsafe { &mut *(ptr as *mut Cache<GoApi, GoStorage, GoQuerier>) };
+        let c = unsafe { &mut *(ptr as *mut GoVm) };
         Some(c)
     }
 }
@@ -52,7 +70,7 @@ fn do_init_cache(
     available_capabilities: ByteSliceView,
     cache_size: u32,            // in MiB
     instance_memory_limit: u32, // in MiB
-) -> Result<*mut Cache<GoApi, GoStorage, GoQuerier>, Error> {
+) -> Result<*mut GoVm, Error> {
     let dir = data_dir
         .read()
         .ok_or_else(|| Error::unset_arg(DATA_DIR_ARG))?;
@@ -72,13 +90,40 @@ fn do_init_cache(
             .try_into()
             .expect("Cannot convert u32 to usize. What kind of system is this?"),
     );
-    let options = CacheOptions {
-        base_dir: dir_str.into(),
-        available_capabilities: capabilities,
+    let options = VmCacheOptions::new(
+        dir_str.into(),
+        capabilities,
         memory_cache_size,
         instance_memory_limit,
-    };
-    let cache = unsafe { Cache::new(options) }?;
+    );
+    let cache = unsafe { Vm::new(options) }?;
+    let out = Box::new(cache);
+    Ok(Box::into_raw(out))
+}
+
+/// Creates a cache from a JSON encoded, versioned config (see `CacheConfig`).
+///
+/// In contrast to `init_cache`, new options can be added to the config without
+/// changing this function's signature.
+#[no_mangle]
+pub extern "C" fn init_cache_with_config(
+    config: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> *mut cache_t {
+    let r = catch_unwind(|| do_init_cache_with_config(config)).unwrap_or_else(|err| {
+        eprintln!("Panic in do_init_cache_with_config: {:?}", err);
+        Err(Error::panic())
+    });
+    handle_c_error_ptr(r, error_msg) as *mut cache_t
+}
+
+fn do_init_cache_with_config(config: ByteSliceView) -> Result<*mut GoVm, Error> {
+    let config = config.read().ok_or_else(|| Error::unset_arg(CONFIG_ARG))?;
+    let config = CacheConfig::from_json(config)?;
+    set_log_level(config.log_level().into());
+    let options = config.into_options()?;
+    let cache = unsafe { Vm::new(options) }?;
+    log::info!("Initialized cache");
     let out = Box::new(cache);
     Ok(Box::into_raw(out))
 }
@@ -102,20 +147,179 @@ pub extern "C" fn save_wasm(
     UnmanagedVector::new(Some(checksum))
 }
 
-fn do_save_wasm(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    wasm: ByteSliceView,
-    unchecked: bool,
-) -> Result<Checksum, Error> {
+fn do_save_wasm(cache: &mut GoVm, wasm: ByteSliceView, unchecked: bool) -> Result<Checksum, Error> {
     let wasm = wasm.read().ok_or_else(|| Error::unset_arg(WASM_ARG))?;
     let checksum = if unchecked {
-        cache.save_wasm_unchecked(wasm)?
+        cache.store_code_unchecked(wasm)?
     } else {
-        cache.save_wasm(wasm)?
+        cache.store_code(wasm)?
     };
     Ok(checksum)
 }
 
+#[no_mangle]
+pub extern "C" fn get_capabilities(
+    cache: *mut cache_t,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> UnmanagedVector {
+    let r = match to_cache(cache) {
+        Some(c) => {
+            catch_unwind(AssertUnwindSafe(move || do_get_capabilities(c))).unwrap_or_else(|err| {
+                eprintln!("Panic in do_get_capabilities: {:?}", err);
+                Err(Error::panic())
+            })
+        }
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    let capabilities = handle_c_error_binary(r, error_msg);
+    UnmanagedVector::new(Some(capabilities))
+}
+
+fn do_get_capabilities(cache: &mut GoVm) -> Result<Vec<u8>, Error> {
+    Ok(set_to_csv(cache.capabilities()).into_bytes())
+}
+
+/// Replaces the available capabilities of the cache. The capabilities are given
+/// as an UTF-8 encoded comma separated list, like in `init_cache`.
+///
+/// Returns an UTF-8 encoded comma separated list of the hex encoded checksums of all stored
+/// Wasm blobs that require capabilities which are not available anymore. Every stored blob is
+/// analyzed for this, so this takes longer the more codes are stored.
+#[no_mangle]
+pub extern "C" fn set_capabilities(
+    cache: *mut cache_t,
+    available_capabilities: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> UnmanagedVector {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_set_capabilities(c, available_capabilities)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_set_capabilities: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    let unusable = handle_c_error_binary(r, error_msg);
+    UnmanagedVector::new(Some(unusable))
+}
+
+fn do_set_capabilities(
+    cache: &mut GoVm,
+    available_capabilities: ByteSliceView,
+) -> Result<Vec<u8>, Error> {
+    let capabilities_bin = available_capabilities
+        .read()
+        .ok_or_else(|| Error::unset_arg(AVAILABLE_CAPABILITIES_ARG))?;
+    let capabilities = capabilities_from_csv(from_utf8(capabilities_bin)?);
+    let unusable: Vec<String> = cache
+        .set_capabilities(capabilities)?
+        .into_iter()
+        .map(|checksum| checksum.to_hex())
+        .collect();
+    Ok(unusable.join(",").into_bytes())
+}
+
+/// Replaces the query snapshot of the cache, i.e. the responses used for query routes
+/// with the `snapshot` handler. The snapshot is a JSON encoded list of
+/// `{"request":<base64>,"response":<QuerierResult>}` entries.
+///
+/// The snapshot only answers calls at the block height, all other calls ask Go. It must only
+/// contain responses that cannot change within the block, since calls of all transactions in
+/// the block use it.
+#[no_mangle]
+pub extern "C" fn set_query_snapshot(
+    cache: *mut cache_t,
+    height: u64,
+    snapshot: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_set_query_snapshot(c, height, snapshot)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_set_query_snapshot: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    handle_c_error_default(r, error_msg)
+}
+
+fn do_set_query_snapshot(
+    cache: &mut GoVm,
+    height: u64,
+    snapshot: ByteSliceView,
+) -> Result<(), Error> {
+    let snapshot = snapshot
+        .read()
+        .ok_or_else(|| Error::unset_arg(SNAPSHOT_ARG))?;
+    let len = cache.set_query_snapshot(height, snapshot)?;
+    log::debug!(
+        "Replaced query snapshot with {} entries at height {}",
+        len,
+        height
+    );
+    Ok(())
+}
+
+/// Uses the JSON encoded query routes (see `query_routes` of the cache config) for calls from
+/// the block height on. Updates are not persisted, so they have to be made again after a
+/// restart unless they are part of the cache config.
+#[no_mangle]
+pub extern "C" fn update_query_routes(
+    cache: *mut cache_t,
+    height: u64,
+    routes: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_update_query_routes(c, height, routes)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_update_query_routes: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    handle_c_error_default(r, error_msg)
+}
+
+fn do_update_query_routes(
+    cache: &mut GoVm,
+    height: u64,
+    routes: ByteSliceView,
+) -> Result<(), Error> {
+    let routes = routes.read().ok_or_else(|| Error::unset_arg(ROUTES_ARG))?;
+    let routes: QueryRoutes = serde_json::from_slice(routes).map_err(Error::invalid_config)?;
+    cache.update_query_routes(height, routes);
+    Ok(())
+}
+
+/// Registers a native query handler, which query routes can refer to by `name`.
+/// This is only available to Rust code linking this library. Returns false if the cache is unset.
+///
+/// # Safety
+///
+/// `cache` must be null or a pointer returned by `init_cache` or `init_cache_with_config`
+/// that was not released yet.
+pub unsafe fn register_query_handler(
+    cache: *mut cache_t,
+    name: &str,
+    handler: Arc<dyn QueryHandler>,
+) -> bool {
+    match to_cache(cache) {
+        Some(c) => {
+            c.register_query_handler(name, handler);
+            true
+        }
+        None => false,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn remove_wasm(
     cache: *mut cache_t,
@@ -133,15 +337,12 @@ pub extern "C" fn remove_wasm(
     handle_c_error_default(r, error_msg)
 }
 
-fn do_remove_wasm(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: ByteSliceView,
-) -> Result<(), Error> {
+fn do_remove_wasm(cache: &mut GoVm, checksum: ByteSliceView) -> Result<(), Error> {
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
         .try_into()?;
-    cache.remove_wasm(&checksum)?;
+    cache.remove_code(&checksum)?;
     Ok(())
 }
 
@@ -163,15 +364,12 @@ pub extern "C" fn load_wasm(
     UnmanagedVector::new(Some(data))
 }
 
-fn do_load_wasm(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: ByteSliceView,
-) -> Result<Vec<u8>, Error> {
+fn do_load_wasm(cache: &mut GoVm, checksum: ByteSliceView) -> Result<Vec<u8>, Error> {
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
         .try_into()?;
-    let wasm = cache.load_wasm(&checksum)?;
+    let wasm = cache.get_code(&checksum)?;
     Ok(wasm)
 }
 
@@ -193,10 +391,7 @@ pub extern "C" fn pin(
     handle_c_error_default(r, error_msg)
 }
 
-fn do_pin(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: ByteSliceView,
-) -> Result<(), Error> {
+fn do_pin(cache: &mut GoVm, checksum: ByteSliceView) -> Result<(), Error> {
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
@@ -223,10 +418,7 @@ pub extern "C" fn unpin(
     handle_c_error_default(r, error_msg)
 }
 
-fn do_unpin(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: ByteSliceView,
-) -> Result<(), Error> {
+fn do_unpin(cache: &mut GoVm, checksum: ByteSliceView) -> Result<(), Error> {
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
@@ -235,11 +427,104 @@ fn do_unpin(
     Ok(())
 }
 
+/// Sets the instance memory limit in MiB for calls of the checksum. 0 resets it to the
+/// `instance_memory_limit` of the cache. The limit must not exceed `max_instance_memory_limit_mib`.
+/// Limits are stored in the data directory and loaded again by `init_cache`, unless the cache
+/// is in memory only.
+#[no_mangle]
+pub extern "C" fn set_instance_memory_limit(
+    cache: *mut cache_t,
+    checksum: ByteSliceView,
+    limit_mib: u32,
+    error_msg: Option<&mut UnmanagedVector>,
+) {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_set_instance_memory_limit(c, checksum, limit_mib)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_set_instance_memory_limit: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    handle_c_error_default(r, error_msg)
+}
+
+fn do_set_instance_memory_limit(
+    cache: &mut GoVm,
+    checksum: ByteSliceView,
+    limit_mib: u32,
+) -> Result<(), Error> {
+    let checksum: Checksum = checksum
+        .read()
+        .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
+        .try_into()?;
+    let limit = match limit_mib {
+        0 => None,
+        limit_mib => {
+            Some(Size::mebi(limit_mib.try_into().expect(
+                "Cannot convert u32 to usize. What kind of system is this?",
+            )))
+        }
+    };
+    cache.set_instance_memory_limit(&checksum, limit)
+}
+
+/// Uses the JSON encoded gas config for calls from the block height on, e.g. after a
+/// governance proposal. Unset costs use the defaults. Updates are not persisted, so they
+/// have to be made again after a restart unless they are part of the cache config.
+#[no_mangle]
+pub extern "C" fn update_gas_config(
+    cache: *mut cache_t,
+    height: u64,
+    config: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_update_gas_config(c, height, config)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_update_gas_config: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    handle_c_error_default(r, error_msg)
+}
+
+fn do_update_gas_config(cache: &mut GoVm, height: u64, config: ByteSliceView) -> Result<(), Error> {
+    let config = config.read().ok_or_else(|| Error::unset_arg(CONFIG_ARG))?;
+    let config: GasConfig = serde_json::from_slice(config).map_err(Error::invalid_config)?;
+    cache.update_gas_config(height, config)
+}
+
+/// Returns the id of the feature set of calls at the block height, i.e. the number of
+/// capability activations at or below it. Calls with the same id have the same capabilities
+/// and host features active.
+#[no_mangle]
+pub extern "C" fn get_feature_set_id(
+    cache: *mut cache_t,
+    height: u64,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> u32 {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || Ok(c.feature_set(height).id)))
+            .unwrap_or_else(|err| {
+                eprintln!("Panic in get_feature_set_id: {:?}", err);
+                Err(Error::panic())
+            }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    handle_c_error_default(r, error_msg)
+}
+
 /// The result type of the FFI function analyze_code.
 ///
-/// Please note that the unmanaged vector in `required_capabilities`
-/// has to be destroyed exactly once. When calling `analyze_code`
-/// from Go this is done via `C.destroy_unmanaged_vector`.
+/// Please note that the unmanaged vectors in `required_capabilities`, `entrypoints`,
+/// `interface_version` and `custom_sections` have to be destroyed exactly once.
+/// When calling `analyze_code` from Go this is done via `C.destroy_unmanaged_vector`.
 #[repr(C)]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct AnalysisReport {
@@ -247,19 +532,77 @@ pub struct AnalysisReport {
     /// An UTF-8 encoded comma separated list of reqired capabilities.
     /// This is never None/nil.
     pub required_capabilities: UnmanagedVector,
+    /// An UTF-8 encoded comma separated list of the exported entry points,
+    /// e.g. "execute,instantiate,query". This is never None/nil.
+    pub entrypoints: UnmanagedVector,
+    /// The UTF-8 encoded name of the interface version marker export, e.g. "interface_version_8".
+    /// Empty if the contract has no marker. This is never None/nil.
+    pub interface_version: UnmanagedVector,
+    /// The size of the Wasm bytecode in bytes
+    pub code_size: u64,
+    /// The number of functions defined in the contract (imports not included)
+    pub function_count: u64,
+    /// An UTF-8 encoded JSON object mapping custom section names to their base64 encoded
+    /// content, e.g. `{"cw2":"Y3ctZXhhbXBsZQ=="}`. This is never None/nil.
+    pub custom_sections: UnmanagedVector,
+    /// The migrate version from the `cw_migrate_version` custom section, if any
+    pub contract_migrate_version: OptionalU64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct OptionalU64 {
+    pub is_some: bool,
+    pub value: u64,
+}
+
+impl From<Option<u64>> for OptionalU64 {
+    fn from(value: Option<u64>) -> Self {
+        match value {
+            None => OptionalU64 {
+                is_some: false,
+                value: 0, // value is ignored
+            },
+            Some(value) => OptionalU64 {
+                is_some: true,
+                value,
+            },
+        }
+    }
 }
 
-impl From<cosmwasm_vm::AnalysisReport> for AnalysisReport {
-    fn from(report: cosmwasm_vm::AnalysisReport) -> Self {
-        let cosmwasm_vm::AnalysisReport {
+impl From<static_analysis::AnalysisReport> for AnalysisReport {
+    fn from(report: static_analysis::AnalysisReport) -> Self {
+        let static_analysis::AnalysisReport {
             has_ibc_entry_points,
             required_capabilities,
+            entrypoints,
+            interface_version,
+            code_size,
+            function_count,
+            custom_sections,
+            contract_migrate_version,
         } = report;
 
         let required_capabilities_utf8 = set_to_csv(required_capabilities).into_bytes();
+        let entrypoints_utf8 = entrypoints.into_iter().collect::<Vec<_>>().join(",");
+        let custom_sections: BTreeMap<String, Binary> = custom_sections
+            .into_iter()
+            .map(|(name, content)| (name, Binary(content)))
+            .collect();
+        let custom_sections_json =
+            serde_json::to_vec(&custom_sections).expect("serializing a map of strings cannot fail");
         AnalysisReport {
             has_ibc_entry_points,
             required_capabilities: UnmanagedVector::new(Some(required_capabilities_utf8)),
+            entrypoints: UnmanagedVector::new(Some(entrypoints_utf8.into_bytes())),
+            interface_version: UnmanagedVector::new(Some(
+                interface_version.unwrap_or_default().into_bytes(),
+            )),
+            code_size: code_size as u64,
+            function_count: function_count as u64,
+            custom_sections: UnmanagedVector::new(Some(custom_sections_json)),
+            contract_migrate_version: contract_migrate_version.into(),
         }
     }
 }
@@ -287,18 +630,50 @@ pub extern "C" fn analyze_code(
     handle_c_error_default(r, error_msg)
 }
 
-fn do_analyze_code(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: ByteSliceView,
-) -> Result<AnalysisReport, Error> {
+fn do_analyze_code(cache: &mut GoVm, checksum: ByteSliceView) -> Result<AnalysisReport, Error> {
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
         .try_into()?;
-    let report = cache.analyze(&checksum)?;
+    let report = cache.analyze_code(&checksum)?;
     Ok(report.into())
 }
 
+/// Runs the same checks as `save_wasm` without storing anything.
+///
+/// The analysis report is returned whenever the Wasm can be analyzed, even if a check fails.
+/// In that case the error is written to `error_msg` as usual. The report's unmanaged vectors
+/// have to be destroyed in any case.
+#[no_mangle]
+pub extern "C" fn validate_wasm(
+    cache: *mut cache_t,
+    wasm: ByteSliceView,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> AnalysisReport {
+    let (report, r) = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || do_validate_wasm(c, wasm)))
+            .unwrap_or_else(|err| {
+                eprintln!("Panic in do_validate_wasm: {:?}", err);
+                (AnalysisReport::default(), Err(Error::panic()))
+            }),
+        None => (AnalysisReport::default(), Err(Error::unset_arg(CACHE_ARG))),
+    };
+    handle_c_error_with_value(report, r, error_msg)
+}
+
+fn do_validate_wasm(cache: &mut GoVm, wasm: ByteSliceView) -> (AnalysisReport, Result<(), Error>) {
+    let wasm = match wasm.read() {
+        Some(wasm) => wasm,
+        None => return (AnalysisReport::default(), Err(Error::unset_arg(WASM_ARG))),
+    };
+    let result = cache.validate_code(wasm);
+    match static_analysis::analyze_wasm(wasm) {
+        Ok(report) => (report.into(), result),
+        // report the validation error first since it covers the analysis error
+        Err(err) => (AnalysisReport::default(), result.and(Err(err))),
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Metrics {
@@ -367,7 +742,7 @@ pub extern "C" fn get_metrics(
 }
 
 #[allow(clippy::unnecessary_wraps)] // Keep unused Result for consistent boilerplate for all fn do_*
-fn do_get_metrics(cache: &mut Cache<GoApi, GoStorage, GoQuerier>) -> Result<Metrics, Error> {
+fn do_get_metrics(cache: &mut GoVm) -> Result<Metrics, Error> {
     Ok(cache.metrics().into())
 }
 
@@ -381,7 +756,7 @@ fn do_get_metrics(cache: &mut Cache<GoApi, GoStorage, GoQuerier>) -> Result<Metr
 pub extern "C" fn release_cache(cache: *mut cache_t) {
     if !cache.is_null() {
         // this will free cache when it goes out of scope
-        let _ = unsafe { Box::from_raw(cache as *mut Cache<GoApi, GoStorage, GoQuerier>) };
+        let _ = unsafe { Box::from_raw(cache as *mut GoVm) };
     }
 }
 
@@ -437,6 +812,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn init_cache_with_config_works() {
+        for (fs_cache, in_memory) in [(true, false), (false, false), (false, true)] {
+            let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+            let config = format!(
+                r#"{{"v1":{{"data_dir":{:?},"available_capabilities":["staking"],"memory_cache_size_mib":512,"instance_memory_limit_mib":32,"pinned_cache_size_mib":64,"compile_threads":2,"fs_cache":{},"in_memory":{}}}}}"#,
+                dir, fs_cache, in_memory
+            );
+
+            let mut error_msg = UnmanagedVector::default();
+            let cache_ptr =
+                init_cache_with_config(ByteSliceView::new(config.as_bytes()), Some(&mut error_msg));
+            assert!(error_msg.is_none());
+            let _ = error_msg.consume();
+
+            let mut error_msg = UnmanagedVector::default();
+            let checksum = save_wasm(
+                cache_ptr,
+                ByteSliceView::new(HACKATOM),
+                false,
+                Some(&mut error_msg),
+            );
+            assert!(error_msg.is_none());
+            let _ = error_msg.consume();
+            let checksum = checksum.consume().unwrap_or_default();
+
+            let mut error_msg = UnmanagedVector::default();
+            pin(
+                cache_ptr,
+                ByteSliceView::new(&checksum),
+                Some(&mut error_msg),
+            );
+            assert!(error_msg.is_none());
+            let _ = error_msg.consume();
+
+            let mut error_msg = UnmanagedVector::default();
+            let metrics = get_metrics(cache_ptr, Some(&mut error_msg));
+            let _ = error_msg.consume();
+            assert_eq!(metrics.elements_pinned_memory_cache, 1);
+
+            release_cache(cache_ptr);
+        }
+    }
+
+    #[test]
+    fn init_cache_with_config_writes_error() {
+        let config = br#"{"v1":{"available_capabilities":["staking"],"memory_cache_size_mib":512,"instance_memory_limit_mib":32}}"#;
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache_with_config(ByteSliceView::new(config), Some(&mut error_msg));
+        assert!(cache_ptr.is_null());
+        assert!(error_msg.is_some());
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            "Invalid cache config: data_dir must be set unless in_memory is enabled"
+        );
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache_with_config(ByteSliceView::nil(), Some(&mut error_msg));
+        assert!(cache_ptr.is_null());
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(msg, "Null/Nil argument: config");
+    }
+
     #[test]
     fn save_wasm_works() {
         let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
@@ -719,6 +1159,20 @@ mod tests {
             hackatom_report.required_capabilities.consume().unwrap(),
             b""
         );
+        assert_eq!(
+            hackatom_report.entrypoints.consume().unwrap(),
+            b"execute,instantiate,migrate,query,sudo"
+        );
+        assert_eq!(
+            hackatom_report.interface_version.consume().unwrap(),
+            b"interface_version_8"
+        );
+        assert_eq!(hackatom_report.code_size, HACKATOM.len() as u64);
+        assert!(hackatom_report.function_count > 0);
+        let custom_sections: BTreeMap<String, Binary> =
+            serde_json::from_slice(&hackatom_report.custom_sections.consume().unwrap()).unwrap();
+        assert!(!custom_sections.contains_key("cw_migrate_version"));
+        assert!(!hackatom_report.contract_migrate_version.is_some);
 
         let mut error_msg = UnmanagedVector::default();
         let ibc_reflect_report = analyze_code(
@@ -732,6 +1186,288 @@ mod tests {
             String::from_utf8_lossy(&ibc_reflect_report.required_capabilities.consume().unwrap())
                 .to_string();
         assert_eq!(required_capabilities, "iterator,stargate");
+        let entrypoints =
+            String::from_utf8_lossy(&ibc_reflect_report.entrypoints.consume().unwrap()).to_string();
+        assert!(entrypoints.contains("ibc_channel_open"));
+        let _ = ibc_reflect_report.interface_version.consume();
+        let _ = ibc_reflect_report.custom_sections.consume();
+
+        release_cache(cache_ptr);
+    }
+
+    #[test]
+    fn validate_wasm_works() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+        let capabilities = b"staking";
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache(
+            ByteSliceView::new(dir.as_bytes()),
+            ByteSliceView::new(capabilities),
+            512,
+            32,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+
+        let mut error_msg = UnmanagedVector::default();
+        let report = validate_wasm(
+            cache_ptr,
+            ByteSliceView::new(HACKATOM),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        assert!(!report.has_ibc_entry_points);
+        assert_eq!(
+            report.entrypoints.consume().unwrap(),
+            b"execute,instantiate,migrate,query,sudo"
+        );
+        let _ = report.required_capabilities.consume();
+        let _ = report.interface_version.consume();
+        let _ = report.custom_sections.consume();
+
+        // The report is returned along with the error
+        let mut error_msg = UnmanagedVector::default();
+        let report = validate_wasm(
+            cache_ptr,
+            ByteSliceView::new(IBC_REFLECT),
+            Some(&mut error_msg),
+        );
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert!(
+            msg.contains("Wasm contract requires unavailable capabilities"),
+            "{}",
+            msg
+        );
+        assert!(report.has_ibc_entry_points);
+        assert_eq!(
+            report.required_capabilities.consume().unwrap(),
+            b"iterator,stargate"
+        );
+        let _ = report.entrypoints.consume();
+        let _ = report.interface_version.consume();
+        let _ = report.custom_sections.consume();
+
+        // No report for invalid Wasm
+        let mut error_msg = UnmanagedVector::default();
+        let report = validate_wasm(
+            cache_ptr,
+            ByteSliceView::new(b"not wasm"),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_some());
+        let _ = error_msg.consume();
+        assert_eq!(report, AnalysisReport::default());
+
+        // Nothing was stored
+        let checksum: Vec<u8> = Checksum::generate(HACKATOM).into();
+        let mut error_msg = UnmanagedVector::default();
+        let wasm = load_wasm(
+            cache_ptr,
+            ByteSliceView::new(&checksum),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_some());
+        let _ = error_msg.consume();
+        let _ = wasm.consume();
+
+        release_cache(cache_ptr);
+    }
+
+    #[test]
+    fn get_and_set_capabilities_work() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+        let capabilities = b"staking,stargate,iterator";
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache(
+            ByteSliceView::new(dir.as_bytes()),
+            ByteSliceView::new(capabilities),
+            512,
+            32,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+
+        let mut error_msg = UnmanagedVector::default();
+        let capabilities = get_capabilities(cache_ptr, Some(&mut error_msg));
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        assert_eq!(
+            capabilities.consume().unwrap(),
+            b"iterator,staking,stargate"
+        );
+
+        let mut error_msg = UnmanagedVector::default();
+        let checksum = save_wasm(
+            cache_ptr,
+            ByteSliceView::new(IBC_REFLECT),
+            false,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        let checksum = checksum.consume().unwrap_or_default();
+
+        let mut error_msg = UnmanagedVector::default();
+        let unusable = set_capabilities(
+            cache_ptr,
+            ByteSliceView::new(b"staking,iterator"),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        assert_eq!(
+            unusable.consume().unwrap(),
+            hex::encode(checksum).into_bytes()
+        );
+
+        let mut error_msg = UnmanagedVector::default();
+        let capabilities = get_capabilities(cache_ptr, Some(&mut error_msg));
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        assert_eq!(capabilities.consume().unwrap(), b"iterator,staking");
+
+        let mut error_msg = UnmanagedVector::default();
+        let unusable = set_capabilities(cache_ptr, ByteSliceView::nil(), Some(&mut error_msg));
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(msg, "Null/Nil argument: available_capabilities");
+        assert_eq!(unusable.consume().unwrap(), b"");
+
+        release_cache(cache_ptr);
+    }
+
+    #[test]
+    fn set_query_snapshot_works() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache(
+            ByteSliceView::new(dir.as_bytes()),
+            ByteSliceView::new(b"staking"),
+            512,
+            32,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+
+        let snapshot = br#"[{"request":"e30=","response":{"ok":{"ok":"e30="}}}]"#;
+        let mut error_msg = UnmanagedVector::default();
+        set_query_snapshot(
+            cache_ptr,
+            42,
+            ByteSliceView::new(snapshot),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        let snapshot = to_cache(cache_ptr)
+            .unwrap()
+            .cache()
+            .query_router()
+            .snapshot();
+        assert!(snapshot.handle(b"{}", 42).is_some());
+        assert!(snapshot.handle(b"{}", 43).is_none());
+
+        let mut error_msg = UnmanagedVector::default();
+        set_query_snapshot(
+            cache_ptr,
+            42,
+            ByteSliceView::new(b"[{}]"),
+            Some(&mut error_msg),
+        );
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert!(msg.contains("Error parsing query snapshot"), "{}", msg);
+
+        let mut error_msg = UnmanagedVector::default();
+        set_query_snapshot(cache_ptr, 42, ByteSliceView::nil(), Some(&mut error_msg));
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(msg, "Null/Nil argument: snapshot");
+
+        release_cache(cache_ptr);
+    }
+
+    #[test]
+    fn update_query_routes_works() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr = init_cache(
+            ByteSliceView::new(dir.as_bytes()),
+            ByteSliceView::new(b"staking"),
+            512,
+            32,
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        let router = to_cache(cache_ptr).unwrap().cache().query_router();
+        assert!(router.is_empty());
+
+        let mut error_msg = UnmanagedVector::default();
+        update_query_routes(
+            cache_ptr,
+            10,
+            ByteSliceView::new(br#"{"custom":{"handler":"snapshot","gas_cost":1}}"#),
+            Some(&mut error_msg),
+        );
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+        assert!(!router.is_empty());
+
+        let mut error_msg = UnmanagedVector::default();
+        update_query_routes(
+            cache_ptr,
+            10,
+            ByteSliceView::new(br#"{"custom":{}}"#),
+            Some(&mut error_msg),
+        );
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert!(
+            msg.starts_with("Invalid cache config: missing field"),
+            "{}",
+            msg
+        );
+
+        let mut error_msg = UnmanagedVector::default();
+        update_query_routes(cache_ptr, 10, ByteSliceView::nil(), Some(&mut error_msg));
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(msg, "Null/Nil argument: routes");
+
+        release_cache(cache_ptr);
+    }
+
+    #[test]
+    fn get_feature_set_id_works() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+        let config = format!(
+            r#"{{"v1":{{"data_dir":{:?},"available_capabilities":["staking"],"memory_cache_size_mib":512,"instance_memory_limit_mib":32,"capability_activations":[{{"height":10,"capabilities":["staking"]}},{{"height":20,"host_features":["crypto_queries"]}}]}}}}"#,
+            dir
+        );
+        let mut error_msg = UnmanagedVector::default();
+        let cache_ptr =
+            init_cache_with_config(ByteSliceView::new(config.as_bytes()), Some(&mut error_msg));
+        assert!(error_msg.is_none());
+        let _ = error_msg.consume();
+
+        for (height, id) in [(9, 0), (10, 1), (19, 1), (20, 2)] {
+            let mut error_msg = UnmanagedVector::default();
+            assert_eq!(
+                get_feature_set_id(cache_ptr, height, Some(&mut error_msg)),
+                id
+            );
+            assert!(error_msg.is_none());
+            let _ = error_msg.consume();
+        }
+
+        let mut error_msg = UnmanagedVector::default();
+        get_feature_set_id(std::ptr::null_mut(), 10, Some(&mut error_msg));
+        let msg = String::from_utf8(error_msg.consume().unwrap()).unwrap();
+        assert_eq!(msg, "Null/Nil argument: cache");
 
         release_cache(cache_ptr);
     }
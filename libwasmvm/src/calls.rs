@@ -3,28 +3,41 @@
 use std::convert::TryInto;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 
+use cosmwasm_std::{Binary, HexBinary};
 use cosmwasm_vm::{
     call_execute_raw, call_ibc_channel_close_raw, call_ibc_channel_connect_raw,
     call_ibc_channel_open_raw, call_ibc_packet_ack_raw, call_ibc_packet_receive_raw,
     call_ibc_packet_timeout_raw, call_instantiate_raw, call_migrate_raw, call_query_raw,
-    call_reply_raw, call_sudo_raw, Backend, Cache, Checksum, Instance, InstanceOptions, VmResult,
+    call_reply_raw, call_sudo_raw, Backend, BackendApi, Checksum, GasReport, Instance, Querier,
+    Storage, VmResult,
 };
 
+use crate::activation::ActivationContext;
 use crate::api::GoApi;
-use crate::args::{ARG1, ARG2, ARG3, CACHE_ARG, CHECKSUM_ARG, GAS_USED_ARG};
-use crate::cache::{cache_t, to_cache};
+use crate::args::{ARG1, ARG2, ARG3, CACHE_ARG, CHECKSUM_ARG, RECORDING_ARG, REPORT_ARG};
+use crate::cache::{cache_t, to_cache, GoVm};
 use crate::db::Db;
 use crate::error::{handle_c_error_binary, Error};
+use crate::fingerprint::FINGERPRINT_LENGTH;
+use crate::gas_config::{GasConfig, GasScope};
 use crate::memory::{ByteSliceView, UnmanagedVector};
-use crate::querier::GoQuerier;
-use crate::storage::GoStorage;
+use crate::querier::{CallScope, GoQuerier};
+use crate::replay::{recorded_result, replay, write_recording, RecordScope, Recording};
+use crate::vm::VmBackend;
+use crate::vm_cache::VmCache;
 
-fn into_backend(db: Db, api: GoApi, querier: GoQuerier) -> Backend<GoApi, GoStorage, GoQuerier> {
-    Backend {
-        api,
-        storage: GoStorage::new(db),
-        querier,
-    }
+/// The outputs of a call next to its result, written even if the call fails. It is zeroed
+/// before the call, so a call failing before the contract runs reports no gas.
+#[repr(C)]
+#[derive(Default)]
+pub struct CallReport {
+    /// The gas used by the call
+    pub gas_used: u64,
+    /// The EVM gas used by EVM queries of the call
+    pub evm_gas_used: u64,
+    /// The fingerprint of the call if the cache is configured with `fingerprints`, see
+    /// [`crate::fingerprint`]. Must be destroyed by the caller.
+    pub fingerprint: UnmanagedVector,
 }
 
 #[no_mangle]
@@ -39,11 +52,11 @@ pub extern "C" fn instantiate(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_3_args(
-        call_instantiate_raw,
+        EntryPoint::Instantiate,
         cache,
         checksum,
         env,
@@ -54,7 +67,7 @@ pub extern "C" fn instantiate(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -71,11 +84,11 @@ pub extern "C" fn execute(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_3_args(
-        call_execute_raw,
+        EntryPoint::Execute,
         cache,
         checksum,
         env,
@@ -86,7 +99,7 @@ pub extern "C" fn execute(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -102,11 +115,11 @@ pub extern "C" fn migrate(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_migrate_raw,
+        EntryPoint::Migrate,
         cache,
         checksum,
         env,
@@ -116,7 +129,7 @@ pub extern "C" fn migrate(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -132,11 +145,11 @@ pub extern "C" fn sudo(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_sudo_raw,
+        EntryPoint::Sudo,
         cache,
         checksum,
         env,
@@ -146,7 +159,7 @@ pub extern "C" fn sudo(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -162,11 +175,11 @@ pub extern "C" fn reply(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_reply_raw,
+        EntryPoint::Reply,
         cache,
         checksum,
         env,
@@ -176,7 +189,7 @@ pub extern "C" fn reply(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -192,11 +205,11 @@ pub extern "C" fn query(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_query_raw,
+        EntryPoint::Query,
         cache,
         checksum,
         env,
@@ -206,7 +219,7 @@ pub extern "C" fn query(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -222,11 +235,11 @@ pub extern "C" fn ibc_channel_open(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_channel_open_raw,
+        EntryPoint::IbcChannelOpen,
         cache,
         checksum,
         env,
@@ -236,7 +249,7 @@ pub extern "C" fn ibc_channel_open(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -252,11 +265,11 @@ pub extern "C" fn ibc_channel_connect(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_channel_connect_raw,
+        EntryPoint::IbcChannelConnect,
         cache,
         checksum,
         env,
@@ -266,7 +279,7 @@ pub extern "C" fn ibc_channel_connect(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -282,11 +295,11 @@ pub extern "C" fn ibc_channel_close(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_channel_close_raw,
+        EntryPoint::IbcChannelClose,
         cache,
         checksum,
         env,
@@ -296,7 +309,7 @@ pub extern "C" fn ibc_channel_close(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -312,11 +325,11 @@ pub extern "C" fn ibc_packet_receive(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_packet_receive_raw,
+        EntryPoint::IbcPacketReceive,
         cache,
         checksum,
         env,
@@ -326,7 +339,7 @@ pub extern "C" fn ibc_packet_receive(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -342,11 +355,11 @@ pub extern "C" fn ibc_packet_ack(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_packet_ack_raw,
+        EntryPoint::IbcPacketAck,
         cache,
         checksum,
         env,
@@ -356,7 +369,7 @@ pub extern "C" fn ibc_packet_ack(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
@@ -372,11 +385,11 @@ pub extern "C" fn ibc_packet_timeout(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     call_2_args(
-        call_ibc_packet_timeout_raw,
+        EntryPoint::IbcPacketTimeout,
         cache,
         checksum,
         env,
@@ -386,22 +399,43 @@ pub extern "C" fn ibc_packet_timeout(
         querier,
         gas_limit,
         print_debug,
-        gas_used,
+        report,
         error_msg,
     )
 }
 
-type VmFn2Args = fn(
-    instance: &mut Instance<GoApi, GoStorage, GoQuerier>,
-    arg1: &[u8],
-    arg2: &[u8],
-) -> VmResult<Vec<u8>>;
+/// Checks the capabilities required by the contract against the ones active at the
+/// block height of the call's `env`. The env is only parsed if activations are scheduled.
+fn check_activation<A, S, Q>(
+    cache: &VmCache<A, S, Q>,
+    checksum: &Checksum,
+    env: &[u8],
+) -> Result<(), Error>
+where
+    A: BackendApi + 'static,
+    S: Storage + 'static,
+    Q: Querier + 'static,
+{
+    if cache.activations().is_empty() {
+        return Ok(());
+    }
+    let context = ActivationContext::from_env(env)?;
+    cache.check_activation(checksum, context)
+}
+
+/// Zeroes the report of a call before anything else happens, such that a call failing before
+/// the contract runs reports no gas and no fingerprint, no matter how the caller initialized it
+fn zeroed_report(report: Option<&mut CallReport>) -> Result<&mut CallReport, Error> {
+    let report = report.ok_or_else(|| Error::empty_arg(REPORT_ARG))?;
+    *report = CallReport::default();
+    Ok(report)
+}
 
 // this wraps all error handling and ffi for the 6 ibc entry points and query.
 // (all of which take env and one "msg" argument).
 // the only difference is which low-level function they dispatch to.
 fn call_2_args(
-    vm_fn: VmFn2Args,
+    entry_point: EntryPoint,
     cache: *mut cache_t,
     checksum: ByteSliceView,
     arg1: ByteSliceView,
@@ -411,13 +445,13 @@ fn call_2_args(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
             do_call_2_args(
-                vm_fn,
+                entry_point,
                 c,
                 checksum,
                 arg1,
@@ -427,7 +461,7 @@ fn call_2_args(
                 querier,
                 gas_limit,
                 print_debug,
-                gas_used,
+                report,
             )
         }))
         .unwrap_or_else(|err| {
@@ -442,8 +476,8 @@ fn call_2_args(
 
 // this is internal processing, same for all the 6 ibc entry points
 fn do_call_2_args(
-    vm_fn: VmFn2Args,
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
+    entry_point: EntryPoint,
+    cache: &mut GoVm,
     checksum: ByteSliceView,
     arg1: ByteSliceView,
     arg2: ByteSliceView,
@@ -452,9 +486,9 @@ fn do_call_2_args(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
 ) -> Result<Vec<u8>, Error> {
-    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let report = zeroed_report(report)?;
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
@@ -462,31 +496,30 @@ fn do_call_2_args(
     let arg1 = arg1.read().ok_or_else(|| Error::unset_arg(ARG1))?;
     let arg2 = arg2.read().ok_or_else(|| Error::unset_arg(ARG2))?;
 
-    let backend = into_backend(db, api, querier);
-    let options = InstanceOptions {
+    let backend = VmBackend { api, db, querier };
+    let output = cache.call(
+        entry_point,
+        &checksum,
+        &[arg1, arg2],
+        backend,
         gas_limit,
         print_debug,
+        cache.cache().fingerprints(),
+    )?;
+    // We only check this result after reporting gas usage
+    *report = CallReport {
+        gas_used: output.gas_report.used_internally,
+        evm_gas_used: output.evm_gas_used,
+        fingerprint: UnmanagedVector::new(output.fingerprint.map(Vec::from)),
     };
-    let mut instance = cache.get_instance(&checksum, backend, options)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = vm_fn(&mut instance, arg1, arg2);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+    output.result
 }
 
-type VmFn3Args = fn(
-    instance: &mut Instance<GoApi, GoStorage, GoQuerier>,
-    arg1: &[u8],
-    arg2: &[u8],
-    arg3: &[u8],
-) -> VmResult<Vec<u8>>;
-
 // This wraps all error handling and ffi for instantiate, execute and migrate
 // (and anything else that takes env, info and msg arguments).
 // The only difference is which low-level function they dispatch to.
 fn call_3_args(
-    vm_fn: VmFn3Args,
+    entry_point: EntryPoint,
     cache: *mut cache_t,
     checksum: ByteSliceView,
     arg1: ByteSliceView,
@@ -497,13 +530,13 @@ fn call_3_args(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
     error_msg: Option<&mut UnmanagedVector>,
 ) -> UnmanagedVector {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
             do_call_3_args(
-                vm_fn,
+                entry_point,
                 c,
                 checksum,
                 arg1,
@@ -514,7 +547,7 @@ fn call_3_args(
                 querier,
                 gas_limit,
                 print_debug,
-                gas_used,
+                report,
             )
         }))
         .unwrap_or_else(|err| {
@@ -528,8 +561,8 @@ fn call_3_args(
 }
 
 fn do_call_3_args(
-    vm_fn: VmFn3Args,
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
+    entry_point: EntryPoint,
+    cache: &mut GoVm,
     checksum: ByteSliceView,
     arg1: ByteSliceView,
     arg2: ByteSliceView,
@@ -539,9 +572,9 @@ fn do_call_3_args(
     querier: GoQuerier,
     gas_limit: u64,
     print_debug: bool,
-    gas_used: Option<&mut u64>,
+    report: Option<&mut CallReport>,
 ) -> Result<Vec<u8>, Error> {
-    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let report = zeroed_report(report)?;
     let checksum: Checksum = checksum
         .read()
         .ok_or_else(|| Error::unset_arg(CHECKSUM_ARG))?
@@ -550,15 +583,821 @@ fn do_call_3_args(
     let arg2 = arg2.read().ok_or_else(|| Error::unset_arg(ARG2))?;
     let arg3 = arg3.read().ok_or_else(|| Error::unset_arg(ARG3))?;
 
-    let backend = into_backend(db, api, querier);
-    let options = InstanceOptions {
+    let backend = VmBackend { api, db, querier };
+    let output = cache.call(
+        entry_point,
+        &checksum,
+        &[arg1, arg2, arg3],
+        backend,
         gas_limit,
         print_debug,
+        cache.cache().fingerprints(),
+    )?;
+    // We only check this result after reporting gas usage
+    *report = CallReport {
+        gas_used: output.gas_report.used_internally,
+        evm_gas_used: output.evm_gas_used,
+        fingerprint: UnmanagedVector::new(output.fingerprint.map(Vec::from)),
+    };
+    output.result
+}
+
+/// Re-executes a call from a replay file written by a cache with `record_dir` set. The code
+/// is taken from this cache. Returns the result of the call and fails if the replay diverged
+/// from the recording. The report contains the fingerprint of the recorded call. EVM gas is not
+/// part of recordings, so it is reported as 0.
+#[no_mangle]
+pub extern "C" fn replay_call(
+    cache: *mut cache_t,
+    recording: ByteSliceView,
+    print_debug: bool,
+    report: Option<&mut CallReport>,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> UnmanagedVector {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_replay_call(c, recording, print_debug, report)
+        }))
+        .unwrap_or_else(|err| {
+            eprintln!("Panic in do_replay_call: {:?}", err);
+            Err(Error::panic())
+        }),
+        None => Err(Error::unset_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error_binary(r, error_msg);
+    UnmanagedVector::new(Some(data))
+}
+
+fn do_replay_call(
+    cache: &mut GoVm,
+    recording: ByteSliceView,
+    print_debug: bool,
+    report: Option<&mut CallReport>,
+) -> Result<Vec<u8>, Error> {
+    let report = zeroed_report(report)?;
+    let recording = recording
+        .read()
+        .ok_or_else(|| Error::unset_arg(RECORDING_ARG))?;
+    let recording: Recording = serde_json::from_slice(recording)
+        .map_err(|e| Error::replay_err(format!("Invalid recording: {}", e)))?;
+    let checksum: Checksum = recording.checksum.as_slice().try_into()?;
+    let wasm = cache.get_code(&checksum)?;
+
+    let output = replay(&recording, &wasm, print_debug)?;
+    *report = CallReport {
+        gas_used: output.gas_report.used_internally,
+        evm_gas_used: 0,
+        fingerprint: UnmanagedVector::new(Some(output.fingerprint.to_vec())),
     };
-    let mut instance = cache.get_instance(&checksum, backend, options)?;
-    // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = vm_fn(&mut instance, arg1, arg2, arg3);
-    *gas_used = instance.create_gas_report().used_internally;
-    instance.recycle();
-    Ok(res?)
+    if !output.matches(&recording) {
+        return Err(Error::replay_err(format!(
+            "Replayed result {:?} with {} gas differs from the recorded result {:?} with {} gas",
+            recorded_result(&output.result),
+            output.gas_report.used_internally,
+            recording.result,
+            recording.gas_used
+        )));
+    }
+    output.result
+}
+
+/// The outcome of a call into a contract
+pub struct CallOutput<A: BackendApi, S: Storage, Q: Querier> {
+    /// The result of the entry point. Gas is reported even if this is an error.
+    pub result: Result<Vec<u8>, Error>,
+    pub gas_report: GasReport,
+    /// The gas config the call was metered with
+    pub gas_config: GasConfig,
+    /// The EVM gas used by EVM queries of the call
+    pub evm_gas_used: u64,
+    /// The fingerprint of the call if requested, see [`crate::fingerprint`]
+    pub fingerprint: Option<[u8; FINGERPRINT_LENGTH]>,
+    /// The backend, returned by the instance after the call
+    pub backend: Option<Backend<A, S, Q>>,
+}
+
+/// Runs `vm_fn` in an instance of the contract with the given backend. This is the common
+/// part of all calls, no matter if the backend is implemented in Go or in Rust.
+///
+/// Errors preventing the call, like unknown code or inactive capabilities, are returned directly.
+/// Exceeding the instance memory limit of the checksum or a query limit is an error result
+/// of the call.
+pub fn call_with_backend<A, S, Q>(
+    cache: &VmCache<A, S, Q>,
+    checksum: &Checksum,
+    env: &[u8],
+    backend: Backend<A, S, Q>,
+    gas_limit: u64,
+    print_debug: bool,
+    vm_fn: impl FnOnce(&mut Instance<A, S, Q>) -> VmResult<Vec<u8>>,
+) -> Result<CallOutput<A, S, Q>, Error>
+where
+    A: BackendApi + 'static,
+    S: Storage + 'static,
+    Q: Querier + 'static,
+{
+    let scope = CallScope::enter(cache.query_options(env)?);
+
+    check_activation(cache, checksum, env)?;
+
+    let gas_config = cache.gas_config(env)?;
+    let _gas_scope = GasScope::enter(gas_config);
+    let options = gas_config.instance_options(gas_limit, print_debug);
+    let mut instance = cache.get_instance(checksum, backend, options)?;
+    let result = vm_fn(&mut instance).map_err(|err| match scope.exceeded_limit() {
+        Some((limit, max)) => Error::query_limit_exceeded(limit, max),
+        None => Error::from(err),
+    });
+    let gas_report = gas_config.gas_report(instance.create_gas_report(), gas_limit);
+    Ok(CallOutput {
+        result,
+        gas_report,
+        gas_config,
+        evm_gas_used: scope.evm_gas_used(),
+        fingerprint: None,
+        backend: instance.recycle(),
+    })
+}
+
+/// Calls an entry point of the contract with the raw arguments, starting with the env.
+/// See [`call_with_backend`].
+pub fn call_entry_point<A, S, Q>(
+    cache: &VmCache<A, S, Q>,
+    entry_point: EntryPoint,
+    checksum: &Checksum,
+    args: &[&[u8]],
+    backend: Backend<A, S, Q>,
+    gas_limit: u64,
+    print_debug: bool,
+    fingerprint: bool,
+) -> Result<CallOutput<A, S, Q>, Error>
+where
+    A: BackendApi + 'static,
+    S: Storage + 'static,
+    Q: Querier + 'static,
+{
+    if args.len() != entry_point.arg_names().len() {
+        return Err(Error::vm_err(format!(
+            "{} takes {} arguments, got {}",
+            entry_point.name(),
+            entry_point.arg_names().len(),
+            args.len()
+        )));
+    }
+    let scope = RecordScope::enter(cache.record_dir().is_some(), fingerprint);
+    let mut output = call_with_backend(
+        cache,
+        checksum,
+        args[0],
+        backend,
+        gas_limit,
+        print_debug,
+        |instance| entry_point.call(instance, args),
+    )?;
+    let frame = scope.finish();
+    if let Some(fingerprint) = frame.fingerprint {
+        output.fingerprint = Some(fingerprint.finish(&output.result, &output.gas_report));
+    }
+    if let (Some(dir), Some(interactions)) = (cache.record_dir(), frame.interactions) {
+        let recording = Recording {
+            entry_point: entry_point.name().to_string(),
+            checksum: HexBinary::from(Vec::from(*checksum)),
+            args: args.iter().map(|arg| Binary::from(*arg)).collect(),
+            gas_limit,
+            instance_memory_limit: cache.instance_memory_limit(checksum).0 as u64,
+            gas_config: output.gas_config,
+            interactions,
+            result: recorded_result(&output.result),
+            gas_used: output.gas_report.used_internally,
+        };
+        write_recording(dir, &recording);
+    }
+    Ok(output)
+}
+
+type VmFn2Args<A, S, Q> =
+    fn(instance: &mut Instance<A, S, Q>, arg1: &[u8], arg2: &[u8]) -> VmResult<Vec<u8>>;
+
+/// The entry points of a contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPoint {
+    Instantiate,
+    Execute,
+    Migrate,
+    Sudo,
+    Reply,
+    Query,
+    IbcChannelOpen,
+    IbcChannelConnect,
+    IbcChannelClose,
+    IbcPacketReceive,
+    IbcPacketAck,
+    IbcPacketTimeout,
+}
+
+impl EntryPoint {
+    pub const ALL: [EntryPoint; 12] = [
+        EntryPoint::Instantiate,
+        EntryPoint::Execute,
+        EntryPoint::Migrate,
+        EntryPoint::Sudo,
+        EntryPoint::Reply,
+        EntryPoint::Query,
+        EntryPoint::IbcChannelOpen,
+        EntryPoint::IbcChannelConnect,
+        EntryPoint::IbcChannelClose,
+        EntryPoint::IbcPacketReceive,
+        EntryPoint::IbcPacketAck,
+        EntryPoint::IbcPacketTimeout,
+    ];
+
+    /// The name of the Wasm export
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryPoint::Instantiate => "instantiate",
+            EntryPoint::Execute => "execute",
+            EntryPoint::Migrate => "migrate",
+            EntryPoint::Sudo => "sudo",
+            EntryPoint::Reply => "reply",
+            EntryPoint::Query => "query",
+            EntryPoint::IbcChannelOpen => "ibc_channel_open",
+            EntryPoint::IbcChannelConnect => "ibc_channel_connect",
+            EntryPoint::IbcChannelClose => "ibc_channel_close",
+            EntryPoint::IbcPacketReceive => "ibc_packet_receive",
+            EntryPoint::IbcPacketAck => "ibc_packet_ack",
+            EntryPoint::IbcPacketTimeout => "ibc_packet_timeout",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|entry_point| entry_point.name() == name)
+    }
+
+    /// The names of the arguments, starting with the env
+    pub fn arg_names(self) -> &'static [&'static str] {
+        match self {
+            EntryPoint::Instantiate | EntryPoint::Execute => &["env", "info", "msg"],
+            EntryPoint::Reply => &["env", "reply"],
+            EntryPoint::IbcPacketReceive | EntryPoint::IbcPacketTimeout => &["env", "packet"],
+            EntryPoint::IbcPacketAck => &["env", "ack"],
+            _ => &["env", "msg"],
+        }
+    }
+
+    /// Calls the entry point with the raw arguments, whose number was checked before
+    fn call<A, S, Q>(self, instance: &mut Instance<A, S, Q>, args: &[&[u8]]) -> VmResult<Vec<u8>>
+    where
+        A: BackendApi + 'static,
+        S: Storage + 'static,
+        Q: Querier + 'static,
+    {
+        let vm_fn: VmFn2Args<A, S, Q> = match (self, args) {
+            (EntryPoint::Instantiate, [env, info, msg]) => {
+                return call_instantiate_raw(instance, env, info, msg)
+            }
+            (EntryPoint::Execute, [env, info, msg]) => {
+                return call_execute_raw(instance, env, info, msg)
+            }
+            (_, [_, _]) => match self {
+                EntryPoint::Migrate => call_migrate_raw,
+                EntryPoint::Sudo => call_sudo_raw,
+                EntryPoint::Reply => call_reply_raw,
+                EntryPoint::Query => call_query_raw,
+                EntryPoint::IbcChannelOpen => call_ibc_channel_open_raw,
+                EntryPoint::IbcChannelConnect => call_ibc_channel_connect_raw,
+                EntryPoint::IbcChannelClose => call_ibc_channel_close_raw,
+                EntryPoint::IbcPacketReceive => call_ibc_packet_receive_raw,
+                EntryPoint::IbcPacketAck => call_ibc_packet_ack_raw,
+                EntryPoint::IbcPacketTimeout => call_ibc_packet_timeout_raw,
+                EntryPoint::Instantiate | EntryPoint::Execute => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        vm_fn(instance, args[0], args[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{init_cache_with_config, release_cache, save_wasm};
+    use crate::error::GoError;
+    use crate::testing::{Callback, MockHost};
+    use cosmwasm_std::testing::{
+        mock_ibc_channel_close_init, mock_ibc_channel_connect_ack, mock_ibc_channel_open_init,
+        mock_ibc_packet_ack, mock_ibc_packet_recv, mock_ibc_packet_timeout,
+    };
+    use cosmwasm_std::{
+        coins, from_slice, to_vec, ContractResult, Empty, Ibc3ChannelOpenResponse,
+        IbcAcknowledgement, IbcOrder, Response,
+    };
+    use cosmwasm_vm::testing::{mock_env, mock_info};
+    use tempfile::TempDir;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+    static IBC_REFLECT: &[u8] = include_bytes!("../../testdata/ibc_reflect.wasm");
+    static QUEUE: &[u8] = include_bytes!("../../testdata/queue.wasm");
+
+    const GAS_LIMIT: u64 = 500_000_000_000;
+
+    type Export2 = extern "C" fn(
+        *mut cache_t,
+        ByteSliceView,
+        ByteSliceView,
+        ByteSliceView,
+        Db,
+        GoApi,
+        GoQuerier,
+        u64,
+        bool,
+        Option<&mut CallReport>,
+        Option<&mut UnmanagedVector>,
+    ) -> UnmanagedVector;
+
+    type Export3 = extern "C" fn(
+        *mut cache_t,
+        ByteSliceView,
+        ByteSliceView,
+        ByteSliceView,
+        ByteSliceView,
+        Db,
+        GoApi,
+        GoQuerier,
+        u64,
+        bool,
+        Option<&mut CallReport>,
+        Option<&mut UnmanagedVector>,
+    ) -> UnmanagedVector;
+
+    /// A cache created via the FFI, released on drop
+    struct TestCache {
+        ptr: *mut cache_t,
+        _dir: TempDir,
+    }
+
+    impl TestCache {
+        fn new() -> Self {
+            Self::with_options("")
+        }
+
+        /// Creates a cache with additional JSON fields in the config, starting with a comma
+        fn with_options(options: &str) -> Self {
+            let dir = TempDir::new().unwrap();
+            let config = format!(
+                r#"{{"v1":{{"data_dir":{:?},"available_capabilities":["iterator","staking","stargate"],"memory_cache_size_mib":100,"instance_memory_limit_mib":32{}}}}}"#,
+                dir.path().to_str().unwrap(),
+                options
+            );
+            let mut error_msg = UnmanagedVector::default();
+            let ptr =
+                init_cache_with_config(ByteSliceView::new(config.as_bytes()), Some(&mut error_msg));
+            assert!(error_msg.consume().is_none());
+            TestCache { ptr, _dir: dir }
+        }
+
+        fn save(&self, wasm: &[u8]) -> Vec<u8> {
+            let mut error_msg = UnmanagedVector::default();
+            let checksum = save_wasm(
+                self.ptr,
+                ByteSliceView::new(wasm),
+                false,
+                Some(&mut error_msg),
+            );
+            assert!(error_msg.consume().is_none());
+            checksum.consume().unwrap()
+        }
+
+        fn call2(
+            &self,
+            export: Export2,
+            checksum: &[u8],
+            host: &MockHost,
+            arg1: &[u8],
+            arg2: &[u8],
+        ) -> (Result<Vec<u8>, String>, u64) {
+            let mut report = CallReport::default();
+            let mut error_msg = UnmanagedVector::default();
+            let data = export(
+                self.ptr,
+                ByteSliceView::new(checksum),
+                ByteSliceView::new(arg1),
+                ByteSliceView::new(arg2),
+                host.db(),
+                host.api(),
+                host.querier(),
+                GAS_LIMIT,
+                false,
+                Some(&mut report),
+                Some(&mut error_msg),
+            );
+            let _ = report.fingerprint.consume();
+            (into_result(data, error_msg), report.gas_used)
+        }
+
+        fn call3(
+            &self,
+            export: Export3,
+            checksum: &[u8],
+            host: &MockHost,
+            arg1: &[u8],
+            arg2: &[u8],
+            arg3: &[u8],
+        ) -> (Result<Vec<u8>, String>, u64) {
+            let mut report = CallReport::default();
+            let mut error_msg = UnmanagedVector::default();
+            let data = export(
+                self.ptr,
+                ByteSliceView::new(checksum),
+                ByteSliceView::new(arg1),
+                ByteSliceView::new(arg2),
+                ByteSliceView::new(arg3),
+                host.db(),
+                host.api(),
+                host.querier(),
+                GAS_LIMIT,
+                false,
+                Some(&mut report),
+                Some(&mut error_msg),
+            );
+            let _ = report.fingerprint.consume();
+            (into_result(data, error_msg), report.gas_used)
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            release_cache(self.ptr);
+        }
+    }
+
+    fn into_result(data: UnmanagedVector, error_msg: UnmanagedVector) -> Result<Vec<u8>, String> {
+        let data = data.consume();
+        match error_msg.consume() {
+            Some(msg) => Err(String::from_utf8(msg).unwrap()),
+            None => Ok(data.unwrap_or_default()),
+        }
+    }
+
+    fn contract_result(data: &[u8]) -> ContractResult<Response<Empty>> {
+        from_slice(data).unwrap()
+    }
+
+    fn instantiate_hackatom(cache: &TestCache, host: &MockHost) -> Vec<u8> {
+        let checksum = cache.save(HACKATOM);
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let (result, gas_used) = cache.call3(instantiate, &checksum, host, &env, &info, msg);
+        assert!(contract_result(&result.unwrap()).is_ok());
+        assert!(gas_used > 0);
+        checksum
+    }
+
+    #[test]
+    fn instantiate_execute_and_query_work() {
+        let cache = TestCache::new();
+        let host = MockHost::new();
+        host.set_gas_per_callback(1000);
+        let checksum = instantiate_hackatom(&cache, &host);
+        assert!(host.get(b"config").is_some());
+        assert!(host.calls(Callback::CanonicalizeAddress) > 0);
+
+        let env = mock_env();
+        host.set_balance(env.contract.address.as_str(), coins(1000, "earth"));
+        let env = to_vec(&env).unwrap();
+        let info = to_vec(&mock_info("verifies", &[])).unwrap();
+        let (result, _) = cache.call3(execute, &checksum, &host, &env, &info, br#"{"release":{}}"#);
+        let response = contract_result(&result.unwrap()).unwrap();
+        assert_eq!(response.messages.len(), 1);
+        assert_eq!(host.calls(Callback::QueryExternal), 1);
+
+        let (result, _) = cache.call2(query, &checksum, &host, &env, br#"{"verifier":{}}"#);
+        let result: ContractResult<Binary> = from_slice(&result.unwrap()).unwrap();
+        assert_eq!(result.unwrap().as_slice(), br#"{"verifier":"verifies"}"#);
+    }
+
+    #[test]
+    fn iterators_work() {
+        let cache = TestCache::new();
+        let host = MockHost::new();
+        let checksum = cache.save(QUEUE);
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let (result, _) = cache.call3(instantiate, &checksum, &host, &env, &info, b"{}");
+        assert!(contract_result(&result.unwrap()).is_ok());
+        for value in [5, 7, 11] {
+            let msg = format!(r#"{{"enqueue":{{"value":{}}}}}"#, value);
+            let (result, _) = cache.call3(execute, &checksum, &host, &env, &info, msg.as_bytes());
+            assert!(contract_result(&result.unwrap()).is_ok());
+        }
+
+        let scans = host.calls(Callback::ScanDb);
+        let nexts = host.calls(Callback::NextDb);
+        let (result, _) = cache.call2(query, &checksum, &host, &env, br#"{"sum":{}}"#);
+        let result: ContractResult<Binary> = from_slice(&result.unwrap()).unwrap();
+        assert_eq!(result.unwrap().as_slice(), br#"{"sum":23}"#);
+        assert_eq!(host.calls(Callback::ScanDb), scans + 1);
+        // one call more than there are records to find the end
+        assert_eq!(host.calls(Callback::NextDb), nexts + 4);
+    }
+
+    #[test]
+    fn calls_report_fingerprints_if_configured() {
+        for fingerprints in [false, true] {
+            let cache = TestCache::with_options(&format!(r#","fingerprints":{}"#, fingerprints));
+            let host = MockHost::new();
+            let checksum = instantiate_hackatom(&cache, &host);
+            let env = to_vec(&mock_env()).unwrap();
+
+            let mut report = CallReport::default();
+            let mut error_msg = UnmanagedVector::default();
+            let data = query(
+                cache.ptr,
+                ByteSliceView::new(&checksum),
+                ByteSliceView::new(&env),
+                ByteSliceView::new(br#"{"verifier":{}}"#),
+                host.db(),
+                host.api(),
+                host.querier(),
+                GAS_LIMIT,
+                false,
+                Some(&mut report),
+                Some(&mut error_msg),
+            );
+            assert!(into_result(data, error_msg).is_ok());
+            assert!(report.gas_used > 0);
+            assert_eq!(report.evm_gas_used, 0);
+            let fingerprint = report.fingerprint.consume();
+            assert_eq!(
+                fingerprint.map(|f| f.len()),
+                fingerprints.then_some(FINGERPRINT_LENGTH)
+            );
+        }
+    }
+
+    #[test]
+    fn replays_report_the_fingerprint_of_the_call() {
+        let record_dir = TempDir::new().unwrap();
+        let cache = TestCache::with_options(&format!(
+            r#","fingerprints":true,"record_dir":"{}""#,
+            record_dir.path().display()
+        ));
+        let host = MockHost::new();
+        let checksum = instantiate_hackatom(&cache, &host);
+        let env = to_vec(&mock_env()).unwrap();
+
+        let mut report = CallReport::default();
+        let mut error_msg = UnmanagedVector::default();
+        let data = query(
+            cache.ptr,
+            ByteSliceView::new(&checksum),
+            ByteSliceView::new(&env),
+            ByteSliceView::new(br#"{"verifier":{}}"#),
+            host.db(),
+            host.api(),
+            host.querier(),
+            GAS_LIMIT,
+            false,
+            Some(&mut report),
+            Some(&mut error_msg),
+        );
+        let result = into_result(data, error_msg).unwrap();
+        let fingerprint = report.fingerprint.consume().unwrap();
+
+        let recording = std::fs::read_dir(record_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.to_str().unwrap().ends_with("-query.json"))
+            .unwrap();
+        let recording = std::fs::read(recording).unwrap();
+        let mut replayed = CallReport::default();
+        let mut error_msg = UnmanagedVector::default();
+        let data = replay_call(
+            cache.ptr,
+            ByteSliceView::new(&recording),
+            false,
+            Some(&mut replayed),
+            Some(&mut error_msg),
+        );
+        assert_eq!(into_result(data, error_msg).unwrap(), result);
+        assert_eq!(replayed.gas_used, report.gas_used);
+        assert_eq!(replayed.fingerprint.consume().unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn scripted_failures_are_handled() {
+        let cache = TestCache::new();
+        let host = MockHost::new();
+        let checksum = instantiate_hackatom(&cache, &host);
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("verifies", &[])).unwrap();
+
+        // out of gas aborts the call
+        host.fail_next(Callback::ReadDb, GoError::OutOfGas, None);
+        let (result, _) = cache.call3(execute, &checksum, &host, &env, &info, br#"{"release":{}}"#);
+        assert!(result.unwrap_err().contains("Ran out of gas"));
+
+        // storage errors abort the call
+        host.fail_next(Callback::ReadDb, GoError::User, Some("db is broken"));
+        let (result, _) = cache.call2(query, &checksum, &host, &env, br#"{"verifier":{}}"#);
+        assert!(result.unwrap_err().contains("db is broken"));
+
+        // a panic in Go aborts the call
+        host.fail_next(Callback::QueryExternal, GoError::Panic, None);
+        let (result, _) = cache.call3(execute, &checksum, &host, &env, &info, br#"{"release":{}}"#);
+        let err = result.unwrap_err();
+        assert!(err.contains("Panic in FFI call"), "{}", err);
+
+        // user errors of the API are passed to the contract
+        host.fail_next(
+            Callback::CanonicalizeAddress,
+            GoError::User,
+            Some("invalid address"),
+        );
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let (result, _) = cache.call3(instantiate, &checksum, &host, &env, &info, msg);
+        let err = contract_result(&result.unwrap()).unwrap_err();
+        assert!(err.contains("invalid address"), "{}", err);
+
+        // the next call works again
+        let (result, _) = cache.call2(query, &checksum, &host, &env, br#"{"verifier":{}}"#);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn calls_below_activation_height_fail() {
+        let mut env = mock_env();
+        let cache = TestCache::with_options(&format!(
+            r#","capability_activations":[{{"height":{},"capabilities":["stargate"]}}]"#,
+            env.block.height + 1
+        ));
+        let host = MockHost::new();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"reflect_code_id":1}"#;
+        // storing is not gated
+        let checksum = cache.save(IBC_REFLECT);
+
+        let (result, gas_used) = cache.call3(
+            instantiate,
+            &checksum,
+            &host,
+            &to_vec(&env).unwrap(),
+            &info,
+            msg,
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            format!(
+                "Wasm contract requires capabilities that are not active at height {}: stargate",
+                env.block.height
+            )
+        );
+        assert_eq!(gas_used, 0);
+
+        env.block.height += 1;
+        let (result, _) = cache.call3(
+            instantiate,
+            &checksum,
+            &host,
+            &to_vec(&env).unwrap(),
+            &info,
+            msg,
+        );
+        assert!(contract_result(&result.unwrap()).is_ok());
+
+        // contracts not requiring gated capabilities can be called at any height
+        instantiate_hackatom(&cache, &MockHost::new());
+    }
+
+    #[test]
+    fn early_errors_report_zeroed_reports() {
+        let cache = TestCache::new();
+        let host = MockHost::new();
+        let env = to_vec(&mock_env()).unwrap();
+        // the caller's values must not survive
+        let mut report = CallReport {
+            gas_used: 123,
+            evm_gas_used: 456,
+            fingerprint: UnmanagedVector::default(),
+        };
+        let mut error_msg = UnmanagedVector::default();
+        let data = query(
+            cache.ptr,
+            ByteSliceView::new(&[7; 32]),
+            ByteSliceView::new(&env),
+            ByteSliceView::new(br#"{"verifier":{}}"#),
+            host.db(),
+            host.api(),
+            host.querier(),
+            GAS_LIMIT,
+            false,
+            Some(&mut report),
+            Some(&mut error_msg),
+        );
+        assert!(into_result(data, error_msg).is_err());
+        assert_eq!((report.gas_used, report.evm_gas_used), (0, 0));
+        assert!(report.fingerprint.is_none());
+    }
+
+    #[test]
+    fn ibc_flow_works() {
+        let cache = TestCache::new();
+        let host = MockHost::new();
+        let checksum = cache.save(IBC_REFLECT);
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let (result, _) = cache.call3(
+            instantiate,
+            &checksum,
+            &host,
+            &env,
+            &info,
+            br#"{"reflect_code_id":1}"#,
+        );
+        assert!(contract_result(&result.unwrap()).is_ok());
+
+        let version = "ibc-reflect-v1";
+        let msg = mock_ibc_channel_open_init("channel-0", IbcOrder::Ordered, version);
+        let (result, _) = cache.call2(
+            ibc_channel_open,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        let result: ContractResult<Option<Ibc3ChannelOpenResponse>> =
+            from_slice(&result.unwrap()).unwrap();
+        assert!(result.is_ok());
+
+        // unordered channels are rejected by the contract
+        let msg = mock_ibc_channel_open_init("channel-0", IbcOrder::Unordered, version);
+        let (result, _) = cache.call2(
+            ibc_channel_open,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        let result: ContractResult<Option<Ibc3ChannelOpenResponse>> =
+            from_slice(&result.unwrap()).unwrap();
+        assert!(result.is_err());
+
+        let msg = mock_ibc_channel_connect_ack("channel-0", IbcOrder::Ordered, version);
+        let (result, _) = cache.call2(
+            ibc_channel_connect,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        let response = contract_result(&result.unwrap()).unwrap();
+        // instantiates the reflect contract
+        assert_eq!(response.messages.len(), 1);
+
+        let packet = br#"{"dispatch":{"msgs":[]}}"#;
+        let msg = mock_ibc_packet_recv("channel-0", &Binary::from(packet)).unwrap();
+        let (result, _) = cache.call2(
+            ibc_packet_receive,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        result.unwrap();
+
+        let ack = IbcAcknowledgement::new(br#"{"ok":""}"#);
+        let msg = mock_ibc_packet_ack("channel-0", &Binary::from(packet), ack).unwrap();
+        let (result, _) = cache.call2(
+            ibc_packet_ack,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        result.unwrap();
+
+        let msg = mock_ibc_packet_timeout("channel-0", &Binary::from(packet)).unwrap();
+        let (result, _) = cache.call2(
+            ibc_packet_timeout,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        result.unwrap();
+
+        let msg = mock_ibc_channel_close_init("channel-0", IbcOrder::Ordered, version);
+        let (result, _) = cache.call2(
+            ibc_channel_close,
+            &checksum,
+            &host,
+            &env,
+            &to_vec(&msg).unwrap(),
+        );
+        result.unwrap();
+    }
 }
@@ -0,0 +1,574 @@
+//! The serialized configuration accepted by `init_cache_with_config`.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::path::PathBuf;
+
+use cosmwasm_vm::Size;
+use log::LevelFilter;
+use serde::Deserialize;
+
+use crate::activation::{ActivationSchedule, CapabilityActivation};
+use crate::address_codec::{AddressCodec, AddressCodecConfig};
+use crate::error::Error;
+use crate::gas_config::{GasConfig, GasConfigUpdate, GasSchedule};
+use crate::querier::QueryLimits;
+use crate::query_router::{QueryRoutes, QueryRoutesUpdate};
+use crate::upload_limits::UploadLimits;
+use crate::vm_cache::VmCacheOptions;
+
+/// A versioned cache configuration, sent from Go as JSON.
+///
+/// New options are added as optional fields to the latest version. A new version
+/// is only needed when existing fields change their meaning. This way the C
+/// signature of `init_cache_with_config` never has to change.
+///
+/// Example:
+///
+/// ```json
+/// {
+///   "v1": {
+///     "data_dir": "/home/user/.exchaind/data/wasm",
+///     "available_capabilities": ["iterator", "staking", "stargate"],
+///     "memory_cache_size_mib": 100,
+///     "instance_memory_limit_mib": 32,
+///     "max_instance_memory_limit_mib": 128,
+///     "pinned_cache_size_mib": 512,
+///     "compile_threads": 4,
+///     "fs_cache": true,
+///     "in_memory": false,
+///     "upload_limits": {
+///       "max_wasm_size": 819200,
+///       "max_imports": 50
+///     },
+///     "capability_activations": [
+///       { "height": 1200000, "capabilities": ["cosmwasm_1_3"] },
+///       { "height": 1300000, "host_features": ["evm_queries", "crypto_queries"] }
+///     ],
+///     "query_limits": {
+///       "max_depth": 10,
+///       "max_gas": 3000000000,
+///       "abort_on_exceeded": false
+///     },
+///     "memoize_queries": false,
+///     "strict_query_responses": false,
+///     "query_routes": {
+///       "bank.balance": { "handler": "snapshot", "gas_cost": 1000 }
+///     },
+///     "query_route_updates": [
+///       { "height": 1500000, "routes": { "custom": { "handler": "my_handler", "gas_cost": 500 } } }
+///     ],
+///     "address_codec": {
+///       "bech32_prefix": "ex",
+///       "address_lengths": [20, 32],
+///       "canonicalize_cost": 4,
+///       "humanize_cost": 5,
+///       "hex_addresses": true
+///     },
+///     "gas_config": {
+///       "wasm_op_cost": 150000,
+///       "storage_callback_overhead": 0,
+///       "canonicalize_address_cost": 0,
+///       "humanize_address_cost": 0,
+///       "query_overhead": 0,
+///       "keccak256_cost": 1000000000,
+///       "keccak256_per_byte_cost": 10000000,
+///       "secp256k1_recover_evm_address_cost": 163000000000,
+///       "ed25519_batch_verify_per_signature_cost": 31500000000
+///     },
+///     "gas_config_updates": [
+///       { "height": 1500000, "gas_config": { "wasm_op_cost": 200000, "query_overhead": 1000000 } }
+///     ],
+///     "record_dir": "/home/user/.exchaind/data/wasm-recordings",
+///     "fingerprints": true,
+///     "log_level": "info"
+///   }
+/// }
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum CacheConfig {
+    V1(CacheConfigV1),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct CacheConfigV1 {
+    /// The base directory of the cache. Wasm blobs and compiled modules are stored in here.
+    /// Required unless `in_memory` is set.
+    #[serde(default)]
+    pub data_dir: String,
+    pub available_capabilities: Vec<String>,
+    /// The size of the in-memory module cache in MiB
+    pub memory_cache_size_mib: u32,
+    /// The memory limit of a single contract instance in MiB
+    pub instance_memory_limit_mib: u32,
+    /// The highest memory limit in MiB that can be set for a checksum via
+    /// `set_instance_memory_limit`. Defaults to `instance_memory_limit_mib`.
+    #[serde(default)]
+    pub max_instance_memory_limit_mib: Option<u32>,
+    /// The maximum total size of pinned modules in MiB. Unlimited if unset.
+    #[serde(default)]
+    pub pinned_cache_size_mib: Option<u32>,
+    /// The number of threads used to compile Wasm. If unset, one thread per CPU is used.
+    #[serde(default)]
+    pub compile_threads: Option<u32>,
+    /// If false, compiled modules are never written to or read from disk
+    /// and only live in memory for the lifetime of the cache.
+    #[serde(default = "default_fs_cache")]
+    pub fs_cache: bool,
+    /// If true, Wasm blobs and compiled modules are kept in memory only and nothing is
+    /// read from or written to `data_dir`. Useful for tests and light nodes.
+    #[serde(default)]
+    pub in_memory: bool,
+    /// The maximum total size of Wasm blobs kept in memory in MiB. Only used if `in_memory` is set.
+    #[serde(default = "default_in_memory_wasm_store_size_mib")]
+    pub in_memory_wasm_store_size_mib: u32,
+    /// Limits enforced when storing Wasm via `save_wasm`. No additional limits by default.
+    #[serde(default)]
+    pub upload_limits: UploadLimits,
+    /// Capabilities and host features that are only active for calls from a block height on.
+    /// Every activation needs its own height. Scheduled capabilities must be listed in
+    /// `available_capabilities` as well.
+    #[serde(default)]
+    pub capability_activations: Vec<CapabilityActivation>,
+    /// Limits for nested queries during calls. Unlimited by default. Exceeding one fails the
+    /// query with a system error, or aborts the call with a query limit error if configured.
+    #[serde(default)]
+    pub query_limits: QueryLimits,
+    /// If true, Go responses to identical query requests are memoized within a call until the
+    /// contract writes to storage. Go charges a hit like the original query, so a hit cannot be
+    /// told apart from a miss. Queriers not implementing `GasConsumer` are never memoized.
+    #[serde(default)]
+    pub memoize_queries: bool,
+    /// If true, a query response from Go that cannot be parsed aborts the call as a host failure.
+    /// Otherwise it is passed to the contract as `SystemError::InvalidResponse`.
+    #[serde(default)]
+    pub strict_query_responses: bool,
+    /// Queries answered in Rust without calling into Go, by route like `bank.balance` or `custom`.
+    /// The handler is `snapshot` or the name of a native handler. Everything else goes to Go.
+    /// Answered queries are charged `gas_cost` by Go, so queriers must implement `GasConsumer`.
+    #[serde(default)]
+    pub query_routes: QueryRoutes,
+    /// Routing tables that replace `query_routes` for calls from a block height on.
+    /// More can be added at runtime via `update_query_routes`.
+    #[serde(default)]
+    pub query_route_updates: Vec<QueryRoutesUpdate>,
+    /// If set, bech32 addresses are validated and converted natively instead of by Go
+    #[serde(default)]
+    pub address_codec: Option<AddressCodecConfig>,
+    /// The gas costs of calls. Unset costs use the defaults.
+    #[serde(default)]
+    pub gas_config: GasConfig,
+    /// Gas configs that replace `gas_config` for calls from a block height on.
+    /// More can be added at runtime via `update_gas_config`.
+    #[serde(default)]
+    pub gas_config_updates: Vec<GasConfigUpdate>,
+    /// If set, every call is recorded into a replay file in this directory. Meant for debugging
+    /// since it writes a file per call.
+    #[serde(default)]
+    pub record_dir: Option<String>,
+    /// If set, calls report their fingerprint, a hash of everything that determined the result.
+    /// Comparing them between nodes finds the cause of diverging results.
+    #[serde(default)]
+    pub fingerprints: bool,
+    #[serde(default)]
+    pub log_level: LogLevel,
+}
+
+fn default_fs_cache() -> bool {
+    true
+}
+
+fn default_in_memory_wasm_store_size_mib() -> u32 {
+    256
+}
+
+/// The verbosity of log output written to stderr by libwasmvm
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    #[default]
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn from_json(data: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(data).map_err(Error::invalid_config)
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        match self {
+            CacheConfig::V1(config) => config.log_level,
+        }
+    }
+
+    pub fn into_options(self) -> Result<VmCacheOptions, Error> {
+        match self {
+            CacheConfig::V1(config) => {
+                if !config.in_memory && config.data_dir.is_empty() {
+                    return Err(Error::invalid_config(
+                        "data_dir must be set unless in_memory is enabled",
+                    ));
+                }
+                if config.compile_threads == Some(0) {
+                    return Err(Error::invalid_config(
+                        "compile_threads must be greater than zero",
+                    ));
+                }
+                let available_capabilities: HashSet<String> = config
+                    .available_capabilities
+                    .into_iter()
+                    .map(|capability| capability.trim().to_string())
+                    .filter(|capability| !capability.is_empty())
+                    .collect();
+                Ok(VmCacheOptions {
+                    base_dir: config.data_dir.into(),
+                    available_capabilities,
+                    memory_cache_size: mebi(config.memory_cache_size_mib),
+                    instance_memory_limit: mebi(config.instance_memory_limit_mib),
+                    max_instance_memory_limit: config.max_instance_memory_limit_mib.map(mebi),
+                    pinned_cache_size: config.pinned_cache_size_mib.map(mebi),
+                    compile_threads: config.compile_threads.map(|threads| {
+                        threads
+                            .try_into()
+                            .expect("Cannot convert u32 to usize. What kind of system is this?")
+                    }),
+                    fs_cache: config.fs_cache,
+                    in_memory_wasm_store_size: if config.in_memory {
+                        Some(mebi(config.in_memory_wasm_store_size_mib))
+                    } else {
+                        None
+                    },
+                    upload_limits: config.upload_limits,
+                    activations: ActivationSchedule::new(config.capability_activations)?,
+                    query_limits: config.query_limits,
+                    memoize_queries: config.memoize_queries,
+                    strict_query_responses: config.strict_query_responses,
+                    query_routes: config.query_routes,
+                    query_route_updates: config.query_route_updates,
+                    address_codec: config
+                        .address_codec
+                        .as_ref()
+                        .map(AddressCodec::new)
+                        .transpose()?,
+                    gas_schedule: GasSchedule::new(config.gas_config, config.gas_config_updates)?,
+                    record_dir: config.record_dir.map(PathBuf::from),
+                    fingerprints: config.fingerprints,
+                })
+            }
+        }
+    }
+}
+
+fn mebi(value: u32) -> Size {
+    Size::mebi(
+        value
+            .try_into()
+            .expect("Cannot convert u32 to usize. What kind of system is this?"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::HostFeature;
+    use crate::query_router::QueryRoute;
+    use std::collections::BTreeMap;
+
+    /// A config with the defaults of all optional fields
+    fn config_with(data_dir: &str) -> CacheConfigV1 {
+        CacheConfigV1 {
+            data_dir: data_dir.to_string(),
+            available_capabilities: vec![],
+            memory_cache_size_mib: 100,
+            instance_memory_limit_mib: 32,
+            max_instance_memory_limit_mib: None,
+            pinned_cache_size_mib: None,
+            compile_threads: None,
+            fs_cache: true,
+            in_memory: false,
+            in_memory_wasm_store_size_mib: 256,
+            upload_limits: UploadLimits::default(),
+            capability_activations: vec![],
+            query_limits: QueryLimits::default(),
+            memoize_queries: false,
+            strict_query_responses: false,
+            query_routes: BTreeMap::new(),
+            query_route_updates: vec![],
+            address_codec: None,
+            gas_config: GasConfig::default(),
+            gas_config_updates: vec![],
+            record_dir: None,
+            fingerprints: false,
+            log_level: LogLevel::Off,
+        }
+    }
+
+    #[test]
+    fn from_json_works() {
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":["staking"],"memory_cache_size_mib":100,"instance_memory_limit_mib":32}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            CacheConfig::V1(CacheConfigV1 {
+                available_capabilities: vec!["staking".to_string()],
+                ..config_with("/tmp/wasm")
+            })
+        );
+
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":0,"instance_memory_limit_mib":32,"max_instance_memory_limit_mib":64,"pinned_cache_size_mib":512,"compile_threads":2,"fs_cache":false,"upload_limits":{"max_imports":50},"capability_activations":[{"height":10,"capabilities":["cosmwasm_1_3"],"host_features":["crypto_queries"]}],"query_limits":{"max_depth":5,"abort_on_exceeded":true},"memoize_queries":true,"strict_query_responses":true,"query_route_updates":[{"height":30,"routes":{"custom":{"handler":"constant","gas_cost":1}}}],"gas_config":{"query_overhead":7},"gas_config_updates":[{"height":20,"gas_config":{"wasm_op_cost":300000}}],"record_dir":"/tmp/recordings","fingerprints":true,"log_level":"debug"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            CacheConfig::V1(CacheConfigV1 {
+                memory_cache_size_mib: 0,
+                max_instance_memory_limit_mib: Some(64),
+                pinned_cache_size_mib: Some(512),
+                compile_threads: Some(2),
+                fs_cache: false,
+                upload_limits: UploadLimits {
+                    max_imports: Some(50),
+                    ..UploadLimits::default()
+                },
+                capability_activations: vec![CapabilityActivation {
+                    height: 10,
+                    capabilities: vec!["cosmwasm_1_3".to_string()],
+                    host_features: vec![HostFeature::CryptoQueries],
+                }],
+                query_limits: QueryLimits {
+                    max_depth: Some(5),
+                    max_gas: None,
+                    abort_on_exceeded: true,
+                },
+                memoize_queries: true,
+                strict_query_responses: true,
+                query_route_updates: vec![QueryRoutesUpdate {
+                    height: 30,
+                    routes: BTreeMap::from([(
+                        "custom".to_string(),
+                        QueryRoute {
+                            handler: "constant".to_string(),
+                            gas_cost: 1,
+                        },
+                    )]),
+                }],
+                gas_config: GasConfig {
+                    query_overhead: 7,
+                    ..GasConfig::default()
+                },
+                gas_config_updates: vec![GasConfigUpdate {
+                    height: 20,
+                    gas_config: GasConfig {
+                        wasm_op_cost: 300000,
+                        ..GasConfig::default()
+                    },
+                }],
+                record_dir: Some("/tmp/recordings".to_string()),
+                fingerprints: true,
+                log_level: LogLevel::Debug,
+                ..config_with("/tmp/wasm")
+            })
+        );
+    }
+
+    #[test]
+    fn from_json_fails_for_invalid_input() {
+        // missing version
+        let err = CacheConfig::from_json(
+            br#"{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+
+        // unknown version
+        let err = CacheConfig::from_json(
+            br#"{"v999":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+
+        // missing required field
+        let err = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","memory_cache_size_mib":100,"instance_memory_limit_mib":32}}"#,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("missing field `available_capabilities`"));
+
+        // unknown field
+        let err = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"foo":1}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown field `foo`"));
+
+        // not JSON
+        let err = CacheConfig::from_json(b"data_dir=/tmp/wasm").unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+    }
+
+    #[test]
+    fn into_options_works() {
+        let config = CacheConfig::V1(CacheConfigV1 {
+            available_capabilities: vec![
+                "staking".to_string(),
+                " iterator".to_string(),
+                "".to_string(),
+            ],
+            max_instance_memory_limit_mib: Some(64),
+            pinned_cache_size_mib: Some(10),
+            compile_threads: Some(3),
+            fs_cache: false,
+            log_level: LogLevel::Warn,
+            ..config_with("/tmp/wasm")
+        });
+        let options = config.into_options().unwrap();
+        assert_eq!(options.base_dir.to_str().unwrap(), "/tmp/wasm");
+        assert_eq!(
+            options.available_capabilities,
+            HashSet::from(["staking".to_string(), "iterator".to_string()])
+        );
+        assert_eq!(options.memory_cache_size.0, Size::mebi(100).0);
+        assert_eq!(options.instance_memory_limit.0, Size::mebi(32).0);
+        assert_eq!(
+            options.max_instance_memory_limit.map(|size| size.0),
+            Some(Size::mebi(64).0)
+        );
+        assert_eq!(
+            options.pinned_cache_size.map(|size| size.0),
+            Some(Size::mebi(10).0)
+        );
+        assert_eq!(options.compile_threads, Some(3));
+        assert!(!options.fs_cache);
+        assert!(options.in_memory_wasm_store_size.is_none());
+    }
+
+    #[test]
+    fn into_options_handles_in_memory() {
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"in_memory":true,"in_memory_wasm_store_size_mib":50}}"#,
+        )
+        .unwrap();
+        let options = config.into_options().unwrap();
+        assert_eq!(
+            options.in_memory_wasm_store_size.map(|size| size.0),
+            Some(Size::mebi(50).0)
+        );
+
+        // data_dir is required otherwise
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32}}"#,
+        )
+        .unwrap();
+        let err = config.into_options().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: data_dir must be set unless in_memory is enabled"
+        );
+    }
+
+    #[test]
+    fn into_options_handles_address_codec() {
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"address_codec":{"bech32_prefix":"ex","address_lengths":[20,32],"canonicalize_cost":4,"humanize_cost":5}}}"#,
+        )
+        .unwrap();
+        let codec = config.into_options().unwrap().address_codec.unwrap();
+        assert_eq!(codec.canonicalize_cost, 4);
+        assert_eq!(codec.humanize_cost, 5);
+        assert!(codec.humanize(&[7; 20]).unwrap().starts_with("ex1"));
+
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"address_codec":{"bech32_prefix":"EX","address_lengths":[20],"canonicalize_cost":4,"humanize_cost":5}}}"#,
+        )
+        .unwrap();
+        let err = config.into_options().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: bech32_prefix must consist of lowercase ASCII characters"
+        );
+    }
+
+    #[test]
+    fn into_options_rejects_zero_compile_threads() {
+        let config = CacheConfig::V1(CacheConfigV1 {
+            compile_threads: Some(0),
+            ..config_with("/tmp/wasm")
+        });
+        let err = config.into_options().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: compile_threads must be greater than zero"
+        );
+    }
+
+    #[test]
+    fn into_options_rejects_duplicate_activation_heights() {
+        let activation = |capability: &str| CapabilityActivation {
+            height: 10,
+            capabilities: vec![capability.to_string()],
+            host_features: vec![],
+        };
+        let config = CacheConfig::V1(CacheConfigV1 {
+            available_capabilities: vec!["cosmwasm_1_2".to_string(), "cosmwasm_1_3".to_string()],
+            capability_activations: vec![activation("cosmwasm_1_2"), activation("cosmwasm_1_3")],
+            ..config_with("/tmp/wasm")
+        });
+        let err = config.into_options().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: Multiple capability activations at height 10"
+        );
+    }
+
+    #[test]
+    fn into_options_handles_gas_config() {
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"gas_config":{"storage_callback_overhead":3},"gas_config_updates":[{"height":10,"gas_config":{"wasm_op_cost":1}}]}}"#,
+        )
+        .unwrap();
+        let schedule = config.into_options().unwrap().gas_schedule;
+        assert_eq!(schedule.at(9).storage_callback_overhead, 3);
+        assert_eq!(
+            schedule.at(10),
+            GasConfig {
+                wasm_op_cost: 1,
+                ..GasConfig::default()
+            }
+        );
+
+        let config = CacheConfig::from_json(
+            br#"{"v1":{"data_dir":"/tmp/wasm","available_capabilities":[],"memory_cache_size_mib":100,"instance_memory_limit_mib":32,"gas_config_updates":[{"height":10,"gas_config":{"wasm_op_cost":0}}]}}"#,
+        )
+        .unwrap();
+        let err = config.into_options().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: wasm_op_cost must be greater than zero"
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Crypto queries for EVM compatible contracts: keccak256, secp256k1 public key recovery to an
+//! EVM address and ed25519 batch verification.
+//!
+//! cosmwasm-vm fixes the imports of contracts, so these are raw queries like the ones of
+//! [`evm`](crate::evm), e.g. `{"crypto":{"keccak256":{"data":"<base64>"}}}`. They are answered
+//! natively without calling into Go and charged the costs of the call's [`GasConfig`], which
+//! only depend on the input sizes.
+
+use cosmwasm_std::{to_binary, Binary};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::address_codec::to_checksum_hex_address;
+use crate::gas_config::GasConfig;
+use crate::query_router::request_route;
+
+/// The outer key of crypto queries
+pub const CRYPTO_QUERY_KEY: &str = "crypto";
+
+/// The length of an EVM address in bytes
+const EVM_ADDRESS_LENGTH: usize = 20;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct CryptoQueryRequest {
+    crypto: CryptoQuery,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum CryptoQuery {
+    /// Returns a [`Keccak256Response`]
+    Keccak256 { data: Binary },
+    /// Recovers the signer of a 32 byte message hash from a 64 byte signature.
+    /// Returns a [`Secp256k1RecoverEvmAddressResponse`].
+    Secp256k1RecoverEvmAddress {
+        message_hash: Binary,
+        signature: Binary,
+        recovery_param: u8,
+    },
+    /// Returns an [`Ed25519BatchVerifyResponse`]
+    Ed25519BatchVerify {
+        messages: Vec<Binary>,
+        signatures: Vec<Binary>,
+        public_keys: Vec<Binary>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Keccak256Response {
+    pub hash: Binary,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Secp256k1RecoverEvmAddressResponse {
+    /// The EIP-55 checksummed address of the signer
+    pub address: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Ed25519BatchVerifyResponse {
+    pub valid: bool,
+}
+
+/// Parses a crypto query. Returns None if the request is not a crypto query
+/// and an error message if it is an invalid one.
+pub fn parse_crypto_query(request: &[u8]) -> Option<Result<CryptoQuery, String>> {
+    let (outer, _) = request_route(request)?;
+    if outer != CRYPTO_QUERY_KEY {
+        return None;
+    }
+    let parsed = serde_json::from_slice(request)
+        .map_err(|e| format!("Invalid crypto query: {}", e))
+        .map(|request: CryptoQueryRequest| request.crypto);
+    Some(parsed)
+}
+
+impl CryptoQuery {
+    /// The cost of the query in host gas
+    pub fn gas_cost(&self, config: &GasConfig) -> u64 {
+        match self {
+            CryptoQuery::Keccak256 { data } => config.keccak256_cost.saturating_add(
+                config
+                    .keccak256_per_byte_cost
+                    .saturating_mul(data.len() as u64),
+            ),
+            CryptoQuery::Secp256k1RecoverEvmAddress { .. } => {
+                config.secp256k1_recover_evm_address_cost
+            }
+            CryptoQuery::Ed25519BatchVerify { signatures, .. } => config
+                .ed25519_batch_verify_per_signature_cost
+                .saturating_mul(signatures.len() as u64),
+        }
+    }
+
+    /// Returns the JSON encoded response, or an error message if the inputs are invalid
+    pub fn execute(&self) -> Result<Binary, String> {
+        let response = match self {
+            CryptoQuery::Keccak256 { data } => to_binary(&Keccak256Response {
+                hash: keccak256(data).to_vec().into(),
+            }),
+            CryptoQuery::Secp256k1RecoverEvmAddress {
+                message_hash,
+                signature,
+                recovery_param,
+            } => {
+                let address =
+                    secp256k1_recover_evm_address(message_hash, signature, *recovery_param)?;
+                to_binary(&Secp256k1RecoverEvmAddressResponse {
+                    address: to_checksum_hex_address(&address),
+                })
+            }
+            CryptoQuery::Ed25519BatchVerify {
+                messages,
+                signatures,
+                public_keys,
+            } => {
+                let valid = ed25519_batch_verify(
+                    &slices(messages),
+                    &slices(signatures),
+                    &slices(public_keys),
+                )?;
+                to_binary(&Ed25519BatchVerifyResponse { valid })
+            }
+        };
+        response.map_err(|e| e.to_string())
+    }
+}
+
+fn slices(items: &[Binary]) -> Vec<&[u8]> {
+    items.iter().map(Binary::as_slice).collect()
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Recovers the uncompressed secp256k1 public key of a signature and returns its EVM address,
+/// i.e. the last 20 bytes of the keccak256 hash of the public key without the 0x04 prefix.
+pub fn secp256k1_recover_evm_address(
+    message_hash: &[u8],
+    signature: &[u8],
+    recovery_param: u8,
+) -> Result<[u8; EVM_ADDRESS_LENGTH], String> {
+    let public_key =
+        cosmwasm_crypto::secp256k1_recover_pubkey(message_hash, signature, recovery_param)
+            .map_err(|e| e.to_string())?;
+    let hash = keccak256(&public_key[1..]);
+    let mut address = [0_u8; EVM_ADDRESS_LENGTH];
+    address.copy_from_slice(&hash[32 - EVM_ADDRESS_LENGTH..]);
+    Ok(address)
+}
+
+pub fn ed25519_batch_verify(
+    messages: &[&[u8]],
+    signatures: &[&[u8]],
+    public_keys: &[&[u8]],
+) -> Result<bool, String> {
+    cosmwasm_crypto::ed25519_batch_verify(messages, signatures, public_keys)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::from_slice;
+
+    const MESSAGE_HASH: &str = "1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8";
+    // signed by the private key 1
+    const SIGNATURE: &str = "433ec3d37e4f1253df15e2dea412fed8e915737730f74b3dfb1353268f932ef5557c9158e0b34bce39de28d11797b42e9b1acb2749230885fe075aedc3e491a4";
+
+    // RFC 8032 test 1, the message is empty
+    const ED25519_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+    const ED25519_PUBLIC_KEY: &str =
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn binary(hex: &str) -> Binary {
+        hex::decode(hex).unwrap().into()
+    }
+
+    #[test]
+    fn parse_crypto_query_works() {
+        let request = br#"{"crypto":{"keccak256":{"data":"aGVsbG8="}}}"#;
+        assert_eq!(
+            parse_crypto_query(request).unwrap().unwrap(),
+            CryptoQuery::Keccak256 {
+                data: Binary::from(b"hello")
+            }
+        );
+
+        let request = br#"{"crypto":{"secp256k1_recover_evm_address":{"message_hash":"","signature":"","recovery_param":1}}}"#;
+        assert!(matches!(
+            parse_crypto_query(request).unwrap().unwrap(),
+            CryptoQuery::Secp256k1RecoverEvmAddress {
+                recovery_param: 1,
+                ..
+            }
+        ));
+
+        // not a crypto query
+        assert_eq!(parse_crypto_query(br#"{"bank":{"balance":{}}}"#), None);
+        assert_eq!(parse_crypto_query(b"foo"), None);
+
+        let err = parse_crypto_query(br#"{"crypto":{"sha256":{"data":""}}}"#)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.starts_with("Invalid crypto query: "), "{}", err);
+    }
+
+    #[test]
+    fn keccak256_works() {
+        assert_eq!(
+            hex::encode(keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        let query = CryptoQuery::Keccak256 {
+            data: Binary::from(b"hello"),
+        };
+        let response: Keccak256Response = from_slice(&query.execute().unwrap()).unwrap();
+        assert_eq!(response.hash, binary(MESSAGE_HASH));
+    }
+
+    #[test]
+    fn secp256k1_recover_evm_address_works() {
+        let query = |recovery_param| CryptoQuery::Secp256k1RecoverEvmAddress {
+            message_hash: binary(MESSAGE_HASH),
+            signature: binary(SIGNATURE),
+            recovery_param,
+        };
+        let response: Secp256k1RecoverEvmAddressResponse =
+            from_slice(&query(0).execute().unwrap()).unwrap();
+        assert_eq!(
+            response.address,
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+
+        // another signer
+        let response: Secp256k1RecoverEvmAddressResponse =
+            from_slice(&query(1).execute().unwrap()).unwrap();
+        assert_ne!(
+            response.address,
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+
+        query(4).execute().unwrap_err();
+    }
+
+    #[test]
+    fn ed25519_batch_verify_works() {
+        let query = |messages: &[&[u8]]| CryptoQuery::Ed25519BatchVerify {
+            messages: messages.iter().map(|m| Binary::from(*m)).collect(),
+            signatures: vec![binary(ED25519_SIGNATURE); 2],
+            public_keys: vec![binary(ED25519_PUBLIC_KEY); 2],
+        };
+        let response: Ed25519BatchVerifyResponse =
+            from_slice(&query(&[b"", b""]).execute().unwrap()).unwrap();
+        assert!(response.valid);
+
+        let response: Ed25519BatchVerifyResponse =
+            from_slice(&query(&[b"", b"foo"]).execute().unwrap()).unwrap();
+        assert!(!response.valid);
+
+        // mismatched lengths
+        query(&[b"", b"", b""]).execute().unwrap_err();
+    }
+
+    #[test]
+    fn gas_cost_works() {
+        let config = GasConfig {
+            keccak256_cost: 10,
+            keccak256_per_byte_cost: 2,
+            secp256k1_recover_evm_address_cost: 7,
+            ed25519_batch_verify_per_signature_cost: 3,
+            ..GasConfig::default()
+        };
+        let keccak256 = CryptoQuery::Keccak256 {
+            data: Binary::from(b"hello"),
+        };
+        assert_eq!(keccak256.gas_cost(&config), 20);
+        let recover = CryptoQuery::Secp256k1RecoverEvmAddress {
+            message_hash: Binary::default(),
+            signature: Binary::default(),
+            recovery_param: 0,
+        };
+        assert_eq!(recover.gas_cost(&config), 7);
+        let verify = CryptoQuery::Ed25519BatchVerify {
+            messages: vec![],
+            signatures: vec![Binary::default(); 4],
+            public_keys: vec![],
+        };
+        assert_eq!(verify.gas_cost(&config), 12);
+    }
+}
@@ -1,6 +1,11 @@
+use cosmwasm_std::Order;
+use cosmwasm_vm::{BackendResult, GasInfo};
+
+use crate::error::GoError;
 use crate::gas_meter::gas_meter_t;
 use crate::iterator::GoIter;
 use crate::memory::{U8SliceView, UnmanagedVector};
+use crate::vm::VmDb;
 
 // this represents something passed in from the caller side of FFI
 #[repr(C)]
@@ -56,3 +61,134 @@ pub struct Db {
     pub state: *mut db_t,
     pub vtable: Db_vtable,
 }
+
+impl VmDb for Db {
+    type Iterator = GoIter;
+
+    fn read(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+        let mut output = UnmanagedVector::default();
+        let mut error_msg = UnmanagedVector::default();
+        let mut used_gas = 0_u64;
+        let go_error: GoError = (self.vtable.read_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            U8SliceView::new(Some(key)),
+            &mut output as *mut UnmanagedVector,
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+        // We destruct the UnmanagedVector here, no matter if we need the data.
+        let output = output.consume();
+
+        let gas_info = GasInfo::with_externally_used(used_gas);
+
+        // return complete error message (reading from buffer for GoError::Other)
+        let default = || {
+            format!(
+                "Failed to read a key in the db: {}",
+                String::from_utf8_lossy(key)
+            )
+        };
+        unsafe {
+            if let Err(err) = go_error.into_result(error_msg, default) {
+                return (Err(err), gas_info);
+            }
+        }
+
+        (Ok(output), gas_info)
+    }
+
+    fn scan(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> BackendResult<GoIter> {
+        let mut error_msg = UnmanagedVector::default();
+        let mut iter = GoIter::new(self.gas_meter);
+        let mut used_gas = 0_u64;
+        let go_error: GoError = (self.vtable.scan_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            U8SliceView::new(start),
+            U8SliceView::new(end),
+            order.into(),
+            &mut iter as *mut GoIter,
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+        let gas_info = GasInfo::with_externally_used(used_gas);
+
+        // return complete error message (reading from buffer for GoError::Other)
+        let default = || {
+            format!(
+                "Failed to read the next key between {:?} and {:?}",
+                start.map(String::from_utf8_lossy),
+                end.map(String::from_utf8_lossy),
+            )
+        };
+        unsafe {
+            if let Err(err) = go_error.into_result(error_msg, default) {
+                return (Err(err), gas_info);
+            }
+        }
+
+        (Ok(iter), gas_info)
+    }
+
+    fn write(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+        let mut error_msg = UnmanagedVector::default();
+        let mut used_gas = 0_u64;
+        let go_error: GoError = (self.vtable.write_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            U8SliceView::new(Some(key)),
+            U8SliceView::new(Some(value)),
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+        let gas_info = GasInfo::with_externally_used(used_gas);
+        // return complete error message (reading from buffer for GoError::Other)
+        let default = || {
+            format!(
+                "Failed to set a key in the db: {}",
+                String::from_utf8_lossy(key),
+            )
+        };
+        unsafe {
+            if let Err(err) = go_error.into_result(error_msg, default) {
+                return (Err(err), gas_info);
+            }
+        }
+        (Ok(()), gas_info)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+        let mut error_msg = UnmanagedVector::default();
+        let mut used_gas = 0_u64;
+        let go_error: GoError = (self.vtable.remove_db)(
+            self.state,
+            self.gas_meter,
+            &mut used_gas as *mut u64,
+            U8SliceView::new(Some(key)),
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+        let gas_info = GasInfo::with_externally_used(used_gas);
+        let default = || {
+            format!(
+                "Failed to delete a key in the db: {}",
+                String::from_utf8_lossy(key),
+            )
+        };
+        unsafe {
+            if let Err(err) = go_error.into_result(error_msg, default) {
+                return (Err(err), gas_info);
+            }
+        }
+        (Ok(()), gas_info)
+    }
+}
@@ -5,9 +5,17 @@ use std::backtrace::Backtrace;
 use thiserror::Error;
 
 use crate::memory::UnmanagedVector;
+use crate::querier::QueryLimit;
+use crate::upload_limits::UploadLimit;
 
 #[derive(Error, Debug)]
 pub enum RustError {
+    #[error("Cache error: {}", msg)]
+    CacheErr {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
     #[error("Empty argument: {}", name)]
     EmptyArg {
         name: String,
@@ -21,22 +29,84 @@ pub enum RustError {
         #[cfg(feature = "backtraces")]
         backtrace: Backtrace,
     },
+    #[error(
+        "Wasm contract requires capabilities that are not active at height {}: {}",
+        height,
+        capabilities
+    )]
+    InactiveCapabilities {
+        /// A comma separated list of the inactive capabilities
+        capabilities: String,
+        height: u64,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
+    /// The memory of an instance cannot be created within the memory limit of its checksum
+    #[error(
+        "Instance memory limit of {} bytes exceeded by Wasm contract {}",
+        limit,
+        checksum
+    )]
+    InstanceMemoryLimitExceeded {
+        /// The hex encoded checksum of the contract
+        checksum: String,
+        limit: u64,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
+    #[error("Invalid cache config: {}", msg)]
+    InvalidConfig {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
     #[error("Ran out of gas")]
     OutOfGas {
         #[cfg(feature = "backtraces")]
         backtrace: Backtrace,
     },
+    /// A query limit of the cache was exceeded with `abort_on_exceeded` set, which aborts the
+    /// whole call tree
+    #[error("Query {} limit of {} exceeded", limit, max)]
+    QueryLimitExceeded {
+        limit: QueryLimit,
+        max: u64,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
     #[error("Caught panic")]
     Panic {
         #[cfg(feature = "backtraces")]
         backtrace: Backtrace,
     },
+    /// Failures of the connection to an out-of-process VM and errors reported by it
+    #[error("Remote VM error: {}", msg)]
+    RemoteErr {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
+    /// A replayed call diverged from its recording
+    #[error("Replay error: {}", msg)]
+    ReplayErr {
+        msg: String,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
     #[error("Null/Nil argument: {}", name)]
     UnsetArg {
         name: String,
         #[cfg(feature = "backtraces")]
         backtrace: Backtrace,
     },
+    #[error("Upload limit exceeded: {} is {}, the limit is {}", limit, actual, max)]
+    UploadLimitExceeded {
+        limit: UploadLimit,
+        actual: usize,
+        max: u64,
+        #[cfg(feature = "backtraces")]
+        backtrace: Backtrace,
+    },
     #[error("Error calling the VM: {}", msg)]
     VmErr {
         msg: String,
@@ -46,6 +116,14 @@ pub enum RustError {
 }
 
 impl RustError {
+    pub fn cache_err<S: ToString>(msg: S) -> Self {
+        RustError::CacheErr {
+            msg: msg.to_string(),
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
     pub fn empty_arg<T: Into<String>>(name: T) -> Self {
         RustError::EmptyArg {
             name: name.into(),
@@ -62,6 +140,32 @@ impl RustError {
         }
     }
 
+    pub fn inactive_capabilities<S: Into<String>>(capabilities: S, height: u64) -> Self {
+        RustError::InactiveCapabilities {
+            capabilities: capabilities.into(),
+            height,
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    pub fn instance_memory_limit_exceeded<S: Into<String>>(checksum: S, limit: u64) -> Self {
+        RustError::InstanceMemoryLimitExceeded {
+            checksum: checksum.into(),
+            limit,
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    pub fn invalid_config<S: ToString>(msg: S) -> Self {
+        RustError::InvalidConfig {
+            msg: msg.to_string(),
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
     pub fn panic() -> Self {
         RustError::Panic {
             #[cfg(feature = "backtraces")]
@@ -69,6 +173,31 @@ impl RustError {
         }
     }
 
+    pub fn query_limit_exceeded(limit: QueryLimit, max: u64) -> Self {
+        RustError::QueryLimitExceeded {
+            limit,
+            max,
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    pub fn remote_err<S: ToString>(msg: S) -> Self {
+        RustError::RemoteErr {
+            msg: msg.to_string(),
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    pub fn replay_err<S: ToString>(msg: S) -> Self {
+        RustError::ReplayErr {
+            msg: msg.to_string(),
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
     pub fn unset_arg<T: Into<String>>(name: T) -> Self {
         RustError::UnsetArg {
             name: name.into(),
@@ -77,6 +206,16 @@ impl RustError {
         }
     }
 
+    pub fn upload_limit_exceeded(limit: UploadLimit, actual: usize, max: u64) -> Self {
+        RustError::UploadLimitExceeded {
+            limit,
+            actual,
+            max,
+            #[cfg(feature = "backtraces")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
     pub fn vm_err<S: ToString>(msg: S) -> Self {
         RustError::VmErr {
             msg: msg.to_string(),
@@ -120,6 +259,8 @@ enum ErrnoValue {
     Success = 0,
     Other = 1,
     OutOfGas = 2,
+    QueryLimitExceeded = 3,
+    InstanceMemoryLimitExceeded = 4,
 }
 
 pub fn clear_error() {
@@ -137,6 +278,8 @@ pub fn set_error(err: RustError, error_msg: Option<&mut UnmanagedVector>) {
 
     let errno = match err {
         RustError::OutOfGas { .. } => ErrnoValue::OutOfGas,
+        RustError::QueryLimitExceeded { .. } => ErrnoValue::QueryLimitExceeded,
+        RustError::InstanceMemoryLimitExceeded { .. } => ErrnoValue::InstanceMemoryLimitExceeded,
         _ => ErrnoValue::Other,
     } as i32;
     set_errno(Errno(errno));
@@ -208,6 +351,22 @@ where
     }
 }
 
+/// Returns `value` in any case. If `result` is Ok, this clears [errno].
+/// Otherwise it writes the error message to `error_msg` and sets [errno].
+///
+/// [errno]: https://utcc.utoronto.ca/~cks/space/blog/programming/GoCgoErrorReturns
+pub fn handle_c_error_with_value<T>(
+    value: T,
+    result: Result<(), RustError>,
+    error_msg: Option<&mut UnmanagedVector>,
+) -> T {
+    match result {
+        Ok(()) => clear_error(),
+        Err(error) => set_error(error, error_msg),
+    }
+    value
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -215,6 +374,17 @@ mod tests {
     use errno::errno;
     use std::str;
 
+    #[test]
+    fn cache_err_works() {
+        let error = RustError::cache_err("Wasm file does not exist");
+        match error {
+            RustError::CacheErr { msg, .. } => {
+                assert_eq!(msg, "Wasm file does not exist");
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
     #[test]
     fn empty_arg_works() {
         let error = RustError::empty_arg("gas");
@@ -249,6 +419,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn inactive_capabilities_works() {
+        let error = RustError::inactive_capabilities("cosmwasm_1_3", 42);
+        match error {
+            RustError::InactiveCapabilities {
+                capabilities,
+                height,
+                ..
+            } => {
+                assert_eq!(capabilities, "cosmwasm_1_3");
+                assert_eq!(height, 42);
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn instance_memory_limit_exceeded_works() {
+        let error = RustError::instance_memory_limit_exceeded("aabb", 65536);
+        match error {
+            RustError::InstanceMemoryLimitExceeded {
+                checksum, limit, ..
+            } => {
+                assert_eq!(checksum, "aabb");
+                assert_eq!(limit, 65536);
+            }
+            _ => panic!("expect different error"),
+        }
+
+        let mut error_msg = UnmanagedVector::default();
+        set_error(
+            RustError::instance_memory_limit_exceeded("aabb", 65536),
+            Some(&mut error_msg),
+        );
+        assert_eq!(errno().0, ErrnoValue::InstanceMemoryLimitExceeded as i32);
+        assert_eq!(
+            error_msg.consume().unwrap(),
+            b"Instance memory limit of 65536 bytes exceeded by Wasm contract aabb"
+        );
+    }
+
+    #[test]
+    fn invalid_config_works() {
+        let error = RustError::invalid_config("missing field `data_dir`");
+        match error {
+            RustError::InvalidConfig { msg, .. } => {
+                assert_eq!(msg, "missing field `data_dir`");
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
     #[test]
     fn panic_works() {
         let error = RustError::panic();
@@ -258,6 +480,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn query_limit_exceeded_works() {
+        let error = RustError::query_limit_exceeded(QueryLimit::Depth, 3);
+        match error {
+            RustError::QueryLimitExceeded { limit, max, .. } => {
+                assert_eq!(limit, QueryLimit::Depth);
+                assert_eq!(max, 3);
+            }
+            _ => panic!("expect different error"),
+        }
+        assert_eq!(
+            RustError::query_limit_exceeded(QueryLimit::Gas, 250).to_string(),
+            "Query gas limit of 250 exceeded"
+        );
+
+        let mut error_msg = UnmanagedVector::default();
+        set_error(
+            RustError::query_limit_exceeded(QueryLimit::Depth, 3),
+            Some(&mut error_msg),
+        );
+        assert_eq!(errno().0, ErrnoValue::QueryLimitExceeded as i32);
+        assert_eq!(
+            error_msg.consume().unwrap(),
+            b"Query depth limit of 3 exceeded"
+        );
+    }
+
+    #[test]
+    fn remote_err_works() {
+        let error = RustError::remote_err("Connection reset by peer");
+        match error {
+            RustError::RemoteErr { msg, .. } => {
+                assert_eq!(msg, "Connection reset by peer");
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
+    #[test]
+    fn replay_err_works() {
+        let error = RustError::replay_err("Interaction 3 was not recorded");
+        match error {
+            RustError::ReplayErr { msg, .. } => {
+                assert_eq!(msg, "Interaction 3 was not recorded");
+            }
+            _ => panic!("expect different error"),
+        }
+    }
+
     #[test]
     fn unset_arg_works() {
         let error = RustError::unset_arg("gas");
@@ -269,6 +540,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn upload_limit_exceeded_works() {
+        let error = RustError::upload_limit_exceeded(UploadLimit::Imports, 101, 100);
+        match error {
+            RustError::UploadLimitExceeded {
+                limit, actual, max, ..
+            } => {
+                assert_eq!(limit, UploadLimit::Imports);
+                assert_eq!(actual, 101);
+                assert_eq!(max, 100);
+            }
+            _ => panic!("expect different error"),
+        }
+        assert_eq!(
+            RustError::upload_limit_exceeded(UploadLimit::Imports, 101, 100).to_string(),
+            "Upload limit exceeded: number of imports is 101, the limit is 100"
+        );
+    }
+
     #[test]
     fn vm_err_works_for_strings() {
         let error = RustError::vm_err("my text");
@@ -501,6 +791,29 @@ mod tests {
         let _ = error_msg.consume();
     }
 
+    #[test]
+    fn handle_c_error_with_value_works() {
+        // Ok
+        let mut error_msg = UnmanagedVector::default();
+        let data = handle_c_error_with_value(vec![0xF0, 0x0B], Ok(()), Some(&mut error_msg));
+        assert_eq!(errno().0, ErrnoValue::Success as i32);
+        assert!(error_msg.is_none());
+        assert_eq!(data, vec![0xF0, 0x0B]);
+        let _ = error_msg.consume();
+
+        // Err
+        let mut error_msg = UnmanagedVector::default();
+        let data = handle_c_error_with_value(
+            vec![0xF0, 0x0B],
+            Err(RustError::panic()),
+            Some(&mut error_msg),
+        );
+        assert_eq!(errno().0, ErrnoValue::Other as i32);
+        assert!(error_msg.is_some());
+        assert_eq!(data, vec![0xF0, 0x0B]);
+        let _ = error_msg.consume();
+    }
+
     #[test]
     fn handle_c_error_default_clears_an_old_error() {
         // Err
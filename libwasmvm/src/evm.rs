@@ -0,0 +1,130 @@
+//! Queries of EVM contract state, i.e. an `eth_call` into the EVM module of the chain.
+//!
+//! A contract sends `{"evm":{"call":{"to":"0x…","data":"<base64>","gas":100000}}}` as a raw query
+//! and gets an [`EvmCallResponse`] back. Those queries are answered by the optional `query_evm`
+//! callback of the querier. The EVM gas used is reported separately from the gas of the call.
+
+use cosmwasm_std::Binary;
+use serde::{Deserialize, Serialize};
+
+use crate::address_codec::decode_hex_address;
+use crate::query_router::request_route;
+
+/// The outer key of EVM queries
+pub const EVM_QUERY_KEY: &str = "evm";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct EvmQueryRequest {
+    evm: EvmQuery,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum EvmQuery {
+    Call(EvmCallRequest),
+}
+
+/// A read-only call of an EVM contract
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct EvmCallRequest {
+    /// The `0x` prefixed hex address of the EVM contract
+    pub to: String,
+    /// The ABI encoded call data
+    pub data: Binary,
+    /// The EVM gas limit of the call
+    pub gas: u64,
+}
+
+/// The response to an [`EvmCallRequest`] as seen by the contract
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct EvmCallResponse {
+    /// The ABI encoded return data
+    pub data: Binary,
+    /// The EVM gas used by the call
+    pub gas_used: u64,
+}
+
+/// Parses an EVM query. Returns None if the request is not an EVM query
+/// and an error message if it is an invalid one.
+pub fn parse_evm_query(request: &[u8]) -> Option<Result<EvmCallRequest, String>> {
+    let (outer, _) = request_route(request)?;
+    if outer != EVM_QUERY_KEY {
+        return None;
+    }
+    let parsed = serde_json::from_slice(request)
+        .map_err(|e| format!("Invalid EVM query: {}", e))
+        .map(|request: EvmQueryRequest| match request.evm {
+            EvmQuery::Call(call) => call,
+        });
+    Some(parsed)
+}
+
+/// Returns the address of the called EVM contract
+pub fn evm_call_target(call: &EvmCallRequest) -> Result<Vec<u8>, String> {
+    if !call.to.starts_with("0x") {
+        return Err("Invalid EVM address: missing 0x prefix".to_string());
+    }
+    decode_hex_address(&call.to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_evm_query_works() {
+        let request =
+            br#"{"evm":{"call":{"to":"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed","data":"AQI=","gas":50000}}}"#;
+        let call = parse_evm_query(request).unwrap().unwrap();
+        assert_eq!(
+            call,
+            EvmCallRequest {
+                to: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                data: Binary::from([1, 2]),
+                gas: 50000,
+            }
+        );
+        assert_eq!(
+            hex::encode(evm_call_target(&call).unwrap()),
+            "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        );
+
+        // other queries
+        assert_eq!(parse_evm_query(br#"{"bank":{"balance":{}}}"#), None);
+        assert_eq!(parse_evm_query(b"not json"), None);
+
+        // invalid EVM queries
+        let err = parse_evm_query(br#"{"evm":{"call":{"to":"0x00"}}}"#)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.starts_with("Invalid EVM query: "), "{}", err);
+        let err = parse_evm_query(br#"{"evm":{"send":{}}}"#)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.starts_with("Invalid EVM query: "), "{}", err);
+    }
+
+    #[test]
+    fn evm_call_target_validates_address() {
+        let call = |to: &str| EvmCallRequest {
+            to: to.to_string(),
+            data: Binary::default(),
+            gas: 0,
+        };
+        assert_eq!(
+            evm_call_target(&call("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")).unwrap_err(),
+            "Invalid EVM address: missing 0x prefix"
+        );
+        assert_eq!(
+            evm_call_target(&call("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea")).unwrap_err(),
+            "Invalid hex address length: expected 40 digits, got 38"
+        );
+        assert_eq!(
+            evm_call_target(&call("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")).unwrap_err(),
+            "Invalid hex address checksum"
+        );
+    }
+}
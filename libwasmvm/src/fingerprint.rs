@@ -0,0 +1,143 @@
+//! Fingerprints of calls for consensus debugging.
+//!
+//! A fingerprint is a running hash over everything a call exchanged with the host, in order,
+//! followed by its result and gas report. Nodes that disagree about the outcome of a block can
+//! compare the fingerprints of its calls to find the first one where their executions diverged.
+//!
+//! Interactions are collected by the host adapters (`HostApi`, `HostStorage` and `HostQuerier`),
+//! i.e. for calls through the FFI, through [`crate::Vm`] and for replays.
+//!
+//! Calls through the FFI only compute fingerprints if the cache is configured with
+//! `fingerprints`. They are returned in the `CallReport` of the call, which Go passes to
+//! queriers implementing `types.CallReporter`.
+
+use cosmwasm_vm::GasReport;
+use sha3::{Digest, Sha3_256};
+
+use crate::error::Error;
+use crate::replay::{recorded_result, Interaction};
+
+pub const FINGERPRINT_LENGTH: usize = 32;
+
+// Domain separation between the parts of a fingerprint
+const TAG_INTERACTION: u8 = 1;
+const TAG_RESULT: u8 = 2;
+const TAG_GAS_REPORT: u8 = 3;
+
+pub struct Fingerprint {
+    hasher: Sha3_256,
+}
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint {
+            hasher: Sha3_256::new(),
+        }
+    }
+
+    /// Adds a request to the host with its response and gas
+    pub fn add_interaction(&mut self, interaction: &Interaction) {
+        let data = serde_json::to_vec(interaction).expect("Interactions are serializable");
+        self.update(TAG_INTERACTION, &data);
+    }
+
+    /// Adds the outcome of the call and returns the fingerprint
+    pub fn finish(
+        mut self,
+        result: &Result<Vec<u8>, Error>,
+        gas_report: &GasReport,
+    ) -> [u8; FINGERPRINT_LENGTH] {
+        let result =
+            serde_json::to_vec(&recorded_result(result)).expect("Results are serializable");
+        self.update(TAG_RESULT, &result);
+        let gas: Vec<u8> = [
+            gas_report.limit,
+            gas_report.remaining,
+            gas_report.used_externally,
+            gas_report.used_internally,
+        ]
+        .iter()
+        .flat_map(|gas| gas.to_be_bytes())
+        .collect();
+        self.update(TAG_GAS_REPORT, &gas);
+        self.hasher.finalize().into()
+    }
+
+    fn update(&mut self, tag: u8, data: &[u8]) {
+        self.hasher.update([tag]);
+        self.hasher.update((data.len() as u64).to_be_bytes());
+        self.hasher.update(data);
+    }
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{BackendRequest, BackendResponse};
+
+    fn gas_report(used_internally: u64) -> GasReport {
+        GasReport {
+            limit: 1000,
+            remaining: 1000 - used_internally - 10,
+            used_externally: 10,
+            used_internally,
+        }
+    }
+
+    fn interaction(key: &[u8]) -> Interaction {
+        Interaction {
+            request: BackendRequest::Get { key: key.into() },
+            response: Ok(BackendResponse::Value(None)),
+            gas_cost: 0,
+            gas_externally_used: 10,
+        }
+    }
+
+    fn fingerprint(
+        interactions: &[Interaction],
+        result: &Result<Vec<u8>, Error>,
+        used: u64,
+    ) -> [u8; 32] {
+        let mut fingerprint = Fingerprint::new();
+        for interaction in interactions {
+            fingerprint.add_interaction(interaction);
+        }
+        fingerprint.finish(result, &gas_report(used))
+    }
+
+    #[test]
+    fn fingerprint_covers_interactions_result_and_gas() {
+        let interactions = [interaction(b"foo"), interaction(b"bar")];
+        let original = fingerprint(&interactions, &Ok(b"ok".to_vec()), 100);
+        assert_eq!(
+            original,
+            fingerprint(&interactions, &Ok(b"ok".to_vec()), 100)
+        );
+
+        // order matters
+        let reversed = [interaction(b"bar"), interaction(b"foo")];
+        assert_ne!(original, fingerprint(&reversed, &Ok(b"ok".to_vec()), 100));
+        assert_ne!(
+            original,
+            fingerprint(&interactions[..1], &Ok(b"ok".to_vec()), 100)
+        );
+        assert_ne!(
+            original,
+            fingerprint(&interactions, &Ok(b"ko".to_vec()), 100)
+        );
+        assert_ne!(
+            original,
+            fingerprint(&interactions, &Err(Error::out_of_gas()), 100)
+        );
+        assert_ne!(
+            original,
+            fingerprint(&interactions, &Ok(b"ok".to_vec()), 101)
+        );
+    }
+}
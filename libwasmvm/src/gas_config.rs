@@ -0,0 +1,379 @@
+//! Gas costs of calls that can be adjusted by the chain.
+//!
+//! cosmwasm-vm charges a flat cost per Wasm operation, which is fixed when a module is compiled.
+//! To make it configurable anyway, an instance meters gas in its own units: the gas limit of a
+//! call is converted into instance gas before the call and the gas report back into host gas
+//! afterwards. Gas charged by the host for storage, api calls and queries (including the
+//! overheads configured here) is converted into instance gas before it is charged. Limits are
+//! rounded down and charges up, such that a charge is never lost when the gas report is
+//! converted back. With the default `wasm_op_cost` both units are the same.
+//!
+//! Which config is used is decided per call based on the block height in the call's env,
+//! such that a running node can switch to a new config at an upgrade height.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use cosmwasm_vm::{BackendResult, GasInfo, GasReport, InstanceOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// The cost of every Wasm operation as charged by cosmwasm-vm
+pub const DEFAULT_WASM_OP_COST: u64 = 150_000;
+
+/// Target is 10^12 gas per millisecond, as in cosmwasm-vm's `GasConfig`
+const GAS_PER_US: u64 = 1_000_000_000;
+
+/// The gas costs of a call on top of the ones of cosmwasm-vm and the host
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+pub struct GasConfig {
+    /// The cost of a Wasm operation. Gas charged by cosmwasm-vm itself (e.g. for crypto
+    /// imports) is scaled along with it.
+    pub wasm_op_cost: u64,
+    /// Charged for every storage callback (get, set, remove, scan and next)
+    pub storage_callback_overhead: u64,
+    /// Charged for every `addr_canonicalize`, in addition to the cost of the conversion
+    pub canonicalize_address_cost: u64,
+    /// Charged for every `addr_humanize`, in addition to the cost of the conversion
+    pub humanize_address_cost: u64,
+    /// Charged for every query, in addition to the gas used by the query
+    pub query_overhead: u64,
+    /// Charged for every keccak256 crypto query
+    pub keccak256_cost: u64,
+    /// Charged per byte hashed by a keccak256 crypto query
+    pub keccak256_per_byte_cost: u64,
+    /// Charged for every secp256k1 EVM address recovery crypto query
+    pub secp256k1_recover_evm_address_cost: u64,
+    /// Charged per signature of an ed25519 batch verification crypto query
+    pub ed25519_batch_verify_per_signature_cost: u64,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        GasConfig {
+            wasm_op_cost: DEFAULT_WASM_OP_COST,
+            storage_callback_overhead: 0,
+            canonicalize_address_cost: 0,
+            humanize_address_cost: 0,
+            query_overhead: 0,
+            keccak256_cost: GAS_PER_US,
+            keccak256_per_byte_cost: GAS_PER_US / 100,
+            // public key recovery as in cosmwasm-vm plus hashing
+            secp256k1_recover_evm_address_cost: 163 * GAS_PER_US,
+            // as in cosmwasm-vm
+            ed25519_batch_verify_per_signature_cost: 63 * GAS_PER_US / 2,
+        }
+    }
+}
+
+impl GasConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.wasm_op_cost == 0 {
+            return Err(Error::invalid_config(
+                "wasm_op_cost must be greater than zero",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The options of an instance for a call with the given gas limit in host gas
+    pub fn instance_options(&self, gas_limit: u64, print_debug: bool) -> InstanceOptions {
+        InstanceOptions {
+            gas_limit: self.to_instance_gas(gas_limit),
+            print_debug,
+        }
+    }
+
+    /// Converts the gas report of an instance into host gas
+    pub fn gas_report(&self, report: GasReport, gas_limit: u64) -> GasReport {
+        GasReport {
+            limit: gas_limit,
+            remaining: self.to_host_gas(report.remaining),
+            used_externally: self.to_host_gas(report.used_externally),
+            used_internally: self.to_host_gas(report.used_internally),
+        }
+    }
+
+    /// Adds the overhead to the gas of a host response and converts it into instance gas
+    pub fn charge<T>(&self, outcome: BackendResult<T>, overhead: u64) -> BackendResult<T> {
+        let (result, gas_info) = outcome;
+        let gas_info = GasInfo::new(
+            self.to_instance_cost(gas_info.cost.saturating_add(overhead)),
+            self.to_instance_cost(gas_info.externally_used),
+        );
+        (result, gas_info)
+    }
+
+    pub fn to_instance_gas(&self, gas: u64) -> u64 {
+        scale(gas, DEFAULT_WASM_OP_COST, self.wasm_op_cost)
+    }
+
+    /// Like `to_instance_gas` but rounded up, for gas charged by the host
+    pub fn to_instance_cost(&self, gas: u64) -> u64 {
+        scale_up(gas, DEFAULT_WASM_OP_COST, self.wasm_op_cost)
+    }
+
+    pub fn to_host_gas(&self, gas: u64) -> u64 {
+        scale(gas, self.wasm_op_cost, DEFAULT_WASM_OP_COST)
+    }
+}
+
+/// Returns `gas * numerator / denominator`, rounded down and saturating at `u64::MAX`
+fn scale(gas: u64, numerator: u64, denominator: u64) -> u64 {
+    if numerator == denominator {
+        return gas;
+    }
+    let scaled = gas as u128 * numerator as u128 / denominator as u128;
+    scaled.try_into().unwrap_or(u64::MAX)
+}
+
+/// Returns `gas * numerator / denominator`, rounded up and saturating at `u64::MAX`
+fn scale_up(gas: u64, numerator: u64, denominator: u64) -> u64 {
+    if numerator == denominator {
+        return gas;
+    }
+    let product = gas as u128 * numerator as u128;
+    let denominator = denominator as u128;
+    let (quotient, remainder) = (product / denominator, product % denominator);
+    let scaled = if remainder == 0 {
+        quotient
+    } else {
+        quotient + 1
+    };
+    scaled.try_into().unwrap_or(u64::MAX)
+}
+
+/// A gas config that applies to calls from a block height on
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct GasConfigUpdate {
+    pub height: u64,
+    pub gas_config: GasConfig,
+}
+
+/// The gas configs of a cache by the height they apply from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasSchedule {
+    configs: BTreeMap<u64, GasConfig>,
+}
+
+impl GasSchedule {
+    pub fn new(initial: GasConfig, updates: Vec<GasConfigUpdate>) -> Result<Self, Error> {
+        let mut schedule = GasSchedule::default();
+        schedule.update(0, initial)?;
+        for update in updates {
+            schedule.update(update.height, update.gas_config)?;
+        }
+        Ok(schedule)
+    }
+
+    /// Returns true if the same config applies at all heights
+    pub fn is_static(&self) -> bool {
+        self.configs.len() <= 1
+    }
+
+    /// The config of calls at the height
+    pub fn at(&self, height: u64) -> GasConfig {
+        self.configs
+            .range(..=height)
+            .next_back()
+            .map(|(_, config)| *config)
+            .unwrap_or_default()
+    }
+
+    /// Uses the config for calls from the height on, replacing an update at the same height
+    pub fn update(&mut self, height: u64, config: GasConfig) -> Result<(), Error> {
+        config.validate()?;
+        self.configs.insert(height, config);
+        Ok(())
+    }
+}
+
+thread_local! {
+    // Like the query state, the configs of a call tree live in the thread of the outermost call
+    static GAS_CONFIGS: RefCell<Vec<GasConfig>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a call using the gas config. Nested calls have their own.
+pub struct GasScope;
+
+impl GasScope {
+    pub fn enter(config: GasConfig) -> Self {
+        GAS_CONFIGS.with(|configs| configs.borrow_mut().push(config));
+        GasScope
+    }
+}
+
+impl Drop for GasScope {
+    fn drop(&mut self) {
+        GAS_CONFIGS.with(|configs| {
+            configs.borrow_mut().pop();
+        });
+    }
+}
+
+/// The gas config of the innermost call on this thread, or the default outside of calls
+pub fn current_gas_config() -> GasConfig {
+    GAS_CONFIGS.with(|configs| configs.borrow().last().copied().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion_works() {
+        let config = GasConfig::default();
+        assert_eq!(config.to_instance_gas(123), 123);
+        assert_eq!(config.to_host_gas(u64::MAX), u64::MAX);
+
+        let config = GasConfig {
+            wasm_op_cost: 2 * DEFAULT_WASM_OP_COST,
+            ..GasConfig::default()
+        };
+        assert_eq!(config.to_instance_gas(1000), 500);
+        assert_eq!(config.to_instance_gas(1001), 500);
+        assert_eq!(config.to_instance_cost(1000), 500);
+        assert_eq!(config.to_instance_cost(1001), 501);
+        assert_eq!(config.to_instance_cost(1), 1);
+        assert_eq!(config.to_instance_cost(0), 0);
+        assert_eq!(config.to_host_gas(500), 1000);
+        assert_eq!(config.to_host_gas(u64::MAX), u64::MAX);
+        assert_eq!(config.instance_options(1000, true).gas_limit, 500);
+
+        let report = config.gas_report(
+            GasReport {
+                limit: 500,
+                remaining: 100,
+                used_externally: 150,
+                used_internally: 250,
+            },
+            1001,
+        );
+        assert_eq!(
+            (
+                report.limit,
+                report.remaining,
+                report.used_externally,
+                report.used_internally
+            ),
+            (1001, 200, 300, 500)
+        );
+    }
+
+    #[test]
+    fn charge_works() {
+        let config = GasConfig {
+            wasm_op_cost: DEFAULT_WASM_OP_COST / 2,
+            ..GasConfig::default()
+        };
+        let (result, gas_info) = config.charge((Ok(()), GasInfo::new(10, 20)), 5);
+        assert!(result.is_ok());
+        assert_eq!(gas_info, GasInfo::new(30, 40));
+
+        let (_, gas_info) = GasConfig::default().charge((Ok(()), GasInfo::new(10, 20)), 0);
+        assert_eq!(gas_info, GasInfo::new(10, 20));
+    }
+
+    #[test]
+    fn charges_are_not_lost_in_conversion() {
+        // neither divides the charges exactly
+        for wasm_op_cost in [7 * DEFAULT_WASM_OP_COST, 100_000] {
+            let config = GasConfig {
+                wasm_op_cost,
+                ..GasConfig::default()
+            };
+            for overhead in 1..=20 {
+                let (_, gas_info) = config.charge((Ok(()), GasInfo::new(0, overhead)), overhead);
+                let report = config.gas_report(
+                    GasReport {
+                        limit: 0,
+                        remaining: 0,
+                        used_externally: gas_info.externally_used,
+                        used_internally: gas_info.cost,
+                    },
+                    0,
+                );
+                assert!(report.used_internally >= overhead);
+                assert!(report.used_externally >= overhead);
+            }
+        }
+
+        let config = GasConfig {
+            wasm_op_cost: 7 * DEFAULT_WASM_OP_COST,
+            ..GasConfig::default()
+        };
+        let (_, gas_info) = config.charge((Ok(()), GasInfo::new(10, 0)), 5);
+        assert_eq!(gas_info, GasInfo::new(3, 0));
+        assert_eq!(config.to_host_gas(gas_info.cost), 21);
+    }
+
+    #[test]
+    fn schedule_works() {
+        let config = |query_overhead| GasConfig {
+            query_overhead,
+            ..GasConfig::default()
+        };
+        let mut schedule = GasSchedule::new(
+            config(1),
+            vec![
+                GasConfigUpdate {
+                    height: 200,
+                    gas_config: config(3),
+                },
+                GasConfigUpdate {
+                    height: 100,
+                    gas_config: config(2),
+                },
+            ],
+        )
+        .unwrap();
+        assert!(!schedule.is_static());
+        assert_eq!(schedule.at(0), config(1));
+        assert_eq!(schedule.at(99), config(1));
+        assert_eq!(schedule.at(100), config(2));
+        assert_eq!(schedule.at(u64::MAX), config(3));
+
+        schedule.update(200, config(4)).unwrap();
+        assert_eq!(schedule.at(200), config(4));
+
+        let err = schedule
+            .update(
+                300,
+                GasConfig {
+                    wasm_op_cost: 0,
+                    ..GasConfig::default()
+                },
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid cache config: wasm_op_cost must be greater than zero"
+        );
+        assert_eq!(schedule.at(300), config(4));
+
+        assert!(GasSchedule::default().is_static());
+        assert_eq!(GasSchedule::default().at(5), GasConfig::default());
+    }
+
+    #[test]
+    fn gas_scope_works() {
+        assert_eq!(current_gas_config(), GasConfig::default());
+        let outer = GasConfig {
+            query_overhead: 1,
+            ..GasConfig::default()
+        };
+        let _outer = GasScope::enter(outer);
+        {
+            let inner = GasConfig {
+                query_overhead: 2,
+                ..GasConfig::default()
+            };
+            let _inner = GasScope::enter(inner);
+            assert_eq!(current_gas_config(), inner);
+        }
+        assert_eq!(current_gas_config(), outer);
+    }
+}
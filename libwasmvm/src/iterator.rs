@@ -4,6 +4,7 @@ use cosmwasm_vm::{BackendError, BackendResult, GasInfo};
 use crate::error::GoError;
 use crate::gas_meter::gas_meter_t;
 use crate::memory::UnmanagedVector;
+use crate::vm::VmIterator;
 
 // Iterator maintains integer references to some tables on the Go side
 #[repr(C)]
@@ -46,8 +47,10 @@ impl GoIter {
             vtable: Iterator_vtable::default(),
         }
     }
+}
 
-    pub fn next(&mut self) -> BackendResult<Option<Record>> {
+impl VmIterator for GoIter {
+    fn next(&mut self) -> BackendResult<Option<Record>> {
         let next_db = match self.vtable.next_db {
             Some(f) => f,
             None => {
@@ -1,30 +1,76 @@
 #![cfg_attr(feature = "backtraces", feature(backtrace))]
 #![allow(clippy::not_unsafe_ptr_arg_deref, clippy::missing_safety_doc)]
 
+mod activation;
+mod address_codec;
 mod api;
 mod args;
 mod cache;
 mod calls;
+mod config;
+mod crypto;
 mod db;
 mod error;
+mod evm;
+mod fingerprint;
+mod gas_config;
 mod gas_meter;
 mod iterator;
+mod local_cache;
+mod logger;
 mod memory;
 mod querier;
+mod query_router;
+mod remote;
+mod replay;
+mod static_analysis;
 mod storage;
 mod test_utils;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod tests;
+mod upload_limits;
 mod version;
+mod vm;
+mod vm_cache;
 
-// We only interact with this crate via `extern "C"` interfaces, not those public
+// The Rust embedding API. Unlike the exports below, these are supported: a `Vm` with its
+// host traits and everything needed to configure it and to handle its results.
+pub use activation::{ActivationSchedule, CapabilityActivation, FeatureSet, HostFeature};
+pub use address_codec::{AddressCodec, AddressCodecConfig};
+pub use calls::EntryPoint;
+pub use config::CacheConfig;
+pub use error::Error;
+pub use evm::{EvmCallRequest, EvmCallResponse};
+pub use fingerprint::FINGERPRINT_LENGTH;
+pub use gas_config::{GasConfig, GasConfigUpdate, GasSchedule, DEFAULT_WASM_OP_COST};
+pub use querier::{QueryLimit, QueryLimits};
+pub use query_router::{QueryHandler, QueryResult, QueryRoute, QueryRoutes, QueryRoutesUpdate};
+pub use static_analysis::AnalysisReport;
+pub use upload_limits::UploadLimits;
+pub use vm::{HostCache, Vm, VmApi, VmBackend, VmDb, VmIterator, VmOutput, VmQuerier};
+pub use vm_cache::{VmCache, VmCacheOptions};
+
+// We only interact with the following via `extern "C"` interfaces, not those public
 // exports. There are no guarantees those exports are stable.
 // We keep them here such that we can access them in the docs (`cargo doc`).
-pub use api::GoApi;
-pub use cache::{cache_t, load_wasm};
+pub use api::{GoApi, HostApi};
+pub use cache::{
+    cache_t, init_cache_with_config, load_wasm, register_query_handler, release_cache, save_wasm,
+};
+pub use calls::{
+    call_entry_point, call_with_backend, execute, instantiate, query, CallOutput, CallReport,
+};
 pub use db::{db_t, Db};
 pub use error::GoError;
 pub use memory::{
     destroy_unmanaged_vector, new_unmanaged_vector, ByteSliceView, U8SliceView, UnmanagedVector,
 };
-pub use querier::GoQuerier;
-pub use storage::GoStorage;
+pub use querier::{GoQuerier, HostQuerier};
+pub use remote::{
+    RemoteApi, RemoteCallOutput, RemoteClient, RemoteQuerier, RemoteServer, RemoteStorage,
+};
+pub use replay::{
+    replay, BackendRequest, BackendResponse, Interaction, RecordedError, Recording, ReplayOutput,
+};
+pub use storage::{GoStorage, HostStorage};
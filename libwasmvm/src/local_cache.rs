@@ -0,0 +1,658 @@
+//! A module cache that keeps compiled modules in memory only.
+//!
+//! This is used instead of cosmwasm-vm's `Cache` when the file system cache for
+//! compiled modules is disabled. Wasm blobs are stored on disk using the same layout as
+//! cosmwasm-vm, such that a node can switch between the two implementations. In the
+//! in-memory mode, Wasm blobs are kept in memory as well and no disk I/O happens at all.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use clru::{CLruCache, CLruCacheConfig, WeightScale};
+use cosmwasm_vm::internals::{compile, instance_from_module};
+use cosmwasm_vm::{
+    Backend, BackendApi, Checksum, Instance, InstanceOptions, Metrics, Querier, Size, Stats,
+    Storage,
+};
+use wasmer::Module;
+
+use crate::error::Error;
+
+const STATE_DIR: &str = "state";
+// Things related to the state of the blockchain.
+const WASM_DIR: &str = "wasm";
+const INSTANCE_MEMORY_LIMITS_FILE: &str = "instance_memory_limits.json";
+
+#[derive(Clone)]
+struct SizedModule {
+    module: Module,
+    /// The estimated memory usage of the module in bytes
+    size: usize,
+}
+
+impl SizedModule {
+    fn new(module: Module) -> Self {
+        let size = loupe::size_of_val(&module);
+        SizedModule { module, size }
+    }
+}
+
+struct SizeScale;
+
+impl WeightScale<Checksum, SizedModule> for SizeScale {
+    #[inline]
+    fn weight(&self, _key: &Checksum, value: &SizedModule) -> usize {
+        value.size
+    }
+}
+
+/// An LRU cache of modules limited by their total size in bytes.
+///
+/// `CLruCache` is not `Send` because it keeps its keys in `Rc`s. Those `Rc`s are created and
+/// dropped by the LRU cache only and never handed out: lookups and iteration return references
+/// and keys are unwrapped on removal. So all clones of such an `Rc` are owned by the same
+/// `CLruCache` and move between threads together, which is sound. cosmwasm-vm's `Cache` relies
+/// on the same for its memory cache.
+struct MemoryCache(CLruCache<Checksum, SizedModule, RandomState, SizeScale>);
+
+unsafe impl Send for MemoryCache {}
+
+impl Deref for MemoryCache {
+    type Target = CLruCache<Checksum, SizedModule, RandomState, SizeScale>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for MemoryCache {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// The place where the original Wasm blobs are stored
+enum WasmStore {
+    /// The directory in which the Wasm blobs are stored in the file system
+    Disk(PathBuf),
+    /// Wasm blobs kept in memory, limited by their total size in bytes
+    Memory {
+        blobs: HashMap<Checksum, Vec<u8>>,
+        size: usize,
+        limit: Size,
+    },
+}
+
+impl WasmStore {
+    fn save(&mut self, wasm: &[u8]) -> Result<Checksum, Error> {
+        match self {
+            WasmStore::Disk(path) => save_wasm_to_disk(path, wasm),
+            WasmStore::Memory { blobs, size, limit } => {
+                let checksum = Checksum::generate(wasm);
+                if blobs.contains_key(&checksum) {
+                    return Ok(checksum);
+                }
+                let new_size = size.saturating_add(wasm.len());
+                if new_size > limit.0 {
+                    return Err(Error::cache_err(format!(
+                        "In-memory Wasm store size limit of {} bytes exceeded",
+                        limit.0
+                    )));
+                }
+                blobs.insert(checksum, wasm.to_vec());
+                *size = new_size;
+                Ok(checksum)
+            }
+        }
+    }
+
+    fn load(&self, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+        match self {
+            WasmStore::Disk(path) => load_wasm_from_disk(path, checksum),
+            WasmStore::Memory { blobs, .. } => blobs
+                .get(checksum)
+                .cloned()
+                .ok_or_else(|| Error::cache_err("Wasm not found in memory")),
+        }
+    }
+
+    fn remove(&mut self, checksum: &Checksum) -> Result<(), Error> {
+        match self {
+            WasmStore::Disk(path) => remove_wasm_from_disk(path, checksum),
+            WasmStore::Memory { blobs, size, .. } => {
+                let wasm = blobs
+                    .remove(checksum)
+                    .ok_or_else(|| Error::cache_err("Wasm not found in memory"))?;
+                *size -= wasm.len();
+                Ok(())
+            }
+        }
+    }
+}
+
+struct LocalCacheInner {
+    wasm_store: WasmStore,
+    instance_memory_limit: Size,
+    pinned_memory_cache: HashMap<Checksum, SizedModule>,
+    /// An LRU cache limited by the total size of the modules. None if the configured size is 0.
+    memory_cache: Option<MemoryCache>,
+    stats: Stats,
+}
+
+pub struct LocalCache {
+    inner: Mutex<LocalCacheInner>,
+    /// To prevent concurrent access to `WasmerInstance::new`
+    instantiation_lock: Mutex<()>,
+}
+
+impl LocalCache {
+    pub fn new(
+        base_dir: &Path,
+        memory_cache_size: Size,
+        instance_memory_limit: Size,
+    ) -> Result<Self, Error> {
+        let state_path = base_dir.join(STATE_DIR);
+        let wasm_path = wasm_dir(base_dir);
+        fs::create_dir_all(&state_path)
+            .map_err(|_e| Error::cache_err("Error creating state directory"))?;
+        fs::create_dir_all(&wasm_path)
+            .map_err(|_e| Error::cache_err("Error creating wasm directory"))?;
+
+        Ok(Self::with_wasm_store(
+            WasmStore::Disk(wasm_path),
+            memory_cache_size,
+            instance_memory_limit,
+        ))
+    }
+
+    /// Creates a cache that does not touch the file system. Wasm blobs are kept in memory
+    /// up to a total size of `wasm_store_size`.
+    pub fn in_memory(
+        wasm_store_size: Size,
+        memory_cache_size: Size,
+        instance_memory_limit: Size,
+    ) -> Self {
+        let wasm_store = WasmStore::Memory {
+            blobs: HashMap::new(),
+            size: 0,
+            limit: wasm_store_size,
+        };
+        Self::with_wasm_store(wasm_store, memory_cache_size, instance_memory_limit)
+    }
+
+    fn with_wasm_store(
+        wasm_store: WasmStore,
+        memory_cache_size: Size,
+        instance_memory_limit: Size,
+    ) -> Self {
+        let memory_cache = NonZeroUsize::new(memory_cache_size.0).map(|size| {
+            MemoryCache(CLruCache::with_config(
+                CLruCacheConfig::new(size).with_scale(SizeScale),
+            ))
+        });
+        LocalCache {
+            inner: Mutex::new(LocalCacheInner {
+                wasm_store,
+                instance_memory_limit,
+                pinned_memory_cache: HashMap::new(),
+                memory_cache,
+                stats: Stats::default(),
+            }),
+            instantiation_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let cache = self.inner.lock().unwrap();
+        Metrics {
+            stats: cache.stats,
+            elements_pinned_memory_cache: cache.pinned_memory_cache.len(),
+            elements_memory_cache: cache.memory_cache.as_ref().map_or(0, |c| c.len()),
+            size_pinned_memory_cache: cache
+                .pinned_memory_cache
+                .values()
+                .map(|module| module.size)
+                .sum(),
+            size_memory_cache: cache.memory_cache.as_ref().map_or(0, |c| c.weight()),
+        }
+    }
+
+    /// Compiles the Wasm, stores it in the Wasm store and keeps the module in the memory cache.
+    /// No static checks are performed here.
+    pub fn save_wasm_unchecked(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        let limit = self.inner.lock().unwrap().instance_memory_limit;
+        let module = compile(wasm, Some(limit), &[])?;
+
+        let mut cache = self.inner.lock().unwrap();
+        let checksum = cache.wasm_store.save(wasm)?;
+        cache.store_in_memory_cache(checksum, SizedModule::new(module))?;
+        Ok(checksum)
+    }
+
+    pub fn remove_wasm(&self, checksum: &Checksum) -> Result<(), Error> {
+        let mut cache = self.inner.lock().unwrap();
+        if let Some(memory_cache) = &mut cache.memory_cache {
+            memory_cache.pop(checksum);
+        }
+        cache.wasm_store.remove(checksum)
+    }
+
+    pub fn load_wasm(&self, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+        self.inner.lock().unwrap().wasm_store.load(checksum)
+    }
+
+    pub fn pin(&self, checksum: &Checksum) -> Result<(), Error> {
+        let mut cache = self.inner.lock().unwrap();
+        if cache.pinned_memory_cache.contains_key(checksum) {
+            return Ok(());
+        }
+
+        let cached = cache
+            .memory_cache
+            .as_mut()
+            .and_then(|memory_cache| memory_cache.get(checksum).cloned());
+        let module = match cached {
+            Some(module) => {
+                cache.stats.hits_memory_cache = cache.stats.hits_memory_cache.saturating_add(1);
+                module
+            }
+            None => {
+                cache.stats.misses = cache.stats.misses.saturating_add(1);
+                cache.compile(checksum)?
+            }
+        };
+        cache.pinned_memory_cache.insert(*checksum, module);
+        Ok(())
+    }
+
+    /// The checksums of all Wasm blobs in the Wasm store
+    pub fn stored_checksums(&self) -> Result<Vec<Checksum>, Error> {
+        match &self.inner.lock().unwrap().wasm_store {
+            WasmStore::Disk(path) => stored_checksums_on_disk(path),
+            WasmStore::Memory { blobs, .. } => Ok(blobs.keys().copied().collect()),
+        }
+    }
+
+    /// Unpins a module. Not found checksums are silently ignored.
+    pub fn unpin(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.inner
+            .lock()
+            .unwrap()
+            .pinned_memory_cache
+            .remove(checksum);
+        Ok(())
+    }
+
+    pub fn get_instance<A, S, Q>(
+        &self,
+        checksum: &Checksum,
+        backend: Backend<A, S, Q>,
+        options: InstanceOptions,
+    ) -> Result<Instance<A, S, Q>, Error>
+    where
+        A: BackendApi + 'static,
+        S: Storage + 'static,
+        Q: Querier + 'static,
+    {
+        let module = self.get_module(checksum)?;
+        let _lock = self.instantiation_lock.lock().unwrap();
+        let instance = instance_from_module(
+            &module,
+            backend,
+            options.gas_limit,
+            options.print_debug,
+            None,
+        )?;
+        Ok(instance)
+    }
+
+    fn get_module(&self, checksum: &Checksum) -> Result<Module, Error> {
+        let mut cache = self.inner.lock().unwrap();
+        if let Some(module) = cache.pinned_memory_cache.get(checksum) {
+            let module = module.module.clone();
+            cache.stats.hits_pinned_memory_cache =
+                cache.stats.hits_pinned_memory_cache.saturating_add(1);
+            return Ok(module);
+        }
+
+        let cached = cache
+            .memory_cache
+            .as_mut()
+            .and_then(|memory_cache| memory_cache.get(checksum).cloned());
+        if let Some(module) = cached {
+            cache.stats.hits_memory_cache = cache.stats.hits_memory_cache.saturating_add(1);
+            return Ok(module.module);
+        }
+
+        cache.stats.misses = cache.stats.misses.saturating_add(1);
+        let module = cache.compile(checksum)?;
+        cache.store_in_memory_cache(*checksum, module.clone())?;
+        Ok(module.module)
+    }
+}
+
+impl LocalCacheInner {
+    /// Loads the Wasm blob from the Wasm store and compiles it for execution
+    fn compile(&self, checksum: &Checksum) -> Result<SizedModule, Error> {
+        let wasm = self.wasm_store.load(checksum)?;
+        let module = compile(&wasm, Some(self.instance_memory_limit), &[])?;
+        Ok(SizedModule::new(module))
+    }
+
+    fn store_in_memory_cache(
+        &mut self,
+        checksum: Checksum,
+        module: SizedModule,
+    ) -> Result<(), Error> {
+        if let Some(memory_cache) = &mut self.memory_cache {
+            memory_cache
+                .put_with_weight(checksum, module)
+                .map_err(|_e| Error::cache_err("Module too large for the memory cache"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The directory in which Wasm blobs are stored, by cosmwasm-vm's `Cache` as well
+pub fn wasm_dir(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join(WASM_DIR)
+}
+
+/// The file in which the instance memory limits set per checksum are stored, next to the
+/// Wasm directory
+pub fn instance_memory_limits_file(base_dir: &Path) -> PathBuf {
+    base_dir.join(STATE_DIR).join(INSTANCE_MEMORY_LIMITS_FILE)
+}
+
+/// The checksums of the Wasm blobs in the directory, judging by their file names
+pub fn stored_checksums_on_disk(dir: &Path) -> Result<Vec<Checksum>, Error> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| Error::cache_err(format!("Error reading Wasm directory: {}", e)))?;
+    let mut checksums = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| Error::cache_err(format!("Error reading Wasm directory: {}", e)))?;
+        // Files stored by older versions of cosmwasm-vm have no extension
+        let path = entry.path();
+        let checksum = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| hex::decode(stem).ok())
+            .and_then(|bytes| Checksum::try_from(bytes.as_slice()).ok());
+        if let Some(checksum) = checksum {
+            checksums.push(checksum);
+        }
+    }
+    Ok(checksums)
+}
+
+fn save_wasm_to_disk(dir: &Path, wasm: &[u8]) -> Result<Checksum, Error> {
+    let checksum = Checksum::generate(wasm);
+    let filepath = dir.join(checksum.to_hex()).with_extension("wasm");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(filepath)
+        .map_err(|e| Error::cache_err(format!("Error opening Wasm file for writing: {}", e)))?;
+    file.write_all(wasm)
+        .map_err(|e| Error::cache_err(format!("Error writing Wasm file: {}", e)))?;
+    Ok(checksum)
+}
+
+fn load_wasm_from_disk(dir: &Path, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+    // Files stored by older versions of cosmwasm-vm have no extension
+    let path = dir.join(checksum.to_hex());
+    let mut file = File::open(path.with_extension("wasm"))
+        .or_else(|_| File::open(path))
+        .map_err(|_e| Error::cache_err("Error opening Wasm file for reading"))?;
+    let mut wasm = Vec::<u8>::new();
+    file.read_to_end(&mut wasm)
+        .map_err(|_e| Error::cache_err("Error reading Wasm file"))?;
+    // verify hash matches (integrity check)
+    if Checksum::generate(&wasm) != *checksum {
+        return Err(Error::cache_err("Hash doesn't match stored data"));
+    }
+    Ok(wasm)
+}
+
+fn remove_wasm_from_disk(dir: &Path, checksum: &Checksum) -> Result<(), Error> {
+    let path = dir.join(checksum.to_hex());
+    let wasm_path = path.with_extension("wasm");
+
+    let path_exists = path.exists();
+    let wasm_path_exists = wasm_path.exists();
+    if !path_exists && !wasm_path_exists {
+        return Err(Error::cache_err("Wasm file does not exist"));
+    }
+    for path in [path, wasm_path] {
+        if path.exists() {
+            fs::remove_file(path)
+                .map_err(|_e| Error::cache_err("Error removing Wasm file from disk"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_vm::testing::{mock_backend, mock_env, mock_info};
+    use cosmwasm_vm::{call_instantiate_raw, to_vec};
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::TempDir;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+
+    fn new_cache(dir: &TempDir) -> LocalCache {
+        LocalCache::new(dir.path(), Size::mebi(200), Size::mebi(32)).unwrap()
+    }
+
+    #[test]
+    fn save_wasm_unchecked_works() {
+        let dir = TempDir::new().unwrap();
+        let cache = new_cache(&dir);
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+        assert_eq!(checksum, Checksum::generate(HACKATOM));
+        assert!(dir
+            .path()
+            .join("state/wasm")
+            .join(checksum.to_hex())
+            .with_extension("wasm")
+            .exists());
+        assert_eq!(cache.metrics().elements_memory_cache, 1);
+        assert_eq!(cache.load_wasm(&checksum).unwrap(), HACKATOM);
+    }
+
+    #[test]
+    fn stored_checksums_works() {
+        let dir = TempDir::new().unwrap();
+        let cache = new_cache(&dir);
+        assert_eq!(cache.stored_checksums().unwrap(), vec![]);
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+        // other files are ignored
+        fs::write(wasm_dir(dir.path()).join("README"), b"not wasm").unwrap();
+        assert_eq!(cache.stored_checksums().unwrap(), vec![checksum]);
+
+        let cache = LocalCache::in_memory(Size::mebi(10), Size::mebi(200), Size::mebi(32));
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+        assert_eq!(cache.stored_checksums().unwrap(), vec![checksum]);
+        cache.remove_wasm(&checksum).unwrap();
+        assert_eq!(cache.stored_checksums().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn remove_wasm_works() {
+        let dir = TempDir::new().unwrap();
+        let cache = new_cache(&dir);
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+
+        cache.remove_wasm(&checksum).unwrap();
+        assert_eq!(cache.metrics().elements_memory_cache, 0);
+        let err = cache.load_wasm(&checksum).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cache error: Error opening Wasm file for reading"
+        );
+
+        let err = cache.remove_wasm(&checksum).unwrap_err();
+        assert_eq!(err.to_string(), "Cache error: Wasm file does not exist");
+    }
+
+    #[test]
+    fn in_memory_works() {
+        let cache = LocalCache::in_memory(Size::mebi(10), Size::mebi(200), Size::mebi(32));
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+        // saving the same Wasm again does not count twice
+        cache.save_wasm_unchecked(HACKATOM).unwrap();
+        assert_eq!(cache.load_wasm(&checksum).unwrap(), HACKATOM);
+
+        // modules are recompiled from the in-memory Wasm store
+        cache.inner.lock().unwrap().memory_cache = None;
+        cache.pin(&checksum).unwrap();
+        assert_eq!(cache.metrics().stats.misses, 1);
+
+        cache.remove_wasm(&checksum).unwrap();
+        let err = cache.load_wasm(&checksum).unwrap_err();
+        assert_eq!(err.to_string(), "Cache error: Wasm not found in memory");
+        let err = cache.remove_wasm(&checksum).unwrap_err();
+        assert_eq!(err.to_string(), "Cache error: Wasm not found in memory");
+    }
+
+    #[test]
+    fn in_memory_respects_wasm_store_size() {
+        let cache =
+            LocalCache::in_memory(Size(HACKATOM.len() - 1), Size::mebi(200), Size::mebi(32));
+        let err = cache.save_wasm_unchecked(HACKATOM).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Cache error: In-memory Wasm store size limit of {} bytes exceeded",
+                HACKATOM.len() - 1
+            )
+        );
+
+        let cache = LocalCache::in_memory(Size(HACKATOM.len()), Size::mebi(200), Size::mebi(32));
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+        cache.remove_wasm(&checksum).unwrap();
+        // the space is available again after removal
+        cache.save_wasm_unchecked(HACKATOM).unwrap();
+    }
+
+    #[test]
+    fn pin_and_get_instance_work() {
+        let dir = TempDir::new().unwrap();
+        let cache = new_cache(&dir);
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+
+        cache.pin(&checksum).unwrap();
+        cache.pin(&checksum).unwrap();
+        let metrics = cache.metrics();
+        assert_eq!(metrics.stats.hits_memory_cache, 1);
+        assert_eq!(metrics.elements_pinned_memory_cache, 1);
+        assert!(metrics.size_pinned_memory_cache > 0);
+
+        let options = InstanceOptions {
+            gas_limit: 500_000_000_000,
+            print_debug: false,
+        };
+        let mut instance = cache
+            .get_instance(&checksum, mock_backend(&[]), options)
+            .unwrap();
+        let raw_env = to_vec(&mock_env()).unwrap();
+        let raw_info = to_vec(&mock_info("creator", &[])).unwrap();
+        let res = call_instantiate_raw(
+            &mut instance,
+            &raw_env,
+            &raw_info,
+            br#"{"verifier": "verifies", "beneficiary": "benefits"}"#,
+        );
+        assert!(res.is_ok());
+        assert_eq!(cache.metrics().stats.hits_pinned_memory_cache, 1);
+
+        cache.unpin(&checksum).unwrap();
+        assert_eq!(cache.metrics().elements_pinned_memory_cache, 0);
+    }
+
+    #[test]
+    fn get_instance_recompiles_from_disk() {
+        let dir = TempDir::new().unwrap();
+        let checksum = new_cache(&dir).save_wasm_unchecked(HACKATOM).unwrap();
+
+        // A new cache instance with the same directory has nothing in memory
+        let cache = new_cache(&dir);
+        let options = InstanceOptions {
+            gas_limit: 500_000_000_000,
+            print_debug: false,
+        };
+        let _instance = cache
+            .get_instance(&checksum, mock_backend(&[]), options)
+            .unwrap();
+        let _instance = cache
+            .get_instance(&checksum, mock_backend(&[]), options)
+            .unwrap();
+        let stats = cache.metrics().stats;
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits_memory_cache, 1);
+    }
+
+    #[test]
+    fn local_cache_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LocalCache>();
+    }
+
+    #[test]
+    fn concurrent_access_works() {
+        let dir = TempDir::new().unwrap();
+        let cache = Arc::new(new_cache(&dir));
+        let checksum = cache.save_wasm_unchecked(HACKATOM).unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|index| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    let options = InstanceOptions {
+                        gas_limit: 500_000_000_000,
+                        print_debug: false,
+                    };
+                    let raw_env = to_vec(&mock_env()).unwrap();
+                    let raw_info = to_vec(&mock_info("creator", &[])).unwrap();
+                    for _ in 0..5 {
+                        if index % 2 == 0 {
+                            cache.pin(&checksum).unwrap();
+                        } else {
+                            cache.unpin(&checksum).unwrap();
+                        }
+                        let mut instance = cache
+                            .get_instance(&checksum, mock_backend(&[]), options)
+                            .unwrap();
+                        let res = call_instantiate_raw(
+                            &mut instance,
+                            &raw_env,
+                            &raw_info,
+                            br#"{"verifier": "verifies", "beneficiary": "benefits"}"#,
+                        );
+                        assert!(res.is_ok());
+                        assert_eq!(cache.stored_checksums().unwrap(), vec![checksum]);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // every instance was created from memory
+        let stats = cache.metrics().stats;
+        assert!(stats.hits_pinned_memory_cache + stats.hits_memory_cache >= 20);
+        assert_eq!(stats.misses, 0);
+    }
+}
@@ -0,0 +1,30 @@
+//! A minimal logger writing to stderr, similar to the panic messages we print elsewhere.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[libwasmvm] {} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs the stderr logger (if no other logger is installed yet) and sets the max log level.
+///
+/// The level is process wide, i.e. the last cache initialized wins.
+pub fn set_log_level(level: LevelFilter) {
+    // This fails if a logger is already set, which is fine since we only need one.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
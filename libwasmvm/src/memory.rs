@@ -18,7 +18,7 @@ pub struct ByteSliceView {
 impl ByteSliceView {
     /// ByteSliceViews are only constructed in Go. This constructor is a way to mimic the behaviour
     /// when testing FFI calls from Rust. It must not be used in production code.
-    #[cfg(test)]
+    #[cfg(any(test, feature = "testing"))]
     pub fn new(source: &[u8]) -> Self {
         Self {
             is_nil: false,
@@ -29,7 +29,7 @@ impl ByteSliceView {
 
     /// ByteSliceViews are only constructed in Go. This constructor is a way to mimic the behaviour
     /// when testing FFI calls from Rust. It must not be used in production code.
-    #[cfg(test)]
+    #[cfg(any(test, feature = "testing"))]
     pub fn nil() -> Self {
         Self {
             is_nil: true,
@@ -89,6 +89,19 @@ impl U8SliceView {
             },
         }
     }
+
+    /// Provides a reference to the included data. Go does this on the other side of the FFI
+    /// boundary, so this is only needed for test callbacks written in Rust.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn read(&self) -> Option<&[u8]> {
+        if self.is_none {
+            None
+        } else if self.len == 0 {
+            Some(&[])
+        } else {
+            Some(unsafe { slice::from_raw_parts(self.ptr, self.len) })
+        }
+    }
 }
 
 /// An optional Vector type that requires explicit creation and destruction
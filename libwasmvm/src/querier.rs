@@ -1,8 +1,22 @@
-use cosmwasm_std::{Binary, ContractResult, SystemError, SystemResult};
-use cosmwasm_vm::{BackendResult, GasInfo, Querier};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
 
+use cosmwasm_std::{to_binary, Binary, ContractResult, SystemError, SystemResult};
+use cosmwasm_vm::{BackendError, BackendResult, GasInfo, Querier};
+use serde::Deserialize;
+
+use crate::activation::{FeatureSet, HostFeature};
+use crate::crypto::{parse_crypto_query, CryptoQuery, CRYPTO_QUERY_KEY};
 use crate::error::GoError;
+use crate::evm::{
+    evm_call_target, parse_evm_query, EvmCallRequest, EvmCallResponse, EVM_QUERY_KEY,
+};
+use crate::gas_config::current_gas_config;
 use crate::memory::{U8SliceView, UnmanagedVector};
+use crate::query_router::{QueryResult, QueryRouter};
+use crate::replay::{record, BackendRequest, BackendResponse};
+use crate::vm::VmQuerier;
 
 // this represents something passed in from the caller side of FFI
 #[repr(C)]
@@ -23,6 +37,30 @@ pub struct Querier_vtable {
         *mut UnmanagedVector, // result output
         *mut UnmanagedVector, // error message output
     ) -> i32,
+    /// Answers EVM queries. Those are unsupported if unset.
+    pub query_evm: Option<
+        extern "C" fn(
+            *const querier_t,
+            u64,
+            *mut u64,
+            U8SliceView,          // EVM contract address
+            U8SliceView,          // call data
+            u64,                  // EVM gas limit
+            *mut u64,             // EVM gas used
+            *mut UnmanagedVector, // return data output
+            *mut UnmanagedVector, // error message output
+        ) -> i32,
+    >,
+    /// Charges the gas of a query answered from memoized responses to the gas meter used by
+    /// queries. Queries are not memoized if unset.
+    pub consume_gas: Option<
+        extern "C" fn(
+            *const querier_t,
+            u64,                  // gas to consume
+            *mut u64,             // used gas output
+            *mut UnmanagedVector, // error message output
+        ) -> i32,
+    >,
 }
 
 #[repr(C)]
@@ -35,12 +73,317 @@ pub struct GoQuerier {
 // TODO: check if we can do this safer...
 unsafe impl Send for GoQuerier {}
 
-impl Querier for GoQuerier {
-    fn query_raw(
-        &self,
-        request: &[u8],
-        gas_limit: u64,
-    ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+/// Limits for nested queries, i.e. contract A queries B, which queries C and so on.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct QueryLimits {
+    /// The maximum number of nested queries in flight. Unlimited if unset.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// The maximum gas that all queries of a call (including nested ones) may use together.
+    /// Unlimited if unset.
+    #[serde(default)]
+    pub max_gas: Option<u64>,
+    /// If true, exceeding a limit aborts the whole call tree with a query limit error.
+    /// Otherwise only the query exceeding it fails, with a system error the contract can handle.
+    #[serde(default)]
+    pub abort_on_exceeded: bool,
+}
+
+/// A limit of [`QueryLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryLimit {
+    Depth,
+    Gas,
+}
+
+impl std::fmt::Display for QueryLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryLimit::Depth => write!(f, "depth"),
+            QueryLimit::Gas => write!(f, "gas"),
+        }
+    }
+}
+
+/// The query behaviour of a call tree, set by the outermost call
+#[derive(Clone, Default)]
+pub struct QueryOptions {
+    pub limits: QueryLimits,
+    /// If true, responses are memoized per call
+    pub memoize: bool,
+    /// If true, a response from Go that cannot be parsed aborts the call with a backend error
+    /// instead of being passed to the contract as a system error
+    pub strict: bool,
+    /// Answers selected queries without calling into Go
+    pub router: Option<Arc<QueryRouter>>,
+    /// The block height of the outermost call, which selects the query routes
+    pub height: u64,
+    /// The host features of the outermost call
+    pub features: FeatureSet,
+}
+
+#[derive(Default)]
+struct QueryState {
+    options: QueryOptions,
+    /// The memoized responses and their gas cost by raw request, one map per call in progress
+    memos: Vec<HashMap<Vec<u8>, (QueryResult, u64)>>,
+    /// The number of contract calls in progress on this thread
+    calls: u32,
+    /// The number of queries in progress on this thread
+    depth: u32,
+    /// The gas used by all completed queries since the outermost call started
+    gas_used: u64,
+    /// The EVM gas used by all EVM queries since the outermost call started
+    evm_gas_used: u64,
+    /// The first limit exceeded by a query and its value if exceeding limits aborts the call tree
+    exceeded: Option<(QueryLimit, u64)>,
+}
+
+thread_local! {
+    // A query from Go into another contract is a nested cgo call, which runs on the same
+    // thread as the outer call. So all state of a call tree lives in one thread.
+    static QUERY_STATE: RefCell<QueryState> = RefCell::new(QueryState::default());
+}
+
+/// Marks a call into a contract. The outermost call on a thread sets the query options
+/// and resets the query state.
+pub struct CallScope {
+    /// The EVM gas used in the call tree when this call started
+    evm_gas_before: u64,
+}
+
+impl CallScope {
+    pub fn enter(options: QueryOptions) -> Self {
+        let evm_gas_before = QUERY_STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            if state.calls == 0 {
+                *state = QueryState {
+                    options,
+                    ..QueryState::default()
+                };
+            }
+            state.calls += 1;
+            state.memos.push(HashMap::new());
+            state.evm_gas_used
+        });
+        CallScope { evm_gas_before }
+    }
+
+    /// The query limit exceeded in the call tree, if any. Calls failing after that failed
+    /// because of it.
+    pub fn exceeded_limit(&self) -> Option<(QueryLimit, u64)> {
+        QUERY_STATE.with(|state| state.borrow().exceeded)
+    }
+
+    /// The EVM gas used by EVM queries of this call, including the ones of nested calls
+    pub fn evm_gas_used(&self) -> u64 {
+        QUERY_STATE.with(|state| {
+            state
+                .borrow()
+                .evm_gas_used
+                .saturating_sub(self.evm_gas_before)
+        })
+    }
+}
+
+impl Drop for CallScope {
+    fn drop(&mut self) {
+        QUERY_STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.calls = state.calls.saturating_sub(1);
+            state.memos.pop();
+        });
+    }
+}
+
+/// Drops the memoized responses of the current call. This must be called whenever the
+/// contract writes to storage since responses (e.g. of queries to itself) might change.
+pub fn invalidate_query_memo() {
+    QUERY_STATE.with(|state| {
+        if let Some(memo) = state.borrow_mut().memos.last_mut() {
+            memo.clear();
+        }
+    });
+}
+
+/// Returns the memoized response and the gas used by the original query if memoization is
+/// enabled and the gas fits into `gas_limit`, such that a hit behaves exactly like a miss.
+fn memoized(request: &[u8], gas_limit: u64) -> Option<(QueryResult, u64)> {
+    QUERY_STATE.with(|state| {
+        let state = state.borrow();
+        if !state.options.memoize {
+            return None;
+        }
+        state
+            .memos
+            .last()
+            .and_then(|memo| memo.get(request))
+            .filter(|(_, gas)| *gas <= gas_limit)
+            .cloned()
+    })
+}
+
+fn memoize(request: &[u8], result: &QueryResult, gas: u64) {
+    QUERY_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state.options.memoize {
+            return;
+        }
+        if let Some(memo) = state.memos.last_mut() {
+            memo.insert(request.to_vec(), (result.clone(), gas));
+        }
+    });
+}
+
+/// Returns the response of the router and its gas cost if the router answers the request
+/// within `gas_limit`
+fn routed(request: &[u8], gas_limit: u64) -> Option<(QueryResult, u64)> {
+    let (router, height) = QUERY_STATE.with(|state| {
+        let state = state.borrow();
+        (state.options.router.clone(), state.options.height)
+    });
+    router?
+        .route(request, height)
+        .filter(|(_, gas_cost)| *gas_cost <= gas_limit)
+}
+
+fn strict() -> bool {
+    QUERY_STATE.with(|state| state.borrow().options.strict)
+}
+
+fn feature_active(feature: HostFeature) -> bool {
+    QUERY_STATE.with(|state| state.borrow().options.features.is_active(feature))
+}
+
+/// The outcome of a query that exceeded a limit, see [`QueryLimits::abort_on_exceeded`]
+type Exceeded = Result<QueryResult, BackendError>;
+
+/// Starts a query. Returns the gas limit for the query and the gas used before it,
+/// or the outcome of the query if a limit is exceeded.
+fn begin_query(gas_limit: u64, request: &[u8]) -> Result<(u64, u64), Exceeded> {
+    QUERY_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(exceeded) = state.exceeded {
+            return Err(limit_exceeded(&mut state, exceeded, request));
+        }
+        if let Some(max_depth) = state.options.limits.max_depth {
+            if state.depth >= max_depth {
+                return Err(limit_exceeded(
+                    &mut state,
+                    (QueryLimit::Depth, max_depth.into()),
+                    request,
+                ));
+            }
+        }
+        let gas_limit = match state.options.limits.max_gas {
+            Some(max_gas) => {
+                let remaining = max_gas.saturating_sub(state.gas_used);
+                if remaining == 0 {
+                    return Err(limit_exceeded(
+                        &mut state,
+                        (QueryLimit::Gas, max_gas),
+                        request,
+                    ));
+                }
+                gas_limit.min(remaining)
+            }
+            None => gas_limit,
+        };
+        state.depth += 1;
+        Ok((gas_limit, state.gas_used))
+    })
+}
+
+fn add_evm_gas(evm_gas_used: u64) {
+    QUERY_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.evm_gas_used = state.evm_gas_used.saturating_add(evm_gas_used);
+    });
+}
+
+/// Ends a query that used `used_gas` including all queries nested in it.
+/// Returns the outcome of the query if a limit was exceeded by it or a nested one.
+fn end_query(gas_used_before: u64, used_gas: u64, request: &[u8]) -> Result<(), Exceeded> {
+    QUERY_STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.depth = state.depth.saturating_sub(1);
+        // Nested queries are included in `used_gas`, so we overwrite their contribution
+        state.gas_used = gas_used_before.saturating_add(used_gas);
+        if let Some(exceeded) = state.exceeded {
+            return Err(limit_exceeded(&mut state, exceeded, request));
+        }
+        match state.options.limits.max_gas {
+            Some(max_gas) if state.gas_used > max_gas => Err(limit_exceeded(
+                &mut state,
+                (QueryLimit::Gas, max_gas),
+                request,
+            )),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// By default, the query exceeding a limit fails with a system error, which the querying
+/// contract can handle like any other failed query. If exceeding limits aborts calls, the
+/// exceeded limit is recorded for the call tree and all queries in it fail with a backend error.
+fn limit_exceeded(state: &mut QueryState, exceeded: (QueryLimit, u64), request: &[u8]) -> Exceeded {
+    if state.options.limits.abort_on_exceeded {
+        let (limit, max) = *state.exceeded.get_or_insert(exceeded);
+        return Err(BackendError::unknown(format!(
+            "Query {} limit of {} exceeded",
+            limit, max
+        )));
+    }
+    let (limit, max) = exceeded;
+    Ok(SystemResult::Err(SystemError::InvalidRequest {
+        error: format!("Query {} limit of {} exceeded", limit, max),
+        request: request.into(),
+    }))
+}
+
+/// Answers a query the host does not support, e.g. of a host feature that is not active yet
+fn unsupported(kind: &str, request: &[u8], gas_used_before: u64) -> BackendResult<QueryResult> {
+    let _ = end_query(gas_used_before, 0, request);
+    let result = SystemResult::Err(SystemError::UnsupportedRequest {
+        kind: kind.to_string(),
+    });
+    (Ok(result), GasInfo::free())
+}
+
+/// Answers a crypto query natively. Its cost is charged to the instance like the ones of
+/// cosmwasm-vm's crypto imports and counts towards the query gas limit.
+fn query_crypto(
+    query: Result<CryptoQuery, String>,
+    request: &[u8],
+    gas_used_before: u64,
+) -> BackendResult<QueryResult> {
+    let query = match query {
+        Ok(query) => query,
+        Err(error) => {
+            let _ = end_query(gas_used_before, 0, request);
+            let result = SystemResult::Err(SystemError::InvalidRequest {
+                error,
+                request: request.into(),
+            });
+            return (Ok(result), GasInfo::free());
+        }
+    };
+    let gas_cost = query.gas_cost(&current_gas_config());
+    let gas_info = GasInfo::with_cost(gas_cost);
+    if let Err(outcome) = end_query(gas_used_before, gas_cost, request) {
+        return (outcome, gas_info);
+    }
+    let result = match query.execute() {
+        Ok(data) => SystemResult::Ok(ContractResult::Ok(data)),
+        // Invalid inputs, e.g. a malformed signature, are an error the contract can handle
+        Err(msg) => SystemResult::Ok(ContractResult::Err(msg)),
+    };
+    (Ok(result), gas_info)
+}
+
+impl VmQuerier for GoQuerier {
+    fn query_external(&self, request: &[u8], gas_limit: u64) -> BackendResult<Vec<u8>> {
         let mut output = UnmanagedVector::default();
         let mut error_msg = UnmanagedVector::default();
         let mut used_gas = 0_u64;
@@ -70,14 +413,853 @@ impl Querier for GoQuerier {
                 return (Err(err), gas_info);
             }
         }
+        (Ok(output.unwrap_or_default()), gas_info)
+    }
+
+    fn query_evm(
+        &self,
+        to: &[u8],
+        data: &[u8],
+        evm_gas_limit: u64,
+        gas_limit: u64,
+    ) -> Option<(BackendResult<Vec<u8>>, u64)> {
+        let callback = self.vtable.query_evm?;
+
+        let mut output = UnmanagedVector::default();
+        let mut error_msg = UnmanagedVector::default();
+        let mut used_gas = 0_u64;
+        let mut evm_gas_used = 0_u64;
+        let go_result: GoError = callback(
+            self.state,
+            gas_limit,
+            &mut used_gas as *mut u64,
+            U8SliceView::new(Some(to)),
+            U8SliceView::new(Some(data)),
+            evm_gas_limit,
+            &mut evm_gas_used as *mut u64,
+            &mut output as *mut UnmanagedVector,
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+        // We destruct the UnmanagedVector here, no matter if we need the data.
+        let output = output.consume();
+
+        let gas_info = GasInfo::with_externally_used(used_gas);
+
+        let default = || format!("Failed to call EVM contract 0x{}", hex::encode(to));
+        let result = unsafe { go_result.into_result(error_msg, default) }
+            .map(|()| output.unwrap_or_default());
+        Some(((result, gas_info), evm_gas_used))
+    }
+
+    fn consume_gas(&self, gas: u64) -> Option<BackendResult<()>> {
+        let callback = self.vtable.consume_gas?;
+
+        let mut error_msg = UnmanagedVector::default();
+        let mut used_gas = 0_u64;
+        let go_result: GoError = callback(
+            self.state,
+            gas,
+            &mut used_gas as *mut u64,
+            &mut error_msg as *mut UnmanagedVector,
+        )
+        .into();
+
+        let gas_info = GasInfo::with_externally_used(used_gas);
+
+        let default = || format!("Failed to consume {} gas for a memoized query", gas);
+        let result = unsafe { go_result.into_result(error_msg, default) };
+        Some((result, gas_info))
+    }
+}
+
+/// The `Querier` used for calls. It answers queries via the query router and memoized responses
+/// where possible and asks the host (Go by default) otherwise. Query limits apply to all of them.
+pub struct HostQuerier<Q = GoQuerier> {
+    inner: Q,
+}
+
+impl<Q: VmQuerier> HostQuerier<Q> {
+    pub fn new(inner: Q) -> Self {
+        HostQuerier { inner }
+    }
+
+    pub fn into_inner(self) -> Q {
+        self.inner
+    }
+}
+
+impl<Q: VmQuerier> Querier for HostQuerier<Q> {
+    fn query_raw(
+        &self,
+        request: &[u8],
+        gas_limit: u64,
+    ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+        // The gas limit is in instance gas, the host works with host gas
+        let config = current_gas_config();
+        let outcome = self.query(request, config.to_host_gas(gas_limit));
+        let outcome = config.charge(outcome, config.query_overhead);
+        record(
+            || BackendRequest::Query {
+                request: request.into(),
+                gas_limit,
+            },
+            &outcome,
+            |response| BackendResponse::Query(response.clone()),
+        );
+        outcome
+    }
+}
+
+impl<Q: VmQuerier> HostQuerier<Q> {
+    fn query(
+        &self,
+        request: &[u8],
+        gas_limit: u64,
+    ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+        let (gas_limit, gas_used_before) = match begin_query(gas_limit, request) {
+            Ok(limits) => limits,
+            Err(outcome) => return (outcome, GasInfo::free()),
+        };
+
+        if let Some((result, gas_cost)) = routed(request, gas_limit) {
+            if let Some(outcome) = self.answer(request, result, gas_cost, gas_used_before) {
+                return outcome;
+            }
+        }
+
+        if let Some(call) = parse_evm_query(request) {
+            if !feature_active(HostFeature::EvmQueries) {
+                return unsupported(EVM_QUERY_KEY, request, gas_used_before);
+            }
+            return self.query_evm(call, request, gas_limit, gas_used_before);
+        }
+
+        if let Some(query) = parse_crypto_query(request) {
+            if !feature_active(HostFeature::CryptoQueries) {
+                return unsupported(CRYPTO_QUERY_KEY, request, gas_used_before);
+            }
+            return query_crypto(query, request, gas_used_before);
+        }
+
+        if let Some((result, used_gas)) = memoized(request, gas_limit) {
+            // A hit is charged the gas of the original query, so it cannot be told apart from a miss
+            if let Some(outcome) = self.answer(request, result, used_gas, gas_used_before) {
+                return outcome;
+            }
+        }
 
-        let bin_result: Vec<u8> = output.unwrap_or_default();
-        let result = serde_json::from_slice(&bin_result).or_else(|e| {
-            Ok(SystemResult::Err(SystemError::InvalidResponse {
+        let (output, gas_info) = self.inner.query_external(request, gas_limit);
+        let used_gas = gas_info.cost.saturating_add(gas_info.externally_used);
+        if let Err(outcome) = end_query(gas_used_before, used_gas, request) {
+            return (outcome, gas_info);
+        }
+        let bin_result = match output {
+            Ok(output) => output,
+            Err(err) => return (Err(err), gas_info),
+        };
+
+        let result = match serde_json::from_slice(&bin_result) {
+            Ok(result) => {
+                memoize(request, &result, used_gas);
+                result
+            }
+            Err(e) if strict() => {
+                // A broken response is a bug in the host, so the contract must not see it
+                return (
+                    Err(BackendError::unknown(format!(
+                        "Invalid Go response to query: {}",
+                        e
+                    ))),
+                    gas_info,
+                );
+            }
+            Err(e) => SystemResult::Err(SystemError::InvalidResponse {
                 error: format!("Parsing Go response: {}", e),
                 response: bin_result.into(),
-            }))
+            }),
+        };
+        (Ok(result), gas_info)
+    }
+}
+
+impl<Q: VmQuerier> HostQuerier<Q> {
+    /// Answers a query without asking the host, charging `gas` via [`VmQuerier::consume_gas`]
+    /// such that the gas is accounted for exactly like the one of queries answered by the host.
+    /// Returns None if the host cannot consume gas, in which case it has to be asked.
+    fn answer(
+        &self,
+        request: &[u8],
+        result: QueryResult,
+        gas: u64,
+        gas_used_before: u64,
+    ) -> Option<BackendResult<QueryResult>> {
+        let (charged, gas_info) = self.inner.consume_gas(gas)?;
+        if let Err(outcome) = end_query(gas_used_before, gas, request) {
+            return Some((outcome, gas_info));
+        }
+        Some((charged.map(|()| result), gas_info))
+    }
+
+    /// Answers an EVM query via [`VmQuerier::query_evm`]. The gas used is handled like the one of
+    /// other queries, the EVM gas used is accounted separately.
+    fn query_evm(
+        &self,
+        call: Result<EvmCallRequest, String>,
+        request: &[u8],
+        gas_limit: u64,
+        gas_used_before: u64,
+    ) -> BackendResult<QueryResult> {
+        let (call, to) = match call.and_then(|call| evm_call_target(&call).map(|to| (call, to))) {
+            Ok(call) => call,
+            Err(error) => {
+                let _ = end_query(gas_used_before, 0, request);
+                let result = SystemResult::Err(SystemError::InvalidRequest {
+                    error,
+                    request: request.into(),
+                });
+                return (Ok(result), GasInfo::free());
+            }
+        };
+
+        let ((output, gas_info), evm_gas_used) =
+            match self
+                .inner
+                .query_evm(&to, call.data.as_slice(), call.gas, gas_limit)
+            {
+                Some(response) => response,
+                None => return unsupported(EVM_QUERY_KEY, request, gas_used_before),
+            };
+        let used_gas = gas_info.cost.saturating_add(gas_info.externally_used);
+        add_evm_gas(evm_gas_used);
+        if let Err(outcome) = end_query(gas_used_before, used_gas, request) {
+            return (outcome, gas_info);
+        }
+
+        let result = match output {
+            Ok(data) => {
+                let response = EvmCallResponse {
+                    data: data.into(),
+                    gas_used: evm_gas_used,
+                };
+                match to_binary(&response) {
+                    Ok(data) => SystemResult::Ok(ContractResult::Ok(data)),
+                    Err(e) => return (Err(BackendError::unknown(e.to_string())), gas_info),
+                }
+            }
+            // A reverted call is an error the contract can handle
+            Err(BackendError::UserErr { msg }) => SystemResult::Ok(ContractResult::Err(msg)),
+            Err(err) => return (Err(err), gas_info),
+        };
+        (Ok(result), gas_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::{ActivationContext, ActivationSchedule, CapabilityActivation};
+    use crate::crypto::{keccak256, Keccak256Response};
+    use crate::gas_config::{GasConfig, GasScope};
+    use crate::query_router::{QueryRoute, SNAPSHOT_HANDLER};
+    use std::cell::Cell;
+
+    /// The state behind the `querier_t` pointer of the test querier
+    struct TestState {
+        /// If true, every query queries again until a limit is hit
+        recursive: bool,
+        gas_per_query: u64,
+        gas_limits: RefCell<Vec<u64>>,
+        innermost: RefCell<Option<Result<QueryResult, BackendError>>>,
+        calls: Cell<u32>,
+        /// The gas consumed for memoized responses
+        consumed: Cell<u64>,
+        /// The raw response returned to Rust. A valid response if unset.
+        raw_response: Option<Vec<u8>>,
+    }
+
+    impl TestState {
+        fn new(recursive: bool) -> Self {
+            TestState {
+                recursive,
+                gas_per_query: 100,
+                gas_limits: RefCell::new(vec![]),
+                innermost: RefCell::new(None),
+                calls: Cell::new(0),
+                consumed: Cell::new(0),
+                raw_response: None,
+            }
+        }
+
+        fn go_querier(&self) -> GoQuerier {
+            GoQuerier {
+                state: self as *const TestState as *const querier_t,
+                vtable: Querier_vtable {
+                    query_external: test_query_external,
+                    query_evm: Some(test_query_evm),
+                    consume_gas: Some(test_consume_gas),
+                },
+            }
+        }
+
+        fn querier(&self) -> HostQuerier {
+            HostQuerier::new(self.go_querier())
+        }
+    }
+
+    extern "C" fn test_query_external(
+        querier: *const querier_t,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        _request: U8SliceView,
+        result: *mut UnmanagedVector,
+        _error_msg: *mut UnmanagedVector,
+    ) -> i32 {
+        let state = unsafe { &*(querier as *const TestState) };
+        state.calls.set(state.calls.get() + 1);
+        state.gas_limits.borrow_mut().push(gas_limit);
+
+        let mut gas = state.gas_per_query;
+        if state.recursive {
+            let (inner, gas_info) = state.querier().query_raw(b"{}", gas_limit);
+            gas += gas_info.externally_used;
+            if state.innermost.borrow().is_none() {
+                *state.innermost.borrow_mut() = Some(inner);
+            }
+        }
+
+        let response: SystemResult<ContractResult<Binary>> =
+            SystemResult::Ok(ContractResult::Ok(Binary::from(b"{}")));
+        let raw_response = state
+            .raw_response
+            .clone()
+            .unwrap_or_else(|| serde_json::to_vec(&response).unwrap());
+        unsafe {
+            *used_gas = gas;
+            *result = UnmanagedVector::new(Some(raw_response));
+        }
+        GoError::None as i32
+    }
+
+    extern "C" fn test_consume_gas(
+        querier: *const querier_t,
+        gas: u64,
+        used_gas: *mut u64,
+        _error_msg: *mut UnmanagedVector,
+    ) -> i32 {
+        let state = unsafe { &*(querier as *const TestState) };
+        state.consumed.set(state.consumed.get() + gas);
+        unsafe {
+            *used_gas = gas;
+        }
+        GoError::None as i32
+    }
+
+    /// Returns the reversed call data and uses 21000 EVM gas. Reverts if the EVM gas limit is 0.
+    extern "C" fn test_query_evm(
+        _querier: *const querier_t,
+        _gas_limit: u64,
+        used_gas: *mut u64,
+        to: U8SliceView,
+        data: U8SliceView,
+        evm_gas_limit: u64,
+        evm_gas_used: *mut u64,
+        result: *mut UnmanagedVector,
+        error_msg: *mut UnmanagedVector,
+    ) -> i32 {
+        assert_eq!(to.read().unwrap().len(), 20);
+        unsafe {
+            *used_gas = 500;
+            *evm_gas_used = evm_gas_limit.min(21000);
+            if evm_gas_limit == 0 {
+                *error_msg = UnmanagedVector::new(Some(b"execution reverted".to_vec()));
+                return GoError::User as i32;
+            }
+            let mut data = data.read().unwrap().to_vec();
+            data.reverse();
+            *result = UnmanagedVector::new(Some(data));
+        }
+        GoError::None as i32
+    }
+
+    fn limit_exceeded_error(limit: &str) -> QueryResult {
+        SystemResult::Err(SystemError::InvalidRequest {
+            error: format!("Query {} exceeded", limit),
+            request: Binary::from(b"{}"),
+        })
+    }
+
+    #[test]
+    fn query_raw_enforces_max_depth() {
+        let scope = CallScope::enter(QueryOptions {
+            limits: QueryLimits {
+                max_depth: Some(3),
+                ..QueryLimits::default()
+            },
+            ..QueryOptions::default()
+        });
+        let state = TestState::new(true);
+        let (result, gas_info) = state.querier().query_raw(b"{}", 1_000_000);
+        // only the innermost query fails, the contract making it handles the error
+        assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+        assert_eq!(gas_info.externally_used, 300);
+        assert_eq!(state.calls.get(), 3);
+        let innermost = state.innermost.borrow_mut().take().unwrap();
+        assert_eq!(innermost.unwrap(), limit_exceeded_error("depth limit of 3"));
+        assert_eq!(scope.exceeded_limit(), None);
+    }
+
+    #[test]
+    fn query_raw_aborts_on_exceeded_limit_if_configured() {
+        let scope = CallScope::enter(QueryOptions {
+            limits: QueryLimits {
+                max_depth: Some(3),
+                abort_on_exceeded: true,
+                ..QueryLimits::default()
+            },
+            ..QueryOptions::default()
+        });
+        let state = TestState::new(true);
+        let (result, gas_info) = state.querier().query_raw(b"{}", 1_000_000);
+        // the outer queries fail as well, since the call is aborted
+        assert_eq!(
+            result.unwrap_err(),
+            BackendError::unknown("Query depth limit of 3 exceeded")
+        );
+        assert_eq!(gas_info.externally_used, 300);
+        assert_eq!(state.calls.get(), 3);
+        let innermost = state.innermost.borrow_mut().take().unwrap();
+        assert_eq!(
+            innermost.unwrap_err(),
+            BackendError::unknown("Query depth limit of 3 exceeded")
+        );
+        assert_eq!(scope.exceeded_limit(), Some((QueryLimit::Depth, 3)));
+    }
+
+    #[test]
+    fn query_raw_enforces_max_gas() {
+        let scope = CallScope::enter(QueryOptions {
+            limits: QueryLimits {
+                max_gas: Some(250),
+                ..QueryLimits::default()
+            },
+            ..QueryOptions::default()
+        });
+        let state = TestState::new(false);
+        let querier = state.querier();
+        for _ in 0..2 {
+            let (result, _) = querier.query_raw(b"{}", 1_000_000);
+            assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+        }
+
+        // Only the remaining gas is available to the query
+        let (result, gas_info) = querier.query_raw(b"{}", 1_000_000);
+        assert_eq!(gas_info.externally_used, 100);
+        assert_eq!(result.unwrap(), limit_exceeded_error("gas limit of 250"));
+        assert_eq!(*state.gas_limits.borrow(), vec![250, 150, 50]);
+
+        // Go is not called anymore
+        let (result, gas_info) = querier.query_raw(b"{}", 1_000_000);
+        assert_eq!(result.unwrap(), limit_exceeded_error("gas limit of 250"));
+        assert_eq!(gas_info.externally_used, 0);
+        assert_eq!(state.calls.get(), 3);
+        assert_eq!(scope.exceeded_limit(), None);
+    }
+
+    #[test]
+    fn call_scope_resets_state() {
+        let state = TestState::new(false);
+        let options = QueryOptions {
+            limits: QueryLimits {
+                max_gas: Some(100),
+                abort_on_exceeded: true,
+                ..QueryLimits::default()
+            },
+            ..QueryOptions::default()
+        };
+        {
+            let scope = CallScope::enter(options.clone());
+            state.querier().query_raw(b"{}", 1_000_000).0.unwrap();
+            // nested calls share the budget
+            let _nested = CallScope::enter(QueryOptions::default());
+            let (result, _) = state.querier().query_raw(b"{}", 1_000_000);
+            assert!(result.is_err());
+            assert_eq!(scope.exceeded_limit(), Some((QueryLimit::Gas, 100)));
+        }
+        let _scope = CallScope::enter(options);
+        let (result, _) = state.querier().query_raw(b"{}", 1_000_000);
+        assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+    }
+
+    #[test]
+    fn query_raw_memoizes_responses() {
+        let state = TestState::new(false);
+        let querier = state.querier();
+
+        let _scope = CallScope::enter(QueryOptions {
+            memoize: true,
+            ..QueryOptions::default()
+        });
+        let (miss, miss_gas_info) = querier.query_raw(b"{}", 1_000_000);
+        assert!(matches!(miss, Ok(SystemResult::Ok(_))));
+        assert_eq!(miss_gas_info, GasInfo::with_externally_used(100));
+
+        // A hit is charged by the host like a miss
+        let (hit, hit_gas_info) = querier.query_raw(b"{}", 1_000_000);
+        assert_eq!(hit, miss);
+        assert_eq!(hit_gas_info, miss_gas_info);
+        assert_eq!(state.calls.get(), 1);
+        assert_eq!(state.consumed.get(), 100);
+
+        // different request
+        querier.query_raw(b"[]", 1_000_000).0.unwrap();
+        assert_eq!(state.calls.get(), 2);
+
+        // not enough gas for the original query, so we ask Go
+        querier.query_raw(b"{}", 99).0.unwrap();
+        assert_eq!(state.calls.get(), 3);
+
+        // a nested call has its own memo
+        {
+            let _nested = CallScope::enter(QueryOptions::default());
+            querier.query_raw(b"{}", 1_000_000).0.unwrap();
+            assert_eq!(state.calls.get(), 4);
+        }
+
+        // storage writes invalidate the memo
+        invalidate_query_memo();
+        querier.query_raw(b"{}", 1_000_000).0.unwrap();
+        assert_eq!(state.calls.get(), 5);
+
+        // hosts that cannot consume gas are always asked
+        let mut go_querier = state.go_querier();
+        go_querier.vtable.consume_gas = None;
+        let querier = HostQuerier::new(go_querier);
+        querier.query_raw(b"{}", 1_000_000).0.unwrap();
+        assert_eq!(state.calls.get(), 6);
+        assert_eq!(state.consumed.get(), 100);
+    }
+
+    #[test]
+    fn query_raw_uses_router() {
+        let state = TestState::new(false);
+        let querier = state.querier();
+        let router = Arc::new(QueryRouter::new(
+            [(
+                "bank".to_string(),
+                QueryRoute {
+                    handler: SNAPSHOT_HANDLER.to_string(),
+                    gas_cost: 30,
+                },
+            )]
+            .into(),
+            vec![],
+        ));
+        let request = br#"{"bank":{"balance":{"address":"foo","denom":"okt"}}}"#;
+        let snapshot = format!(
+            r#"[{{"request":"{}","response":{{"ok":{{"ok":"e30="}}}}}}]"#,
+            Binary::from(&request[..]).to_base64()
+        );
+        router
+            .snapshot()
+            .replace_from_json(7, snapshot.as_bytes())
+            .unwrap();
+
+        let _scope = CallScope::enter(QueryOptions {
+            limits: QueryLimits {
+                max_gas: Some(200),
+                ..QueryLimits::default()
+            },
+            router: Some(router),
+            height: 7,
+            ..QueryOptions::default()
+        });
+        // charged by the host like a query answered by it
+        let (result, gas_info) = querier.query_raw(request, 1_000_000);
+        assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+        assert_eq!(gas_info, GasInfo::with_externally_used(30));
+        assert_eq!(state.consumed.get(), 30);
+        assert_eq!(state.calls.get(), 0);
+
+        // not in the snapshot
+        querier.query_raw(b"{}", 1_000_000).0.unwrap();
+        assert_eq!(state.calls.get(), 1);
+
+        // routed queries count towards the query gas limit
+        for _ in 0..2 {
+            querier.query_raw(request, 1_000_000).0.unwrap();
+        }
+        assert_eq!(state.calls.get(), 1);
+        // the remaining 10 are not enough, so Go is asked and exceeds the limit
+        let (result, _) = querier.query_raw(request, 1_000_000);
+        assert!(matches!(result.unwrap(), SystemResult::Err(_)));
+        assert_eq!(state.calls.get(), 2);
+        assert_eq!(*state.gas_limits.borrow(), vec![170, 10]);
+        assert_eq!(state.consumed.get(), 90);
+    }
+
+    #[test]
+    fn query_raw_routes_only_if_host_consumes_gas() {
+        let state = TestState::new(false);
+        let mut go_querier = state.go_querier();
+        go_querier.vtable.consume_gas = None;
+        let querier = HostQuerier::new(go_querier);
+        let router = Arc::new(QueryRouter::new(
+            [(
+                "custom".to_string(),
+                QueryRoute {
+                    handler: SNAPSHOT_HANDLER.to_string(),
+                    gas_cost: 30,
+                },
+            )]
+            .into(),
+            vec![],
+        ));
+        router
+            .snapshot()
+            .replace_from_json(
+                0,
+                br#"[{"request":"eyJjdXN0b20iOnt9fQ==","response":{"ok":{"ok":"e30="}}}]"#,
+            )
+            .unwrap();
+        let _scope = CallScope::enter(QueryOptions {
+            router: Some(router),
+            ..QueryOptions::default()
+        });
+        let (_, gas_info) = querier.query_raw(br#"{"custom":{}}"#, 1_000_000);
+        assert_eq!(gas_info, GasInfo::with_externally_used(100));
+        assert_eq!(state.calls.get(), 1);
+    }
+
+    const BROKEN_RESPONSES: [&[u8]; 4] = [b"", b"{", br#"{"ok":{"ok":42}}"#, br#"{"foo":{}}"#];
+
+    #[test]
+    fn query_raw_passes_broken_responses_to_contract() {
+        let _scope = CallScope::enter(QueryOptions::default());
+        for response in BROKEN_RESPONSES {
+            let mut state = TestState::new(false);
+            state.raw_response = Some(response.to_vec());
+            let (result, gas_info) = state.querier().query_raw(b"{}", 1_000_000);
+            assert_eq!(gas_info.externally_used, 100);
+            match result.unwrap() {
+                SystemResult::Err(SystemError::InvalidResponse { error, response: r }) => {
+                    assert!(error.starts_with("Parsing Go response: "), "{}", error);
+                    assert_eq!(r.as_slice(), response);
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn query_raw_fails_for_broken_responses_in_strict_mode() {
+        let _scope = CallScope::enter(QueryOptions {
+            strict: true,
+            memoize: true,
+            ..QueryOptions::default()
         });
-        (result, gas_info)
+        for response in BROKEN_RESPONSES {
+            let mut state = TestState::new(false);
+            state.raw_response = Some(response.to_vec());
+            let (result, gas_info) = state.querier().query_raw(b"{}", 1_000_000);
+            // the gas used by Go is still reported
+            assert_eq!(gas_info.externally_used, 100);
+            match result.unwrap_err() {
+                BackendError::Unknown { msg } => {
+                    assert!(msg.starts_with("Invalid Go response to query: "), "{}", msg)
+                }
+                err => panic!("Unexpected error: {:?}", err),
+            }
+        }
+
+        // valid responses are not affected
+        let state = TestState::new(false);
+        let (result, _) = state.querier().query_raw(b"{}", 1_000_000);
+        assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+        // and broken ones were not memoized
+        assert_eq!(state.calls.get(), 1);
+    }
+
+    #[test]
+    fn query_raw_does_not_memoize_by_default() {
+        let state = TestState::new(false);
+        let _scope = CallScope::enter(QueryOptions::default());
+        for _ in 0..2 {
+            state.querier().query_raw(b"{}", 1_000_000).0.unwrap();
+        }
+        assert_eq!(state.calls.get(), 2);
+    }
+
+    #[test]
+    fn query_raw_answers_evm_queries() {
+        let scope = CallScope::enter(QueryOptions::default());
+        let state = TestState::new(false);
+        let request = |gas: u64| {
+            format!(
+                r#"{{"evm":{{"call":{{"to":"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed","data":"AQID","gas":{}}}}}}}"#,
+                gas
+            )
+        };
+
+        let (result, gas_info) = state
+            .querier()
+            .query_raw(request(50000).as_bytes(), 1_000_000);
+        assert_eq!(gas_info.externally_used, 500);
+        let response = EvmCallResponse {
+            data: Binary::from([3, 2, 1]),
+            gas_used: 21000,
+        };
+        assert_eq!(
+            result.unwrap(),
+            SystemResult::Ok(ContractResult::Ok(to_binary(&response).unwrap()))
+        );
+
+        // a revert is passed to the contract
+        let (result, gas_info) = state.querier().query_raw(request(0).as_bytes(), 1_000_000);
+        assert_eq!(gas_info.externally_used, 500);
+        assert_eq!(
+            result.unwrap(),
+            SystemResult::Ok(ContractResult::Err("execution reverted".to_string()))
+        );
+
+        // nested calls report their own EVM gas
+        {
+            let inner = CallScope::enter(QueryOptions::default());
+            state
+                .querier()
+                .query_raw(request(1000).as_bytes(), 1_000_000)
+                .0
+                .unwrap();
+            assert_eq!(inner.evm_gas_used(), 1000);
+        }
+        assert_eq!(scope.evm_gas_used(), 22000);
+        // Go's generic querier is not involved
+        assert_eq!(state.calls.get(), 0);
+
+        // invalid requests
+        let (result, gas_info) = state.querier().query_raw(
+            br#"{"evm":{"call":{"to":"0x00","data":"","gas":1}}}"#,
+            1_000_000,
+        );
+        assert_eq!(gas_info.externally_used, 0);
+        match result.unwrap() {
+            SystemResult::Err(SystemError::InvalidRequest { error, .. }) => {
+                assert!(error.starts_with("Invalid hex address length"), "{}", error)
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // without callback
+        let mut go_querier = state.go_querier();
+        go_querier.vtable.query_evm = None;
+        let (result, _) =
+            HostQuerier::new(go_querier).query_raw(request(50000).as_bytes(), 1_000_000);
+        assert_eq!(
+            result.unwrap(),
+            SystemResult::Err(SystemError::UnsupportedRequest {
+                kind: "evm".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn query_raw_answers_crypto_queries() {
+        let _gas_scope = GasScope::enter(GasConfig {
+            keccak256_cost: 10,
+            keccak256_per_byte_cost: 2,
+            secp256k1_recover_evm_address_cost: 100,
+            ..GasConfig::default()
+        });
+        let _scope = CallScope::enter(QueryOptions {
+            limits: QueryLimits {
+                max_gas: Some(125),
+                ..QueryLimits::default()
+            },
+            ..QueryOptions::default()
+        });
+        let state = TestState::new(false);
+        let querier = state.querier();
+
+        let (result, gas_info) = querier.query_raw(
+            br#"{"crypto":{"keccak256":{"data":"aGVsbG8="}}}"#,
+            1_000_000,
+        );
+        assert_eq!(gas_info, GasInfo::with_cost(20));
+        let response = Keccak256Response {
+            hash: keccak256(b"hello").to_vec().into(),
+        };
+        assert_eq!(
+            result.unwrap(),
+            SystemResult::Ok(ContractResult::Ok(to_binary(&response).unwrap()))
+        );
+
+        // invalid inputs are passed to the contract and charged anyway
+        let request = br#"{"crypto":{"secp256k1_recover_evm_address":{"message_hash":"","signature":"","recovery_param":0}}}"#;
+        let (result, gas_info) = querier.query_raw(request, 1_000_000);
+        assert_eq!(gas_info, GasInfo::with_cost(100));
+        assert!(matches!(
+            result.unwrap(),
+            SystemResult::Ok(ContractResult::Err(_))
+        ));
+
+        // invalid requests
+        let (result, gas_info) = querier.query_raw(br#"{"crypto":{"sha256":{}}}"#, 1_000_000);
+        assert_eq!(gas_info, GasInfo::free());
+        match result.unwrap() {
+            SystemResult::Err(SystemError::InvalidRequest { error, .. }) => {
+                assert!(error.starts_with("Invalid crypto query"), "{}", error)
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // crypto queries count towards the query gas limit
+        let (result, _) = querier.query_raw(br#"{"crypto":{"keccak256":{"data":""}}}"#, 1_000_000);
+        assert!(matches!(
+            result.unwrap(),
+            SystemResult::Err(SystemError::InvalidRequest { .. })
+        ));
+
+        // Go is not involved
+        assert_eq!(state.calls.get(), 0);
+        assert_eq!(state.consumed.get(), 0);
+    }
+
+    #[test]
+    fn query_raw_rejects_inactive_host_features() {
+        let schedule = ActivationSchedule::new(vec![CapabilityActivation {
+            height: 100,
+            capabilities: vec![],
+            host_features: vec![HostFeature::EvmQueries, HostFeature::CryptoQueries],
+        }])
+        .unwrap();
+        let state = TestState::new(false);
+        let evm_request = br#"{"evm":{"call":{"to":"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed","data":"","gas":1}}}"#;
+        let crypto_request = br#"{"crypto":{"keccak256":{"data":""}}}"#;
+
+        {
+            let _scope = CallScope::enter(QueryOptions {
+                features: schedule.feature_set(ActivationContext { height: 99 }),
+                ..QueryOptions::default()
+            });
+            for (request, kind) in [(&evm_request[..], "evm"), (&crypto_request[..], "crypto")] {
+                let (result, gas_info) = state.querier().query_raw(request, 1_000_000);
+                assert_eq!(gas_info, GasInfo::free());
+                assert_eq!(
+                    result.unwrap(),
+                    SystemResult::Err(SystemError::UnsupportedRequest {
+                        kind: kind.to_string()
+                    })
+                );
+            }
+        }
+
+        let _scope = CallScope::enter(QueryOptions {
+            features: schedule.feature_set(ActivationContext { height: 100 }),
+            ..QueryOptions::default()
+        });
+        for request in [&evm_request[..], &crypto_request[..]] {
+            let (result, _) = state.querier().query_raw(request, 1_000_000);
+            assert!(matches!(result.unwrap(), SystemResult::Ok(_)));
+        }
     }
 }
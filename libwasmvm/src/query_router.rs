@@ -0,0 +1,315 @@
+//! Answering selected queries in Rust without calling into Go.
+//!
+//! A routing table maps query routes like `bank.balance` to a handler. A handler is either the
+//! built-in snapshot, which contains responses pre-populated by the host, or a native handler
+//! registered by name. Queries without a route, or which a handler does not answer, are sent to Go.
+//!
+//! Since all nodes must answer a query the same way, routing depends on chain state only: the
+//! routing table is chosen by the block height of the call like the gas config, a snapshot only
+//! answers calls at the height it was taken at and answered queries are charged by the Go gas
+//! meter like the ones answered by Go.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use cosmwasm_std::{Binary, ContractResult, SystemResult};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// The name of the built-in snapshot handler
+pub const SNAPSHOT_HANDLER: &str = "snapshot";
+
+pub type QueryResult = SystemResult<ContractResult<Binary>>;
+
+/// A handler for queries that are answered in Rust
+pub trait QueryHandler: Send + Sync {
+    /// Answers the raw request of a call at the block height or returns None to fall back to Go.
+    /// An answer must only depend on the chain state at that height.
+    fn handle(&self, request: &[u8], height: u64) -> Option<QueryResult>;
+}
+
+/// The routing table, by route like `bank.balance` or `custom`
+pub type QueryRoutes = BTreeMap<String, QueryRoute>;
+
+/// The handler of a route and the gas charged for each answered query
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct QueryRoute {
+    pub handler: String,
+    pub gas_cost: u64,
+}
+
+/// A routing table that applies to calls from a block height on
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct QueryRoutesUpdate {
+    pub height: u64,
+    pub routes: QueryRoutes,
+}
+
+/// An entry of a snapshot as sent from Go
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct SnapshotEntry {
+    request: Binary,
+    response: QueryResult,
+}
+
+/// Responses to raw requests at a block height, pre-populated by the host
+#[derive(Default)]
+pub struct Snapshot {
+    /// The height the responses were taken at and the responses
+    responses: RwLock<(u64, HashMap<Vec<u8>, QueryResult>)>,
+}
+
+impl Snapshot {
+    /// Replaces the snapshot with the JSON encoded list of `{"request":…,"response":…}`
+    /// entries taken at the block height, where the request is base64 encoded.
+    pub fn replace_from_json(&self, height: u64, data: &[u8]) -> Result<usize, Error> {
+        let entries: Vec<SnapshotEntry> = serde_json::from_slice(data)
+            .map_err(|e| Error::vm_err(format!("Error parsing query snapshot: {}", e)))?;
+        let responses: HashMap<Vec<u8>, QueryResult> = entries
+            .into_iter()
+            .map(|entry| (entry.request.0, entry.response))
+            .collect();
+        let len = responses.len();
+        *self.responses.write().unwrap() = (height, responses);
+        Ok(len)
+    }
+}
+
+impl QueryHandler for Snapshot {
+    fn handle(&self, request: &[u8], height: u64) -> Option<QueryResult> {
+        let responses = self.responses.read().unwrap();
+        if responses.0 != height {
+            return None;
+        }
+        responses.1.get(request).cloned()
+    }
+}
+
+pub struct QueryRouter {
+    /// The routing tables by the height they apply from
+    routes: RwLock<BTreeMap<u64, QueryRoutes>>,
+    snapshot: Arc<Snapshot>,
+    handlers: RwLock<HashMap<String, Arc<dyn QueryHandler>>>,
+}
+
+impl QueryRouter {
+    pub fn new(routes: QueryRoutes, updates: Vec<QueryRoutesUpdate>) -> Self {
+        let snapshot = Arc::new(Snapshot::default());
+        let mut handlers: HashMap<String, Arc<dyn QueryHandler>> = HashMap::new();
+        handlers.insert(SNAPSHOT_HANDLER.to_string(), snapshot.clone());
+        let mut tables = BTreeMap::from([(0, routes)]);
+        for update in updates {
+            tables.insert(update.height, update.routes);
+        }
+        QueryRouter {
+            routes: RwLock::new(tables),
+            snapshot,
+            handlers: RwLock::new(handlers),
+        }
+    }
+
+    /// Returns true if no query is routed at any height
+    pub fn is_empty(&self) -> bool {
+        self.routes.read().unwrap().values().all(BTreeMap::is_empty)
+    }
+
+    /// Uses the routing table for calls from the height on, replacing an update at the same height
+    pub fn update_routes(&self, height: u64, routes: QueryRoutes) {
+        self.routes.write().unwrap().insert(height, routes);
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+
+    /// Registers a native handler that routes can refer to by name
+    pub fn register(&self, name: impl Into<String>, handler: Arc<dyn QueryHandler>) {
+        self.handlers.write().unwrap().insert(name.into(), handler);
+    }
+
+    /// Answers the request of a call at the block height in Rust if possible.
+    /// Returns the response and the gas cost.
+    pub fn route(&self, request: &[u8], height: u64) -> Option<(QueryResult, u64)> {
+        let route = {
+            let tables = self.routes.read().unwrap();
+            let (_, routes) = tables.range(..=height).next_back()?;
+            if routes.is_empty() {
+                return None;
+            }
+            let (outer, inner) = request_route(request)?;
+            // the more specific route wins
+            inner
+                .and_then(|inner| routes.get(&format!("{}.{}", outer, inner)))
+                .or_else(|| routes.get(&outer))?
+                .clone()
+        };
+        let handler = self.handlers.read().unwrap().get(&route.handler).cloned();
+        match handler {
+            Some(handler) => handler
+                .handle(request, height)
+                .map(|response| (response, route.gas_cost)),
+            None => {
+                log::warn!("Query handler {} is not registered", route.handler);
+                None
+            }
+        }
+    }
+}
+
+/// Returns the outer and inner key of a request like `{"bank":{"balance":{…}}}`
+pub(crate) fn request_route(request: &[u8]) -> Option<(String, Option<String>)> {
+    let value: Value = serde_json::from_slice(request).ok()?;
+    let (outer, inner) = value.as_object()?.iter().next()?;
+    let inner = inner
+        .as_object()
+        .and_then(|inner| inner.keys().next())
+        .cloned();
+    Some((outer.clone(), inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Constant;
+
+    impl QueryHandler for Constant {
+        fn handle(&self, request: &[u8], _height: u64) -> Option<QueryResult> {
+            if request.len() > 100 {
+                return None;
+            }
+            Some(SystemResult::Ok(ContractResult::Ok(Binary::from(b"42"))))
+        }
+    }
+
+    fn route(handler: &str, gas_cost: u64) -> QueryRoute {
+        QueryRoute {
+            handler: handler.to_string(),
+            gas_cost,
+        }
+    }
+
+    #[test]
+    fn route_works_for_snapshot() {
+        let router = QueryRouter::new(
+            BTreeMap::from([("bank.balance".to_string(), route(SNAPSHOT_HANDLER, 1000))]),
+            vec![],
+        );
+        let request = br#"{"bank":{"balance":{"address":"foo","denom":"okt"}}}"#;
+        assert_eq!(router.route(request, 5), None);
+
+        let snapshot = format!(
+            r#"[{{"request":"{}","response":{{"ok":{{"ok":"e30="}}}}}}]"#,
+            Binary::from(&request[..]).to_base64()
+        );
+        assert_eq!(
+            router
+                .snapshot()
+                .replace_from_json(5, snapshot.as_bytes())
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            router.route(request, 5),
+            Some((
+                SystemResult::Ok(ContractResult::Ok(Binary::from(b"{}"))),
+                1000
+            ))
+        );
+        // the snapshot only answers calls at its height
+        assert_eq!(router.route(request, 4), None);
+        assert_eq!(router.route(request, 6), None);
+
+        // other routes and requests go to Go
+        assert_eq!(
+            router.route(br#"{"bank":{"all_balances":{"address":"foo"}}}"#, 5),
+            None
+        );
+        assert_eq!(router.route(br#"{"bank":{"balance":{}}}"#, 5), None);
+
+        let err = router.snapshot().replace_from_json(6, b"{}").unwrap_err();
+        assert!(err.to_string().contains("Error parsing query snapshot"));
+    }
+
+    #[test]
+    fn route_works_for_native_handlers() {
+        let router = QueryRouter::new(
+            BTreeMap::from([
+                ("custom".to_string(), route("constant", 7)),
+                ("wasm.smart".to_string(), route("missing", 7)),
+            ]),
+            vec![],
+        );
+        let request = br#"{"custom":{"answer":{}}}"#;
+        // not registered yet
+        assert_eq!(router.route(request, 1), None);
+
+        router.register("constant", Arc::new(Constant));
+        assert_eq!(
+            router.route(request, 1),
+            Some((SystemResult::Ok(ContractResult::Ok(Binary::from(b"42"))), 7))
+        );
+        // custom queries don't need an inner object
+        assert!(router.route(br#"{"custom":"answer"}"#, 1).is_some());
+        // the handler can fall back to Go
+        let long = format!(r#"{{"custom":"{}"}}"#, "a".repeat(100));
+        assert_eq!(router.route(long.as_bytes(), 1), None);
+
+        assert_eq!(
+            router.route(
+                br#"{"wasm":{"smart":{"contract_addr":"foo","msg":"e30="}}}"#,
+                1
+            ),
+            None
+        );
+        assert_eq!(router.route(b"not json", 1), None);
+    }
+
+    #[test]
+    fn routes_depend_on_height() {
+        let router = QueryRouter::new(
+            BTreeMap::new(),
+            vec![QueryRoutesUpdate {
+                height: 100,
+                routes: BTreeMap::from([("custom".to_string(), route("constant", 7))]),
+            }],
+        );
+        router.register("constant", Arc::new(Constant));
+        assert!(!router.is_empty());
+        let request = br#"{"custom":{}}"#;
+        assert_eq!(router.route(request, 99), None);
+        assert!(router.route(request, 100).is_some());
+
+        router.update_routes(
+            200,
+            BTreeMap::from([("custom".to_string(), route("constant", 9))]),
+        );
+        assert_eq!(router.route(request, 150).unwrap().1, 7);
+        assert_eq!(router.route(request, 200).unwrap().1, 9);
+
+        router.update_routes(100, BTreeMap::new());
+        router.update_routes(200, BTreeMap::new());
+        assert_eq!(router.route(request, 150), None);
+        assert!(router.is_empty());
+        assert!(QueryRouter::new(BTreeMap::new(), vec![]).is_empty());
+    }
+
+    #[test]
+    fn request_route_works() {
+        assert_eq!(
+            request_route(br#"{"bank":{"balance":{}}}"#),
+            Some(("bank".to_string(), Some("balance".to_string())))
+        );
+        assert_eq!(
+            request_route(br#"{"custom":[]}"#),
+            Some(("custom".to_string(), None))
+        );
+        assert_eq!(request_route(br#"[]"#), None);
+        assert_eq!(request_route(br#"{}"#), None);
+    }
+}
@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+use std::convert::{TryFrom, TryInto};
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use cosmwasm_std::Order;
+use cosmwasm_vm::{
+    Backend, BackendApi, BackendError, BackendResult, Checksum, GasInfo, GasReport, Querier,
+    Storage,
+};
+
+use super::protocol::{
+    read_message, write_message, Callback, CallbackResponse, Message, Request, Response,
+};
+use crate::calls::EntryPoint;
+use crate::error::Error;
+
+/// The result of a call executed by a [`super::RemoteServer`]
+#[derive(Debug)]
+pub struct RemoteCallOutput {
+    /// The contract result or the VM error. Errors with an errno of their own, like running out
+    /// of gas, keep their type. Others are remote errors with the message of the server.
+    pub result: Result<Vec<u8>, Error>,
+    pub gas_report: GasReport,
+}
+
+/// A connection to a [`super::RemoteServer`]. Requests on one connection are executed one after
+/// the other.
+pub struct RemoteClient {
+    stream: RefCell<UnixStream>,
+}
+
+impl RemoteClient {
+    pub fn connect(socket_path: &Path) -> Result<Self, Error> {
+        let stream = UnixStream::connect(socket_path).map_err(|e| {
+            Error::remote_err(format!(
+                "Error connecting to {}: {}",
+                socket_path.display(),
+                e
+            ))
+        })?;
+        Ok(RemoteClient {
+            stream: RefCell::new(stream),
+        })
+    }
+
+    pub fn save_wasm(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        let checksum = self.data(Request::SaveWasm {
+            wasm: wasm.to_vec(),
+        })?;
+        Ok(checksum.as_slice().try_into()?)
+    }
+
+    pub fn remove_wasm(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.ok(Request::RemoveWasm {
+            checksum: Vec::from(*checksum),
+        })
+    }
+
+    pub fn load_wasm(&self, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+        self.data(Request::LoadWasm {
+            checksum: Vec::from(*checksum),
+        })
+    }
+
+    pub fn pin(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.ok(Request::Pin {
+            checksum: Vec::from(*checksum),
+        })
+    }
+
+    pub fn unpin(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.ok(Request::Unpin {
+            checksum: Vec::from(*checksum),
+        })
+    }
+
+    /// Calls an entry point on the server. Callbacks of the call are answered by the given backend.
+    ///
+    /// Contract calls caused by the querier may use this client again. They are nested into
+    /// the running call on the server.
+    pub fn call<A, S, Q>(
+        &self,
+        entry_point: EntryPoint,
+        checksum: &Checksum,
+        args: &[&[u8]],
+        backend: &mut Backend<A, S, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<RemoteCallOutput, Error>
+    where
+        A: BackendApi,
+        S: Storage,
+        Q: Querier,
+    {
+        let request = Request::Call {
+            entry_point: entry_point.name().to_string(),
+            checksum: Vec::from(*checksum),
+            args: args.iter().map(|arg| arg.to_vec()).collect(),
+            gas_limit,
+            print_debug,
+        };
+        match self.request(request, backend)? {
+            Response::Call { result, gas_report } => Ok(RemoteCallOutput {
+                result: result.map_err(Error::from),
+                gas_report,
+            }),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    fn ok(&self, request: Request) -> Result<(), Error> {
+        match self.request_without_callbacks(request)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    fn data(&self, request: Request) -> Result<Vec<u8>, Error> {
+        match self.request_without_callbacks(request)? {
+            Response::Data(data) => Ok(data),
+            response => Err(unexpected_response(response)),
+        }
+    }
+
+    fn request_without_callbacks(&self, request: Request) -> Result<Response, Error> {
+        self.write(&Message::Request(request))?;
+        match self.read()? {
+            Message::Response(Response::Error(msg)) => Err(Error::remote_err(msg)),
+            Message::Response(response) => Ok(response),
+            message => Err(Error::remote_err(format!(
+                "Unexpected message: {:?}",
+                message
+            ))),
+        }
+    }
+
+    /// Sends a request and answers callbacks until the response arrives
+    fn request<A, S, Q>(
+        &self,
+        request: Request,
+        backend: &mut Backend<A, S, Q>,
+    ) -> Result<Response, Error>
+    where
+        A: BackendApi,
+        S: Storage,
+        Q: Querier,
+    {
+        self.write(&Message::Request(request))?;
+        loop {
+            match self.read()? {
+                Message::Response(Response::Error(msg)) => return Err(Error::remote_err(msg)),
+                Message::Response(response) => return Ok(response),
+                Message::Callback(callback) => {
+                    let response = answer_callback(callback, backend);
+                    self.write(&Message::CallbackResponse(response))?;
+                }
+                message => {
+                    return Err(Error::remote_err(format!(
+                        "Unexpected message: {:?}",
+                        message
+                    )))
+                }
+            }
+        }
+    }
+
+    fn read(&self) -> Result<Message, Error> {
+        match read_message(&mut *self.stream.borrow_mut()) {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => Err(Error::remote_err("Connection closed by the server")),
+            Err(e) => Err(io_err(e)),
+        }
+    }
+
+    fn write(&self, message: &Message) -> Result<(), Error> {
+        write_message(&mut *self.stream.borrow_mut(), message).map_err(io_err)
+    }
+}
+
+fn io_err(e: io::Error) -> Error {
+    Error::remote_err(format!("Connection failed: {}", e))
+}
+
+fn unexpected_response(response: Response) -> Error {
+    Error::remote_err(format!("Unexpected response: {:?}", response))
+}
+
+fn answer_callback<A, S, Q>(callback: Callback, backend: &mut Backend<A, S, Q>) -> CallbackResponse
+where
+    A: BackendApi,
+    S: Storage,
+    Q: Querier,
+{
+    let (result, gas_info): BackendResult<Vec<Vec<u8>>> = match callback {
+        Callback::Get { key } => {
+            let (result, gas_info) = backend.storage.get(&key);
+            (result.map(|value| value.into_iter().collect()), gas_info)
+        }
+        Callback::Set { key, value } => {
+            let (result, gas_info) = backend.storage.set(&key, &value);
+            (result.map(|()| vec![]), gas_info)
+        }
+        Callback::Remove { key } => {
+            let (result, gas_info) = backend.storage.remove(&key);
+            (result.map(|()| vec![]), gas_info)
+        }
+        Callback::Scan { start, end, order } => match Order::try_from(order) {
+            Ok(order) => {
+                let (result, gas_info) =
+                    backend
+                        .storage
+                        .scan(start.as_deref(), end.as_deref(), order);
+                (result.map(|id| vec![id.to_be_bytes().to_vec()]), gas_info)
+            }
+            Err(_) => (Err(BackendError::bad_argument()), GasInfo::free()),
+        },
+        Callback::Next { iterator_id } => {
+            let (result, gas_info) = backend.storage.next(iterator_id);
+            let result =
+                result.map(|record| record.map_or_else(Vec::new, |(key, value)| vec![key, value]));
+            (result, gas_info)
+        }
+        Callback::CanonicalAddress { human } => {
+            let (result, gas_info) = backend.api.canonical_address(&human);
+            (result.map(|canonical| vec![canonical]), gas_info)
+        }
+        Callback::HumanAddress { canonical } => {
+            let (result, gas_info) = backend.api.human_address(&canonical);
+            (result.map(|human| vec![human.into_bytes()]), gas_info)
+        }
+        Callback::Query { request, gas_limit } => {
+            let (result, gas_info) = backend.querier.query_raw(&request, gas_limit);
+            let result = result.and_then(|response| {
+                serde_json::to_vec(&response).map_err(|e| BackendError::unknown(e.to_string()))
+            });
+            (result.map(|response| vec![response]), gas_info)
+        }
+    };
+    CallbackResponse { result, gas_info }
+}
@@ -0,0 +1,122 @@
+//! Runs the VM in a separate process.
+//!
+//! A [`RemoteServer`] hosts a cache and executes calls requested by [`RemoteClient`]s over a
+//! Unix socket. Storage, API and querier callbacks are relayed back to the client over the same
+//! connection, so a crash of the VM does not take down the node.
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::{RemoteCallOutput, RemoteClient};
+pub use server::{RemoteApi, RemoteQuerier, RemoteServer, RemoteStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calls::EntryPoint;
+    use crate::vm_cache::VmCacheOptions;
+    use cosmwasm_std::{from_slice, to_vec, Binary, ContractResult};
+    use cosmwasm_vm::testing::{mock_backend, mock_env, mock_info};
+    use cosmwasm_vm::{Size, Storage};
+    use std::collections::HashSet;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+    use tempfile::TempDir;
+
+    static HACKATOM: &[u8] = include_bytes!("../../../testdata/hackatom.wasm");
+
+    #[test]
+    fn remote_calls_work() {
+        let dir = TempDir::new().unwrap();
+        let options = VmCacheOptions::new(
+            dir.path().join("cache"),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        let server = unsafe { RemoteServer::new(options) }.unwrap();
+        let socket = dir.path().join("wasmvm.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            server.serve_connection(stream).unwrap();
+        });
+
+        let client = RemoteClient::connect(&socket).unwrap();
+        let checksum = client.save_wasm(HACKATOM).unwrap();
+        assert_eq!(client.load_wasm(&checksum).unwrap(), HACKATOM);
+        client.pin(&checksum).unwrap();
+
+        let mut backend = mock_backend(&[]);
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let output = client
+            .call(
+                EntryPoint::Instantiate,
+                &checksum,
+                &[&env, &info, msg],
+                &mut backend,
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let data = output.result.unwrap();
+        assert!(String::from_utf8_lossy(&data).contains(r#""ok""#));
+        assert!(output.gas_report.used_internally > 0);
+        // the state was written to the client's storage
+        assert!(backend.storage.get(b"config").0.unwrap().is_some());
+
+        let output = client
+            .call(
+                EntryPoint::Query,
+                &checksum,
+                &[&env, br#"{"verifier": {}}"#],
+                &mut backend,
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let data: ContractResult<Binary> = from_slice(&output.result.unwrap()).unwrap();
+        assert_eq!(data.unwrap().as_slice(), br#"{"verifier":"verifies"}"#);
+
+        // errors with their own errno keep their type
+        let output = client
+            .call(
+                EntryPoint::Query,
+                &checksum,
+                &[&env, br#"{"verifier": {}}"#],
+                &mut backend,
+                1,
+                false,
+            )
+            .unwrap();
+        let err = output.result.unwrap_err();
+        assert!(
+            matches!(err, crate::error::Error::OutOfGas { .. }),
+            "{}",
+            err
+        );
+
+        let err = client
+            .call(
+                EntryPoint::Query,
+                &checksum,
+                &[&env],
+                &mut backend,
+                500_000_000_000,
+                false,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("query takes 2 arguments, got 1"));
+
+        client.unpin(&checksum).unwrap();
+        client.remove_wasm(&checksum).unwrap();
+        let err = client.load_wasm(&checksum).unwrap_err();
+        assert!(matches!(err, crate::error::Error::RemoteErr { .. }));
+
+        drop(client);
+        handle.join().unwrap();
+    }
+}
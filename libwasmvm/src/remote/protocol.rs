@@ -0,0 +1,617 @@
+//! The wire format between client and server.
+//!
+//! Every message is a frame consisting of the payload length as a big endian u32 followed by the
+//! payload. The first byte of the payload is the message kind, the second one the message type.
+//! Integers are big endian, byte strings are prefixed with their length as a u32 and optional
+//! values with a presence flag byte.
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use cosmwasm_vm::{BackendError, GasInfo, GasReport};
+
+use crate::error::Error;
+use crate::querier::QueryLimit;
+
+/// Frames larger than this are rejected in order to protect against garbage on the socket
+const MAX_FRAME_LENGTH: usize = 256 * 1024 * 1024;
+
+const KIND_REQUEST: u8 = 1;
+const KIND_RESPONSE: u8 = 2;
+const KIND_CALLBACK: u8 = 3;
+const KIND_CALLBACK_RESPONSE: u8 = 4;
+
+/// Requests from the client, answered by a [`Response`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    SaveWasm {
+        wasm: Vec<u8>,
+    },
+    RemoveWasm {
+        checksum: Vec<u8>,
+    },
+    LoadWasm {
+        checksum: Vec<u8>,
+    },
+    Pin {
+        checksum: Vec<u8>,
+    },
+    Unpin {
+        checksum: Vec<u8>,
+    },
+    Call {
+        /// The name of the entry point, e.g. `execute`
+        entry_point: String,
+        checksum: Vec<u8>,
+        /// The raw arguments, starting with the env
+        args: Vec<Vec<u8>>,
+        gas_limit: u64,
+        print_debug: bool,
+    },
+}
+
+#[derive(Debug)]
+pub enum Response {
+    /// The request succeeded without a result
+    Ok,
+    /// A checksum or Wasm blob
+    Data(Vec<u8>),
+    /// The result of a call. Gas is reported even if the call failed.
+    Call {
+        result: Result<Vec<u8>, CallError>,
+        gas_report: GasReport,
+    },
+    /// The request failed before doing anything
+    Error(String),
+}
+
+/// The error of a call, typed like the errno it gets at the FFI. Errors without an errno
+/// of their own are passed by their message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallError {
+    Other(String),
+    OutOfGas,
+    QueryLimitExceeded { limit: QueryLimit, max: u64 },
+}
+
+impl From<&Error> for CallError {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::OutOfGas { .. } => CallError::OutOfGas,
+            Error::QueryLimitExceeded { limit, max, .. } => CallError::QueryLimitExceeded {
+                limit: *limit,
+                max: *max,
+            },
+            err => CallError::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<CallError> for Error {
+    fn from(err: CallError) -> Self {
+        match err {
+            CallError::Other(msg) => Error::remote_err(msg),
+            CallError::OutOfGas => Error::out_of_gas(),
+            CallError::QueryLimitExceeded { limit, max } => Error::query_limit_exceeded(limit, max),
+        }
+    }
+}
+
+/// Storage, API and querier callbacks from the server during a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callback {
+    Get {
+        key: Vec<u8>,
+    },
+    Set {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Remove {
+        key: Vec<u8>,
+    },
+    Scan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        /// 1 for ascending, 2 for descending as in `cosmwasm_std::Order`
+        order: i32,
+    },
+    Next {
+        iterator_id: u32,
+    },
+    CanonicalAddress {
+        human: String,
+    },
+    HumanAddress {
+        canonical: Vec<u8>,
+    },
+    Query {
+        request: Vec<u8>,
+        gas_limit: u64,
+    },
+}
+
+/// The answer to a [`Callback`]. The values depend on the callback, e.g. none or one
+/// for `Get`, key and value for `Next` and the JSON encoded result for `Query`.
+#[derive(Debug)]
+pub struct CallbackResponse {
+    pub result: Result<Vec<Vec<u8>>, BackendError>,
+    pub gas_info: GasInfo,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    Request(Request),
+    Response(Response),
+    Callback(Callback),
+    CallbackResponse(CallbackResponse),
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        match self {
+            Message::Request(request) => {
+                out.u8(KIND_REQUEST);
+                match request {
+                    Request::SaveWasm { wasm } => out.u8(1).bytes(wasm),
+                    Request::RemoveWasm { checksum } => out.u8(2).bytes(checksum),
+                    Request::LoadWasm { checksum } => out.u8(3).bytes(checksum),
+                    Request::Pin { checksum } => out.u8(4).bytes(checksum),
+                    Request::Unpin { checksum } => out.u8(5).bytes(checksum),
+                    Request::Call {
+                        entry_point,
+                        checksum,
+                        args,
+                        gas_limit,
+                        print_debug,
+                    } => out
+                        .u8(6)
+                        .bytes(entry_point.as_bytes())
+                        .bytes(checksum)
+                        .list(args)
+                        .u64(*gas_limit)
+                        .bool(*print_debug),
+                };
+            }
+            Message::Response(response) => {
+                out.u8(KIND_RESPONSE);
+                match response {
+                    Response::Ok => out.u8(1),
+                    Response::Data(data) => out.u8(2).bytes(data),
+                    Response::Call { result, gas_report } => {
+                        out.u8(3);
+                        match result {
+                            Ok(data) => out.bool(true).bytes(data),
+                            Err(err) => encode_call_error(out.bool(false), err),
+                        };
+                        out.u64(gas_report.limit)
+                            .u64(gas_report.remaining)
+                            .u64(gas_report.used_externally)
+                            .u64(gas_report.used_internally)
+                    }
+                    Response::Error(msg) => out.u8(4).bytes(msg.as_bytes()),
+                };
+            }
+            Message::Callback(callback) => {
+                out.u8(KIND_CALLBACK);
+                match callback {
+                    Callback::Get { key } => out.u8(1).bytes(key),
+                    Callback::Set { key, value } => out.u8(2).bytes(key).bytes(value),
+                    Callback::Remove { key } => out.u8(3).bytes(key),
+                    Callback::Scan { start, end, order } => out
+                        .u8(4)
+                        .optional(start.as_deref())
+                        .optional(end.as_deref())
+                        .u32(*order as u32),
+                    Callback::Next { iterator_id } => out.u8(5).u32(*iterator_id),
+                    Callback::CanonicalAddress { human } => out.u8(6).bytes(human.as_bytes()),
+                    Callback::HumanAddress { canonical } => out.u8(7).bytes(canonical),
+                    Callback::Query { request, gas_limit } => {
+                        out.u8(8).bytes(request).u64(*gas_limit)
+                    }
+                };
+            }
+            Message::CallbackResponse(response) => {
+                out.u8(KIND_CALLBACK_RESPONSE);
+                match &response.result {
+                    Ok(values) => out.u8(0).list(values),
+                    Err(err) => encode_backend_error(&mut out, err),
+                };
+                out.u64(response.gas_info.cost)
+                    .u64(response.gas_info.externally_used);
+            }
+        }
+        out.0
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut input = Decoder { data, pos: 0 };
+        let kind = input.u8()?;
+        let message_type = input.u8()?;
+        let message = match (kind, message_type) {
+            (KIND_REQUEST, 1) => Message::Request(Request::SaveWasm {
+                wasm: input.bytes()?,
+            }),
+            (KIND_REQUEST, 2) => Message::Request(Request::RemoveWasm {
+                checksum: input.bytes()?,
+            }),
+            (KIND_REQUEST, 3) => Message::Request(Request::LoadWasm {
+                checksum: input.bytes()?,
+            }),
+            (KIND_REQUEST, 4) => Message::Request(Request::Pin {
+                checksum: input.bytes()?,
+            }),
+            (KIND_REQUEST, 5) => Message::Request(Request::Unpin {
+                checksum: input.bytes()?,
+            }),
+            (KIND_REQUEST, 6) => Message::Request(Request::Call {
+                entry_point: input.string()?,
+                checksum: input.bytes()?,
+                args: input.list()?,
+                gas_limit: input.u64()?,
+                print_debug: input.bool()?,
+            }),
+            (KIND_RESPONSE, 1) => Message::Response(Response::Ok),
+            (KIND_RESPONSE, 2) => Message::Response(Response::Data(input.bytes()?)),
+            (KIND_RESPONSE, 3) => {
+                let result = if input.bool()? {
+                    Ok(input.bytes()?)
+                } else {
+                    Err(decode_call_error(&mut input)?)
+                };
+                let gas_report = GasReport {
+                    limit: input.u64()?,
+                    remaining: input.u64()?,
+                    used_externally: input.u64()?,
+                    used_internally: input.u64()?,
+                };
+                Message::Response(Response::Call { result, gas_report })
+            }
+            (KIND_RESPONSE, 4) => Message::Response(Response::Error(input.string()?)),
+            (KIND_CALLBACK, 1) => Message::Callback(Callback::Get {
+                key: input.bytes()?,
+            }),
+            (KIND_CALLBACK, 2) => Message::Callback(Callback::Set {
+                key: input.bytes()?,
+                value: input.bytes()?,
+            }),
+            (KIND_CALLBACK, 3) => Message::Callback(Callback::Remove {
+                key: input.bytes()?,
+            }),
+            (KIND_CALLBACK, 4) => Message::Callback(Callback::Scan {
+                start: input.optional()?,
+                end: input.optional()?,
+                order: input.u32()? as i32,
+            }),
+            (KIND_CALLBACK, 5) => Message::Callback(Callback::Next {
+                iterator_id: input.u32()?,
+            }),
+            (KIND_CALLBACK, 6) => Message::Callback(Callback::CanonicalAddress {
+                human: input.string()?,
+            }),
+            (KIND_CALLBACK, 7) => Message::Callback(Callback::HumanAddress {
+                canonical: input.bytes()?,
+            }),
+            (KIND_CALLBACK, 8) => Message::Callback(Callback::Query {
+                request: input.bytes()?,
+                gas_limit: input.u64()?,
+            }),
+            (KIND_CALLBACK_RESPONSE, error_type) => {
+                let result = match error_type {
+                    0 => Ok(input.list()?),
+                    _ => Err(decode_backend_error(error_type, &mut input)?),
+                };
+                let gas_info = GasInfo::new(input.u64()?, input.u64()?);
+                Message::CallbackResponse(CallbackResponse { result, gas_info })
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown message type {} of kind {}",
+                    message_type, kind
+                ))
+            }
+        };
+        if input.pos != data.len() {
+            return Err("Trailing bytes after message".to_string());
+        }
+        Ok(message)
+    }
+}
+
+fn encode_call_error<'a>(out: &'a mut Encoder, err: &CallError) -> &'a mut Encoder {
+    match err {
+        CallError::Other(msg) => out.u8(1).bytes(msg.as_bytes()),
+        CallError::OutOfGas => out.u8(2),
+        CallError::QueryLimitExceeded { limit, max } => {
+            let limit = match limit {
+                QueryLimit::Depth => 1,
+                QueryLimit::Gas => 2,
+            };
+            out.u8(3).u8(limit).u64(*max)
+        }
+    }
+}
+
+fn decode_call_error(input: &mut Decoder) -> Result<CallError, String> {
+    Ok(match input.u8()? {
+        1 => CallError::Other(input.string()?),
+        2 => CallError::OutOfGas,
+        3 => {
+            let limit = match input.u8()? {
+                1 => QueryLimit::Depth,
+                2 => QueryLimit::Gas,
+                limit => return Err(format!("Unknown query limit {}", limit)),
+            };
+            CallError::QueryLimitExceeded {
+                limit,
+                max: input.u64()?,
+            }
+        }
+        error_type => return Err(format!("Unknown call error type {}", error_type)),
+    })
+}
+
+fn encode_backend_error<'a>(out: &'a mut Encoder, err: &BackendError) -> &'a mut Encoder {
+    match err {
+        BackendError::ForeignPanic {} => out.u8(1),
+        BackendError::BadArgument {} => out.u8(2),
+        BackendError::InvalidUtf8 {} => out.u8(3),
+        BackendError::IteratorDoesNotExist { id } => out.u8(4).u32(*id),
+        BackendError::OutOfGas {} => out.u8(5),
+        BackendError::Unknown { msg } => out.u8(6).bytes(msg.as_bytes()),
+        BackendError::UserErr { msg } => out.u8(7).bytes(msg.as_bytes()),
+        err => out.u8(6).bytes(err.to_string().as_bytes()),
+    }
+}
+
+fn decode_backend_error(error_type: u8, input: &mut Decoder) -> Result<BackendError, String> {
+    Ok(match error_type {
+        1 => BackendError::foreign_panic(),
+        2 => BackendError::bad_argument(),
+        3 => BackendError::InvalidUtf8 {},
+        4 => BackendError::iterator_does_not_exist(input.u32()?),
+        5 => BackendError::out_of_gas(),
+        6 => BackendError::unknown(input.string()?),
+        7 => BackendError::user_err(input.string()?),
+        _ => return Err(format!("Unknown backend error type {}", error_type)),
+    })
+}
+
+/// Writes a message as one frame
+pub fn write_message(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    let payload = message.encode();
+    let length: u32 = payload
+        .len()
+        .try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message too long"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+/// Reads the message of the next frame. Returns None if the connection was closed
+/// before a new frame started.
+pub fn read_message(reader: &mut impl Read) -> io::Result<Option<Message>> {
+    let mut length = [0_u8; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_LENGTH {
+        return Err(invalid_data(format!(
+            "Frame of {} bytes is too long",
+            length
+        )));
+    }
+    let mut payload = vec![0_u8; length];
+    reader.read_exact(&mut payload)?;
+    Message::decode(&payload).map(Some).map_err(invalid_data)
+}
+
+pub fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn u64(&mut self, value: u64) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn optional(&mut self, value: Option<&[u8]>) -> &mut Self {
+        match value {
+            Some(value) => self.bool(true).bytes(value),
+            None => self.bool(false),
+        }
+    }
+
+    fn list(&mut self, values: &[Vec<u8>]) -> &mut Self {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.bytes(value);
+        }
+        self
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or("Unexpected end of message")?;
+        let taken = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("Invalid bool {}", value)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?).map_err(|e| e.to_string())
+    }
+
+    fn optional(&mut self) -> Result<Option<Vec<u8>>, String> {
+        if self.bool()? {
+            self.bytes().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<Vec<u8>>, String> {
+        let length = self.u32()?;
+        (0..length).map(|_| self.bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: Message) {
+        let mut frame = vec![];
+        write_message(&mut frame, &message).unwrap();
+        assert_eq!(
+            u32::from_be_bytes(frame[..4].try_into().unwrap()) as usize,
+            frame.len() - 4
+        );
+        let decoded = read_message(&mut frame.as_slice()).unwrap().unwrap();
+        assert_eq!(decoded.encode(), message.encode());
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        roundtrip(Message::Request(Request::SaveWasm {
+            wasm: b"\0asm".to_vec(),
+        }));
+        roundtrip(Message::Request(Request::Call {
+            entry_point: "execute".to_string(),
+            checksum: vec![7; 32],
+            args: vec![b"{}".to_vec(), vec![], b"{\"foo\":{}}".to_vec()],
+            gas_limit: u64::MAX,
+            print_debug: true,
+        }));
+        roundtrip(Message::Response(Response::Ok));
+        let gas_report = GasReport {
+            limit: 4,
+            remaining: 3,
+            used_externally: 2,
+            used_internally: 1,
+        };
+        roundtrip(Message::Response(Response::Call {
+            result: Ok(b"{}".to_vec()),
+            gas_report,
+        }));
+        roundtrip(Message::Response(Response::Call {
+            result: Err(CallError::Other("Caught panic".to_string())),
+            gas_report,
+        }));
+        roundtrip(Message::Response(Response::Call {
+            result: Err(CallError::OutOfGas),
+            gas_report,
+        }));
+        roundtrip(Message::Response(Response::Call {
+            result: Err(CallError::QueryLimitExceeded {
+                limit: QueryLimit::Gas,
+                max: 7,
+            }),
+            gas_report,
+        }));
+        roundtrip(Message::Callback(Callback::Scan {
+            start: Some(b"a".to_vec()),
+            end: None,
+            order: 2,
+        }));
+        roundtrip(Message::Callback(Callback::Query {
+            request: b"{}".to_vec(),
+            gas_limit: 5,
+        }));
+        roundtrip(Message::CallbackResponse(CallbackResponse {
+            result: Ok(vec![b"key".to_vec(), b"value".to_vec()]),
+            gas_info: GasInfo::new(1, 2),
+        }));
+        roundtrip(Message::CallbackResponse(CallbackResponse {
+            result: Err(BackendError::user_err("Invalid address")),
+            gas_info: GasInfo::with_externally_used(3),
+        }));
+        roundtrip(Message::CallbackResponse(CallbackResponse {
+            result: Err(BackendError::iterator_does_not_exist(9)),
+            gas_info: GasInfo::free(),
+        }));
+    }
+
+    #[test]
+    fn read_message_rejects_invalid_frames() {
+        // a closed connection
+        assert!(read_message(&mut [].as_slice()).unwrap().is_none());
+
+        let err = read_message(&mut [0xff, 0xff, 0xff, 0xff].as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "Frame of 4294967295 bytes is too long");
+
+        let err = read_message(&mut [0, 0, 0, 2, 9, 1].as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown message type 1 of kind 9");
+
+        // a truncated checksum
+        let err = read_message(&mut [0, 0, 0, 6, 1, 4, 0, 0, 0, 32].as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected end of message");
+
+        let err = read_message(&mut [0, 0, 0, 3, 2, 1, 0].as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "Trailing bytes after message");
+    }
+}
@@ -0,0 +1,341 @@
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use cosmwasm_std::{Binary, ContractResult, Order, Record, SystemError, SystemResult};
+use cosmwasm_vm::{
+    Backend, BackendApi, BackendError, BackendResult, Checksum, GasInfo, Querier, Storage,
+};
+
+use super::protocol::{
+    invalid_data, read_message, write_message, CallError, Callback, CallbackResponse, Message,
+    Request, Response,
+};
+use crate::calls::{call_entry_point, EntryPoint};
+use crate::error::Error;
+use crate::vm_cache::{VmCache, VmCacheOptions};
+
+type RemoteCache = VmCache<RemoteApi, RemoteStorage, RemoteQuerier>;
+
+/// The connection of the call in progress on this thread
+struct Session {
+    stream: UnixStream,
+    cache: Arc<RemoteCache>,
+}
+
+thread_local! {
+    // Every connection is served by its own thread. The backends are stateless and relay
+    // to the connection of their thread, which allows the API to be `Copy`.
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// Hosts a cache and serves calls from clients connecting to a Unix socket
+pub struct RemoteServer {
+    cache: Arc<RemoteCache>,
+}
+
+impl RemoteServer {
+    /// # Safety
+    ///
+    /// This creates a cache via [`VmCache::new`], which loads compiled modules from the cache
+    /// directory `options.base_dir` without validating them and executes them as native code.
+    /// The caller must ensure that the directory is only written by wasmvm, i.e. that nobody
+    /// else can write to it.
+    pub unsafe fn new(options: VmCacheOptions) -> Result<Self, Error> {
+        Ok(RemoteServer {
+            cache: Arc::new(VmCache::new(options)?),
+        })
+    }
+
+    /// Listens on the socket and serves every connection in its own thread until
+    /// accepting a connection fails
+    pub fn serve(&self, socket_path: &Path) -> io::Result<()> {
+        let listener = UnixListener::bind(socket_path)?;
+        loop {
+            let (stream, _) = listener.accept()?;
+            let cache = self.cache.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve_connection(cache, stream) {
+                    log::warn!("Remote VM connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Serves a single connection on the current thread until the client disconnects
+    pub fn serve_connection(&self, stream: UnixStream) -> io::Result<()> {
+        serve_connection(self.cache.clone(), stream)
+    }
+}
+
+fn serve_connection(cache: Arc<RemoteCache>, stream: UnixStream) -> io::Result<()> {
+    SESSION.with(|session| *session.borrow_mut() = Some(Session { stream, cache }));
+    let result = serve_requests();
+    SESSION.with(|session| *session.borrow_mut() = None);
+    result
+}
+
+fn serve_requests() -> io::Result<()> {
+    loop {
+        match read()? {
+            Some(Message::Request(request)) => {
+                let response = handle_request(request);
+                write(&Message::Response(response))?;
+            }
+            Some(message) => return Err(unexpected(&message)),
+            None => return Ok(()),
+        }
+    }
+}
+
+fn read() -> io::Result<Option<Message>> {
+    SESSION.with(|session| match session.borrow_mut().as_mut() {
+        Some(session) => read_message(&mut session.stream),
+        None => Err(io::Error::new(io::ErrorKind::NotConnected, "No session")),
+    })
+}
+
+fn write(message: &Message) -> io::Result<()> {
+    SESSION.with(|session| match session.borrow_mut().as_mut() {
+        Some(session) => write_message(&mut session.stream, message),
+        None => Err(io::Error::new(io::ErrorKind::NotConnected, "No session")),
+    })
+}
+
+fn unexpected(message: &Message) -> io::Error {
+    invalid_data(format!("Unexpected message: {:?}", message))
+}
+
+fn handle_request(request: Request) -> Response {
+    let cache = SESSION.with(|session| session.borrow().as_ref().map(|s| s.cache.clone()));
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return Response::Error("No session".to_string()),
+    };
+    let result = match request {
+        Request::SaveWasm { wasm } => cache
+            .save_wasm(&wasm)
+            .map(|checksum| Response::Data(checksum.into())),
+        Request::RemoveWasm { checksum } => {
+            to_checksum(checksum).and_then(|c| cache.remove_wasm(&c).map(|()| Response::Ok))
+        }
+        Request::LoadWasm { checksum } => {
+            to_checksum(checksum).and_then(|c| cache.load_wasm(&c).map(Response::Data))
+        }
+        Request::Pin { checksum } => {
+            to_checksum(checksum).and_then(|c| cache.pin(&c).map(|()| Response::Ok))
+        }
+        Request::Unpin { checksum } => {
+            to_checksum(checksum).and_then(|c| cache.unpin(&c).map(|()| Response::Ok))
+        }
+        Request::Call {
+            entry_point,
+            checksum,
+            args,
+            gas_limit,
+            print_debug,
+        } => handle_call(
+            &cache,
+            &entry_point,
+            checksum,
+            &args,
+            gas_limit,
+            print_debug,
+        ),
+    };
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+fn to_checksum(checksum: Vec<u8>) -> Result<Checksum, Error> {
+    Ok(checksum.as_slice().try_into()?)
+}
+
+fn handle_call(
+    cache: &RemoteCache,
+    entry_point: &str,
+    checksum: Vec<u8>,
+    args: &[Vec<u8>],
+    gas_limit: u64,
+    print_debug: bool,
+) -> Result<Response, Error> {
+    let entry_point = EntryPoint::from_name(entry_point)
+        .ok_or_else(|| Error::vm_err(format!("Unknown entry point {}", entry_point)))?;
+    let checksum = to_checksum(checksum)?;
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let backend = Backend {
+        api: RemoteApi,
+        storage: RemoteStorage,
+        querier: RemoteQuerier,
+    };
+    let output = call_entry_point(
+        cache,
+        entry_point,
+        &checksum,
+        &args,
+        backend,
+        gas_limit,
+        print_debug,
+        false,
+    )?;
+    Ok(Response::Call {
+        result: output.result.map_err(|e| CallError::from(&e)),
+        gas_report: output.gas_report,
+    })
+}
+
+/// Sends a callback to the client and waits for its response. Requests received meanwhile
+/// are calls into other contracts caused by queries, which are served before continuing.
+fn callback(callback: Callback) -> BackendResult<Vec<Vec<u8>>> {
+    let relay = || -> io::Result<CallbackResponse> {
+        write(&Message::Callback(callback))?;
+        loop {
+            match read()? {
+                Some(Message::CallbackResponse(response)) => return Ok(response),
+                Some(Message::Request(request)) => {
+                    let response = handle_request(request);
+                    write(&Message::Response(response))?;
+                }
+                Some(message) => return Err(unexpected(&message)),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Connection closed",
+                    ))
+                }
+            }
+        }
+    };
+    match relay() {
+        Ok(response) => (response.result, response.gas_info),
+        Err(e) => (
+            Err(BackendError::unknown(format!(
+                "Remote VM callback failed: {}",
+                e
+            ))),
+            GasInfo::free(),
+        ),
+    }
+}
+
+fn invalid_callback_response() -> BackendError {
+    BackendError::unknown("Invalid remote VM callback response")
+}
+
+#[derive(Copy, Clone)]
+pub struct RemoteApi;
+
+impl BackendApi for RemoteApi {
+    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+        let (result, gas_info) = callback(Callback::CanonicalAddress {
+            human: human.to_string(),
+        });
+        let result = result.and_then(|values| match <[Vec<u8>; 1]>::try_from(values) {
+            Ok([canonical]) => Ok(canonical),
+            Err(_) => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+
+    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+        let (result, gas_info) = callback(Callback::HumanAddress {
+            canonical: canonical.to_vec(),
+        });
+        let result = result.and_then(|values| match <[Vec<u8>; 1]>::try_from(values) {
+            Ok([human]) => String::from_utf8(human).map_err(|_| BackendError::InvalidUtf8 {}),
+            Err(_) => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+}
+
+pub struct RemoteStorage;
+
+impl Storage for RemoteStorage {
+    fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+        let (result, gas_info) = callback(Callback::Get { key: key.to_vec() });
+        let result = result.and_then(|mut values| match values.len() {
+            0 => Ok(None),
+            1 => Ok(values.pop()),
+            _ => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+
+    fn scan(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> BackendResult<u32> {
+        let (result, gas_info) = callback(Callback::Scan {
+            start: start.map(|start| start.to_vec()),
+            end: end.map(|end| end.to_vec()),
+            order: order as i32,
+        });
+        let result = result.and_then(|values| match <[Vec<u8>; 1]>::try_from(values) {
+            Ok([id]) => id
+                .as_slice()
+                .try_into()
+                .map(u32::from_be_bytes)
+                .map_err(|_| invalid_callback_response()),
+            Err(_) => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+
+    fn next(&mut self, iterator_id: u32) -> BackendResult<Option<Record>> {
+        let (result, gas_info) = callback(Callback::Next { iterator_id });
+        let result = result.and_then(|values| match values.len() {
+            0 => Ok(None),
+            2 => {
+                let mut values = values.into_iter();
+                Ok(values.next().zip(values.next()))
+            }
+            _ => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+        let (result, gas_info) = callback(Callback::Set {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        (result.map(|_| ()), gas_info)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+        let (result, gas_info) = callback(Callback::Remove { key: key.to_vec() });
+        (result.map(|_| ()), gas_info)
+    }
+}
+
+pub struct RemoteQuerier;
+
+impl Querier for RemoteQuerier {
+    fn query_raw(
+        &self,
+        request: &[u8],
+        gas_limit: u64,
+    ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+        let (result, gas_info) = callback(Callback::Query {
+            request: request.to_vec(),
+            gas_limit,
+        });
+        let result = result.and_then(|values| match <[Vec<u8>; 1]>::try_from(values) {
+            // parsed like responses from Go
+            Ok([response]) => Ok(serde_json::from_slice(&response).unwrap_or_else(|e| {
+                SystemResult::Err(SystemError::InvalidResponse {
+                    error: format!("Parsing remote response: {}", e),
+                    response: response.into(),
+                })
+            })),
+            Err(_) => Err(invalid_callback_response()),
+        });
+        (result, gas_info)
+    }
+}
@@ -0,0 +1,526 @@
+//! Recording of calls and their deterministic replay for debugging.
+//!
+//! If a record directory is configured, every call writes a [`Recording`] of its inputs and of
+//! all responses of the host (storage, address conversions and queries, each with its gas)
+//! to that directory. [`replay`] re-executes such a call from the recording alone.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use cosmwasm_std::{Binary, ContractResult, HexBinary, Order, Record, SystemResult};
+use cosmwasm_vm::{
+    Backend, BackendApi, BackendError, BackendResult, Checksum, GasInfo, GasReport, Querier, Size,
+    Storage,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::activation::ActivationContext;
+use crate::calls::{call_entry_point, EntryPoint};
+use crate::error::Error;
+use crate::fingerprint::{Fingerprint, FINGERPRINT_LENGTH};
+use crate::gas_config::{GasConfig, GasSchedule};
+use crate::static_analysis::analyze_wasm;
+use crate::vm_cache::{VmCache, VmCacheOptions};
+
+/// Everything needed to re-execute a call
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Recording {
+    /// The name of the entry point, e.g. `execute`
+    pub entry_point: String,
+    pub checksum: HexBinary,
+    /// The raw arguments, starting with the env
+    pub args: Vec<Binary>,
+    pub gas_limit: u64,
+    /// The memory limit of the instance in bytes
+    pub instance_memory_limit: u64,
+    /// The gas config of the call. The gas of the interactions already includes its overheads.
+    #[serde(default)]
+    pub gas_config: GasConfig,
+    /// The responses of the host in the order the contract asked for them
+    pub interactions: Vec<Interaction>,
+    /// The result of the call or the VM error message
+    pub result: Result<Binary, String>,
+    /// The gas used by the call, excluding the gas used externally
+    pub gas_used: u64,
+}
+
+/// A request of the contract to the host and its response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Interaction {
+    pub request: BackendRequest,
+    pub response: Result<BackendResponse, RecordedError>,
+    pub gas_cost: u64,
+    pub gas_externally_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendRequest {
+    Get {
+        key: Binary,
+    },
+    Set {
+        key: Binary,
+        value: Binary,
+    },
+    Remove {
+        key: Binary,
+    },
+    Scan {
+        start: Option<Binary>,
+        end: Option<Binary>,
+        /// 1 for ascending, 2 for descending as in `cosmwasm_std::Order`
+        order: i32,
+    },
+    Next {
+        iterator_id: u32,
+    },
+    CanonicalAddress {
+        human: String,
+    },
+    HumanAddress {
+        canonical: Binary,
+    },
+    Query {
+        request: Binary,
+        gas_limit: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendResponse {
+    /// The response to `get`
+    Value(Option<Binary>),
+    /// The response to `set` and `remove`
+    Done,
+    /// The response to `scan`
+    IteratorId(u32),
+    /// The response to `next`
+    Record(Option<(Binary, Binary)>),
+    /// The response to `canonical_address`
+    Canonical(Binary),
+    /// The response to `human_address`
+    Human(String),
+    /// The response to `query`
+    Query(SystemResult<ContractResult<Binary>>),
+}
+
+/// A serializable [`BackendError`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedError {
+    ForeignPanic,
+    BadArgument,
+    InvalidUtf8,
+    IteratorDoesNotExist { id: u32 },
+    OutOfGas,
+    Unknown { msg: String },
+    UserErr { msg: String },
+}
+
+impl From<&BackendError> for RecordedError {
+    fn from(err: &BackendError) -> Self {
+        match err {
+            BackendError::ForeignPanic {} => RecordedError::ForeignPanic,
+            BackendError::BadArgument {} => RecordedError::BadArgument,
+            BackendError::InvalidUtf8 {} => RecordedError::InvalidUtf8,
+            BackendError::IteratorDoesNotExist { id } => {
+                RecordedError::IteratorDoesNotExist { id: *id }
+            }
+            BackendError::OutOfGas {} => RecordedError::OutOfGas,
+            BackendError::Unknown { msg } => RecordedError::Unknown { msg: msg.clone() },
+            BackendError::UserErr { msg } => RecordedError::UserErr { msg: msg.clone() },
+            err => RecordedError::Unknown {
+                msg: err.to_string(),
+            },
+        }
+    }
+}
+
+impl From<RecordedError> for BackendError {
+    fn from(err: RecordedError) -> Self {
+        match err {
+            RecordedError::ForeignPanic => BackendError::foreign_panic(),
+            RecordedError::BadArgument => BackendError::bad_argument(),
+            RecordedError::InvalidUtf8 => BackendError::InvalidUtf8 {},
+            RecordedError::IteratorDoesNotExist { id } => BackendError::iterator_does_not_exist(id),
+            RecordedError::OutOfGas => BackendError::out_of_gas(),
+            RecordedError::Unknown { msg } => BackendError::unknown(msg),
+            RecordedError::UserErr { msg } => BackendError::user_err(msg),
+        }
+    }
+}
+
+thread_local! {
+    // One entry per call in progress on this thread, like the query state. Nested calls
+    // have their own entry, so only the interactions of the innermost call are recorded.
+    static FRAMES: RefCell<Vec<CallFrame>> = const { RefCell::new(Vec::new()) };
+    static REPLAY: RefCell<Option<ReplayState>> = const { RefCell::new(None) };
+}
+
+/// What is collected about the interactions of a call
+#[derive(Default)]
+pub struct CallFrame {
+    pub interactions: Option<Vec<Interaction>>,
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// Records the interactions of a call while it is alive
+pub struct RecordScope {
+    _private: (),
+}
+
+impl RecordScope {
+    pub fn enter(record: bool, fingerprint: bool) -> Self {
+        let frame = CallFrame {
+            interactions: if record { Some(Vec::new()) } else { None },
+            fingerprint: if fingerprint {
+                Some(Fingerprint::new())
+            } else {
+                None
+            },
+        };
+        FRAMES.with(|frames| frames.borrow_mut().push(frame));
+        RecordScope { _private: () }
+    }
+
+    /// Returns what was collected about the call
+    pub fn finish(self) -> CallFrame {
+        FRAMES.with(|frames| {
+            frames
+                .borrow_mut()
+                .last_mut()
+                .map(std::mem::take)
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for RecordScope {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| {
+            frames.borrow_mut().pop();
+        });
+    }
+}
+
+/// Records the outcome of a request to the host if the current call is recorded
+pub fn record<T>(
+    request: impl FnOnce() -> BackendRequest,
+    outcome: &BackendResult<T>,
+    response: impl FnOnce(&T) -> BackendResponse,
+) {
+    if !is_recording() {
+        return;
+    }
+    let (result, gas_info) = outcome;
+    record_interaction(Interaction {
+        request: request(),
+        response: result.as_ref().map(response).map_err(RecordedError::from),
+        gas_cost: gas_info.cost,
+        gas_externally_used: gas_info.externally_used,
+    });
+}
+
+fn is_recording() -> bool {
+    FRAMES.with(|frames| {
+        matches!(frames.borrow().last(), Some(frame) if frame.interactions.is_some() || frame.fingerprint.is_some())
+    })
+}
+
+fn record_interaction(interaction: Interaction) {
+    FRAMES.with(|frames| {
+        if let Some(frame) = frames.borrow_mut().last_mut() {
+            if let Some(fingerprint) = &mut frame.fingerprint {
+                fingerprint.add_interaction(&interaction);
+            }
+            if let Some(interactions) = &mut frame.interactions {
+                interactions.push(interaction);
+            }
+        }
+    });
+}
+
+static RECORDING_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Writes the recording to `<height>-<sequence>-<entry point>.json` in the directory.
+/// Failures are logged only since they must not affect the call.
+pub fn write_recording(dir: &Path, recording: &Recording) {
+    let height = recording
+        .args
+        .first()
+        .and_then(|env| ActivationContext::from_env(env).ok())
+        .map_or(0, |context| context.height);
+    let sequence = RECORDING_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!(
+        "{}-{}-{}.json",
+        height, sequence, recording.entry_point
+    ));
+    let result = fs::create_dir_all(dir).and_then(|()| {
+        let data = serde_json::to_vec(recording).map_err(std::io::Error::from)?;
+        fs::write(&path, data)
+    });
+    if let Err(e) = result {
+        log::warn!("Error writing recording {}: {}", path.display(), e);
+    }
+}
+
+/// The result of a call as stored in a recording
+pub fn recorded_result(result: &Result<Vec<u8>, Error>) -> Result<Binary, String> {
+    match result {
+        Ok(data) => Ok(data.as_slice().into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The outcome of a replayed call
+pub struct ReplayOutput {
+    pub result: Result<Vec<u8>, Error>,
+    pub gas_report: GasReport,
+    /// The fingerprint of the replayed call, which is the same as the one of the recorded call
+    pub fingerprint: [u8; FINGERPRINT_LENGTH],
+}
+
+impl ReplayOutput {
+    /// Returns true if result and gas are the same as in the recording
+    pub fn matches(&self, recording: &Recording) -> bool {
+        recorded_result(&self.result) == recording.result
+            && self.gas_report.used_internally == recording.gas_used
+    }
+}
+
+/// Re-executes a recorded call of the given code, answering all requests to the host from the
+/// recording. Fails if the contract makes different requests than in the recording.
+pub fn replay(
+    recording: &Recording,
+    wasm: &[u8],
+    print_debug: bool,
+) -> Result<ReplayOutput, Error> {
+    let entry_point = EntryPoint::from_name(&recording.entry_point).ok_or_else(|| {
+        Error::replay_err(format!("Unknown entry point {}", recording.entry_point))
+    })?;
+    if Vec::from(Checksum::generate(wasm)) != recording.checksum.as_slice() {
+        return Err(Error::replay_err(
+            "The Wasm does not match the checksum of the recording",
+        ));
+    }
+
+    // The code was accepted when it was recorded, so everything it requires is available
+    let capabilities = analyze_wasm(wasm)?.required_capabilities;
+    let mut options = VmCacheOptions::new(
+        PathBuf::new(),
+        capabilities,
+        Size::mebi(0),
+        Size(recording.instance_memory_limit as usize),
+    );
+    options.in_memory_wasm_store_size = Some(Size(wasm.len()));
+    options.gas_schedule = GasSchedule::new(recording.gas_config, vec![])?;
+    let cache: VmCache<ReplayApi, ReplayStorage, ReplayQuerier> = unsafe { VmCache::new(options) }?;
+    let checksum = cache.save_wasm_unchecked(wasm)?;
+
+    REPLAY.with(|replay| {
+        *replay.borrow_mut() = Some(ReplayState {
+            interactions: recording.interactions.clone(),
+            next: 0,
+            divergence: None,
+        })
+    });
+    let args: Vec<&[u8]> = recording.args.iter().map(Binary::as_slice).collect();
+    let backend = Backend {
+        api: ReplayApi,
+        storage: ReplayStorage,
+        querier: ReplayQuerier,
+    };
+    let output = call_entry_point(
+        &cache,
+        entry_point,
+        &checksum,
+        &args,
+        backend,
+        recording.gas_limit,
+        print_debug,
+        true,
+    );
+    let state = REPLAY.with(|replay| replay.borrow_mut().take());
+    let output = output?;
+
+    if let Some(state) = state {
+        if let Some(divergence) = state.divergence {
+            return Err(Error::replay_err(divergence));
+        }
+        if state.next != state.interactions.len() {
+            return Err(Error::replay_err(format!(
+                "The call ended after {} of {} recorded interactions",
+                state.next,
+                state.interactions.len()
+            )));
+        }
+    }
+    Ok(ReplayOutput {
+        result: output.result,
+        gas_report: output.gas_report,
+        fingerprint: output
+            .fingerprint
+            .expect("The fingerprint is computed when requested"),
+    })
+}
+
+struct ReplayState {
+    interactions: Vec<Interaction>,
+    next: usize,
+    /// The first difference between the replay and the recording
+    divergence: Option<String>,
+}
+
+/// Returns the recorded response to the next request. Once the replay diverged from the
+/// recording, all requests fail.
+fn replayed(request: BackendRequest) -> BackendResult<BackendResponse> {
+    REPLAY.with(|replay| {
+        let mut replay = replay.borrow_mut();
+        let state = match replay.as_mut() {
+            Some(state) => state,
+            None => return (Err(BackendError::unknown("No replay")), GasInfo::free()),
+        };
+        if state.divergence.is_none() {
+            match state.interactions.get(state.next) {
+                Some(interaction) if interaction.request == request => {
+                    state.next += 1;
+                    let gas_info =
+                        GasInfo::new(interaction.gas_cost, interaction.gas_externally_used);
+                    let result = interaction.response.clone().map_err(BackendError::from);
+                    // replayed interactions are part of the fingerprint like the original ones
+                    record_interaction(interaction.clone());
+                    return (result, gas_info);
+                }
+                Some(interaction) => {
+                    state.divergence = Some(format!(
+                        "Interaction {} diverged: recorded {:?}, replayed {:?}",
+                        state.next, interaction.request, request
+                    ))
+                }
+                None => {
+                    state.divergence = Some(format!(
+                        "Interaction {} was not recorded: {:?}",
+                        state.next, request
+                    ))
+                }
+            }
+        }
+        let divergence = state.divergence.clone().unwrap_or_default();
+        (Err(BackendError::unknown(divergence)), GasInfo::free())
+    })
+}
+
+fn unexpected_response(response: BackendResponse) -> BackendError {
+    BackendError::unknown(format!("Unexpected recorded response {:?}", response))
+}
+
+#[derive(Copy, Clone)]
+pub struct ReplayApi;
+
+impl BackendApi for ReplayApi {
+    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+        let request = BackendRequest::CanonicalAddress {
+            human: human.to_string(),
+        };
+        let (result, gas_info) = replayed(request);
+        let result = result.and_then(|response| match response {
+            BackendResponse::Canonical(canonical) => Ok(canonical.0),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+
+    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+        let request = BackendRequest::HumanAddress {
+            canonical: canonical.into(),
+        };
+        let (result, gas_info) = replayed(request);
+        let result = result.and_then(|response| match response {
+            BackendResponse::Human(human) => Ok(human),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+}
+
+pub struct ReplayStorage;
+
+impl Storage for ReplayStorage {
+    fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+        let (result, gas_info) = replayed(BackendRequest::Get { key: key.into() });
+        let result = result.and_then(|response| match response {
+            BackendResponse::Value(value) => Ok(value.map(|value| value.0)),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+
+    fn scan(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> BackendResult<u32> {
+        let request = BackendRequest::Scan {
+            start: start.map(Binary::from),
+            end: end.map(Binary::from),
+            order: order as i32,
+        };
+        let (result, gas_info) = replayed(request);
+        let result = result.and_then(|response| match response {
+            BackendResponse::IteratorId(id) => Ok(id),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+
+    fn next(&mut self, iterator_id: u32) -> BackendResult<Option<Record>> {
+        let (result, gas_info) = replayed(BackendRequest::Next { iterator_id });
+        let result = result.and_then(|response| match response {
+            BackendResponse::Record(record) => Ok(record.map(|(key, value)| (key.0, value.0))),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+        let request = BackendRequest::Set {
+            key: key.into(),
+            value: value.into(),
+        };
+        let (result, gas_info) = replayed(request);
+        (result.map(|_| ()), gas_info)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+        let (result, gas_info) = replayed(BackendRequest::Remove { key: key.into() });
+        (result.map(|_| ()), gas_info)
+    }
+}
+
+pub struct ReplayQuerier;
+
+impl Querier for ReplayQuerier {
+    fn query_raw(
+        &self,
+        request: &[u8],
+        gas_limit: u64,
+    ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+        let request = BackendRequest::Query {
+            request: request.into(),
+            gas_limit,
+        };
+        let (result, gas_info) = replayed(request);
+        let result = result.and_then(|response| match response {
+            BackendResponse::Query(result) => Ok(result),
+            response => Err(unexpected_response(response)),
+        });
+        (result, gas_info)
+    }
+}
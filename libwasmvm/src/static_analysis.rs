@@ -0,0 +1,243 @@
+//! Static analysis of Wasm bytecode.
+//!
+//! This creates a superset of cosmwasm-vm's `AnalysisReport` from the original Wasm bytecode.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use parity_wasm::elements::{deserialize_buffer, Internal, Module};
+
+use crate::error::Error;
+
+/// Must match cosmwasm-vm's list of IBC entry points
+const REQUIRED_IBC_EXPORTS: &[&str] = &[
+    "ibc_channel_open",
+    "ibc_channel_connect",
+    "ibc_channel_close",
+    "ibc_packet_receive",
+    "ibc_packet_ack",
+    "ibc_packet_timeout",
+];
+
+/// Entry points that are not related to IBC
+const ENTRY_POINTS: &[&str] = &[
+    "instantiate",
+    "execute",
+    "migrate",
+    "sudo",
+    "reply",
+    "query",
+];
+
+const REQUIRES_PREFIX: &str = "requires_";
+
+/// The prefixes of the interface version marker exports. The second one is used by
+/// contracts built for CosmWasm 0.x.
+const INTERFACE_VERSION_PREFIXES: &[&str] = &["interface_version_", "cosmwasm_vm_version_"];
+
+/// The custom section in which a contract can store its migrate version as a decimal string
+const MIGRATE_VERSION_SECTION: &str = "cw_migrate_version";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisReport {
+    /// True if all IBC entry points are exported
+    pub has_ibc_entry_points: bool,
+    pub required_capabilities: HashSet<String>,
+    /// All known entry points exported by the contract, including IBC entry points
+    pub entrypoints: BTreeSet<String>,
+    /// The name of the interface version marker export, e.g. `interface_version_8`.
+    /// The one with the highest version if there are multiple.
+    pub interface_version: Option<String>,
+    /// The size of the Wasm bytecode in bytes
+    pub code_size: usize,
+    /// The number of functions defined in the module (imports not included)
+    pub function_count: usize,
+    /// The content of custom sections by name. If a name occurs multiple times, the last section wins.
+    pub custom_sections: BTreeMap<String, Vec<u8>>,
+    /// The version from the `cw_migrate_version` custom section, if present and valid
+    pub contract_migrate_version: Option<u64>,
+}
+
+pub fn deserialize_wasm(wasm: &[u8]) -> Result<Module, Error> {
+    deserialize_buffer(wasm).map_err(|err| {
+        Error::vm_err(format!(
+            "Error during static Wasm validation: Wasm bytecode could not be deserialized. Deserialization error: \"{}\"",
+            err
+        ))
+    })
+}
+
+/// Returns all exported function names of the module
+pub fn exported_function_names(module: &Module) -> HashSet<String> {
+    module
+        .export_section()
+        .map_or(HashSet::default(), |export_section| {
+            export_section
+                .entries()
+                .iter()
+                .filter_map(|entry| match entry.internal() {
+                    Internal::Function(_) => Some(entry.field().to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+}
+
+/// Analyzes the original Wasm bytecode
+pub fn analyze_wasm(wasm: &[u8]) -> Result<AnalysisReport, Error> {
+    let module = deserialize_wasm(wasm)?;
+    let exports = exported_function_names(&module);
+    let has_ibc_entry_points = REQUIRED_IBC_EXPORTS
+        .iter()
+        .all(|required| exports.contains(*required));
+    let required_capabilities = exports
+        .iter()
+        .filter_map(|name| name.strip_prefix(REQUIRES_PREFIX))
+        .filter(|capability| !capability.is_empty())
+        .map(|capability| capability.to_string())
+        .collect();
+    let entrypoints = ENTRY_POINTS
+        .iter()
+        .chain(REQUIRED_IBC_EXPORTS)
+        .filter(|name| exports.contains(**name))
+        .map(|name| name.to_string())
+        .collect();
+    // Exports are unordered, so pick the highest of multiple markers to keep the report deterministic
+    let interface_version = exports
+        .iter()
+        .filter_map(|name| {
+            INTERFACE_VERSION_PREFIXES
+                .iter()
+                .find_map(|prefix| name.strip_prefix(prefix))
+                .map(|version| (version.parse::<u64>().ok(), name))
+        })
+        .max()
+        .map(|(_, name)| name.clone());
+    let custom_sections: BTreeMap<String, Vec<u8>> = module
+        .custom_sections()
+        .map(|section| (section.name().to_string(), section.payload().to_vec()))
+        .collect();
+    let contract_migrate_version = custom_sections
+        .get(MIGRATE_VERSION_SECTION)
+        .and_then(|payload| std::str::from_utf8(payload).ok())
+        .and_then(|version| version.parse().ok());
+    Ok(AnalysisReport {
+        has_ibc_entry_points,
+        required_capabilities,
+        entrypoints,
+        interface_version,
+        code_size: wasm.len(),
+        function_count: module.function_section().map_or(0, |s| s.entries().len()),
+        custom_sections,
+        contract_migrate_version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+    static IBC_REFLECT: &[u8] = include_bytes!("../../testdata/ibc_reflect.wasm");
+
+    #[test]
+    fn analyze_wasm_works() {
+        let report = analyze_wasm(HACKATOM).unwrap();
+        assert!(!report.has_ibc_entry_points);
+        assert_eq!(report.required_capabilities, HashSet::new());
+        assert_eq!(
+            report.entrypoints,
+            BTreeSet::from(
+                ["execute", "instantiate", "migrate", "query", "sudo"].map(String::from)
+            )
+        );
+        assert_eq!(
+            report.interface_version.as_deref(),
+            Some("interface_version_8")
+        );
+        assert_eq!(report.code_size, HACKATOM.len());
+        assert!(report.function_count > 0);
+        assert_eq!(report.contract_migrate_version, None);
+
+        let report = analyze_wasm(IBC_REFLECT).unwrap();
+        assert!(report.has_ibc_entry_points);
+        assert_eq!(
+            report.required_capabilities,
+            HashSet::from(["iterator".to_string(), "stargate".to_string()])
+        );
+        assert!(report.entrypoints.contains("ibc_packet_receive"));
+        assert!(report.entrypoints.contains("reply"));
+    }
+
+    /// A module with one empty function exported under each of the names
+    fn module_exporting(names: &[&str]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // type section: one `func () -> ()`
+        wasm.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]);
+        // function section: one function of type 0
+        wasm.extend_from_slice(&[3, 2, 1, 0]);
+        // export section: function 0 under every name
+        let mut exports = vec![names.len() as u8];
+        for name in names {
+            exports.push(name.len() as u8);
+            exports.extend_from_slice(name.as_bytes());
+            exports.extend_from_slice(&[0, 0]);
+        }
+        wasm.push(7);
+        wasm.push(exports.len() as u8);
+        wasm.extend(exports);
+        // code section: one empty body
+        wasm.extend_from_slice(&[10, 4, 1, 2, 0, 0x0b]);
+        wasm
+    }
+
+    #[test]
+    fn analyze_wasm_picks_highest_interface_version() {
+        let names = [
+            "interface_version_8",
+            "interface_version_10",
+            "cosmwasm_vm_version_4",
+        ];
+        let report = analyze_wasm(&module_exporting(&names)).unwrap();
+        assert_eq!(
+            report.interface_version.as_deref(),
+            Some("interface_version_10")
+        );
+
+        let mut reversed = names;
+        reversed.reverse();
+        let report = analyze_wasm(&module_exporting(&reversed)).unwrap();
+        assert_eq!(
+            report.interface_version.as_deref(),
+            Some("interface_version_10")
+        );
+
+        let report = analyze_wasm(&module_exporting(&["execute"])).unwrap();
+        assert_eq!(report.interface_version, None);
+    }
+
+    #[test]
+    fn analyze_wasm_reads_custom_sections() {
+        // Appends custom sections (id 0) to the module
+        let mut wasm = HACKATOM.to_vec();
+        for (name, payload) in [("cw2", &b"metadata"[..]), ("cw_migrate_version", b"42")] {
+            wasm.push(0);
+            wasm.push((1 + name.len() + payload.len()) as u8);
+            wasm.push(name.len() as u8);
+            wasm.extend_from_slice(name.as_bytes());
+            wasm.extend_from_slice(payload);
+        }
+
+        let report = analyze_wasm(&wasm).unwrap();
+        assert_eq!(report.custom_sections["cw2"], b"metadata");
+        assert_eq!(report.contract_migrate_version, Some(42));
+        assert_eq!(report.code_size, wasm.len());
+    }
+
+    #[test]
+    fn analyze_wasm_fails_for_invalid_wasm() {
+        let err = analyze_wasm(b"definitely not wasm").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Wasm bytecode could not be deserialized"));
+    }
+}
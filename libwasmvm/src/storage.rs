@@ -1,98 +1,48 @@
 use std::collections::HashMap;
 use std::convert::TryInto;
 
-use cosmwasm_std::{Order, Record};
+use cosmwasm_std::{Binary, Order, Record};
 use cosmwasm_vm::{BackendError, BackendResult, GasInfo, Storage};
 
 use crate::db::Db;
-use crate::error::GoError;
-use crate::iterator::GoIter;
-use crate::memory::{U8SliceView, UnmanagedVector};
+use crate::gas_config::current_gas_config;
+use crate::querier::invalidate_query_memo;
+use crate::replay::{record, BackendRequest, BackendResponse};
+use crate::vm::{VmDb, VmIterator};
 
-pub struct GoStorage {
-    db: Db,
-    iterators: HashMap<u32, GoIter>,
+/// The `Storage` used for calls. It keeps the iterators of the host's db by ID.
+pub struct HostStorage<D: VmDb> {
+    db: D,
+    iterators: HashMap<u32, D::Iterator>,
 }
 
-impl GoStorage {
-    pub fn new(db: Db) -> Self {
-        GoStorage {
+pub type GoStorage = HostStorage<Db>;
+
+impl<D: VmDb> HostStorage<D> {
+    pub fn new(db: D) -> Self {
+        HostStorage {
             db,
             iterators: HashMap::new(),
         }
     }
-}
-
-impl Storage for GoStorage {
-    fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
-        let mut output = UnmanagedVector::default();
-        let mut error_msg = UnmanagedVector::default();
-        let mut used_gas = 0_u64;
-        let go_error: GoError = (self.db.vtable.read_db)(
-            self.db.state,
-            self.db.gas_meter,
-            &mut used_gas as *mut u64,
-            U8SliceView::new(Some(key)),
-            &mut output as *mut UnmanagedVector,
-            &mut error_msg as *mut UnmanagedVector,
-        )
-        .into();
-        // We destruct the UnmanagedVector here, no matter if we need the data.
-        let output = output.consume();
-
-        let gas_info = GasInfo::with_externally_used(used_gas);
 
-        // return complete error message (reading from buffer for GoError::Other)
-        let default = || {
-            format!(
-                "Failed to read a key in the db: {}",
-                String::from_utf8_lossy(key)
-            )
-        };
-        unsafe {
-            if let Err(err) = go_error.into_result(error_msg, default) {
-                return (Err(err), gas_info);
-            }
-        }
-
-        (Ok(output), gas_info)
+    pub fn into_inner(self) -> D {
+        self.db
     }
+}
 
-    fn scan(
+impl<D: VmDb> HostStorage<D> {
+    fn open_iterator(
         &mut self,
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         order: Order,
     ) -> BackendResult<u32> {
-        let mut error_msg = UnmanagedVector::default();
-        let mut iter = GoIter::new(self.db.gas_meter);
-        let mut used_gas = 0_u64;
-        let go_error: GoError = (self.db.vtable.scan_db)(
-            self.db.state,
-            self.db.gas_meter,
-            &mut used_gas as *mut u64,
-            U8SliceView::new(start),
-            U8SliceView::new(end),
-            order.into(),
-            &mut iter as *mut GoIter,
-            &mut error_msg as *mut UnmanagedVector,
-        )
-        .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-
-        // return complete error message (reading from buffer for GoError::Other)
-        let default = || {
-            format!(
-                "Failed to read the next key between {:?} and {:?}",
-                start.map(String::from_utf8_lossy),
-                end.map(String::from_utf8_lossy),
-            )
+        let (iter, gas_info) = self.db.scan(start, end, order);
+        let iter = match iter {
+            Ok(iter) => iter,
+            Err(err) => return (Err(err), gas_info),
         };
-        unsafe {
-            if let Err(err) = go_error.into_result(error_msg, default) {
-                return (Err(err), gas_info);
-            }
-        }
 
         let next_id: u32 = self
             .iterators
@@ -103,7 +53,7 @@ impl Storage for GoStorage {
         (Ok(next_id), gas_info)
     }
 
-    fn next(&mut self, iterator_id: u32) -> BackendResult<Option<Record>> {
+    fn next_record(&mut self, iterator_id: u32) -> BackendResult<Option<Record>> {
         let iterator = match self.iterators.get_mut(&iterator_id) {
             Some(i) => i,
             None => {
@@ -115,58 +65,82 @@ impl Storage for GoStorage {
         };
         iterator.next()
     }
+}
+
+impl<D: VmDb> Storage for HostStorage<D> {
+    fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+        let outcome = charge(self.db.read(key));
+        record(
+            || BackendRequest::Get { key: key.into() },
+            &outcome,
+            |value| BackendResponse::Value(value.as_deref().map(Binary::from)),
+        );
+        outcome
+    }
+
+    fn scan(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> BackendResult<u32> {
+        let outcome = charge(self.open_iterator(start, end, order));
+        record(
+            || BackendRequest::Scan {
+                start: start.map(Binary::from),
+                end: end.map(Binary::from),
+                order: order as i32,
+            },
+            &outcome,
+            |id| BackendResponse::IteratorId(*id),
+        );
+        outcome
+    }
+
+    fn next(&mut self, iterator_id: u32) -> BackendResult<Option<Record>> {
+        let outcome = charge(self.next_record(iterator_id));
+        record(
+            || BackendRequest::Next { iterator_id },
+            &outcome,
+            |record| {
+                BackendResponse::Record(
+                    record
+                        .as_ref()
+                        .map(|(key, value)| (key.as_slice().into(), value.as_slice().into())),
+                )
+            },
+        );
+        outcome
+    }
 
     fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
-        let mut error_msg = UnmanagedVector::default();
-        let mut used_gas = 0_u64;
-        let go_error: GoError = (self.db.vtable.write_db)(
-            self.db.state,
-            self.db.gas_meter,
-            &mut used_gas as *mut u64,
-            U8SliceView::new(Some(key)),
-            U8SliceView::new(Some(value)),
-            &mut error_msg as *mut UnmanagedVector,
-        )
-        .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        // return complete error message (reading from buffer for GoError::Other)
-        let default = || {
-            format!(
-                "Failed to set a key in the db: {}",
-                String::from_utf8_lossy(key),
-            )
-        };
-        unsafe {
-            if let Err(err) = go_error.into_result(error_msg, default) {
-                return (Err(err), gas_info);
-            }
-        }
-        (Ok(()), gas_info)
+        invalidate_query_memo();
+        let outcome = charge(self.db.write(key, value));
+        record(
+            || BackendRequest::Set {
+                key: key.into(),
+                value: value.into(),
+            },
+            &outcome,
+            |()| BackendResponse::Done,
+        );
+        outcome
     }
 
     fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
-        let mut error_msg = UnmanagedVector::default();
-        let mut used_gas = 0_u64;
-        let go_error: GoError = (self.db.vtable.remove_db)(
-            self.db.state,
-            self.db.gas_meter,
-            &mut used_gas as *mut u64,
-            U8SliceView::new(Some(key)),
-            &mut error_msg as *mut UnmanagedVector,
-        )
-        .into();
-        let gas_info = GasInfo::with_externally_used(used_gas);
-        let default = || {
-            format!(
-                "Failed to delete a key in the db: {}",
-                String::from_utf8_lossy(key),
-            )
-        };
-        unsafe {
-            if let Err(err) = go_error.into_result(error_msg, default) {
-                return (Err(err), gas_info);
-            }
-        }
-        (Ok(()), gas_info)
+        invalidate_query_memo();
+        let outcome = charge(self.db.remove(key));
+        record(
+            || BackendRequest::Remove { key: key.into() },
+            &outcome,
+            |()| BackendResponse::Done,
+        );
+        outcome
     }
 }
+
+/// Applies the gas config of the call to the outcome of a storage callback
+fn charge<T>(outcome: BackendResult<T>) -> BackendResult<T> {
+    let config = current_gas_config();
+    config.charge(outcome, config.storage_callback_overhead)
+}
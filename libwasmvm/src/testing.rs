@@ -0,0 +1,436 @@
+//! In-Rust implementations of the Go callbacks.
+//!
+//! [`MockHost`] is the state behind the `db_t`, `api_t` and `querier_t` pointers of the vtables
+//! Go passes to the call exports. This allows calling the exports from Rust as Go does, e.g. in
+//! tests, fuzzing and benchmarks. Storage is a `BTreeMap`, addresses are converted by
+//! cosmwasm-vm's `MockApi` and queries are answered by its `MockQuerier`. Responses of single
+//! callbacks can be scripted in order to test how failures and malformed output from Go are handled.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::ops::Bound;
+
+use cosmwasm_std::{Coin, Order, Record};
+use cosmwasm_vm::testing::{MockApi, MockQuerier};
+use cosmwasm_vm::{BackendApi, BackendError, Querier};
+
+use crate::api::{api_t, GoApi, GoApi_vtable};
+use crate::db::{db_t, Db, Db_vtable};
+use crate::error::GoError;
+use crate::gas_meter::gas_meter_t;
+use crate::iterator::{iterator_t, GoIter, Iterator_vtable};
+use crate::memory::{U8SliceView, UnmanagedVector};
+use crate::querier::{querier_t, GoQuerier, Querier_vtable};
+
+/// The Go callbacks, for scripting failures and counting calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Callback {
+    ReadDb,
+    WriteDb,
+    RemoveDb,
+    ScanDb,
+    NextDb,
+    HumanizeAddress,
+    CanonicalizeAddress,
+    QueryExternal,
+}
+
+/// What a callback returns instead of doing its work
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScriptedResponse {
+    /// The raw return code, usually a [`GoError`]
+    pub code: i32,
+    /// Written to the outputs of the callback in order, e.g. key and value for `next_db`.
+    /// Outputs the callback does not have are ignored.
+    pub outputs: Vec<Option<Vec<u8>>>,
+    /// Written to the error message output
+    pub error_msg: Option<Vec<u8>>,
+}
+
+impl ScriptedResponse {
+    pub fn failure(error: GoError, error_msg: Option<&str>) -> Self {
+        ScriptedResponse {
+            code: error as i32,
+            outputs: vec![],
+            error_msg: error_msg.map(|msg| msg.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// A host implementing the Go callbacks in Rust.
+///
+/// The vtables returned by [`MockHost::db`], [`MockHost::api`] and [`MockHost::querier`] point
+/// to this host, so it must not be moved or dropped while they are in use.
+pub struct MockHost {
+    records: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+    /// The iterators created by `scan_db` by `iterator_index`
+    iterators: RefCell<Vec<std::vec::IntoIter<Record>>>,
+    api: MockApi,
+    querier: RefCell<MockQuerier>,
+    /// The gas reported as used by every callback
+    gas_per_callback: Cell<u64>,
+    scripted: RefCell<HashMap<Callback, VecDeque<ScriptedResponse>>>,
+    calls: RefCell<HashMap<Callback, usize>>,
+}
+
+impl MockHost {
+    pub fn new() -> Self {
+        MockHost {
+            records: RefCell::new(BTreeMap::new()),
+            iterators: RefCell::new(Vec::new()),
+            api: MockApi::default(),
+            querier: RefCell::new(MockQuerier::new(&[])),
+            gas_per_callback: Cell::new(0),
+            scripted: RefCell::new(HashMap::new()),
+            calls: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn db(&self) -> Db {
+        Db {
+            gas_meter: std::ptr::null_mut(),
+            state: self as *const MockHost as *mut db_t,
+            vtable: Db_vtable {
+                read_db: mock_read_db,
+                write_db: mock_write_db,
+                remove_db: mock_remove_db,
+                scan_db: mock_scan_db,
+            },
+        }
+    }
+
+    pub fn api(&self) -> GoApi {
+        GoApi {
+            state: self as *const MockHost as *const api_t,
+            vtable: GoApi_vtable {
+                humanize_address: mock_humanize_address,
+                canonicalize_address: mock_canonicalize_address,
+            },
+        }
+    }
+
+    pub fn querier(&self) -> GoQuerier {
+        GoQuerier {
+            state: self as *const MockHost as *const querier_t,
+            vtable: Querier_vtable {
+                query_external: mock_query_external,
+                query_evm: None,
+                consume_gas: None,
+            },
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.records.borrow().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &[u8], value: &[u8]) {
+        self.records
+            .borrow_mut()
+            .insert(key.to_vec(), value.to_vec());
+    }
+
+    pub fn records(&self) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        self.records.borrow().clone()
+    }
+
+    /// Sets the bank balance the querier reports for the address
+    pub fn set_balance(&self, address: &str, balance: Vec<Coin>) {
+        self.querier.borrow_mut().update_balance(address, balance);
+    }
+
+    pub fn set_gas_per_callback(&self, gas: u64) {
+        self.gas_per_callback.set(gas);
+    }
+
+    /// Makes the next call of the callback fail with the error
+    pub fn fail_next(&self, callback: Callback, error: GoError, error_msg: Option<&str>) {
+        self.script_next(callback, ScriptedResponse::failure(error, error_msg));
+    }
+
+    /// Makes the next call of the callback return the response. Responses for the same callback
+    /// are used in the order they were scripted.
+    pub fn script_next(&self, callback: Callback, response: ScriptedResponse) {
+        self.scripted
+            .borrow_mut()
+            .entry(callback)
+            .or_default()
+            .push_back(response);
+    }
+
+    /// The number of times the callback was called
+    pub fn calls(&self, callback: Callback) -> usize {
+        self.calls.borrow().get(&callback).copied().unwrap_or(0)
+    }
+
+    /// Counts the call and reports the gas. Returns the return code if a response is scripted.
+    fn begin(
+        &self,
+        callback: Callback,
+        used_gas: *mut u64,
+        outputs: &[*mut UnmanagedVector],
+        error_msg: *mut UnmanagedVector,
+    ) -> Option<i32> {
+        *self.calls.borrow_mut().entry(callback).or_default() += 1;
+        unsafe { *used_gas = self.gas_per_callback.get() };
+        let response = self
+            .scripted
+            .borrow_mut()
+            .get_mut(&callback)
+            .and_then(VecDeque::pop_front)?;
+        for (output, data) in outputs.iter().zip(response.outputs) {
+            unsafe { **output = UnmanagedVector::new(data) };
+        }
+        unsafe { *error_msg = UnmanagedVector::new(response.error_msg) };
+        Some(response.code)
+    }
+}
+
+impl Default for MockHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn host<'a, T>(state: *const T) -> &'a MockHost {
+    unsafe { &*(state as *const MockHost) }
+}
+
+/// Returns the error like Go does, i.e. user errors are passed to the contract
+fn backend_error(error: BackendError, error_msg: *mut UnmanagedVector) -> i32 {
+    let code = match error {
+        BackendError::UserErr { .. } => GoError::User,
+        BackendError::OutOfGas {} => GoError::OutOfGas,
+        BackendError::BadArgument {} => GoError::BadArgument,
+        _ => GoError::Other,
+    };
+    let msg = match error {
+        BackendError::UserErr { msg } => msg,
+        error => error.to_string(),
+    };
+    unsafe { *error_msg = UnmanagedVector::new(Some(msg.into_bytes())) };
+    code as i32
+}
+
+extern "C" fn mock_read_db(
+    db: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: U8SliceView,
+    result: *mut UnmanagedVector,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(db);
+    if let Some(code) = host.begin(Callback::ReadDb, used_gas, &[result], error_msg) {
+        return code;
+    }
+    let key = match key.read() {
+        Some(key) => key,
+        None => return GoError::BadArgument as i32,
+    };
+    unsafe { *result = UnmanagedVector::new(host.get(key)) };
+    GoError::None as i32
+}
+
+extern "C" fn mock_write_db(
+    db: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: U8SliceView,
+    value: U8SliceView,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(db);
+    if let Some(code) = host.begin(Callback::WriteDb, used_gas, &[], error_msg) {
+        return code;
+    }
+    match (key.read(), value.read()) {
+        (Some(key), Some(value)) => {
+            host.set(key, value);
+            GoError::None as i32
+        }
+        _ => GoError::BadArgument as i32,
+    }
+}
+
+extern "C" fn mock_remove_db(
+    db: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: U8SliceView,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(db);
+    if let Some(code) = host.begin(Callback::RemoveDb, used_gas, &[], error_msg) {
+        return code;
+    }
+    let key = match key.read() {
+        Some(key) => key,
+        None => return GoError::BadArgument as i32,
+    };
+    host.records.borrow_mut().remove(key);
+    GoError::None as i32
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "C" fn mock_scan_db(
+    db: *mut db_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    start: U8SliceView,
+    end: U8SliceView,
+    order: i32,
+    iterator_out: *mut GoIter,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(db);
+    if let Some(code) = host.begin(Callback::ScanDb, used_gas, &[], error_msg) {
+        return code;
+    }
+    let order = match Order::try_from(order) {
+        Ok(order) => order,
+        Err(_) => return GoError::BadArgument as i32,
+    };
+    let mut records: Vec<Record> = match (start.read(), end.read()) {
+        // Like in Go, the range is empty. `BTreeMap::range` would panic.
+        (Some(start), Some(end)) if start > end => Vec::new(),
+        (start, end) => {
+            let start = start.map_or(Bound::Unbounded, Bound::Included);
+            let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+            host.records
+                .borrow()
+                .range::<[u8], _>((start, end))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        }
+    };
+    if order == Order::Descending {
+        records.reverse();
+    }
+
+    let mut iterators = host.iterators.borrow_mut();
+    let iterator_index = iterators.len() as u64;
+    iterators.push(records.into_iter());
+    unsafe {
+        // Like Go, we identify the iterator by the call and its index. The host is the call here.
+        (*iterator_out).state = iterator_t {
+            call_id: host as *const MockHost as u64,
+            iterator_index,
+        };
+        (*iterator_out).vtable = Iterator_vtable {
+            next_db: Some(mock_next_db),
+        };
+    }
+    GoError::None as i32
+}
+
+extern "C" fn mock_next_db(
+    iterator: iterator_t,
+    _gas_meter: *mut gas_meter_t,
+    used_gas: *mut u64,
+    key: *mut UnmanagedVector,
+    value: *mut UnmanagedVector,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(iterator.call_id as *const MockHost);
+    if let Some(code) = host.begin(Callback::NextDb, used_gas, &[key, value], error_msg) {
+        return code;
+    }
+    let mut iterators = host.iterators.borrow_mut();
+    let iterator = match iterators.get_mut(iterator.iterator_index as usize) {
+        Some(iterator) => iterator,
+        None => return GoError::BadArgument as i32,
+    };
+    match iterator.next() {
+        Some((next_key, next_value)) => unsafe {
+            *key = UnmanagedVector::new(Some(next_key));
+            *value = UnmanagedVector::new(Some(next_value));
+        },
+        // Frees the records, as iterators are kept for the lifetime of the host
+        None => *iterator = Vec::new().into_iter(),
+    }
+    GoError::None as i32
+}
+
+extern "C" fn mock_humanize_address(
+    api: *const api_t,
+    input: U8SliceView,
+    human: *mut UnmanagedVector,
+    error_msg: *mut UnmanagedVector,
+    used_gas: *mut u64,
+) -> i32 {
+    let host = host(api);
+    if let Some(code) = host.begin(Callback::HumanizeAddress, used_gas, &[human], error_msg) {
+        return code;
+    }
+    let canonical = match input.read() {
+        Some(canonical) => canonical,
+        None => return GoError::BadArgument as i32,
+    };
+    match host.api.human_address(canonical).0 {
+        Ok(address) => {
+            unsafe { *human = UnmanagedVector::new(Some(address.into_bytes())) };
+            GoError::None as i32
+        }
+        Err(error) => backend_error(error, error_msg),
+    }
+}
+
+extern "C" fn mock_canonicalize_address(
+    api: *const api_t,
+    input: U8SliceView,
+    canonical: *mut UnmanagedVector,
+    error_msg: *mut UnmanagedVector,
+    used_gas: *mut u64,
+) -> i32 {
+    let host = host(api);
+    if let Some(code) = host.begin(
+        Callback::CanonicalizeAddress,
+        used_gas,
+        &[canonical],
+        error_msg,
+    ) {
+        return code;
+    }
+    let human = match input.read().map(std::str::from_utf8) {
+        Some(Ok(human)) => human,
+        _ => return GoError::BadArgument as i32,
+    };
+    match host.api.canonical_address(human).0 {
+        Ok(address) => {
+            unsafe { *canonical = UnmanagedVector::new(Some(address)) };
+            GoError::None as i32
+        }
+        Err(error) => backend_error(error, error_msg),
+    }
+}
+
+extern "C" fn mock_query_external(
+    querier: *const querier_t,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    request: U8SliceView,
+    result: *mut UnmanagedVector,
+    error_msg: *mut UnmanagedVector,
+) -> i32 {
+    let host = host(querier);
+    if let Some(code) = host.begin(Callback::QueryExternal, used_gas, &[result], error_msg) {
+        return code;
+    }
+    let request = match request.read() {
+        Some(request) => request,
+        None => return GoError::BadArgument as i32,
+    };
+    let response = match host.querier.borrow().query_raw(request, gas_limit).0 {
+        Ok(response) => response,
+        Err(error) => return backend_error(error, error_msg),
+    };
+    match serde_json::to_vec(&response) {
+        Ok(response) => {
+            unsafe { *result = UnmanagedVector::new(Some(response)) };
+            GoError::None as i32
+        }
+        Err(_) => GoError::CannotSerialize as i32,
+    }
+}
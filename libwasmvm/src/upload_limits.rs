@@ -0,0 +1,323 @@
+//! Upload policy limits that are enforced in addition to cosmwasm-vm's static checks.
+
+use std::fmt;
+
+use parity_wasm::elements::{Deserialize as _, External, Module, VarUint32};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::static_analysis::deserialize_wasm;
+
+/// Limits for Wasm code stored via `save_wasm`. Unset limits are not checked.
+///
+/// All limits are checked against the original Wasm bytecode before it is compiled.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct UploadLimits {
+    /// The maximum size of the Wasm bytecode in bytes
+    #[serde(default)]
+    pub max_wasm_size: Option<u64>,
+    /// The maximum number of functions defined in the module (imports not included)
+    #[serde(default)]
+    pub max_functions: Option<u64>,
+    #[serde(default)]
+    pub max_imports: Option<u64>,
+    #[serde(default)]
+    pub max_exports: Option<u64>,
+    /// The maximum number of globals defined in the module (imports not included)
+    #[serde(default)]
+    pub max_globals: Option<u64>,
+    /// The maximum number of elements of a table, initial or maximum
+    #[serde(default)]
+    pub max_table_size: Option<u64>,
+    /// The maximum size of a single function body in bytes
+    #[serde(default)]
+    pub max_function_body_size: Option<u64>,
+}
+
+/// The limit that was exceeded, see [`UploadLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadLimit {
+    WasmSize,
+    Functions,
+    Imports,
+    Exports,
+    Globals,
+    TableSize,
+    FunctionBodySize,
+}
+
+impl fmt::Display for UploadLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            UploadLimit::WasmSize => "Wasm size",
+            UploadLimit::Functions => "number of functions",
+            UploadLimit::Imports => "number of imports",
+            UploadLimit::Exports => "number of exports",
+            UploadLimit::Globals => "number of globals",
+            UploadLimit::TableSize => "table size",
+            UploadLimit::FunctionBodySize => "function body size",
+        };
+        f.write_str(name)
+    }
+}
+
+impl UploadLimits {
+    /// Returns true if no limit is set, i.e. checking is a no-op
+    pub fn is_unlimited(&self) -> bool {
+        *self == UploadLimits::default()
+    }
+
+    /// Checks the Wasm bytecode against all configured limits
+    pub fn check(&self, wasm: &[u8]) -> Result<(), Error> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+        check_limit(UploadLimit::WasmSize, wasm.len(), self.max_wasm_size)?;
+
+        let module = deserialize_wasm(wasm)?;
+        check_limit(
+            UploadLimit::Functions,
+            module.function_section().map_or(0, |s| s.entries().len()),
+            self.max_functions,
+        )?;
+        check_limit(
+            UploadLimit::Imports,
+            module.import_section().map_or(0, |s| s.entries().len()),
+            self.max_imports,
+        )?;
+        check_limit(
+            UploadLimit::Exports,
+            module.export_section().map_or(0, |s| s.entries().len()),
+            self.max_exports,
+        )?;
+        check_limit(
+            UploadLimit::Globals,
+            module.global_section().map_or(0, |s| s.entries().len()),
+            self.max_globals,
+        )?;
+        if self.max_table_size.is_some() {
+            check_limit(
+                UploadLimit::TableSize,
+                max_table_size(&module),
+                self.max_table_size,
+            )?;
+        }
+        if self.max_function_body_size.is_some() {
+            check_limit(
+                UploadLimit::FunctionBodySize,
+                max_function_body_size(wasm)?,
+                self.max_function_body_size,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn check_limit(limit: UploadLimit, actual: usize, max: Option<u64>) -> Result<(), Error> {
+    match max {
+        Some(max) if actual as u64 > max => Err(Error::upload_limit_exceeded(limit, actual, max)),
+        _ => Ok(()),
+    }
+}
+
+/// The largest initial or maximum size of all defined and imported tables
+fn max_table_size(module: &Module) -> usize {
+    let defined = module
+        .table_section()
+        .map_or(&[][..], |s| s.entries())
+        .iter()
+        .map(|table| table.limits());
+    let imported = module
+        .import_section()
+        .map_or(&[][..], |s| s.entries())
+        .iter()
+        .filter_map(|entry| match entry.external() {
+            External::Table(table) => Some(table.limits()),
+            _ => None,
+        });
+    defined
+        .chain(imported)
+        .map(|limits| limits.maximum().unwrap_or(0).max(limits.initial()) as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The id of the code section
+const CODE_SECTION_ID: u8 = 10;
+
+/// The size of the largest function body in bytes, as encoded in the code section.
+/// This reads the sizes from the original bytecode since a re-encoding might be shorter.
+fn max_function_body_size(wasm: &[u8]) -> Result<usize, Error> {
+    // skip magic number and version
+    let mut reader = wasm.get(8..).unwrap_or_default();
+    while let Some((&id, rest)) = reader.split_first() {
+        reader = rest;
+        let section_size = read_u32(&mut reader)? as usize;
+        let section = take(&mut reader, section_size)?;
+        if id != CODE_SECTION_ID {
+            continue;
+        }
+        let mut bodies = section;
+        let mut max = 0;
+        for _ in 0..read_u32(&mut bodies)? {
+            let size = read_u32(&mut bodies)? as usize;
+            take(&mut bodies, size)?;
+            max = max.max(size);
+        }
+        return Ok(max);
+    }
+    Ok(0)
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32, Error> {
+    Ok(VarUint32::deserialize(reader)
+        .map_err(|e| Error::vm_err(format!("Error reading code section: {}", e)))?
+        .into())
+}
+
+/// Splits off the first `len` bytes of the reader
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if reader.len() < len {
+        return Err(Error::vm_err("Error reading code section: unexpected end"));
+    }
+    let (taken, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+
+    fn exceeded(err: Error) -> UploadLimit {
+        match err {
+            Error::UploadLimitExceeded { limit, .. } => limit,
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn check_works_without_limits() {
+        UploadLimits::default().check(HACKATOM).unwrap();
+        // nothing is parsed if no limit is set
+        UploadLimits::default().check(b"not wasm").unwrap();
+    }
+
+    #[test]
+    fn check_works_with_generous_limits() {
+        let limits = UploadLimits {
+            max_wasm_size: Some(HACKATOM.len() as u64),
+            max_functions: Some(10_000),
+            max_imports: Some(100),
+            max_exports: Some(100),
+            max_globals: Some(100),
+            max_table_size: Some(2500),
+            max_function_body_size: Some(1_000_000),
+        };
+        limits.check(HACKATOM).unwrap();
+    }
+
+    #[test]
+    fn check_reports_exceeded_limit() {
+        let err = UploadLimits {
+            max_wasm_size: Some(HACKATOM.len() as u64 - 1),
+            ..UploadLimits::default()
+        }
+        .check(HACKATOM)
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Upload limit exceeded: Wasm size is {}, the limit is {}",
+                HACKATOM.len(),
+                HACKATOM.len() - 1
+            )
+        );
+
+        let cases = [
+            (
+                UploadLimit::Functions,
+                UploadLimits {
+                    max_functions: Some(1),
+                    ..UploadLimits::default()
+                },
+            ),
+            (
+                UploadLimit::Imports,
+                UploadLimits {
+                    max_imports: Some(1),
+                    ..UploadLimits::default()
+                },
+            ),
+            (
+                UploadLimit::Exports,
+                UploadLimits {
+                    max_exports: Some(1),
+                    ..UploadLimits::default()
+                },
+            ),
+            (
+                UploadLimit::Globals,
+                UploadLimits {
+                    max_globals: Some(0),
+                    ..UploadLimits::default()
+                },
+            ),
+            (
+                UploadLimit::TableSize,
+                UploadLimits {
+                    max_table_size: Some(0),
+                    ..UploadLimits::default()
+                },
+            ),
+            (
+                UploadLimit::FunctionBodySize,
+                UploadLimits {
+                    max_function_body_size: Some(10),
+                    ..UploadLimits::default()
+                },
+            ),
+        ];
+        for (expected, limits) in cases {
+            assert_eq!(exceeded(limits.check(HACKATOM).unwrap_err()), expected);
+        }
+    }
+
+    #[test]
+    fn check_fails_for_invalid_wasm() {
+        let limits = UploadLimits {
+            max_functions: Some(10),
+            ..UploadLimits::default()
+        };
+        let err = limits.check(b"not wasm").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Wasm bytecode could not be deserialized"));
+    }
+
+    #[test]
+    fn check_measures_function_bodies_as_encoded() {
+        // A module with one function whose `i32.const 0` uses a 2 byte LEB128 immediate,
+        // which would be 1 byte if the body was encoded again
+        let wasm: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: () -> ()
+            0x03, 0x02, 0x01, 0x00, // function section
+            0x0a, 0x08, 0x01, // code section with one body
+            0x06, 0x00, 0x41, 0x80, 0x00, 0x1a, 0x0b, // no locals, i32.const 0, drop, end
+        ];
+        assert_eq!(max_function_body_size(wasm).unwrap(), 6);
+        let limits = |max| UploadLimits {
+            max_function_body_size: Some(max),
+            ..UploadLimits::default()
+        };
+        limits(6).check(wasm).unwrap();
+        assert_eq!(
+            exceeded(limits(5).check(wasm).unwrap_err()),
+            UploadLimit::FunctionBodySize
+        );
+    }
+}
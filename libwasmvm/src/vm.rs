@@ -0,0 +1,997 @@
+//! The Rust embedding API.
+//!
+//! [`Vm`] runs contracts with the same cache, gas accounting and host features as the C exports,
+//! which are a thin adapter on top of it. The host is represented by implementations of
+//! [`VmDb`], [`VmApi`] and [`VmQuerier`], which mirror `Db_vtable`, `GoApi_vtable` and
+//! `Querier_vtable`.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use cosmwasm_std::{Order, Record};
+use cosmwasm_vm::{Backend, BackendResult, Checksum, GasReport, Metrics, Size};
+
+use crate::activation::FeatureSet;
+use crate::api::HostApi;
+use crate::calls::{call_entry_point, EntryPoint};
+use crate::error::Error;
+use crate::fingerprint::FINGERPRINT_LENGTH;
+use crate::gas_config::GasConfig;
+use crate::querier::HostQuerier;
+use crate::query_router::{QueryHandler, QueryRoutes};
+use crate::static_analysis::AnalysisReport;
+use crate::storage::HostStorage;
+use crate::vm_cache::{VmCache, VmCacheOptions};
+
+/// The contract storage, mirroring `Db_vtable`. Gas is reported as used externally,
+/// i.e. it was charged by the host already.
+pub trait VmDb {
+    type Iterator: VmIterator;
+
+    fn read(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>>;
+
+    fn write(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()>;
+
+    fn remove(&mut self, key: &[u8]) -> BackendResult<()>;
+
+    /// Iterates over the keys in `start..end`. Both bounds are optional.
+    fn scan(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> BackendResult<Self::Iterator>;
+}
+
+/// An iterator created by [`VmDb::scan`], mirroring `Iterator_vtable`
+pub trait VmIterator {
+    fn next(&mut self) -> BackendResult<Option<Record>>;
+}
+
+/// Address conversions, mirroring `GoApi_vtable`. These are not used if an address codec is
+/// configured.
+pub trait VmApi: Copy + Send {
+    fn humanize_address(&self, canonical: &[u8]) -> BackendResult<String>;
+
+    fn canonicalize_address(&self, human: &str) -> BackendResult<Vec<u8>>;
+}
+
+/// Answers queries of contracts, mirroring `Querier_vtable`. Queries answered by the
+/// query router or memoized responses do not get here.
+pub trait VmQuerier {
+    /// Returns the JSON encoded `SystemResult<ContractResult<Binary>>` for the request
+    fn query_external(&self, request: &[u8], gas_limit: u64) -> BackendResult<Vec<u8>>;
+
+    /// Calls the EVM contract at the 20 byte address `to`. Returns the return data along with
+    /// the gas and EVM gas used, or None if EVM queries are unsupported. A reverted call is a
+    /// [`cosmwasm_vm::BackendError::UserErr`].
+    fn query_evm(
+        &self,
+        _to: &[u8],
+        _data: &[u8],
+        _evm_gas_limit: u64,
+        _gas_limit: u64,
+    ) -> Option<(BackendResult<Vec<u8>>, u64)> {
+        None
+    }
+
+    /// Charges `gas` like a query that used it, for queries answered by the query router or
+    /// from memoized responses. Returns None if this is unsupported, in which case all queries
+    /// are answered by [`VmQuerier::query_external`].
+    fn consume_gas(&self, _gas: u64) -> Option<BackendResult<()>> {
+        None
+    }
+}
+
+/// The host of a call
+pub struct VmBackend<A, D, Q> {
+    pub api: A,
+    pub db: D,
+    pub querier: Q,
+}
+
+/// The outcome of a call
+pub struct VmOutput<A, D, Q> {
+    /// The result of the entry point. Gas is reported even if this is an error.
+    pub result: Result<Vec<u8>, Error>,
+    pub gas_report: GasReport,
+    /// The EVM gas used by EVM queries of the call
+    pub evm_gas_used: u64,
+    /// The fingerprint of the call if requested
+    pub fingerprint: Option<[u8; FINGERPRINT_LENGTH]>,
+    /// The backend, returned by the instance after the call
+    pub backend: Option<VmBackend<A, D, Q>>,
+}
+
+pub type HostCache<A, D, Q> = VmCache<HostApi<A>, HostStorage<D>, HostQuerier<Q>>;
+
+/// Stores code and runs contracts
+pub struct Vm<A, D, Q>
+where
+    A: VmApi + 'static,
+    D: VmDb + 'static,
+    Q: VmQuerier + 'static,
+{
+    cache: HostCache<A, D, Q>,
+}
+
+impl<A, D, Q> Vm<A, D, Q>
+where
+    A: VmApi + 'static,
+    D: VmDb + 'static,
+    Q: VmQuerier + 'static,
+{
+    /// Creates a VM that keeps its data in `options.base_dir` or in memory, depending on the options.
+    ///
+    /// # Safety
+    ///
+    /// Unless `options.in_memory_wasm_store_size` is set, compiled modules are loaded from
+    /// `options.base_dir` and executed as native code without any validation, see [`VmCache::new`].
+    /// This cannot be made safe on our side, so the caller must ensure that nothing but wasmvm
+    /// writes to that directory. Use [`Vm::in_memory`] to avoid the disk altogether.
+    pub unsafe fn new(options: VmCacheOptions) -> Result<Self, Error> {
+        Ok(Vm {
+            cache: VmCache::new(options)?,
+        })
+    }
+
+    /// Creates a VM that keeps Wasm blobs and compiled modules in memory only, so contracts
+    /// must be stored again after a restart. Wasm blobs are limited to `wasm_store_size` in total.
+    ///
+    /// `options.base_dir` and `options.fs_cache` are ignored.
+    pub fn in_memory(options: VmCacheOptions, wasm_store_size: Size) -> Result<Self, Error> {
+        let options = VmCacheOptions {
+            in_memory_wasm_store_size: Some(wasm_store_size),
+            ..options
+        };
+        // SAFETY: with an in-memory Wasm store, the cache neither reads nor writes `base_dir`,
+        // so every module it runs was compiled by this process.
+        Ok(Vm {
+            cache: unsafe { VmCache::new(options) }?,
+        })
+    }
+
+    /// The underlying cache, for everything not covered by the methods of the VM
+    pub fn cache(&self) -> &HostCache<A, D, Q> {
+        &self.cache
+    }
+
+    /// Checks and stores the code, returning its checksum
+    pub fn store_code(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        self.cache.save_wasm(wasm)
+    }
+
+    /// Stores the code without checks. Only use this for code that was checked before.
+    pub fn store_code_unchecked(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        self.cache.save_wasm_unchecked(wasm)
+    }
+
+    /// Runs the checks of [`Vm::store_code`] without storing anything
+    pub fn validate_code(&self, wasm: &[u8]) -> Result<(), Error> {
+        self.cache.validate_wasm(wasm)
+    }
+
+    pub fn remove_code(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.cache.remove_wasm(checksum)
+    }
+
+    pub fn get_code(&self, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+        self.cache.load_wasm(checksum)
+    }
+
+    pub fn analyze_code(&self, checksum: &Checksum) -> Result<AnalysisReport, Error> {
+        self.cache.analyze(checksum)
+    }
+
+    pub fn pin(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.cache.pin(checksum)
+    }
+
+    pub fn unpin(&self, checksum: &Checksum) -> Result<(), Error> {
+        self.cache.unpin(checksum)
+    }
+
+    /// Overrides the instance memory limit for calls of the checksum, see
+    /// [`VmCache::set_instance_memory_limit`]
+    pub fn set_instance_memory_limit(
+        &self,
+        checksum: &Checksum,
+        limit: Option<Size>,
+    ) -> Result<(), Error> {
+        self.cache.set_instance_memory_limit(checksum, limit)
+    }
+
+    /// Uses the gas config for calls from the block height on
+    pub fn update_gas_config(&self, height: u64, config: GasConfig) -> Result<(), Error> {
+        self.cache.update_gas_config(height, config)
+    }
+
+    /// The host features of calls at the block height, see [`VmCache::feature_set`]
+    pub fn feature_set(&self, height: u64) -> FeatureSet {
+        self.cache.feature_set(height)
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.cache.metrics()
+    }
+
+    pub fn capabilities(&self) -> HashSet<String> {
+        self.cache.capabilities()
+    }
+
+    /// Replaces the available capabilities. Returns the checksums of stored Wasm blobs that
+    /// require capabilities which are not available anymore.
+    pub fn set_capabilities(&self, capabilities: HashSet<String>) -> Result<Vec<Checksum>, Error> {
+        self.cache.set_capabilities(capabilities)
+    }
+
+    /// Registers a native query handler, which query routes can refer to by `name`
+    pub fn register_query_handler(&self, name: &str, handler: Arc<dyn QueryHandler>) {
+        self.cache.query_router().register(name, handler);
+    }
+
+    /// Uses the query routes for calls from the block height on
+    pub fn update_query_routes(&self, height: u64, routes: QueryRoutes) {
+        self.cache.query_router().update_routes(height, routes);
+    }
+
+    /// Replaces the responses of the `snapshot` query handler with the JSON encoded snapshot
+    /// taken at the block height. Returns the number of responses.
+    pub fn set_query_snapshot(&self, height: u64, snapshot: &[u8]) -> Result<usize, Error> {
+        self.cache
+            .query_router()
+            .snapshot()
+            .replace_from_json(height, snapshot)
+    }
+
+    /// Calls an entry point with the raw arguments, starting with the env.
+    /// See [`EntryPoint::arg_names`] for the arguments of each entry point.
+    /// If `fingerprint` is set, the output contains the fingerprint of the call.
+    pub fn call(
+        &self,
+        entry_point: EntryPoint,
+        checksum: &Checksum,
+        args: &[&[u8]],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+        fingerprint: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let backend = Backend {
+            api: HostApi::new(backend.api, self.cache.address_codec()),
+            storage: HostStorage::new(backend.db),
+            querier: HostQuerier::new(backend.querier),
+        };
+        let output = call_entry_point(
+            &self.cache,
+            entry_point,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            fingerprint,
+        )?;
+        Ok(VmOutput {
+            result: output.result,
+            gas_report: output.gas_report,
+            evm_gas_used: output.evm_gas_used,
+            fingerprint: output.fingerprint,
+            backend: output.backend.map(|backend| VmBackend {
+                api: backend.api.into_inner(),
+                db: backend.storage.into_inner(),
+                querier: backend.querier.into_inner(),
+            }),
+        })
+    }
+
+    pub fn instantiate(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        info: &[u8],
+        msg: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, info, msg];
+        self.call(
+            EntryPoint::Instantiate,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+
+    pub fn execute(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        info: &[u8],
+        msg: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, info, msg];
+        self.call(
+            EntryPoint::Execute,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+
+    pub fn migrate(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        msg: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, msg];
+        self.call(
+            EntryPoint::Migrate,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+
+    pub fn sudo(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        msg: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, msg];
+        self.call(
+            EntryPoint::Sudo,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+
+    pub fn reply(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        reply: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, reply];
+        self.call(
+            EntryPoint::Reply,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+
+    pub fn query(
+        &self,
+        checksum: &Checksum,
+        env: &[u8],
+        msg: &[u8],
+        backend: VmBackend<A, D, Q>,
+        gas_limit: u64,
+        print_debug: bool,
+    ) -> Result<VmOutput<A, D, Q>, Error> {
+        let args: &[&[u8]] = &[env, msg];
+        self.call(
+            EntryPoint::Query,
+            checksum,
+            args,
+            backend,
+            gas_limit,
+            print_debug,
+            false,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::querier::QueryLimit;
+    use crate::replay::{replay, BackendRequest, Recording};
+    use cosmwasm_std::{
+        from_slice, to_vec, Binary, ContractResult, Empty, SystemError, SystemResult,
+    };
+    use cosmwasm_vm::testing::{mock_env, mock_info, MockApi};
+    use cosmwasm_vm::{BackendApi, GasInfo, Size};
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use tempfile::TempDir;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+
+    const GAS_PER_OP: u64 = 7;
+
+    #[derive(Default)]
+    struct TestDb {
+        records: BTreeMap<Vec<u8>, Vec<u8>>,
+    }
+
+    struct TestIter(std::vec::IntoIter<Record>);
+
+    impl VmIterator for TestIter {
+        fn next(&mut self) -> BackendResult<Option<Record>> {
+            (Ok(self.0.next()), GasInfo::with_externally_used(GAS_PER_OP))
+        }
+    }
+
+    impl VmDb for TestDb {
+        type Iterator = TestIter;
+
+        fn read(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
+            let value = self.records.get(key).cloned();
+            (Ok(value), GasInfo::with_externally_used(GAS_PER_OP))
+        }
+
+        fn write(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+            self.records.insert(key.to_vec(), value.to_vec());
+            (Ok(()), GasInfo::with_externally_used(GAS_PER_OP))
+        }
+
+        fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+            self.records.remove(key);
+            (Ok(()), GasInfo::with_externally_used(GAS_PER_OP))
+        }
+
+        fn scan(
+            &mut self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> BackendResult<TestIter> {
+            let start = start.map_or(Bound::Unbounded, Bound::Included);
+            let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+            let mut records: Vec<Record> = self
+                .records
+                .range::<[u8], _>((start, end))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            if order == Order::Descending {
+                records.reverse();
+            }
+            let iter = TestIter(records.into_iter());
+            (Ok(iter), GasInfo::with_externally_used(GAS_PER_OP))
+        }
+    }
+
+    #[derive(Copy, Clone, Default)]
+    struct TestApi(MockApi);
+
+    impl VmApi for TestApi {
+        fn humanize_address(&self, canonical: &[u8]) -> BackendResult<String> {
+            self.0.human_address(canonical)
+        }
+
+        fn canonicalize_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+            self.0.canonical_address(human)
+        }
+    }
+
+    struct TestQuerier;
+
+    impl VmQuerier for TestQuerier {
+        fn query_external(&self, _request: &[u8], _gas_limit: u64) -> BackendResult<Vec<u8>> {
+            let result: SystemResult<ContractResult<Binary>> =
+                SystemResult::Err(SystemError::NoSuchContract {
+                    addr: "none".to_string(),
+                });
+            (Ok(to_vec(&result).unwrap()), GasInfo::free())
+        }
+    }
+
+    fn backend(db: TestDb) -> VmBackend<TestApi, TestDb, TestQuerier> {
+        VmBackend {
+            api: TestApi::default(),
+            db,
+            querier: TestQuerier,
+        }
+    }
+
+    #[test]
+    fn vm_works_with_rust_backend() {
+        let dir = TempDir::new().unwrap();
+        let options = VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+        assert_eq!(vm.get_code(&checksum).unwrap(), HACKATOM);
+        assert!(vm
+            .analyze_code(&checksum)
+            .unwrap()
+            .entrypoints
+            .contains("instantiate"));
+
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let output = vm
+            .instantiate(
+                &checksum,
+                &env,
+                &info,
+                msg,
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let result: ContractResult<cosmwasm_std::Response<Empty>> =
+            from_slice(&output.result.unwrap()).unwrap();
+        assert!(result.is_ok());
+        // storage gas is charged by the host
+        assert!(output.gas_report.used_externally >= GAS_PER_OP);
+        let db = output.backend.unwrap().db;
+        assert!(db.records.contains_key(b"config".as_slice()));
+
+        let output = vm
+            .query(
+                &checksum,
+                &env,
+                br#"{"verifier": {}}"#,
+                backend(db),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let result: ContractResult<Binary> = from_slice(&output.result.unwrap()).unwrap();
+        assert_eq!(
+            result.unwrap().as_slice(),
+            br#"{"verifier":"verifies"}"#.as_slice()
+        );
+
+        let err = vm
+            .call(
+                EntryPoint::Query,
+                &checksum,
+                &[&env],
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+                false,
+            )
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("query takes 2 arguments, got 1"));
+
+        vm.remove_code(&checksum).unwrap();
+        vm.get_code(&checksum).unwrap_err();
+    }
+
+    #[test]
+    fn in_memory_works() {
+        let dir = TempDir::new().unwrap();
+        let options = VmCacheOptions::new(
+            dir.path().join("unused"),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        let vm: Vm<TestApi, TestDb, TestQuerier> = Vm::in_memory(options, Size::mebi(10)).unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+        assert_eq!(vm.get_code(&checksum).unwrap(), HACKATOM);
+
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let output = vm
+            .instantiate(
+                &checksum,
+                &env,
+                &info,
+                msg,
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let result: ContractResult<cosmwasm_std::Response<Empty>> =
+            from_slice(&output.result.unwrap()).unwrap();
+        assert!(result.is_ok());
+        // nothing touches the disk
+        assert!(!dir.path().join("unused").exists());
+    }
+
+    #[test]
+    fn exceeding_query_limits_fails_queries_or_aborts_calls() {
+        for abort_on_exceeded in [false, true] {
+            let dir = TempDir::new().unwrap();
+            let mut options = VmCacheOptions::new(
+                dir.path().to_path_buf(),
+                HashSet::from(["staking".to_string()]),
+                Size::mebi(200),
+                Size::mebi(32),
+            );
+            options.query_limits.max_depth = Some(0);
+            options.query_limits.abort_on_exceeded = abort_on_exceeded;
+            let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+            let checksum = vm.store_code(HACKATOM).unwrap();
+
+            let env = to_vec(&mock_env()).unwrap();
+            let output = vm
+                .query(
+                    &checksum,
+                    &env,
+                    br#"{"other_balance": {"address": "someone"}}"#,
+                    backend(TestDb::default()),
+                    500_000_000_000,
+                    false,
+                )
+                .unwrap();
+            if !abort_on_exceeded {
+                // the contract sees a failed query
+                let result: ContractResult<Binary> = from_slice(&output.result.unwrap()).unwrap();
+                assert!(result
+                    .unwrap_err()
+                    .contains("Query depth limit of 0 exceeded"));
+                continue;
+            }
+            match output.result.unwrap_err() {
+                Error::QueryLimitExceeded { limit, max, .. } => {
+                    assert_eq!(limit, QueryLimit::Depth);
+                    assert_eq!(max, 0);
+                }
+                err => panic!("Unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn recorded_calls_can_be_replayed() {
+        let dir = TempDir::new().unwrap();
+        let record_dir = dir.path().join("recordings");
+        let mut options = VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        options.record_dir = Some(record_dir.clone());
+        let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let output = vm
+            .instantiate(
+                &checksum,
+                &env,
+                &info,
+                msg,
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        let gas_used = output.gas_report.used_internally;
+        let instantiated = output.result.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&record_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("{}-", mock_env().block.height)));
+        assert!(name.ends_with("-instantiate.json"));
+        let recording: Recording =
+            serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+        assert_eq!(recording.entry_point, "instantiate");
+        assert_eq!(recording.result, Ok(Binary::from(instantiated.clone())));
+        assert_eq!(recording.gas_used, gas_used);
+        assert!(recording
+            .interactions
+            .iter()
+            .any(|interaction| matches!(interaction.request, BackendRequest::Set { .. })));
+
+        // no Go host and no database needed
+        let output = replay(&recording, HACKATOM, false).unwrap();
+        assert!(output.matches(&recording));
+        assert_eq!(output.result.unwrap(), instantiated);
+        assert_eq!(output.gas_report.used_internally, gas_used);
+
+        // a recording that does not match the contract's requests is rejected
+        let mut truncated = recording.clone();
+        truncated.interactions.pop();
+        let err = replay(&truncated, HACKATOM, false).err().unwrap();
+        assert!(matches!(err, Error::ReplayErr { .. }), "{}", err);
+
+        let mut tampered = recording;
+        tampered.args[2] = Binary::from(br#"{"verifier": "other", "beneficiary": "benefits"}"#);
+        let err = replay(&tampered, HACKATOM, false).err().unwrap();
+        assert!(err.to_string().contains("diverged"), "{}", err);
+
+        let err = replay(&truncated, b"\0asm", false).err().unwrap();
+        assert!(err.to_string().contains("checksum"), "{}", err);
+    }
+
+    #[test]
+    fn fingerprints_identify_diverging_calls() {
+        let dir = TempDir::new().unwrap();
+        let mut options = VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        options.record_dir = Some(dir.path().join("recordings"));
+        let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("creator", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let fingerprint = |msg: &[u8]| {
+            let output = vm
+                .call(
+                    EntryPoint::Instantiate,
+                    &checksum,
+                    &[&env, &info, msg],
+                    backend(TestDb::default()),
+                    500_000_000_000,
+                    false,
+                    true,
+                )
+                .unwrap();
+            (output.result.unwrap(), output.fingerprint.unwrap())
+        };
+
+        let (result, original) = fingerprint(msg);
+        assert_eq!(fingerprint(msg), (result.clone(), original));
+        // the stored state differs, which is visible in the interactions but not in the result
+        let (other_result, other) =
+            fingerprint(br#"{"verifier": "verifies", "beneficiary": "other"}"#);
+        assert_eq!(other_result, result);
+        assert_ne!(other, original);
+
+        // not computed unless requested
+        let output = vm
+            .instantiate(
+                &checksum,
+                &env,
+                &info,
+                msg,
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        assert!(output.fingerprint.is_none());
+
+        // a replay has the fingerprint of the recorded call
+        let file = std::fs::read_dir(dir.path().join("recordings"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .min_by_key(|path| {
+                // <height>-<sequence>-<entry point>.json
+                let name = path.file_name().unwrap().to_str().unwrap().to_string();
+                name.split('-').nth(1).unwrap().parse::<u64>().unwrap()
+            })
+            .unwrap();
+        let recording: Recording = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        let output = replay(&recording, HACKATOM, false).unwrap();
+        assert_eq!(output.fingerprint, original);
+    }
+
+    #[test]
+    fn instance_memory_limits_can_be_set_per_checksum() {
+        let dir = TempDir::new().unwrap();
+        let mut options = VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        options.max_instance_memory_limit = Some(Size::mebi(64));
+        let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+
+        let env = to_vec(&mock_env()).unwrap();
+        let info = to_vec(&mock_info("verifies", &[])).unwrap();
+        let msg = br#"{"verifier": "verifies", "beneficiary": "benefits"}"#;
+        let output = vm
+            .instantiate(
+                &checksum,
+                &env,
+                &info,
+                msg,
+                backend(TestDb::default()),
+                500_000_000_000,
+                false,
+            )
+            .unwrap();
+        output.result.unwrap();
+        let db = output.backend.unwrap().db;
+        let records = db.records.clone();
+
+        // grows the memory by 40 MiB
+        let allocate = |records: &BTreeMap<Vec<u8>, Vec<u8>>| {
+            let db = TestDb {
+                records: records.clone(),
+            };
+            vm.execute(
+                &checksum,
+                &env,
+                &info,
+                br#"{"allocate_large_memory":{"pages":640}}"#,
+                backend(db),
+                500_000_000_000,
+                false,
+            )
+            .unwrap()
+        };
+        // the memory cannot grow beyond the limit, which the contract sees
+        let result: ContractResult<cosmwasm_std::Response<Empty>> =
+            from_slice(&allocate(&records).result.unwrap()).unwrap();
+        assert_eq!(result.unwrap_err(), "Generic error: memory.grow failed");
+
+        vm.set_instance_memory_limit(&checksum, Some(Size::mebi(48)))
+            .unwrap();
+        let result: ContractResult<cosmwasm_std::Response<Empty>> =
+            from_slice(&allocate(&records).result.unwrap()).unwrap();
+        assert!(result.is_ok());
+
+        // lower than the default, which is exceeded by the initial memory of the contract
+        vm.set_instance_memory_limit(&checksum, Some(Size::kibi(64)))
+            .unwrap();
+        let err = vm
+            .query(
+                &checksum,
+                &env,
+                br#"{"verifier": {}}"#,
+                backend(TestDb { records }),
+                500_000_000_000,
+                false,
+            )
+            .err()
+            .unwrap();
+        match err {
+            Error::InstanceMemoryLimitExceeded {
+                checksum: contract,
+                limit,
+                ..
+            } => {
+                assert_eq!(contract, checksum.to_hex());
+                assert_eq!(limit, 65536);
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        let err = vm
+            .set_instance_memory_limit(&checksum, Some(Size::mebi(65)))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cache error: Instance memory limit of 68157440 bytes exceeds the maximum of 67108864 bytes"
+        );
+
+        vm.set_instance_memory_limit(&checksum, None).unwrap();
+        assert_eq!(
+            vm.cache().instance_memory_limit(&checksum).0,
+            Size::mebi(32).0
+        );
+    }
+
+    #[test]
+    fn gas_config_can_be_updated_at_a_height() {
+        let dir = TempDir::new().unwrap();
+        let record_dir = dir.path().join("recordings");
+        let mut options = VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        );
+        options.record_dir = Some(record_dir.clone());
+        let vm: Vm<TestApi, TestDb, TestQuerier> = unsafe { Vm::new(options) }.unwrap();
+        let checksum = vm.store_code(HACKATOM).unwrap();
+
+        let height = mock_env().block.height;
+        let instantiate = |height| {
+            let mut env = mock_env();
+            env.block.height = height;
+            let output = vm
+                .instantiate(
+                    &checksum,
+                    &to_vec(&env).unwrap(),
+                    &to_vec(&mock_info("creator", &[])).unwrap(),
+                    br#"{"verifier": "verifies", "beneficiary": "benefits"}"#,
+                    backend(TestDb::default()),
+                    500_000_000_000,
+                    false,
+                )
+                .unwrap();
+            output.result.unwrap();
+            output.gas_report.used_internally
+        };
+        let gas_used = instantiate(height);
+
+        vm.update_gas_config(
+            height + 1,
+            GasConfig {
+                wasm_op_cost: 300_000,
+                ..GasConfig::default()
+            },
+        )
+        .unwrap();
+        vm.update_gas_config(
+            height + 2,
+            GasConfig {
+                wasm_op_cost: 600_000,
+                ..GasConfig::default()
+            },
+        )
+        .unwrap();
+        vm.update_gas_config(
+            height + 3,
+            GasConfig {
+                storage_callback_overhead: 1000,
+                ..GasConfig::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(instantiate(height), gas_used);
+        // the operations cost more while the address conversions of the api cost the same,
+        // apart from rounding their charges up to whole instance gas
+        let doubled = instantiate(height + 1);
+        let quadrupled = instantiate(height + 2);
+        assert!(doubled < 2 * gas_used);
+        let rounding = (quadrupled - doubled).abs_diff(2 * (doubled - gas_used));
+        assert!(rounding < 100, "{}", rounding);
+        let overhead = instantiate(height + 3) - gas_used;
+        assert!(overhead > 0 && overhead % 1000 == 0, "{}", overhead);
+
+        // replays are metered with the recorded config
+        for entry in std::fs::read_dir(&record_dir).unwrap() {
+            let recording: Recording =
+                serde_json::from_slice(&std::fs::read(entry.unwrap().path()).unwrap()).unwrap();
+            let output = replay(&recording, HACKATOM, false).unwrap();
+            assert!(output.matches(&recording));
+        }
+
+        let err = vm
+            .update_gas_config(
+                height,
+                GasConfig {
+                    wasm_op_cost: 0,
+                    ..GasConfig::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+    }
+}
@@ -0,0 +1,1058 @@
+//! The cache behind a `*cache_t`.
+//!
+//! This wraps cosmwasm-vm's `Cache` (or our own [`LocalCache`]) and adds the
+//! options that cosmwasm-vm does not support.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use cosmwasm_vm::internals::{check_wasm, compile, instance_from_module};
+use cosmwasm_vm::{
+    Backend, BackendApi, Cache, CacheOptions, Checksum, Instance, InstanceOptions, Metrics,
+    Querier, Size, Storage,
+};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use wasmer::Module;
+
+use crate::activation::{ActivationContext, ActivationSchedule, FeatureSet};
+use crate::address_codec::AddressCodec;
+use crate::error::Error;
+use crate::gas_config::{GasConfig, GasSchedule};
+use crate::local_cache::{
+    instance_memory_limits_file, stored_checksums_on_disk, wasm_dir, LocalCache,
+};
+use crate::querier::{QueryLimits, QueryOptions};
+use crate::query_router::{QueryRouter, QueryRoutes, QueryRoutesUpdate};
+use crate::static_analysis::{analyze_wasm, AnalysisReport};
+use crate::upload_limits::UploadLimits;
+
+#[derive(Clone, Debug)]
+pub struct VmCacheOptions {
+    /// The base directory of this cache. It is created if it does not exist.
+    pub base_dir: PathBuf,
+    pub available_capabilities: HashSet<String>,
+    pub memory_cache_size: Size,
+    /// Memory limit for instances, in bytes. Use a value that is divisible by the Wasm page size 65536,
+    /// e.g. full MiBs.
+    pub instance_memory_limit: Size,
+    /// The highest memory limit that can be set per checksum via `set_instance_memory_limit`.
+    /// None means the same as `instance_memory_limit`.
+    pub max_instance_memory_limit: Option<Size>,
+    /// The maximum total size of all pinned modules. None means unlimited.
+    pub pinned_cache_size: Option<Size>,
+    /// The number of threads used for compiling Wasm. None uses rayon's global thread pool.
+    pub compile_threads: Option<usize>,
+    /// If false, compiled modules are kept in memory only.
+    pub fs_cache: bool,
+    /// If set, Wasm blobs are kept in memory as well, limited to this total size.
+    /// No disk I/O happens and `base_dir` and `fs_cache` are ignored.
+    pub in_memory_wasm_store_size: Option<Size>,
+    /// Limits enforced by `save_wasm` in addition to cosmwasm-vm's static checks
+    pub upload_limits: UploadLimits,
+    /// The heights at which capabilities become active for calls
+    pub activations: ActivationSchedule,
+    /// Limits for nested queries during calls
+    pub query_limits: QueryLimits,
+    /// If true, identical queries within a call are answered from memory after the first one
+    pub memoize_queries: bool,
+    /// If true, malformed query responses from Go abort the call instead of being passed to the contract
+    pub strict_query_responses: bool,
+    /// Query routes that are answered in Rust, by route like `bank.balance`
+    pub query_routes: QueryRoutes,
+    /// Routing tables replacing `query_routes` from a block height on
+    pub query_route_updates: Vec<QueryRoutesUpdate>,
+    /// If set, addresses are converted natively instead of by Go
+    pub address_codec: Option<AddressCodec>,
+    /// The gas configs of calls by the height they apply from
+    pub gas_schedule: GasSchedule,
+    /// If set, a replay file of every call is written to this directory, see `replay`
+    pub record_dir: Option<PathBuf>,
+    /// If set, calls via the FFI report their fingerprint, see `fingerprint`
+    pub fingerprints: bool,
+}
+
+impl VmCacheOptions {
+    /// The options used by the legacy `init_cache` export
+    pub fn new(
+        base_dir: PathBuf,
+        available_capabilities: HashSet<String>,
+        memory_cache_size: Size,
+        instance_memory_limit: Size,
+    ) -> Self {
+        VmCacheOptions {
+            base_dir,
+            available_capabilities,
+            memory_cache_size,
+            instance_memory_limit,
+            max_instance_memory_limit: None,
+            pinned_cache_size: None,
+            compile_threads: None,
+            fs_cache: true,
+            in_memory_wasm_store_size: None,
+            upload_limits: UploadLimits::default(),
+            activations: ActivationSchedule::default(),
+            query_limits: QueryLimits::default(),
+            memoize_queries: false,
+            strict_query_responses: false,
+            query_routes: BTreeMap::new(),
+            query_route_updates: Vec::new(),
+            address_codec: None,
+            gas_schedule: GasSchedule::default(),
+            record_dir: None,
+            fingerprints: false,
+        }
+    }
+}
+
+enum Modules<A: BackendApi, S: Storage, Q: Querier> {
+    /// cosmwasm-vm's cache using the file system cache for compiled modules, and the directory
+    /// of its Wasm blobs
+    FileSystem(Cache<A, S, Q>, PathBuf),
+    /// Compiled modules (and optionally Wasm blobs) are kept in memory only
+    Local(LocalCache),
+}
+
+/// Part of the instantiation error of modules whose initial or maximum memory exceeds their
+/// memory limit, see cosmwasm-vm's `LimitingTunables`
+const MEMORY_LIMIT_EXCEEDED: &str = "exceeds the allowed memory limit";
+
+/// The memory limit set for a checksum and the module compiled with it. Modules of limits
+/// loaded from disk are compiled on first use.
+struct LimitedModule {
+    limit: Size,
+    module: Option<Module>,
+}
+
+pub struct VmCache<A: BackendApi, S: Storage, Q: Querier> {
+    /// Can be changed at runtime via `set_capabilities`
+    available_capabilities: RwLock<HashSet<String>>,
+    upload_limits: UploadLimits,
+    instance_memory_limit: Size,
+    /// The highest limit that can be set per checksum
+    max_instance_memory_limit: Size,
+    /// Memory limits set per checksum, overriding `instance_memory_limit`. Their modules
+    /// are instantiated from here instead of the caches in `modules`.
+    instance_memory_limits: RwLock<HashMap<Checksum, LimitedModule>>,
+    /// The file the limits are persisted in, unless the cache is in memory only
+    instance_memory_limits_file: Option<PathBuf>,
+    /// To prevent concurrent access to `WasmerInstance::new` when instantiating
+    /// from `instance_memory_limits`, like cosmwasm-vm's cache does
+    instantiation_lock: Mutex<()>,
+    activations: ActivationSchedule,
+    query_limits: QueryLimits,
+    memoize_queries: bool,
+    strict_query_responses: bool,
+    query_router: Arc<QueryRouter>,
+    address_codec: Option<AddressCodec>,
+    /// Can be updated at runtime via `update_gas_config`
+    gas_schedule: RwLock<GasSchedule>,
+    record_dir: Option<PathBuf>,
+    fingerprints: bool,
+    /// The capabilities required by modules, filled lazily if activations are scheduled
+    required_capabilities: Mutex<HashMap<Checksum, HashSet<String>>>,
+    modules: Modules<A, S, Q>,
+    pinned_cache_size: Option<Size>,
+    /// The checksums pinned via this cache. The lock is held for the whole
+    /// pin operation such that the pinned size budget can be enforced.
+    pinned: Mutex<HashSet<Checksum>>,
+    /// A dedicated thread pool for compilation, if configured
+    compile_pool: Option<ThreadPool>,
+}
+
+impl<A, S, Q> VmCache<A, S, Q>
+where
+    A: BackendApi + 'static,
+    S: Storage + 'static,
+    Q: Querier + 'static,
+{
+    /// Creates a new cache that stores data in `base_dir` or in memory only.
+    ///
+    /// # Safety
+    ///
+    /// This function is marked unsafe due to `cosmwasm_vm::Cache::new`, which implicitly
+    /// assumes the disk contents are correct.
+    pub unsafe fn new(options: VmCacheOptions) -> Result<Self, Error> {
+        let VmCacheOptions {
+            base_dir,
+            available_capabilities,
+            memory_cache_size,
+            instance_memory_limit,
+            max_instance_memory_limit,
+            pinned_cache_size,
+            compile_threads,
+            fs_cache,
+            in_memory_wasm_store_size,
+            upload_limits,
+            activations,
+            query_limits,
+            memoize_queries,
+            strict_query_responses,
+            query_routes,
+            query_route_updates,
+            address_codec,
+            gas_schedule,
+            record_dir,
+            fingerprints,
+        } = options;
+
+        let max_instance_memory_limit = max_instance_memory_limit.unwrap_or(instance_memory_limit);
+        if max_instance_memory_limit.0 < instance_memory_limit.0 {
+            return Err(Error::invalid_config(
+                "The maximum instance memory limit must not be lower than the instance memory limit",
+            ));
+        }
+        if let Some(capability) = activations
+            .capabilities()
+            .find(|capability| !available_capabilities.contains(*capability))
+        {
+            return Err(Error::invalid_config(format!(
+                "Capability {} is scheduled for activation but not available",
+                capability
+            )));
+        }
+
+        let compile_pool = match compile_threads {
+            Some(threads) => Some(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|index| format!("wasmvm-compile-{}", index))
+                    .build()
+                    .map_err(|e| {
+                        Error::cache_err(format!("Error creating compile thread pool: {}", e))
+                    })?,
+            ),
+            None => None,
+        };
+
+        let instance_memory_limits_file = match in_memory_wasm_store_size {
+            Some(_) => None,
+            None => Some(instance_memory_limits_file(&base_dir)),
+        };
+        let modules = if let Some(wasm_store_size) = in_memory_wasm_store_size {
+            Modules::Local(LocalCache::in_memory(
+                wasm_store_size,
+                memory_cache_size,
+                instance_memory_limit,
+            ))
+        } else if fs_cache {
+            Modules::FileSystem(
+                Cache::new(CacheOptions {
+                    base_dir: base_dir.clone(),
+                    // capabilities are checked by us
+                    available_capabilities: HashSet::new(),
+                    memory_cache_size,
+                    instance_memory_limit,
+                })?,
+                wasm_dir(&base_dir),
+            )
+        } else {
+            Modules::Local(LocalCache::new(
+                &base_dir,
+                memory_cache_size,
+                instance_memory_limit,
+            )?)
+        };
+
+        let instance_memory_limits = match &instance_memory_limits_file {
+            Some(file) => load_instance_memory_limits(file, max_instance_memory_limit)?,
+            None => HashMap::new(),
+        };
+
+        Ok(VmCache {
+            available_capabilities: RwLock::new(available_capabilities),
+            upload_limits,
+            instance_memory_limit,
+            max_instance_memory_limit,
+            instance_memory_limits: RwLock::new(instance_memory_limits),
+            instance_memory_limits_file,
+            instantiation_lock: Mutex::new(()),
+            activations,
+            query_limits,
+            memoize_queries,
+            strict_query_responses,
+            query_router: Arc::new(QueryRouter::new(query_routes, query_route_updates)),
+            address_codec,
+            gas_schedule: RwLock::new(gas_schedule),
+            record_dir,
+            fingerprints,
+            required_capabilities: Mutex::new(HashMap::new()),
+            modules,
+            pinned_cache_size,
+            pinned: Mutex::new(HashSet::new()),
+            compile_pool,
+        })
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        match &self.modules {
+            Modules::FileSystem(cache, _) => cache.metrics(),
+            Modules::Local(cache) => cache.metrics(),
+        }
+    }
+
+    /// Performs static checks and upload limit checks, compiles and stores the Wasm.
+    pub fn save_wasm(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        self.check(wasm)?;
+        self.save_wasm_unchecked(wasm)
+    }
+
+    /// Performs the same checks and compilation as `save_wasm` but does not store anything.
+    pub fn validate_wasm(&self, wasm: &[u8]) -> Result<(), Error> {
+        self.check(wasm)?;
+        self.compiling(|| {
+            compile(wasm, Some(self.instance_memory_limit), &[])?;
+            Ok(())
+        })
+    }
+
+    fn check(&self, wasm: &[u8]) -> Result<(), Error> {
+        self.upload_limits.check(wasm)?;
+        check_wasm(wasm, &self.available_capabilities.read().unwrap())?;
+        Ok(())
+    }
+
+    /// Compiles and stores the Wasm without performing static checks.
+    pub fn save_wasm_unchecked(&self, wasm: &[u8]) -> Result<Checksum, Error> {
+        let checksum = self.compiling(|| match &self.modules {
+            Modules::FileSystem(cache, _) => Ok(cache.save_wasm_unchecked(wasm)?),
+            Modules::Local(cache) => cache.save_wasm_unchecked(wasm),
+        })?;
+        log::debug!("Saved Wasm {}", checksum.to_hex());
+        Ok(checksum)
+    }
+
+    pub fn remove_wasm(&self, checksum: &Checksum) -> Result<(), Error> {
+        match &self.modules {
+            Modules::FileSystem(cache, _) => cache.remove_wasm(checksum)?,
+            Modules::Local(cache) => cache.remove_wasm(checksum)?,
+        }
+        self.required_capabilities.lock().unwrap().remove(checksum);
+        let mut limits = self.instance_memory_limits.write().unwrap();
+        if limits.remove(checksum).is_some() {
+            self.persist_instance_memory_limits(&limits)?;
+        }
+        log::debug!("Removed Wasm {}", checksum.to_hex());
+        Ok(())
+    }
+
+    pub fn load_wasm(&self, checksum: &Checksum) -> Result<Vec<u8>, Error> {
+        match &self.modules {
+            Modules::FileSystem(cache, _) => Ok(cache.load_wasm(checksum)?),
+            Modules::Local(cache) => cache.load_wasm(checksum),
+        }
+    }
+
+    /// Analyzes the original Wasm of a previously saved module
+    pub fn analyze(&self, checksum: &Checksum) -> Result<AnalysisReport, Error> {
+        let wasm = self.load_wasm(checksum)?;
+        analyze_wasm(&wasm)
+    }
+
+    /// Pins a module that was previously stored via save_wasm.
+    ///
+    /// If a pinned cache size is configured and pinning this module would exceed it,
+    /// the module is not pinned and an error is returned.
+    pub fn pin(&self, checksum: &Checksum) -> Result<(), Error> {
+        let mut pinned = self.pinned.lock().unwrap();
+        if pinned.contains(checksum) {
+            return Ok(());
+        }
+
+        self.compiling(|| match &self.modules {
+            Modules::FileSystem(cache, _) => Ok(cache.pin(checksum)?),
+            Modules::Local(cache) => cache.pin(checksum),
+        })?;
+
+        if let Some(limit) = self.pinned_cache_size {
+            let size = self.metrics().size_pinned_memory_cache;
+            if size > limit.0 {
+                self.unpin_module(checksum)?;
+                return Err(Error::cache_err(format!(
+                    "Pinned memory cache size limit of {} bytes exceeded",
+                    limit.0
+                )));
+            }
+        }
+
+        pinned.insert(*checksum);
+        log::debug!("Pinned module {}", checksum.to_hex());
+        Ok(())
+    }
+
+    /// Unpins a module. Not found checksums are silently ignored.
+    pub fn unpin(&self, checksum: &Checksum) -> Result<(), Error> {
+        let mut pinned = self.pinned.lock().unwrap();
+        self.unpin_module(checksum)?;
+        if pinned.remove(checksum) {
+            log::debug!("Unpinned module {}", checksum.to_hex());
+        }
+        Ok(())
+    }
+
+    fn unpin_module(&self, checksum: &Checksum) -> Result<(), Error> {
+        match &self.modules {
+            Modules::FileSystem(cache, _) => Ok(cache.unpin(checksum)?),
+            Modules::Local(cache) => cache.unpin(checksum),
+        }
+    }
+
+    /// Returns an instance of a previously saved Wasm.
+    pub fn get_instance(
+        &self,
+        checksum: &Checksum,
+        backend: Backend<A, S, Q>,
+        options: InstanceOptions,
+    ) -> Result<Instance<A, S, Q>, Error> {
+        // Modules missing in all caches are compiled here as well. This is rare, so we do not
+        // use the compile thread pool, which would require the backend to be `Send`.
+        let (limit, result) = match self.limited_module(checksum)? {
+            Some((limit, module)) => {
+                let _lock = self.instantiation_lock.lock().unwrap();
+                let result = instance_from_module(
+                    &module,
+                    backend,
+                    options.gas_limit,
+                    options.print_debug,
+                    None,
+                );
+                (limit, result.map_err(Error::from))
+            }
+            None => {
+                let result = match &self.modules {
+                    Modules::FileSystem(cache, _) => cache
+                        .get_instance(checksum, backend, options)
+                        .map_err(Error::from),
+                    Modules::Local(cache) => cache.get_instance(checksum, backend, options),
+                };
+                (self.instance_memory_limit, result)
+            }
+        };
+        result.map_err(|err| match err {
+            Error::VmErr { msg, .. } if msg.contains(MEMORY_LIMIT_EXCEEDED) => {
+                Error::instance_memory_limit_exceeded(checksum.to_hex(), limit.0 as u64)
+            }
+            err => err,
+        })
+    }
+
+    /// The memory limit set for the checksum and its module compiled with it, if any
+    fn limited_module(&self, checksum: &Checksum) -> Result<Option<(Size, Module)>, Error> {
+        let limit = match self.instance_memory_limits.read().unwrap().get(checksum) {
+            Some(LimitedModule {
+                limit,
+                module: Some(module),
+            }) => return Ok(Some((*limit, module.clone()))),
+            Some(LimitedModule {
+                limit,
+                module: None,
+            }) => *limit,
+            None => return Ok(None),
+        };
+        let module = compile(&self.load_wasm(checksum)?, Some(limit), &[])?;
+        if let Some(limited) = self
+            .instance_memory_limits
+            .write()
+            .unwrap()
+            .get_mut(checksum)
+        {
+            // the limit may have been changed in the meantime
+            if limited.limit.0 == limit.0 {
+                limited.module = Some(module.clone());
+            }
+        }
+        Ok(Some((limit, module)))
+    }
+
+    /// The checksums of all stored Wasm blobs
+    fn stored_checksums(&self) -> Result<Vec<Checksum>, Error> {
+        match &self.modules {
+            Modules::FileSystem(_, wasm_dir) => stored_checksums_on_disk(wasm_dir),
+            Modules::Local(cache) => cache.stored_checksums(),
+        }
+    }
+
+    pub fn address_codec(&self) -> Option<AddressCodec> {
+        self.address_codec
+    }
+
+    pub fn query_router(&self) -> &QueryRouter {
+        &self.query_router
+    }
+
+    /// The query options of calls at the block height of `env`. The env is only parsed if
+    /// queries are routed or activations are scheduled at some height.
+    pub fn query_options(&self, env: &[u8]) -> Result<QueryOptions, Error> {
+        // no need to look up routes and features if there are none
+        let context = if self.query_router.is_empty() && self.activations.is_empty() {
+            None
+        } else {
+            Some(ActivationContext::from_env(env)?)
+        };
+        let router = if self.query_router.is_empty() {
+            None
+        } else {
+            Some(self.query_router.clone())
+        };
+        Ok(QueryOptions {
+            limits: self.query_limits,
+            memoize: self.memoize_queries,
+            strict: self.strict_query_responses,
+            router,
+            height: context.map_or(0, |context| context.height),
+            features: context
+                .map(|context| self.activations.feature_set(context))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The gas config at the block height of the call's `env`. The env is only parsed if
+    /// the gas config changes at some height.
+    pub fn gas_config(&self, env: &[u8]) -> Result<GasConfig, Error> {
+        let schedule = self.gas_schedule.read().unwrap();
+        if schedule.is_static() {
+            return Ok(schedule.at(0));
+        }
+        let context = ActivationContext::from_env(env)?;
+        Ok(schedule.at(context.height))
+    }
+
+    /// Uses the gas config for calls from the block height on
+    pub fn update_gas_config(&self, height: u64, config: GasConfig) -> Result<(), Error> {
+        self.gas_schedule.write().unwrap().update(height, config)
+    }
+
+    /// The memory limit for instances of the checksum
+    pub fn instance_memory_limit(&self, checksum: &Checksum) -> Size {
+        self.instance_memory_limits
+            .read()
+            .unwrap()
+            .get(checksum)
+            .map_or(self.instance_memory_limit, |limited| limited.limit)
+    }
+
+    /// Overrides the memory limit for instances of the checksum, or resets it to the
+    /// default if None. The limit must not exceed the maximum instance memory limit.
+    ///
+    /// The Wasm is compiled again with the limit, which is enforced when memory is created
+    /// or grown. Instances whose initial memory exceeds it fail with
+    /// [`Error::InstanceMemoryLimitExceeded`]. Such modules are kept in addition to the memory
+    /// caches and are not pinned. Unless the cache is in memory only, limits are stored next
+    /// to the Wasm directory and loaded again when the cache is created.
+    pub fn set_instance_memory_limit(
+        &self,
+        checksum: &Checksum,
+        limit: Option<Size>,
+    ) -> Result<(), Error> {
+        let limit = match limit {
+            Some(limit) if limit.0 > self.max_instance_memory_limit.0 => {
+                return Err(Error::cache_err(format!(
+                    "Instance memory limit of {} bytes exceeds the maximum of {} bytes",
+                    limit.0, self.max_instance_memory_limit.0
+                )))
+            }
+            Some(limit) if limit.0 != self.instance_memory_limit.0 => limit,
+            _ => {
+                let mut limits = self.instance_memory_limits.write().unwrap();
+                if limits.remove(checksum).is_some() {
+                    self.persist_instance_memory_limits(&limits)?;
+                }
+                return Ok(());
+            }
+        };
+        let wasm = self.load_wasm(checksum)?;
+        let module = self.compiling(|| Ok(compile(&wasm, Some(limit), &[])?))?;
+        let mut limits = self.instance_memory_limits.write().unwrap();
+        limits.insert(
+            *checksum,
+            LimitedModule {
+                limit,
+                module: Some(module),
+            },
+        );
+        self.persist_instance_memory_limits(&limits)
+    }
+
+    /// Writes the memory limits set per checksum to their file, if any
+    fn persist_instance_memory_limits(
+        &self,
+        limits: &HashMap<Checksum, LimitedModule>,
+    ) -> Result<(), Error> {
+        let file = match &self.instance_memory_limits_file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let limits: BTreeMap<String, usize> = limits
+            .iter()
+            .map(|(checksum, limited)| (checksum.to_hex(), limited.limit.0))
+            .collect();
+        let json = serde_json::to_vec_pretty(&limits).map_err(Error::cache_err)?;
+        // replaces the file at once such that it is never partially written
+        let tmp_file = file.with_extension("json.tmp");
+        fs::write(&tmp_file, json)
+            .and_then(|()| fs::rename(&tmp_file, file))
+            .map_err(|e| Error::cache_err(format!("Error writing instance memory limits: {}", e)))
+    }
+
+    /// The directory replay files of calls are written to, if any
+    pub fn record_dir(&self) -> Option<&Path> {
+        self.record_dir.as_deref()
+    }
+
+    /// Whether calls via the FFI report their fingerprint
+    pub fn fingerprints(&self) -> bool {
+        self.fingerprints
+    }
+
+    pub fn activations(&self) -> &ActivationSchedule {
+        &self.activations
+    }
+
+    /// The host features of calls at the block height
+    pub fn feature_set(&self, height: u64) -> FeatureSet {
+        self.activations.feature_set(ActivationContext { height })
+    }
+
+    /// Checks that all capabilities required by the module are active in the given context
+    pub fn check_activation(
+        &self,
+        checksum: &Checksum,
+        context: ActivationContext,
+    ) -> Result<(), Error> {
+        if self.activations.is_empty() {
+            return Ok(());
+        }
+        let cached = self
+            .required_capabilities
+            .lock()
+            .unwrap()
+            .get(checksum)
+            .cloned();
+        let required = match cached {
+            Some(required) => required,
+            None => {
+                let required = self.analyze(checksum)?.required_capabilities;
+                self.required_capabilities
+                    .lock()
+                    .unwrap()
+                    .insert(*checksum, required.clone());
+                required
+            }
+        };
+        let inactive = self.activations.inactive(&required, context);
+        if inactive.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::inactive_capabilities(
+                inactive.join(","),
+                context.height,
+            ))
+        }
+    }
+
+    pub fn capabilities(&self) -> HashSet<String> {
+        self.available_capabilities.read().unwrap().clone()
+    }
+
+    /// Replaces the available capabilities used for checking new Wasm.
+    ///
+    /// Returns the checksums of all stored Wasm blobs that require capabilities which are not
+    /// available anymore, sorted by checksum. Those blobs are not removed. Every stored blob is
+    /// analyzed for this, before the capabilities are replaced, such that `save_wasm` is not
+    /// blocked in the meantime.
+    pub fn set_capabilities(&self, capabilities: HashSet<String>) -> Result<Vec<Checksum>, Error> {
+        let mut checksums = self.stored_checksums()?;
+        checksums.sort_unstable_by_key(|checksum| checksum.to_hex());
+        let unusable = checksums
+            .into_iter()
+            .filter(|checksum| match self.analyze(checksum) {
+                Ok(report) => !report.required_capabilities.is_subset(&capabilities),
+                Err(err) => {
+                    log::warn!("Cannot analyze module {}: {}", checksum.to_hex(), err);
+                    false
+                }
+            })
+            .collect();
+
+        log::info!("Set available capabilities to {:?}", capabilities);
+        *self.available_capabilities.write().unwrap() = capabilities;
+        Ok(unusable)
+    }
+
+    /// Runs an operation that potentially compiles Wasm in the configured thread pool.
+    fn compiling<T, F>(&self, operation: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error> + Send,
+        T: Send,
+    {
+        match &self.compile_pool {
+            Some(pool) => pool.install(operation),
+            None => operation(),
+        }
+    }
+}
+
+/// Reads the memory limits set per checksum from their file, which maps hex encoded checksums
+/// to limits in bytes. No limits are set if the file does not exist.
+fn load_instance_memory_limits(
+    file: &Path,
+    max_instance_memory_limit: Size,
+) -> Result<HashMap<Checksum, LimitedModule>, Error> {
+    let json = match fs::read(file) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(Error::cache_err(format!(
+                "Error reading instance memory limits: {}",
+                e
+            )))
+        }
+    };
+    let limits: BTreeMap<String, usize> = serde_json::from_slice(&json)
+        .map_err(|e| Error::cache_err(format!("Invalid instance memory limits: {}", e)))?;
+    limits
+        .into_iter()
+        .map(|(checksum, limit)| {
+            let checksum = hex::decode(&checksum)
+                .ok()
+                .and_then(|checksum| Checksum::try_from(checksum.as_slice()).ok())
+                .ok_or_else(|| {
+                    Error::cache_err(format!(
+                        "Invalid checksum {} in instance memory limits",
+                        checksum
+                    ))
+                })?;
+            if limit > max_instance_memory_limit.0 {
+                return Err(Error::invalid_config(format!(
+                    "Instance memory limit of {} bytes set for Wasm contract {} exceeds the maximum of {} bytes",
+                    limit,
+                    checksum.to_hex(),
+                    max_instance_memory_limit.0
+                )));
+            }
+            let limited = LimitedModule {
+                limit: Size(limit),
+                module: None,
+            };
+            Ok((checksum, limited))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::CapabilityActivation;
+    use cosmwasm_vm::testing::{mock_backend, MockApi, MockQuerier, MockStorage};
+    use tempfile::TempDir;
+
+    static HACKATOM: &[u8] = include_bytes!("../../testdata/hackatom.wasm");
+    static IBC_REFLECT: &[u8] = include_bytes!("../../testdata/ibc_reflect.wasm");
+
+    type TestCache = VmCache<MockApi, MockStorage, MockQuerier>;
+
+    fn options(dir: &TempDir) -> VmCacheOptions {
+        VmCacheOptions::new(
+            dir.path().to_path_buf(),
+            HashSet::from(["staking".to_string()]),
+            Size::mebi(200),
+            Size::mebi(32),
+        )
+    }
+
+    #[test]
+    fn save_wasm_checks_capabilities() {
+        for fs_cache in [true, false] {
+            let dir = TempDir::new().unwrap();
+            let cache: TestCache = unsafe {
+                VmCache::new(VmCacheOptions {
+                    fs_cache,
+                    ..options(&dir)
+                })
+            }
+            .unwrap();
+            cache.save_wasm(HACKATOM).unwrap();
+            let err = cache.save_wasm(IBC_REFLECT).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("Wasm contract requires unavailable capabilities"));
+        }
+    }
+
+    #[test]
+    fn new_rejects_max_instance_memory_limit_below_default() {
+        let dir = TempDir::new().unwrap();
+        let err = unsafe {
+            TestCache::new(VmCacheOptions {
+                max_instance_memory_limit: Some(Size::mebi(16)),
+                ..options(&dir)
+            })
+        }
+        .err()
+        .unwrap();
+        assert!(matches!(err, Error::InvalidConfig { .. }));
+    }
+
+    #[test]
+    fn set_capabilities_works() {
+        for (fs_cache, in_memory_wasm_store_size) in
+            [(true, None), (false, None), (false, Some(Size::mebi(10)))]
+        {
+            let dir = TempDir::new().unwrap();
+            let cache: TestCache = unsafe {
+                VmCache::new(VmCacheOptions {
+                    available_capabilities: HashSet::from(
+                        ["staking", "iterator", "stargate"].map(String::from),
+                    ),
+                    fs_cache,
+                    in_memory_wasm_store_size,
+                    // stored modules are checked whether they are cached or not
+                    memory_cache_size: Size::mebi(0),
+                    ..options(&dir)
+                })
+            }
+            .unwrap();
+            let hackatom = cache.save_wasm(HACKATOM).unwrap();
+            let ibc_reflect = cache.save_wasm(IBC_REFLECT).unwrap();
+            cache.pin(&hackatom).unwrap();
+
+            let unusable = cache
+                .set_capabilities(HashSet::from(["iterator".to_string()]))
+                .unwrap();
+            assert_eq!(unusable, vec![ibc_reflect]);
+            assert_eq!(
+                cache.capabilities(),
+                HashSet::from(["iterator".to_string()])
+            );
+            // new capabilities are used for checking
+            cache.save_wasm(IBC_REFLECT).unwrap_err();
+
+            let unusable = cache
+                .set_capabilities(HashSet::from(["iterator", "stargate"].map(String::from)))
+                .unwrap();
+            assert_eq!(unusable, vec![]);
+            cache.save_wasm(IBC_REFLECT).unwrap();
+
+            cache.remove_wasm(&ibc_reflect).unwrap();
+            let unusable = cache.set_capabilities(HashSet::new()).unwrap();
+            assert_eq!(unusable, vec![]);
+        }
+    }
+
+    #[test]
+    fn check_activation_works() {
+        let dir = TempDir::new().unwrap();
+        let cache: TestCache = unsafe {
+            VmCache::new(VmCacheOptions {
+                available_capabilities: HashSet::from(
+                    ["staking", "iterator", "stargate"].map(String::from),
+                ),
+                activations: ActivationSchedule::new(vec![CapabilityActivation {
+                    height: 100,
+                    capabilities: vec!["stargate".to_string()],
+                    host_features: vec![],
+                }])
+                .unwrap(),
+                ..options(&dir)
+            })
+        }
+        .unwrap();
+        let hackatom = cache.save_wasm(HACKATOM).unwrap();
+        let ibc_reflect = cache.save_wasm(IBC_REFLECT).unwrap();
+
+        let at = |height| ActivationContext { height };
+        cache.check_activation(&hackatom, at(99)).unwrap();
+        let err = cache.check_activation(&ibc_reflect, at(99)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Wasm contract requires capabilities that are not active at height 99: stargate"
+        );
+        cache.check_activation(&ibc_reflect, at(100)).unwrap();
+    }
+
+    #[test]
+    fn new_rejects_unavailable_scheduled_capabilities() {
+        let dir = TempDir::new().unwrap();
+        let result: Result<TestCache, _> = unsafe {
+            VmCache::new(VmCacheOptions {
+                available_capabilities: HashSet::from(["staking".to_string()]),
+                activations: ActivationSchedule::new(vec![CapabilityActivation {
+                    height: 100,
+                    capabilities: vec!["stargate".to_string()],
+                    host_features: vec![],
+                }])
+                .unwrap(),
+                ..options(&dir)
+            })
+        };
+        match result {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Invalid cache config: Capability stargate is scheduled for activation but not available"
+            ),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn save_wasm_checks_upload_limits() {
+        let dir = TempDir::new().unwrap();
+        let cache: TestCache = unsafe {
+            VmCache::new(VmCacheOptions {
+                upload_limits: UploadLimits {
+                    max_wasm_size: Some(1024),
+                    ..UploadLimits::default()
+                },
+                ..options(&dir)
+            })
+        }
+        .unwrap();
+        let err = cache.save_wasm(HACKATOM).unwrap_err();
+        assert!(matches!(err, Error::UploadLimitExceeded { .. }));
+
+        // not checked for trusted code
+        cache.save_wasm_unchecked(HACKATOM).unwrap();
+    }
+
+    #[test]
+    fn validate_wasm_works() {
+        let dir = TempDir::new().unwrap();
+        let cache: TestCache = unsafe { VmCache::new(options(&dir)) }.unwrap();
+        cache.validate_wasm(HACKATOM).unwrap();
+        let err = cache.validate_wasm(IBC_REFLECT).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Wasm contract requires unavailable capabilities"));
+        let err = cache.validate_wasm(b"not wasm").unwrap_err();
+        assert!(err.to_string().contains("could not be deserialized"));
+
+        // nothing was stored
+        let metrics = cache.metrics();
+        assert_eq!(metrics.elements_memory_cache, 0);
+        cache.load_wasm(&Checksum::generate(HACKATOM)).unwrap_err();
+    }
+
+    #[test]
+    fn pin_respects_pinned_cache_size() {
+        for fs_cache in [true, false] {
+            let dir = TempDir::new().unwrap();
+            let cache: TestCache = unsafe {
+                VmCache::new(VmCacheOptions {
+                    pinned_cache_size: Some(Size::kibi(1)),
+                    fs_cache,
+                    ..options(&dir)
+                })
+            }
+            .unwrap();
+            let checksum = cache.save_wasm(HACKATOM).unwrap();
+            let err = cache.pin(&checksum).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Cache error: Pinned memory cache size limit of 1024 bytes exceeded"
+            );
+            assert_eq!(cache.metrics().elements_pinned_memory_cache, 0);
+
+            let dir = TempDir::new().unwrap();
+            let cache: TestCache = unsafe {
+                VmCache::new(VmCacheOptions {
+                    pinned_cache_size: Some(Size::mebi(100)),
+                    fs_cache,
+                    ..options(&dir)
+                })
+            }
+            .unwrap();
+            let checksum = cache.save_wasm(HACKATOM).unwrap();
+            cache.pin(&checksum).unwrap();
+            assert_eq!(cache.metrics().elements_pinned_memory_cache, 1);
+        }
+    }
+
+    #[test]
+    fn in_memory_does_not_touch_base_dir() {
+        let dir = TempDir::new().unwrap();
+        let base_dir = dir.path().join("not-created");
+        let cache: TestCache = unsafe {
+            VmCache::new(VmCacheOptions {
+                base_dir: base_dir.clone(),
+                in_memory_wasm_store_size: Some(Size::mebi(10)),
+                ..options(&dir)
+            })
+        }
+        .unwrap();
+        let checksum = cache.save_wasm(HACKATOM).unwrap();
+        cache.pin(&checksum).unwrap();
+        assert_eq!(cache.load_wasm(&checksum).unwrap(), HACKATOM);
+        assert!(!base_dir.exists());
+    }
+
+    #[test]
+    fn instance_memory_limits_are_persisted() {
+        for fs_cache in [true, false] {
+            let dir = TempDir::new().unwrap();
+            let open = |max_instance_memory_limit| -> Result<TestCache, Error> {
+                unsafe {
+                    VmCache::new(VmCacheOptions {
+                        fs_cache,
+                        max_instance_memory_limit: Some(max_instance_memory_limit),
+                        ..options(&dir)
+                    })
+                }
+            };
+            let instantiate = |cache: &TestCache, checksum| {
+                let options = InstanceOptions {
+                    gas_limit: 500_000_000_000,
+                    print_debug: false,
+                };
+                cache.get_instance(checksum, mock_backend(&[]), options)
+            };
+
+            let cache = open(Size::mebi(64)).unwrap();
+            let checksum = cache.save_wasm(HACKATOM).unwrap();
+            // exceeded by the initial memory of the contract
+            cache
+                .set_instance_memory_limit(&checksum, Some(Size::kibi(64)))
+                .unwrap();
+            drop(cache);
+
+            let cache = open(Size::mebi(64)).unwrap();
+            assert_eq!(cache.instance_memory_limit(&checksum).0, Size::kibi(64).0);
+            let err = instantiate(&cache, &checksum).err().unwrap();
+            assert!(
+                matches!(err, Error::InstanceMemoryLimitExceeded { limit: 65536, .. }),
+                "{}",
+                err
+            );
+            cache.set_instance_memory_limit(&checksum, None).unwrap();
+            drop(cache);
+
+            let cache = open(Size::mebi(64)).unwrap();
+            assert_eq!(cache.instance_memory_limit(&checksum).0, Size::mebi(32).0);
+            instantiate(&cache, &checksum).unwrap();
+            cache
+                .set_instance_memory_limit(&checksum, Some(Size::mebi(48)))
+                .unwrap();
+            drop(cache);
+
+            // a lower maximum does not allow the limit anymore
+            let err = open(Size::mebi(32)).err().unwrap();
+            assert!(matches!(err, Error::InvalidConfig { .. }), "{}", err);
+        }
+    }
+
+    #[test]
+    fn compile_threads_work() {
+        let dir = TempDir::new().unwrap();
+        let cache: TestCache = unsafe {
+            VmCache::new(VmCacheOptions {
+                compile_threads: Some(1),
+                ..options(&dir)
+            })
+        }
+        .unwrap();
+        let checksum = cache.save_wasm(HACKATOM).unwrap();
+        cache.pin(&checksum).unwrap();
+        assert_eq!(cache.metrics().elements_pinned_memory_cache, 1);
+    }
+}
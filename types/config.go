@@ -0,0 +1,139 @@
+package types
+
+// VMConfig is the config of a VM created by NewVMWithConfig. It is sent to libwasmvm as the
+// JSON encoded cache config, so unset optional fields use the defaults of libwasmvm.
+type VMConfig struct {
+	// DataDir is the base directory for Wasm blobs and compiled modules. Required unless InMemory is set.
+	DataDir string `json:"data_dir,omitempty"`
+	// AvailableCapabilities are the capabilities supported by the chain
+	AvailableCapabilities []string `json:"available_capabilities"`
+	// MemoryCacheSizeMiB is the size of the in-memory module cache. 0 disables it.
+	MemoryCacheSizeMiB uint32 `json:"memory_cache_size_mib"`
+	// InstanceMemoryLimitMiB is the memory limit of a single contract instance
+	InstanceMemoryLimitMiB uint32 `json:"instance_memory_limit_mib"`
+	// MaxInstanceMemoryLimitMiB is the highest limit that can be set via VM.SetInstanceMemoryLimit.
+	// Defaults to InstanceMemoryLimitMiB.
+	MaxInstanceMemoryLimitMiB *uint32 `json:"max_instance_memory_limit_mib,omitempty"`
+	// PinnedCacheSizeMiB is the maximum total size of pinned modules. Unlimited if unset.
+	PinnedCacheSizeMiB *uint32 `json:"pinned_cache_size_mib,omitempty"`
+	// CompileThreads is the number of threads used to compile Wasm. One per CPU if unset.
+	CompileThreads *uint32 `json:"compile_threads,omitempty"`
+	// FsCache keeps compiled modules on disk if unset or true. If false, they only live in memory.
+	FsCache *bool `json:"fs_cache,omitempty"`
+	// InMemory keeps Wasm blobs and compiled modules in memory only, nothing touches DataDir
+	InMemory bool `json:"in_memory,omitempty"`
+	// InMemoryWasmStoreSizeMiB is the maximum total size of Wasm blobs if InMemory is set.
+	// Defaults to 256.
+	InMemoryWasmStoreSizeMiB *uint32 `json:"in_memory_wasm_store_size_mib,omitempty"`
+	// UploadLimits are checked when storing code in addition to the static checks
+	UploadLimits UploadLimits `json:"upload_limits"`
+	// CapabilityActivations are capabilities and host features that are only active for calls from
+	// a block height on. Every activation needs its own height. Scheduled capabilities must be
+	// listed in AvailableCapabilities as well.
+	CapabilityActivations []CapabilityActivation `json:"capability_activations,omitempty"`
+	// QueryLimits are limits for nested queries during calls. Unlimited by default.
+	QueryLimits QueryLimits `json:"query_limits"`
+	// MemoizeQueries answers identical queries within a call from memory until the contract
+	// writes to storage. Only used for queriers implementing GasConsumer.
+	MemoizeQueries bool `json:"memoize_queries,omitempty"`
+	// StrictQueryResponses aborts calls on query responses that cannot be parsed instead of
+	// passing a SystemError to the contract
+	StrictQueryResponses bool `json:"strict_query_responses,omitempty"`
+	// QueryRoutes are queries answered in Rust, by route like "bank.balance". See VM.UpdateQueryRoutes.
+	QueryRoutes map[string]QueryRoute `json:"query_routes,omitempty"`
+	// QueryRouteUpdates replace QueryRoutes for calls from a block height on
+	QueryRouteUpdates []QueryRoutesUpdate `json:"query_route_updates,omitempty"`
+	// AddressCodec converts bech32 addresses natively instead of via GoAPI if set
+	AddressCodec *AddressCodecConfig `json:"address_codec,omitempty"`
+	// GasConfig are the gas costs of calls. DefaultGasConfig is used if unset.
+	GasConfig *GasConfig `json:"gas_config,omitempty"`
+	// GasConfigUpdates replace GasConfig for calls from a block height on. See VM.UpdateGasConfig.
+	GasConfigUpdates []GasConfigUpdate `json:"gas_config_updates,omitempty"`
+	// RecordDir is a directory every call is recorded into as a replay file, for debugging
+	RecordDir string `json:"record_dir,omitempty"`
+	// Fingerprints makes calls report their fingerprint, see CallReport
+	Fingerprints bool `json:"fingerprints,omitempty"`
+	// LogLevel is the verbosity of the logs libwasmvm writes to stderr. Off by default.
+	LogLevel LogLevel `json:"log_level,omitempty"`
+}
+
+// UploadLimits are limits for the Wasm code stored via VM.StoreCode. Unset limits are not checked.
+type UploadLimits struct {
+	MaxWasmSize         *uint64 `json:"max_wasm_size,omitempty"`
+	MaxFunctions        *uint64 `json:"max_functions,omitempty"`
+	MaxImports          *uint64 `json:"max_imports,omitempty"`
+	MaxExports          *uint64 `json:"max_exports,omitempty"`
+	MaxGlobals          *uint64 `json:"max_globals,omitempty"`
+	MaxTableSize        *uint64 `json:"max_table_size,omitempty"`
+	MaxFunctionBodySize *uint64 `json:"max_function_body_size,omitempty"`
+}
+
+// CapabilityActivation activates capabilities and host features from a block height on.
+// The activations at or below the height of a call form its feature set, see VM.FeatureSetID.
+type CapabilityActivation struct {
+	Height       uint64   `json:"height"`
+	Capabilities []string `json:"capabilities,omitempty"`
+	// HostFeatures are features beyond the imports of contracts that are not supported
+	// below the height, e.g. HostFeatureEvmQueries
+	HostFeatures []HostFeature `json:"host_features,omitempty"`
+}
+
+// HostFeature is a feature libwasmvm offers contracts that can be activated at a height.
+// Features that are not scheduled are active at all heights.
+type HostFeature string
+
+const (
+	// HostFeatureEvmQueries are {"evm":{...}} queries, see EvmQuerier
+	HostFeatureEvmQueries HostFeature = "evm_queries"
+	// HostFeatureCryptoQueries are {"crypto":{...}} queries answered by libwasmvm
+	HostFeatureCryptoQueries HostFeature = "crypto_queries"
+)
+
+// QueryLimits are limits for nested queries of a call. Unset limits are not checked.
+type QueryLimits struct {
+	// MaxDepth is the maximum number of nested queries in flight
+	MaxDepth *uint32 `json:"max_depth,omitempty"`
+	// MaxGas is the maximum gas that all queries of a call may use together
+	MaxGas *uint64 `json:"max_gas,omitempty"`
+	// AbortOnExceeded aborts the call with a QueryLimitExceededError if a limit is exceeded.
+	// Otherwise only the query exceeding it fails, with a SystemError the contract can handle.
+	AbortOnExceeded bool `json:"abort_on_exceeded,omitempty"`
+}
+
+// QueryRoutesUpdate replaces the query routes for calls from a block height on
+type QueryRoutesUpdate struct {
+	Height uint64                `json:"height"`
+	Routes map[string]QueryRoute `json:"routes"`
+}
+
+// AddressCodecConfig configures the native address conversion of a VM
+type AddressCodecConfig struct {
+	// Bech32Prefix is the human readable part of the chain's addresses, e.g. "ex"
+	Bech32Prefix string `json:"bech32_prefix"`
+	// AddressLengths are the allowed lengths of canonical addresses in bytes
+	AddressLengths []uint64 `json:"address_lengths"`
+	// CanonicalizeCost is the gas charged for a conversion from human to canonical
+	CanonicalizeCost uint64 `json:"canonicalize_cost"`
+	// HumanizeCost is the gas charged for a conversion from canonical to human
+	HumanizeCost uint64 `json:"humanize_cost"`
+	// HexAddresses accepts 20 byte addresses in the 0x prefixed hex form as well
+	HexAddresses bool `json:"hex_addresses,omitempty"`
+}
+
+// GasConfigUpdate replaces the gas config for calls from a block height on
+type GasConfigUpdate struct {
+	Height    uint64    `json:"height"`
+	GasConfig GasConfig `json:"gas_config"`
+}
+
+// LogLevel is the verbosity of the logs of libwasmvm
+type LogLevel string
+
+const (
+	LogLevelOff   LogLevel = "off"
+	LogLevelError LogLevel = "error"
+	LogLevelWarn  LogLevel = "warn"
+	LogLevelInfo  LogLevel = "info"
+	LogLevelDebug LogLevel = "debug"
+	LogLevelTrace LogLevel = "trace"
+)
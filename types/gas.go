@@ -8,3 +8,55 @@ type Gas = uint64
 type GasMeter interface {
 	GasConsumed() Gas
 }
+
+// CallReport is reported by a contract call next to its result
+type CallReport struct {
+	// GasUsed is the gas used by the call in the VM
+	GasUsed uint64
+	// EvmGasUsed is the EVM gas used by EVM queries of the call
+	EvmGasUsed uint64
+	// Fingerprint is a hash of everything that determined the result of the call. It is only
+	// set if the cache is configured with fingerprints.
+	Fingerprint []byte
+}
+
+// GasConfig are the gas costs of contract calls that can be adjusted by the chain.
+// Zero values of the overheads and costs mean no overhead or a free operation. WasmOpCost must
+// be set. DefaultGasConfig returns the defaults.
+type GasConfig struct {
+	// WasmOpCost is the cost of a Wasm operation
+	WasmOpCost uint64 `json:"wasm_op_cost"`
+	// StorageCallbackOverhead is charged for every storage callback (get, set, remove, scan and next)
+	StorageCallbackOverhead uint64 `json:"storage_callback_overhead"`
+	// CanonicalizeAddressCost is charged for every address canonicalization on top of its cost
+	CanonicalizeAddressCost uint64 `json:"canonicalize_address_cost"`
+	// HumanizeAddressCost is charged for every address humanization on top of its cost
+	HumanizeAddressCost uint64 `json:"humanize_address_cost"`
+	// QueryOverhead is charged for every query on top of the gas it used
+	QueryOverhead uint64 `json:"query_overhead"`
+	// Keccak256Cost is charged for every keccak256 crypto query
+	Keccak256Cost uint64 `json:"keccak256_cost"`
+	// Keccak256PerByteCost is charged per byte hashed by a keccak256 crypto query
+	Keccak256PerByteCost uint64 `json:"keccak256_per_byte_cost"`
+	// Secp256k1RecoverEvmAddressCost is charged for every secp256k1 EVM address recovery crypto query
+	Secp256k1RecoverEvmAddressCost uint64 `json:"secp256k1_recover_evm_address_cost"`
+	// Ed25519BatchVerifyPerSignatureCost is charged per signature of an ed25519 batch verification crypto query
+	Ed25519BatchVerifyPerSignatureCost uint64 `json:"ed25519_batch_verify_per_signature_cost"`
+}
+
+// DefaultWasmOpCost is the cost of a Wasm operation charged by cosmwasm-vm
+const DefaultWasmOpCost = 150_000
+
+// gasPerMicrosecond is the gas charged for a microsecond of computation as in cosmwasm-vm
+const gasPerMicrosecond = 1_000_000_000
+
+// DefaultGasConfig returns the gas config used unless the cache config sets one
+func DefaultGasConfig() GasConfig {
+	return GasConfig{
+		WasmOpCost:                         DefaultWasmOpCost,
+		Keccak256Cost:                      gasPerMicrosecond,
+		Keccak256PerByteCost:               gasPerMicrosecond / 100,
+		Secp256k1RecoverEvmAddressCost:     163 * gasPerMicrosecond,
+		Ed25519BatchVerifyPerSignatureCost: 63 * gasPerMicrosecond / 2,
+	}
+}
@@ -33,6 +33,33 @@ type Querier interface {
 	GasConsumed() uint64
 }
 
+// EvmQuerier is implemented by queriers that can answer EVM queries of contracts,
+// i.e. read-only calls of EVM contracts.
+type EvmQuerier interface {
+	Querier
+	// EvmCall calls the EVM contract with the 20 byte address `to` and returns the return data
+	// and the EVM gas used. An error is a reverted call, which is passed to the contract.
+	EvmCall(to []byte, data []byte, evmGasLimit uint64, gasLimit uint64) ([]byte, uint64, error)
+}
+
+// GasConsumer is implemented by queriers that can charge gas to the gas meter used by queries.
+// Only queries of such queriers are memoized or answered by query routes, since those queries
+// must be charged exactly like the ones answered by the querier.
+type GasConsumer interface {
+	Querier
+	// ConsumeGas charges the gas used by the original query. It panics with the same
+	// out of gas error as queries do if the gas meter runs out.
+	ConsumeGas(gas uint64)
+}
+
+// CallReporter is implemented by queriers that want the report of the calls they are used for,
+// e.g. to account for the EVM gas of EVM queries or to compare fingerprints between nodes.
+type CallReporter interface {
+	Querier
+	// ReportCall is called once the call returned, no matter if it succeeded
+	ReportCall(report CallReport)
+}
+
 // this is a thin wrapper around the desired Go API to give us types closer to Rust FFI
 func RustQuery(querier Querier, binRequest []byte, gasLimit uint64) QuerierResult {
 	var request QueryRequest
@@ -57,6 +84,20 @@ type QuerierResult struct {
 	Err *SystemError   `json:"error,omitempty"`
 }
 
+// QuerySnapshotEntry is a pre-populated response to a raw query request.
+// See VM.SetQuerySnapshot.
+type QuerySnapshotEntry struct {
+	Request  []byte        `json:"request"`
+	Response QuerierResult `json:"response"`
+}
+
+// QueryRoute routes queries to a query handler implemented in Rust, e.g. "snapshot".
+// Answered queries are charged GasCost via GasConsumer. See VM.UpdateQueryRoutes.
+type QueryRoute struct {
+	Handler string `json:"handler"`
+	GasCost uint64 `json:"gas_cost"`
+}
+
 func ToQuerierResult(response []byte, err error) QuerierResult {
 	if err == nil {
 		return QuerierResult{
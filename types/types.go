@@ -115,6 +115,32 @@ func (o OutOfGasError) Error() string {
 	return "Out of gas"
 }
 
+// QueryLimitExceededError is returned by calls aborted because their queries exceeded the
+// query depth or gas limit of the cache and QueryLimits.AbortOnExceeded is set.
+// The message says which limit was exceeded.
+type QueryLimitExceededError struct {
+	Msg string
+}
+
+var _ error = QueryLimitExceededError{}
+
+func (q QueryLimitExceededError) Error() string {
+	return q.Msg
+}
+
+// InstanceMemoryLimitExceededError is returned by calls of contracts whose memory cannot be
+// created within the instance memory limit of their checksum.
+// The message contains the checksum and the limit.
+type InstanceMemoryLimitExceededError struct {
+	Msg string
+}
+
+var _ error = InstanceMemoryLimitExceededError{}
+
+func (i InstanceMemoryLimitExceededError) Error() string {
+	return i.Msg
+}
+
 // Contains static analysis info of the contract (the Wasm code to be precise).
 // This type is returned by VM.AnalyzeCode().
 type AnalysisReport struct {
@@ -122,6 +148,20 @@ type AnalysisReport struct {
 	// Deprecated, use RequiredCapabilities. For now both fields contain the same value.
 	RequiredFeatures     string
 	RequiredCapabilities string
+	// A comma separated list of the exported entry points, e.g. "execute,instantiate,query"
+	Entrypoints string
+	// The name of the interface version marker export, e.g. "interface_version_8".
+	// The one with the highest version if there are multiple.
+	// Empty if the contract has no marker.
+	InterfaceVersion string
+	// The size of the Wasm bytecode in bytes
+	CodeSize uint64
+	// The number of functions defined in the contract
+	FunctionCount uint64
+	// The content of custom sections such as cw2 metadata by section name
+	CustomSections map[string][]byte
+	// The version from the cw_migrate_version custom section, if any
+	ContractMigrateVersion *uint64
 }
 
 type Metrics struct {